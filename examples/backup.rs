@@ -0,0 +1,50 @@
+//! Backs up an environment while it is in use, by copying it to a directory, compacting it on the
+//! way, or by streaming it into a writer, such as a file or a socket.
+//!
+//! Run with `cargo run --example backup`.
+
+extern crate lmdb;
+extern crate tempdir;
+
+use std::error::Error;
+use std::fs;
+
+use lmdb::{CopyFlags, Environment, Transaction, WriteFlags};
+use tempdir::TempDir;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::new("backup")?;
+    let env = Environment::new().set_map_size(16 << 20).open(dir.path())?;
+    let db = env.open_db(None)?;
+    let mut txn = env.begin_rw_txn()?;
+    for i in 0..10_000u32 {
+        txn.put(db, &i.to_be_bytes(), &[0; 100], WriteFlags::empty())?;
+    }
+    txn.commit()?;
+    // Deleting items frees pages, which a plain copy keeps and a compacting copy leaves out.
+    let mut txn = env.begin_rw_txn()?;
+    for i in 0..9_000u32 {
+        txn.del(db, &i.to_be_bytes(), None)?;
+    }
+    txn.commit()?;
+
+    // The copy is a snapshot taken with a read-only transaction, so writers may go on meanwhile.
+    let copy_dir = TempDir::new("copy")?;
+    env.copy(copy_dir.path(), CopyFlags::COMPACT)?;
+    println!("data file of {} bytes compacted to {} bytes",
+             fs::metadata(dir.path().join("data.mdb"))?.len(),
+             fs::metadata(copy_dir.path().join("data.mdb"))?.len());
+
+    let copy = Environment::new().open(copy_dir.path())?;
+    let copy_db = copy.open_db(None)?;
+    println!("the copy holds {} items", copy.begin_ro_txn()?.stat(copy_db)?.entries());
+
+    // Streaming needs no temporary file. The bytes are those of the data file of a copy.
+    #[cfg(unix)]
+    {
+        let mut stream = Vec::new();
+        let written = env.copy_to_writer(&mut stream, CopyFlags::COMPACT)?;
+        println!("streamed {} bytes", written);
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Exports databases in the text format of `mdb_dump`, and loads them into another environment,
+//! such as to move them between machines or LMDB versions, or to inspect them.
+//!
+//! Run with `cargo run --example dump_load`. The output can also be read by `mdb_load`, and
+//! `lmdb::load` reads the output of `mdb_dump`.
+
+extern crate lmdb;
+extern crate tempdir;
+
+use std::error::Error;
+use std::str;
+
+use lmdb::{DatabaseFlags, Environment, Transaction, WriteFlags};
+use tempdir::TempDir;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::new("dump")?;
+    let env = Environment::new().set_max_dbs(1).open(dir.path())?;
+    let tags = env.create_db(Some("tags"), DatabaseFlags::DUP_SORT)?;
+    let mut txn = env.begin_rw_txn()?;
+    txn.put(tags, b"color", b"blue", WriteFlags::empty())?;
+    txn.put(tags, b"color", b"red", WriteFlags::empty())?;
+    txn.put(tags, b"shape", b"round", WriteFlags::empty())?;
+    txn.commit()?;
+
+    // Any writer will do, such as a file or standard output.
+    let mut dump = Vec::new();
+    lmdb::dump(&env, &env.begin_ro_txn()?, &[tags], &mut dump)?;
+    print!("{}", str::from_utf8(&dump)?);
+
+    // The database is created with the flags recorded in the dump, here `DUP_SORT`.
+    let load_dir = TempDir::new("load")?;
+    let loaded = Environment::new().set_max_dbs(1).open(load_dir.path())?;
+    let dbs = lmdb::load(&loaded, &dump[..], None)?;
+    let txn = loaded.begin_ro_txn()?;
+    println!("loaded {} items into a database with flags {:?}",
+             txn.stat(dbs[0])?.entries(),
+             txn.db_flags(dbs[0])?);
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Reads the statistics of an environment and of its databases, such as to monitor how full the
+//! map is, or how large each database has grown.
+//!
+//! Run with `cargo run --example stats`. `lmdb-tool stat` prints the same statistics for an
+//! existing environment.
+
+extern crate lmdb;
+extern crate tempdir;
+
+use lmdb::{DatabaseFlags, Environment, Error, Transaction, WriteFlags};
+use tempdir::TempDir;
+
+fn main() -> Result<(), Error> {
+    let dir = TempDir::new("stats").expect("failed to create a temporary directory");
+    let env = Environment::new().set_max_dbs(2).open(dir.path())?;
+    let users = env.create_db(Some("users"), DatabaseFlags::empty())?;
+    let tags = env.create_db(Some("tags"), DatabaseFlags::DUP_SORT)?;
+    let mut txn = env.begin_rw_txn()?;
+    for i in 0..1000u32 {
+        txn.put(users, &format!("user:{:04}", i), &[0; 100], WriteFlags::empty())?;
+        txn.put(tags, b"even", &(i * 2).to_be_bytes(), WriteFlags::empty())?;
+    }
+    txn.commit()?;
+
+    // The environment as of the last committed transaction.
+    let info = env.info()?;
+    let page_size = env.stat()?.page_size() as usize;
+    println!("{} of {} pages used, last transaction {}",
+             info.last_pgno() + 1,
+             info.map_size() / page_size,
+             info.last_txnid());
+    let handles = env.dbi_stats();
+    println!("{} of {} database handles open", handles.open(), handles.max());
+
+    // Handles are opened before the transaction, since opening one fails on a thread which has
+    // an ongoing transaction. The statistics of every database are then read from one snapshot.
+    let dbs = env.list_dbs()?
+                 .into_iter()
+                 .map(|name| env.open_db(Some(&name)).map(|db| (name, db)))
+                 .collect::<Result<Vec<_>, Error>>()?;
+    let txn = env.begin_ro_txn()?;
+    for (name, db) in dbs {
+        let stat = txn.stat(db)?;
+        println!("{}: {} items, depth {}, {} leaf pages, {} overflow pages",
+                 name,
+                 stat.entries(),
+                 stat.depth(),
+                 stat.leaf_pages(),
+                 stat.overflow_pages());
+    }
+    Ok(())
+}
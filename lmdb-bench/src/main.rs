@@ -0,0 +1,287 @@
+//! Runs identical workloads against `lmdb` and, optionally, other embedded key-value stores.
+//!
+//! The workloads are a batched load of the entries, random point gets, and short range scans
+//! starting at random keys. Other stores are enabled with the `sled-backend` and
+//! `rocksdb-backend` features:
+//!
+//! ```text
+//! cargo run --release -p lmdb-bench --features sled-backend,rocksdb-backend -- --entries 1000000
+//! ```
+
+extern crate lmdb;
+extern crate rand;
+extern crate tempdir;
+#[cfg(feature = "rocksdb-backend")] extern crate rocksdb;
+#[cfg(feature = "sled-backend")] extern crate sled;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{env, process};
+
+use lmdb::{Cursor, Transaction};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use tempdir::TempDir;
+
+const USAGE: &str = "\
+usage: lmdb-bench [options]
+
+options:
+    --entries <n>     number of entries to load (default 100000)
+    --value-size <n>  size of each value in bytes (default 100)
+    --batch <n>       entries written per transaction or batch (default 1000)
+    --gets <n>        number of point gets (default 100000)
+    --scans <n>       number of range scans (default 10000)
+    --scan-len <n>    entries read per range scan (default 100)";
+
+struct Config {
+    entries: usize,
+    value_size: usize,
+    batch: usize,
+    gets: usize,
+    scans: usize,
+    scan_len: usize,
+}
+
+/// The operations each store implements for the workloads.
+///
+/// The read operations return the number of bytes read, so that the reads can't be optimized away
+/// and the stores can be checked against each other.
+trait Store {
+
+    /// Writes the entries atomically.
+    fn write_batch(&mut self, entries: &[(Vec<u8>, Vec<u8>)]);
+
+    /// Returns the length of the value of the key, if it exists.
+    fn get(&self, key: &[u8]) -> Option<usize>;
+
+    /// Reads up to `count` entries starting at the first key greater than or equal to `start`.
+    fn scan(&self, start: &[u8], count: usize) -> usize;
+}
+
+struct LmdbStore {
+    env: lmdb::Environment,
+    db: lmdb::Database,
+}
+
+impl LmdbStore {
+    fn open(path: &Path, config: &Config) -> LmdbStore {
+        // Leave plenty of room for page overhead and copy-on-write.
+        let map_size = 4 * config.entries * (config.value_size + 64) + (64 << 20);
+        let env = lmdb::Environment::new().set_map_size(map_size).open(path).unwrap();
+        let db = env.open_db(None).unwrap();
+        LmdbStore { env, db }
+    }
+}
+
+impl Store for LmdbStore {
+    fn write_batch(&mut self, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let mut txn = self.env.begin_rw_txn().unwrap();
+        for (key, value) in entries {
+            txn.put(self.db, key, value, lmdb::WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    fn get(&self, key: &[u8]) -> Option<usize> {
+        let txn = self.env.begin_ro_txn().unwrap();
+        match txn.get(self.db, &key) {
+            Ok(value) => Some(value.len()),
+            Err(lmdb::Error::NotFound) => None,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> usize {
+        let txn = self.env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(self.db).unwrap();
+        cursor.iter_from(start).take(count).map(|(key, value)| key.len() + value.len()).sum()
+    }
+}
+
+#[cfg(feature = "sled-backend")]
+struct SledStore {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled-backend")]
+impl Store for SledStore {
+    fn write_batch(&mut self, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let mut batch = sled::Batch::default();
+        for (key, value) in entries {
+            batch.insert(&key[..], &value[..]);
+        }
+        self.db.apply_batch(batch).unwrap();
+    }
+
+    fn get(&self, key: &[u8]) -> Option<usize> {
+        self.db.get(key).unwrap().map(|value| value.len())
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> usize {
+        self.db.range(start..)
+               .take(count)
+               .map(|entry| {
+                   let (key, value) = entry.unwrap();
+                   key.len() + value.len()
+               })
+               .sum()
+    }
+}
+
+#[cfg(feature = "rocksdb-backend")]
+struct RocksDbStore {
+    db: rocksdb::DB,
+}
+
+#[cfg(feature = "rocksdb-backend")]
+impl Store for RocksDbStore {
+    fn write_batch(&mut self, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (key, value) in entries {
+            batch.put(key, value);
+        }
+        self.db.write(batch).unwrap();
+    }
+
+    fn get(&self, key: &[u8]) -> Option<usize> {
+        self.db.get_pinned(key).unwrap().map(|value| value.len())
+    }
+
+    fn scan(&self, start: &[u8], count: usize) -> usize {
+        let mode = rocksdb::IteratorMode::From(start, rocksdb::Direction::Forward);
+        self.db.iterator(mode)
+               .take(count)
+               .map(|entry| {
+                   let (key, value) = entry.unwrap();
+                   key.len() + value.len()
+               })
+               .sum()
+    }
+}
+
+/// The result of running the workloads against a store.
+struct Report {
+    store: &'static str,
+    load: Duration,
+    gets: Duration,
+    scans: Duration,
+    /// Total bytes read by the gets and scans.
+    bytes_read: usize,
+}
+
+fn key(n: usize) -> Vec<u8> {
+    format!("key{:012}", n).into_bytes()
+}
+
+fn run(name: &'static str, store: &mut dyn Store, config: &Config) -> Report {
+    // Every store sees the same sequence of operations.
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let mut keys = (0..config.entries).collect::<Vec<_>>();
+    rng.shuffle(&mut keys);
+    let value = vec![0x5a; config.value_size];
+
+    let start = Instant::now();
+    for chunk in keys.chunks(config.batch.max(1)) {
+        let entries = chunk.iter().map(|&n| (key(n), value.clone())).collect::<Vec<_>>();
+        store.write_batch(&entries);
+    }
+    let load = start.elapsed();
+
+    let mut bytes_read = 0;
+    let get_keys = (0..config.gets).map(|_| key(rng.gen_range(0, config.entries))).collect::<Vec<_>>();
+    let start = Instant::now();
+    for key in &get_keys {
+        bytes_read += store.get(key).expect("missing key");
+    }
+    let gets = start.elapsed();
+
+    let scan_keys = (0..config.scans).map(|_| key(rng.gen_range(0, config.entries))).collect::<Vec<_>>();
+    let start = Instant::now();
+    for key in &scan_keys {
+        bytes_read += store.scan(key, config.scan_len);
+    }
+    let scans = start.elapsed();
+
+    Report { store: name, load, gets, scans, bytes_read }
+}
+
+fn ops_per_sec(ops: usize, duration: Duration) -> f64 {
+    ops as f64 / duration.as_secs_f64()
+}
+
+fn print_reports(reports: &[Report], config: &Config) {
+    println!("{} entries of {} byte values, {} per batch", config.entries, config.value_size, config.batch);
+    println!("{} point gets, {} scans of {} entries", config.gets, config.scans, config.scan_len);
+    println!();
+    println!("{:<10} {:>16} {:>16} {:>16} {:>14}", "store", "load (entries/s)", "gets (ops/s)", "scans (ops/s)", "bytes read");
+    for report in reports {
+        println!("{:<10} {:>16.0} {:>16.0} {:>16.0} {:>14}",
+                 report.store,
+                 ops_per_sec(config.entries, report.load),
+                 ops_per_sec(config.gets, report.gets),
+                 ops_per_sec(config.scans, report.scans),
+                 report.bytes_read);
+    }
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        entries: 100_000,
+        value_size: 100,
+        batch: 1000,
+        gets: 100_000,
+        scans: 10_000,
+        scan_len: 100,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let field = match &arg[..] {
+            "--entries" => &mut config.entries,
+            "--value-size" => &mut config.value_size,
+            "--batch" => &mut config.batch,
+            "--gets" => &mut config.gets,
+            "--scans" => &mut config.scans,
+            "--scan-len" => &mut config.scan_len,
+            _ => return Err(USAGE.to_owned()),
+        };
+        let value = args.next().ok_or_else(|| format!("{} requires a value", arg))?;
+        *field = value.parse().map_err(|_| format!("{}: invalid number: {}", arg, value))?;
+    }
+    if config.entries == 0 {
+        return Err("--entries must be positive".to_owned());
+    }
+    Ok(config)
+}
+
+fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        },
+    };
+    let mut reports = Vec::new();
+
+    {
+        let dir = TempDir::new("lmdb-bench").unwrap();
+        let mut store = LmdbStore::open(dir.path(), &config);
+        reports.push(run("lmdb", &mut store, &config));
+    }
+
+    #[cfg(feature = "sled-backend")]
+    {
+        let dir = TempDir::new("sled-bench").unwrap();
+        let mut store = SledStore { db: sled::open(dir.path()).unwrap() };
+        reports.push(run("sled", &mut store, &config));
+    }
+
+    #[cfg(feature = "rocksdb-backend")]
+    {
+        let dir = TempDir::new("rocksdb-bench").unwrap();
+        let mut store = RocksDbStore { db: rocksdb::DB::open_default(dir.path()).unwrap() };
+        reports.push(run("rocksdb", &mut store, &config));
+    }
+
+    print_reports(&reports, &config);
+}
@@ -26,6 +26,8 @@ pub const MDB_NOLOCK: c_uint = 0x400000;
 pub const MDB_NORDAHEAD: c_uint = 0x800000;
 /// don't initialize malloc'd memory before writing to datafile
 pub const MDB_NOMEMINIT: c_uint = 0x1000000;
+/// use the previous snapshot rather than the latest one (not supported by LMDB 0.9)
+pub const MDB_PREVSNAPSHOT: c_uint = 0x2000000;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 //// Database Flags
@@ -123,3 +125,12 @@ pub const MDB_BAD_VALSIZE: c_int = -30781;
 pub const MDB_BAD_DBI: c_int = -30780;
 /// The last defined error code.
 pub const MDB_LAST_ERRCODE: c_int = MDB_BAD_DBI;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+//// Version
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Library major version, of the LMDB release these bindings are written against.
+pub const MDB_VERSION_MAJOR: c_int = 0;
+/// Library minor version, of the LMDB release these bindings are written against.
+pub const MDB_VERSION_MINOR: c_int = 9;
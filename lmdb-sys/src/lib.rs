@@ -11,6 +11,13 @@ pub type mode_t = ::libc::mode_t;
 #[allow(non_camel_case_types)]
 pub type mode_t = ::libc::c_int;
 
+#[cfg(unix)]
+#[allow(non_camel_case_types)]
+pub type mdb_filehandle_t = ::libc::c_int;
+#[cfg(windows)]
+#[allow(non_camel_case_types)]
+pub type mdb_filehandle_t = *mut ::libc::c_void;
+
 pub use constants::*;
 pub use ffi::*;
 
@@ -0,0 +1,285 @@
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::sync::Arc;
+use std::{fmt, result, str};
+
+use arrow_array::builder::{BinaryBuilder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use error::Error;
+use transaction::Transaction;
+
+/// An error from exporting a database to Arrow.
+#[derive(Debug)]
+pub enum ExportError {
+    /// An error returned by LMDB.
+    Lmdb(Error),
+    /// A key or value could not be decoded as the type of its column.
+    Decode {
+        /// The name of the column.
+        column: String,
+        /// The key of the item.
+        key: Vec<u8>,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportError::Lmdb(ref error) => error.fmt(fmt),
+            ExportError::Decode { ref column, ref key } => {
+                write!(fmt, "invalid {} of key ", column)?;
+                for byte in key {
+                    write!(fmt, "{:02x}", byte)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl StdError for ExportError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ExportError::Lmdb(ref error) => Some(error),
+            ExportError::Decode { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for ExportError {
+    fn from(error: Error) -> ExportError {
+        ExportError::Lmdb(error)
+    }
+}
+
+/// An Arrow export result.
+pub type ExportResult<T> = result::Result<T, ExportError>;
+
+/// The type of the Arrow column a key or value is decoded into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnType {
+    /// The bytes, as `DataType::Binary`.
+    Binary,
+    /// UTF-8 text, as `DataType::Utf8`.
+    Utf8,
+    /// A 4 byte big-endian integer, as `DataType::UInt32`.
+    UInt32,
+    /// An 8 byte big-endian integer, as `DataType::UInt64`.
+    UInt64,
+}
+
+impl ColumnType {
+    fn data_type(self) -> DataType {
+        match self {
+            ColumnType::Binary => DataType::Binary,
+            ColumnType::Utf8 => DataType::Utf8,
+            ColumnType::UInt32 => DataType::UInt32,
+            ColumnType::UInt64 => DataType::UInt64,
+        }
+    }
+
+    fn builder(self, capacity: usize) -> ColumnBuilder {
+        match self {
+            ColumnType::Binary => ColumnBuilder::Binary(BinaryBuilder::with_capacity(capacity, 0)),
+            ColumnType::Utf8 => ColumnBuilder::Utf8(StringBuilder::with_capacity(capacity, 0)),
+            ColumnType::UInt32 => ColumnBuilder::UInt32(UInt32Builder::with_capacity(capacity)),
+            ColumnType::UInt64 => ColumnBuilder::UInt64(UInt64Builder::with_capacity(capacity)),
+        }
+    }
+}
+
+/// Maps the keys and values of a database to the two columns of Arrow record batches.
+#[derive(Clone, Debug)]
+pub struct SchemaMapping {
+    key_type: ColumnType,
+    value_type: ColumnType,
+    schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl SchemaMapping {
+
+    /// Creates a mapping of keys to a column named `key_name` and values to a column named
+    /// `value_name`, with up to 8192 items per record batch.
+    pub fn new(key_name: &str, key_type: ColumnType, value_name: &str, value_type: ColumnType) -> SchemaMapping {
+        let schema = Schema::new(vec![
+            Field::new(key_name, key_type.data_type(), false),
+            Field::new(value_name, value_type.data_type(), false),
+        ]);
+        SchemaMapping { key_type, value_type, schema: Arc::new(schema), batch_size: 8192 }
+    }
+
+    /// Sets the maximum number of items per record batch.
+    pub fn set_batch_size(&mut self, batch_size: usize) -> &mut SchemaMapping {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Returns the schema of the record batches.
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Exports the items of the database as Arrow record batches, in key order.
+///
+/// The batches can be handed to any Arrow consumer, for example written to a Parquet file with
+/// the `parquet` crate's `ArrowWriter`. Every item is read from the snapshot of the transaction,
+/// so the batches are consistent with each other. Requires the `arrow` feature.
+pub fn export_arrow<'txn, T>(txn: &'txn T, db: Database, mapping: &SchemaMapping) -> ExportResult<ArrowExport<'txn>>
+where T: Transaction {
+    let iter = CursorIter::new(txn.open_ro_cursor(db)?, |cursor| cursor.iter_start());
+    Ok(ArrowExport { iter, mapping: mapping.clone(), done: false })
+}
+
+/// An iterator over the record batches of a database, returned by `export_arrow`.
+///
+/// Yields `ExportError::Decode` for an item which can't be decoded, and then ends.
+pub struct ArrowExport<'txn> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+    mapping: SchemaMapping,
+    done: bool,
+}
+
+impl <'txn> fmt::Debug for ArrowExport<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("ArrowExport").finish()
+    }
+}
+
+impl <'txn> ArrowExport<'txn> {
+
+    /// Ends the export with an error for the column of the item with the key.
+    fn decode_error(&mut self, column: usize, key: &[u8]) -> ExportError {
+        self.done = true;
+        let column = self.mapping.schema.field(column).name().clone();
+        ExportError::Decode { column, key: key.to_vec() }
+    }
+}
+
+impl <'txn> Iterator for ArrowExport<'txn> {
+
+    type Item = ExportResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<ExportResult<RecordBatch>> {
+        if self.done {
+            return None;
+        }
+        let batch_size = self.mapping.batch_size;
+        let mut keys = self.mapping.key_type.builder(batch_size);
+        let mut values = self.mapping.value_type.builder(batch_size);
+        let mut len = 0;
+        while len < batch_size {
+            let (key, value) = match self.iter.next() {
+                Some(item) => item,
+                None => {
+                    self.done = true;
+                    break;
+                },
+            };
+            if !keys.append(key) {
+                return Some(Err(self.decode_error(0, key)));
+            }
+            if !values.append(value) {
+                return Some(Err(self.decode_error(1, key)));
+            }
+            len += 1;
+        }
+        if len == 0 {
+            return None;
+        }
+        let columns = vec![keys.finish(), values.finish()];
+        Some(Ok(RecordBatch::try_new(self.mapping.schema(), columns).expect("columns match the schema")))
+    }
+}
+
+/// Builds the column of a record batch.
+enum ColumnBuilder {
+    Binary(BinaryBuilder),
+    Utf8(StringBuilder),
+    UInt32(UInt32Builder),
+    UInt64(UInt64Builder),
+}
+
+impl ColumnBuilder {
+
+    /// Appends the decoded bytes, returning false if they can't be decoded.
+    fn append(&mut self, bytes: &[u8]) -> bool {
+        match *self {
+            ColumnBuilder::Binary(ref mut builder) => builder.append_value(bytes),
+            ColumnBuilder::Utf8(ref mut builder) => match str::from_utf8(bytes) {
+                Ok(text) => builder.append_value(text),
+                Err(..) => return false,
+            },
+            ColumnBuilder::UInt32(ref mut builder) => match <[u8; 4]>::try_from(bytes) {
+                Ok(bytes) => builder.append_value(u32::from_be_bytes(bytes)),
+                Err(..) => return false,
+            },
+            ColumnBuilder::UInt64(ref mut builder) => match <[u8; 8]>::try_from(bytes) {
+                Ok(bytes) => builder.append_value(u64::from_be_bytes(bytes)),
+                Err(..) => return false,
+            },
+        }
+        true
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match *self {
+            ColumnBuilder::Binary(ref mut builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Utf8(ref mut builder) => Arc::new(builder.finish()),
+            ColumnBuilder::UInt32(ref mut builder) => Arc::new(builder.finish()),
+            ColumnBuilder::UInt64(ref mut builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use arrow_array::{Array, StringArray, UInt32Array};
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_export_arrow() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for i in 0..5u32 {
+            txn.put(db, &i.to_be_bytes(), &format!("value{}", i), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let mut mapping = SchemaMapping::new("id", ColumnType::UInt32, "name", ColumnType::Utf8);
+        mapping.set_batch_size(2);
+        let txn = env.begin_ro_txn().unwrap();
+        let batches = export_arrow(&txn, db, &mapping).unwrap().collect::<ExportResult<Vec<_>>>().unwrap();
+        assert_eq!(vec![2, 2, 1], batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>());
+        assert_eq!(mapping.schema(), batches[0].schema());
+        let ids = batches[1].column(0).as_any().downcast_ref::<UInt32Array>().unwrap();
+        let names = batches[1].column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((2, "value2"), (ids.value(0), names.value(0)));
+        assert_eq!((3, "value3"), (ids.value(1), names.value(1)));
+        drop(txn);
+
+        // Keys of another size can't be decoded.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"x", b"", WriteFlags::empty()).unwrap();
+        let mut export = export_arrow(&txn, db, &mapping).unwrap();
+        assert_eq!(2, export.next().unwrap().unwrap().num_rows());
+        assert_eq!(2, export.next().unwrap().unwrap().num_rows());
+        match export.next() {
+            Some(Err(ExportError::Decode { column, key })) => assert_eq!(("id", &b"x"[..]), (&column[..], &key[..])),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(export.next().is_none());
+    }
+}
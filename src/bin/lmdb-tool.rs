@@ -0,0 +1,570 @@
+//! A command line tool for inspecting and maintaining LMDB environments.
+//!
+//! Provides the functionality of the `mdb_stat`, `mdb_dump`, `mdb_load` and `mdb_copy` utilities
+//! on top of the safe `lmdb` APIs. The dump format is compatible with `mdb_dump` and `mdb_load`
+//! using the default `bytevalue` format.
+
+extern crate lmdb;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+#[cfg(feature = "repl")]
+use std::io::BufRead;
+use std::path::Path;
+use std::{env, process};
+
+#[cfg(test)]
+extern crate tempdir;
+
+use lmdb::{
+    CopyFlags,
+    Cursor,
+    Database,
+    DatabaseFlags,
+    Environment,
+    EnvironmentFlags,
+    Format,
+    PrinterRegistry,
+    Stat,
+    Transaction,
+};
+#[cfg(feature = "repl")]
+use lmdb::{Printer, WriteFlags};
+
+const USAGE: &str = "\
+usage: lmdb-tool <command> [options] <path>
+
+commands:
+    stat  [-a | -s <name>] <path>        print environment and database statistics
+    dump  [-a | -s <name>] <path>        write databases to stdout in mdb_dump format
+    print [-a | -s <name>] <path>        write database entries to stdout in readable form
+    load  [-s <name>] [-f <file>] <path> read databases in mdb_dump format from stdin or a file
+    copy  [-c] <path> <dest>             copy the environment, compacting it with -c
+    check <path>                         verify that all databases can be read
+    repl  [-s <name>] <path>             inspect and modify the environment interactively
+                                         (requires the repl feature)
+
+options:
+    -a          operate on all named databases as well as the main database
+    -s <name>   operate on the named database
+    -f <file>   read from the given file instead of stdin
+    -c          compact while copying
+    -n          the environment is a single file, not a directory
+    -p <spec>   print keys and values of a database in the given formats, where the spec is
+                [name]=<key format>[,<value format>] and an empty name is the main database.
+                Formats are hex, utf8, auto, u32be, u32le, u64be, u64le and native.";
+
+/// The maximum number of named databases the tool may open.
+const MAX_DBS: u32 = 1024;
+
+type Result<T> = ::std::result::Result<T, String>;
+
+struct Options {
+    command: String,
+    all: bool,
+    name: Option<String>,
+    file: Option<String>,
+    compact: bool,
+    no_sub_dir: bool,
+    printers: PrinterRegistry,
+    paths: Vec<String>,
+}
+
+/// Parses the arguments following the name of the program.
+fn parse_args<I>(args: I) -> Result<Options> where I: IntoIterator<Item = String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or_else(|| USAGE.to_owned())?;
+    let mut options = Options {
+        command,
+        all: false,
+        name: None,
+        file: None,
+        compact: false,
+        no_sub_dir: false,
+        printers: PrinterRegistry::new(),
+        paths: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-a" => options.all = true,
+            "-c" => options.compact = true,
+            "-n" => options.no_sub_dir = true,
+            "-s" => options.name = Some(args.next().ok_or("-s requires a database name")?),
+            "-f" => options.file = Some(args.next().ok_or("-f requires a file name")?),
+            "-p" => parse_printers(&mut options.printers, &args.next().ok_or("-p requires a spec")?)?,
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => options.paths.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_format(name: &str) -> Result<Format> {
+    Format::from_name(name).ok_or_else(|| format!("unknown format: {}", name))
+}
+
+/// Registers the printers of a `[name]=<key format>[,<value format>]` spec.
+fn parse_printers(printers: &mut PrinterRegistry, spec: &str) -> Result<()> {
+    let eq = spec.rfind('=').ok_or_else(|| format!("invalid printer spec: {}", spec))?;
+    let name = match &spec[..eq] {
+        "" => None,
+        name => Some(name),
+    };
+    let mut formats = spec[eq + 1..].splitn(2, ',');
+    if let Some(key) = formats.next() {
+        printers.set_key_printer(name, parse_format(key)?);
+    }
+    if let Some(value) = formats.next() {
+        printers.set_value_printer(name, parse_format(value)?);
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(|options| {
+        match &options.command[..] {
+            "stat" => stat(&options),
+            "dump" => dump(&options, &mut io::stdout().lock()),
+            "print" => print(&options),
+            "load" => load(&options),
+            "copy" => copy(&options),
+            "check" => check(&options, &mut io::stdout().lock()),
+            #[cfg(feature = "repl")]
+            "repl" => repl(&options, &mut io::stdin().lock(), &mut io::stdout()),
+            "-h" | "--help" | "help" => Err(USAGE.to_owned()),
+            command => Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+        }
+    });
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn open_env(options: &Options, path: &str, flags: EnvironmentFlags) -> Result<Environment> {
+    open_env_with(options, path, flags, false)
+}
+
+/// Opens the environment, letting it resize its map if `resize` is set.
+fn open_env_with(options: &Options, path: &str, flags: EnvironmentFlags, resize: bool) -> Result<Environment> {
+    let mut flags = flags;
+    if options.no_sub_dir {
+        flags |= EnvironmentFlags::NO_SUB_DIR;
+    }
+    Environment::new().set_flags(flags)
+                      .set_max_dbs(MAX_DBS)
+                      .set_adopt_map_resize(resize)
+                      .open(Path::new(path))
+                      .map_err(|error| format!("{}: {}", path, error))
+}
+
+fn single_path(options: &Options) -> Result<&str> {
+    match options.paths.len() {
+        1 => Ok(&options.paths[0]),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+/// Returns the names of the named databases in the environment.
+fn db_names(env: &Environment) -> Result<Vec<String>> {
+    env.list_dbs().map_err(|e| e.to_string())
+}
+
+/// Returns the databases selected by the options as (name, handle) pairs.
+fn selected_dbs(options: &Options, env: &Environment) -> Result<Vec<(Option<String>, Database)>> {
+    let mut dbs = Vec::new();
+    if let Some(ref name) = options.name {
+        let db = env.open_db(Some(name)).map_err(|e| format!("{}: {}", name, e))?;
+        dbs.push((Some(name.clone()), db));
+    } else {
+        dbs.push((None, env.open_db(None).map_err(|e| e.to_string())?));
+        if options.all {
+            for name in db_names(env)? {
+                let db = env.open_db(Some(&name)).map_err(|e| format!("{}: {}", name, e))?;
+                dbs.push((Some(name), db));
+            }
+        }
+    }
+    Ok(dbs)
+}
+
+fn print_stat(out: &mut dyn Write, stat: &Stat) -> io::Result<()> {
+    writeln!(out, "  Tree depth: {}", stat.depth())?;
+    writeln!(out, "  Branch pages: {}", stat.branch_pages())?;
+    writeln!(out, "  Leaf pages: {}", stat.leaf_pages())?;
+    writeln!(out, "  Overflow pages: {}", stat.overflow_pages())?;
+    writeln!(out, "  Entries: {}", stat.entries())
+}
+
+fn stat(options: &Options) -> Result<()> {
+    let env = open_env(options, single_path(options)?, EnvironmentFlags::READ_ONLY)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let env_stat = env.stat().map_err(|e| e.to_string())?;
+    let info = env.info().map_err(|e| e.to_string())?;
+    let dbs = selected_dbs(options, &env)?;
+    let dbi_stats = env.dbi_stats();
+    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+
+    (|| -> io::Result<()> {
+        writeln!(out, "Environment")?;
+        writeln!(out, "  Map size: {}", info.map_size())?;
+        writeln!(out, "  Page size: {}", env_stat.page_size())?;
+        writeln!(out, "  Max pages: {}", info.map_size() / env_stat.page_size() as usize)?;
+        writeln!(out, "  Number of pages used: {}", info.last_pgno() + 1)?;
+        writeln!(out, "  Last transaction ID: {}", info.last_txnid())?;
+        writeln!(out, "  Max readers: {}", info.max_readers())?;
+        writeln!(out, "  Number of readers used: {}", info.num_readers())?;
+        writeln!(out, "  Max databases: {}", dbi_stats.max())?;
+        writeln!(out, "  Number of databases open: {}", dbi_stats.open())?;
+        for (name, db) in &dbs {
+            let stat = txn.stat(*db).map_err(io::Error::other)?;
+            match *name {
+                Some(ref name) => writeln!(out, "Status of {}", name)?,
+                None => writeln!(out, "Status of Main DB")?,
+            }
+            print_stat(&mut out, &stat)?;
+        }
+        Ok(())
+    })().map_err(|e| e.to_string())
+}
+
+fn dump(options: &Options, out: &mut dyn Write) -> Result<()> {
+    let env = open_env(options, single_path(options)?, EnvironmentFlags::READ_ONLY)?;
+    let dbs = selected_dbs(options, &env)?.into_iter().map(|(_, db)| db).collect::<Vec<_>>();
+    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+    let mut out = BufWriter::new(out);
+    lmdb::dump(&env, &txn, &dbs, &mut out).map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())
+}
+
+fn print(options: &Options) -> Result<()> {
+    let env = open_env(options, single_path(options)?, EnvironmentFlags::READ_ONLY)?;
+    let dbs = selected_dbs(options, &env)?;
+    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    for (name, db) in dbs {
+        let name = name.as_ref().map(|name| &name[..]);
+        let mut cursor = txn.open_ro_cursor(db).map_err(|e| e.to_string())?;
+        (|| -> io::Result<()> {
+            writeln!(out, "[{}]", name.unwrap_or("Main DB"))?;
+            for (key, data) in cursor.iter_start() {
+                writeln!(out, "{} {}", options.printers.key(name, key), options.printers.value(name, data))?;
+            }
+            Ok(())
+        })().map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+/// Decodes a string of hex digits.
+#[cfg(feature = "repl")]
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes().chunks(2).map(|pair| {
+        match ::std::str::from_utf8(pair) {
+            Ok(pair) if pair.len() == 2 => u8::from_str_radix(pair, 16).ok(),
+            _ => None,
+        }
+    }).collect()
+}
+
+fn load(options: &Options) -> Result<()> {
+    // The map is grown to the size recorded in the dump, as mdb_load does.
+    let env = open_env_with(options, single_path(options)?, EnvironmentFlags::empty(), true)?;
+    let name = options.name.as_ref().map(|name| &name[..]);
+    match options.file {
+        Some(ref file) => {
+            let file = File::open(file).map_err(|e| format!("{}: {}", file, e))?;
+            lmdb::load(&env, BufReader::new(file), name)
+        },
+        None => lmdb::load(&env, BufReader::new(io::stdin()), name),
+    }.map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn copy(options: &Options) -> Result<()> {
+    if options.paths.len() != 2 {
+        return Err(USAGE.to_owned());
+    }
+    let env = open_env(options, &options.paths[0], EnvironmentFlags::READ_ONLY)?;
+    let flags = if options.compact { CopyFlags::COMPACT } else { CopyFlags::empty() };
+    env.copy(Path::new(&options.paths[1]), flags)
+       .map_err(|e| format!("{}: {}", options.paths[1], e))
+}
+
+fn check(options: &Options, out: &mut dyn Write) -> Result<()> {
+    let env = open_env(options, single_path(options)?, EnvironmentFlags::READ_ONLY)?;
+    let mut dbs = vec![(None, env.open_db(None).map_err(|e| e.to_string())?)];
+    for name in db_names(&env)? {
+        let db = env.open_db(Some(&name)).map_err(|e| format!("{}: {}", name, e))?;
+        dbs.push((Some(name), db));
+    }
+    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+
+    let mut failures = 0;
+    for (name, db) in dbs {
+        let name = name.unwrap_or_else(|| "Main DB".to_owned());
+        let flags = txn.db_flags(db).map_err(|e| e.to_string())?;
+        let expected = txn.stat(db).map_err(|e| e.to_string())?.entries();
+        let ordered = !flags.intersects(DatabaseFlags::REVERSE_KEY | DatabaseFlags::INTEGER_KEY);
+
+        let mut cursor = txn.open_ro_cursor(db).map_err(|e| e.to_string())?;
+        let mut count = 0;
+        let mut out_of_order = 0;
+        let mut prev: Option<&[u8]> = None;
+        for (key, _) in cursor.iter_start() {
+            count += 1;
+            if let Some(prev) = prev {
+                if ordered && key < prev {
+                    out_of_order += 1;
+                }
+            }
+            prev = Some(key);
+        }
+
+        let result = if count != expected {
+            failures += 1;
+            writeln!(out, "{}: read {} entries, expected {}", name, count, expected)
+        } else if out_of_order > 0 {
+            failures += 1;
+            writeln!(out, "{}: {} keys out of order", name, out_of_order)
+        } else {
+            writeln!(out, "{}: ok ({} entries)", name, count)
+        };
+        result.map_err(|e| e.to_string())?;
+    }
+
+    if failures > 0 {
+        Err(format!("{} database(s) failed verification", failures))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "repl")]
+const REPL_HELP: &str = "\
+commands:
+    get <key>              print the value of the key
+    put <key> <value>      store the key/value pair
+    del <key>              delete the key
+    scan [prefix] [limit]  print the entries whose key starts with the prefix
+    stat                   print statistics of the current database
+    use [name]             switch to the named database, or the main database
+    mode hex|utf8          read and print keys and values as hex or UTF-8
+    format key|value <f>   print keys or values of the current database in the given format
+                           (hex, utf8, auto, u32be, u32le, u64be, u64le or native)
+    help                   print this message
+    quit                   exit
+
+In utf8 mode, arguments starting with 0x are read as hex, and keys and values are printed
+using the formats of the current database.";
+
+#[cfg(feature = "repl")]
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Hex,
+    Utf8,
+}
+
+#[cfg(feature = "repl")]
+fn parse_bytes(mode: Mode, arg: &str) -> Result<Vec<u8>> {
+    match mode {
+        Mode::Hex => decode_hex(arg).ok_or_else(|| format!("invalid hex: {}", arg)),
+        Mode::Utf8 if arg.starts_with("0x") =>
+            decode_hex(&arg[2..]).ok_or_else(|| format!("invalid hex: {}", arg)),
+        Mode::Utf8 => Ok(arg.as_bytes().to_vec()),
+    }
+}
+
+#[cfg(feature = "repl")]
+fn format_hex(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    let _ = Format::Hex.print(bytes, &mut hex);
+    hex
+}
+
+#[cfg(feature = "repl")]
+fn repl(options: &Options, input: &mut dyn BufRead, out: &mut dyn Write) -> Result<()> {
+    let env = open_env(options, single_path(options)?, EnvironmentFlags::empty())?;
+    let mut name = options.name.clone();
+    let mut db = env.open_db(name.as_ref().map(|name| &name[..])).map_err(|e| e.to_string())?;
+    let mut mode = Mode::Utf8;
+    let mut printers = options.printers.clone();
+
+    let mut line = String::new();
+    loop {
+        write!(out, "{}> ", name.as_ref().map(|name| &name[..]).unwrap_or("")).map_err(|e| e.to_string())?;
+        out.flush().map_err(|e| e.to_string())?;
+        line.clear();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return writeln!(out).map_err(|e| e.to_string());
+        }
+        let args = line.split_whitespace().collect::<Vec<_>>();
+        let result = match args.first() {
+            None => Ok(()),
+            Some(&"quit") | Some(&"exit") => return Ok(()),
+            Some(&"help") => writeln!(out, "{}", REPL_HELP).map_err(|e| e.to_string()),
+            Some(&"mode") => match args.get(1) {
+                Some(&"hex") => { mode = Mode::Hex; Ok(()) },
+                Some(&"utf8") => { mode = Mode::Utf8; Ok(()) },
+                _ => Err("usage: mode hex|utf8".to_owned()),
+            },
+            Some(&"format") if args.len() == 3 => {
+                let name = name.as_ref().map(|name| &name[..]);
+                parse_format(args[2]).and_then(|format| match args[1] {
+                    "key" => { printers.set_key_printer(name, format); Ok(()) },
+                    "value" => { printers.set_value_printer(name, format); Ok(()) },
+                    _ => Err("usage: format key|value <format>".to_owned()),
+                })
+            },
+            Some(&"use") => {
+                let new_name = args.get(1).map(|name| name.to_string());
+                env.open_db(new_name.as_ref().map(|name| &name[..]))
+                   .map(|new_db| {
+                       db = new_db;
+                       name = new_name;
+                   })
+                   .map_err(|e| e.to_string())
+            },
+            Some(&"get") if args.len() == 2 => {
+                parse_bytes(mode, args[1]).and_then(|key| {
+                    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+                    let value = txn.get(db, &key).map_err(|e| e.to_string())?;
+                    let name = name.as_ref().map(|name| &name[..]);
+                    match mode {
+                        Mode::Hex => writeln!(out, "{}", format_hex(value)),
+                        Mode::Utf8 => writeln!(out, "{}", printers.value(name, value)),
+                    }.map_err(|e| e.to_string())
+                })
+            },
+            Some(&"put") if args.len() == 3 => {
+                parse_bytes(mode, args[1]).and_then(|key| {
+                    let value = parse_bytes(mode, args[2])?;
+                    let mut txn = env.begin_rw_txn().map_err(|e| e.to_string())?;
+                    txn.put(db, &key, &value, WriteFlags::empty()).map_err(|e| e.to_string())?;
+                    txn.commit().map_err(|e| e.to_string())
+                })
+            },
+            Some(&"del") if args.len() == 2 => {
+                parse_bytes(mode, args[1]).and_then(|key| {
+                    let mut txn = env.begin_rw_txn().map_err(|e| e.to_string())?;
+                    txn.del(db, &key, None).map_err(|e| e.to_string())?;
+                    txn.commit().map_err(|e| e.to_string())
+                })
+            },
+            Some(&"scan") if args.len() <= 3 => {
+                let prefix = match args.get(1) {
+                    Some(prefix) => parse_bytes(mode, prefix),
+                    None => Ok(Vec::new()),
+                };
+                let limit = match args.get(2) {
+                    Some(limit) => limit.parse::<usize>().map_err(|e| e.to_string()),
+                    None => Ok(usize::MAX),
+                };
+                prefix.and_then(|prefix| {
+                    let limit = limit?;
+                    let txn = env.begin_ro_txn().map_err(|e| e.to_string())?;
+                    let mut cursor = txn.open_ro_cursor(db).map_err(|e| e.to_string())?;
+                    let entries = cursor.iter_range(&prefix[..]..)
+                                        .take_while(|&(key, _)| key.starts_with(&prefix))
+                                        .take(limit);
+                    let name = name.as_ref().map(|name| &name[..]);
+                    for (key, value) in entries {
+                        match mode {
+                            Mode::Hex => writeln!(out, "{} {}", format_hex(key), format_hex(value)),
+                            Mode::Utf8 => {
+                                writeln!(out, "{} {}", printers.key(name, key), printers.value(name, value))
+                            },
+                        }.map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                })
+            },
+            Some(&"stat") => {
+                env.begin_ro_txn().and_then(|txn| txn.stat(db))
+                   .map_err(|e| e.to_string())
+                   .and_then(|stat| print_stat(out, &stat).map_err(|e| e.to_string()))
+            },
+            Some(command) => Err(format!("invalid command or arguments: {} (try help)", command)),
+        };
+        if let Err(message) = result {
+            writeln!(out, "error: {}", message).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use lmdb::WriteFlags;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn options(args: &[&str]) -> Options {
+        parse_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    /// Creates an environment holding an item in the main database and in the "tags" database.
+    fn setup() -> TempDir {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let main = env.open_db(None).unwrap();
+        let tags = env.create_db(Some("tags"), DatabaseFlags::empty()).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(main, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.put(tags, b"tag", b"\x00\xff", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_dump() {
+        let dir = setup();
+        let path = dir.path().to_str().unwrap();
+        let mut out = Vec::new();
+        dump(&options(&["dump", "-a", path]), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("VERSION=3\nformat=bytevalue\ntype=btree\n"));
+        assert!(out.contains("HEADER=END\n 6b6579\n 76616c\nDATA=END\n"));
+        assert!(out.contains("database=tags\n"));
+        assert!(out.ends_with("HEADER=END\n 746167\n 00ff\nDATA=END\n"));
+
+        // The dump loads into another environment.
+        let load_dir = TempDir::new("load").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(load_dir.path()).unwrap();
+        let dbs = lmdb::load(&env, out.as_bytes(), None).unwrap();
+        assert_eq!(b"\x00\xff", env.begin_ro_txn().unwrap().get(dbs[1], b"tag").unwrap());
+
+        let error = dump(&options(&["dump", "-s", "missing", path]), &mut Vec::new()).unwrap_err();
+        assert!(error.starts_with("missing: "));
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = setup();
+        let path = dir.path().to_str().unwrap();
+        let mut out = Vec::new();
+        check(&options(&["check", path]), &mut out).unwrap();
+        // The main database also holds the record of the "tags" database.
+        assert_eq!("Main DB: ok (2 entries)\ntags: ok (1 entries)\n", String::from_utf8(out).unwrap());
+
+        let missing = dir.path().join("missing");
+        assert!(check(&options(&["check", missing.to_str().unwrap()]), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "repl")]
+    fn test_repl_get() {
+        let dir = setup();
+        let path = dir.path().to_str().unwrap();
+        let input = "get key\nget missing\nuse tags\nmode hex\nget 746167\nget zz\n";
+        let mut out = Vec::new();
+        repl(&options(&["repl", path]), &mut input.as_bytes(), &mut out).unwrap();
+        assert_eq!(format!("> \"val\"\n> error: {}\n> tags> tags> 0x00ff\ntags> error: invalid hex: zz\ntags> \n",
+                           lmdb::Error::NotFound),
+                   String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,180 @@
+/// The version of the serialized bookmark format.
+const VERSION: u8 = 1;
+
+/// A persistent record of a cursor position, used to resume processing a database in a later
+/// transaction.
+///
+/// A bookmark records the key of the item the cursor was positioned at, and for databases with
+/// duplicate data items (`DatabaseFlags::DUP_SORT`) its data item. It does not record the database,
+/// since database handles are not stable across environments. Bookmarks are created with
+/// `Cursor::bookmark`, and processing resumes at the next item with `Cursor::iter_after`, even if
+/// the bookmarked item has since been deleted.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Bookmark {
+    key: Vec<u8>,
+    data: Option<Vec<u8>>,
+}
+
+impl Bookmark {
+
+    /// Creates a bookmark of a key, and optionally one of its duplicate data items.
+    pub fn new(key: &[u8], data: Option<&[u8]>) -> Bookmark {
+        Bookmark { key: key.to_vec(), data: data.map(<[u8]>::to_vec) }
+    }
+
+    /// Returns the bookmarked key.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns the bookmarked duplicate data item, if the database has duplicate data items.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_ref().map(|data| &data[..])
+    }
+
+    /// Serializes the bookmark to an opaque byte string, which can be persisted, for example in
+    /// another database.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data_len = self.data.as_ref().map_or(0, |data| 4 + data.len());
+        let mut bytes = Vec::with_capacity(2 + 4 + self.key.len() + data_len);
+        bytes.push(VERSION);
+        bytes.push(self.data.is_some() as u8);
+        bytes.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.key);
+        if let Some(ref data) = self.data {
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    /// Deserializes a bookmark serialized by `Bookmark::to_bytes`.
+    ///
+    /// Returns `None` if the bytes are not a valid bookmark.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Bookmark> {
+        fn field(bytes: &mut &[u8]) -> Option<Vec<u8>> {
+            if bytes.len() < 4 {
+                return None;
+            }
+            let mut len = [0u8; 4];
+            len.copy_from_slice(&bytes[..4]);
+            let len = u32::from_le_bytes(len) as usize;
+            let rest = &bytes[4..];
+            if rest.len() < len {
+                return None;
+            }
+            *bytes = &rest[len..];
+            Some(rest[..len].to_vec())
+        }
+
+        let (header, mut rest) = match bytes {
+            [version, has_data, rest @ ..] if *version == VERSION && *has_data <= 1 => (*has_data, rest),
+            _ => return None,
+        };
+        let key = field(&mut rest)?;
+        let data = if header == 1 { Some(field(&mut rest)?) } else { None };
+        if !rest.is_empty() {
+            return None;
+        }
+        Some(Bookmark { key, data })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use flags::*;
+    use transaction::{RwTransaction, Transaction};
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        let bookmarks = [Bookmark::new(b"key", None),
+                         Bookmark::new(b"key", Some(b"")),
+                         Bookmark::new(b"k", Some(b"data"))];
+        for bookmark in &bookmarks {
+            let bytes = bookmark.to_bytes();
+            assert_eq!(Some(bookmark.clone()), Bookmark::from_bytes(&bytes));
+            assert_eq!(None, Bookmark::from_bytes(&bytes[..bytes.len() - 1]));
+        }
+        assert_eq!(None, Bookmark::from_bytes(b""));
+        assert_eq!(None, Bookmark::from_bytes(&[2, 0, 0, 0, 0, 0]));
+        assert_eq!(None, Bookmark::from_bytes(&[VERSION, 0, 0, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_resume() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for key in &[b"key1", b"key2", b"key3"] {
+            txn.put(db, key, key, WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let bookmark = {
+            let txn = env.begin_ro_txn().unwrap();
+            let cursor = txn.open_ro_cursor(db).unwrap();
+            assert!(cursor.bookmark().is_err());
+            cursor.seek(b"key2").unwrap();
+            cursor.bookmark().unwrap().to_bytes()
+        };
+        let bookmark = Bookmark::from_bytes(&bookmark).unwrap();
+        assert_eq!(Bookmark::new(b"key2", None), bookmark);
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![(&b"key3"[..], &b"key3"[..])], cursor.iter_after(&bookmark).collect::<Vec<_>>());
+        assert_eq!(3, cursor.iter_after(&Bookmark::new(b"key0", None)).count());
+        assert_eq!(0, cursor.iter_after(&Bookmark::new(b"key3", None)).count());
+        assert_eq!(0, cursor.iter_after(&Bookmark::new(b"key4", None)).count());
+        drop(cursor);
+        drop(txn);
+
+        // The bookmarked item was deleted.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.del(db, b"key2", None).unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![(&b"key3"[..], &b"key3"[..])], cursor.iter_after(&bookmark).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_resume_dup() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &[(b"key1", b"val1"), (b"key1", b"val3"), (b"key1", b"val5"), (b"key2", b"val1")] {
+            txn.put(db, key, data, WriteFlags::empty()).unwrap();
+        }
+
+        let bookmark = {
+            let cursor = txn.open_ro_cursor(db).unwrap();
+            cursor.seek_both(b"key1", b"val3").unwrap();
+            cursor.bookmark().unwrap()
+        };
+        assert_eq!(Bookmark::new(b"key1", Some(b"val3")), bookmark);
+
+        let after = |txn: &RwTransaction, bookmark: &Bookmark| {
+            let mut cursor = txn.open_ro_cursor(db).unwrap();
+            cursor.iter_after(bookmark).map(|(key, data)| (key.to_vec(), data.to_vec())).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![(b"key1".to_vec(), b"val5".to_vec()), (b"key2".to_vec(), b"val1".to_vec())],
+                   after(&txn, &bookmark));
+        assert_eq!(vec![(b"key2".to_vec(), b"val1".to_vec())],
+                   after(&txn, &Bookmark::new(b"key1", Some(b"val6"))));
+        assert_eq!(vec![(b"key2".to_vec(), b"val1".to_vec())],
+                   after(&txn, &Bookmark::new(b"key1", None)));
+
+        txn.del(db, b"key1", Some(b"val3")).unwrap();
+        assert_eq!(vec![(b"key1".to_vec(), b"val5".to_vec()), (b"key2".to_vec(), b"val1".to_vec())],
+                   after(&txn, &bookmark));
+    }
+}
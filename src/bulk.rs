@@ -0,0 +1,322 @@
+use std::{fmt, result};
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use environment::Environment;
+use error::{Error, Result};
+use flags::{DatabaseFlags, WriteFlags};
+use transaction::{RwTransaction, Transaction};
+
+/// Loads pairs which are already sorted into a database, using `WriteFlags::APPEND` and
+/// committing in batches.
+///
+/// Appending is much faster than writing pairs at random, and leaves the database densely packed.
+/// Pairs must be loaded in key order, and for databases with `DatabaseFlags::DUP_SORT` the data
+/// items of each key in data order. Since pairs are appended, the database should be empty, or
+/// all keys must sort after its existing keys. LMDB rejects a pair which does not sort after the
+/// previous one with `Error::KeyExist`, which also rejects duplicate keys in databases without
+/// `DatabaseFlags::DUP_SORT`.
+///
+/// Keys are compared as byte strings, so the database must not use `DatabaseFlags::REVERSE_KEY`,
+/// `DatabaseFlags::INTEGER_KEY`, `DatabaseFlags::REVERSE_DUP` or `DatabaseFlags::INTEGER_DUP`.
+///
+/// Each batch is written in its own write transaction, which is begun on the first pair of the
+/// batch. Batches committed before an error, or before the loader is dropped without calling
+/// `BulkLoader::finish`, remain in the database; the pairs of the current batch are discarded.
+/// To load pairs which are not sorted, use `ExternalSorter`, or `BulkLoader::set_detect_order`
+/// if they are mostly sorted.
+pub struct BulkLoader<'env> {
+    env: &'env Environment,
+    db: Database,
+    dup_sort: bool,
+    detect_order: bool,
+    commit_interval: usize,
+    progress: Option<Box<dyn FnMut(usize) + 'env>>,
+    txn: Option<RwTransaction<'env>>,
+    prev_key: Option<Vec<u8>>,
+    uncommitted: usize,
+    loaded: usize,
+}
+
+impl <'env> fmt::Debug for BulkLoader<'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("BulkLoader")
+         .field("db", &self.db)
+         .field("commit_interval", &self.commit_interval)
+         .field("loaded", &self.loaded)
+         .finish()
+    }
+}
+
+impl <'env> BulkLoader<'env> {
+
+    /// Creates a loader for the database, which commits every 100,000 pairs.
+    ///
+    /// Fails with `Error::Incompatible` if the database does not order keys and data items as
+    /// byte strings, and with `Error::BadRslot` if called by a thread which has an ongoing
+    /// transaction.
+    pub fn new(env: &'env Environment, db: Database) -> Result<BulkLoader<'env>> {
+        let flags = env.get_db_flags(db)?;
+        if flags.intersects(DatabaseFlags::REVERSE_KEY | DatabaseFlags::INTEGER_KEY
+                            | DatabaseFlags::REVERSE_DUP | DatabaseFlags::INTEGER_DUP) {
+            return Err(Error::Incompatible);
+        }
+        Ok(BulkLoader {
+            env,
+            db,
+            dup_sort: flags.contains(DatabaseFlags::DUP_SORT),
+            detect_order: false,
+            commit_interval: 100_000,
+            progress: None,
+            txn: None,
+            prev_key: None,
+            uncommitted: 0,
+            loaded: 0,
+        })
+    }
+
+    /// Sets the number of pairs written per write transaction.
+    ///
+    /// LMDB limits the number of pages a single transaction may dirty, so very large loads must
+    /// be split into several transactions.
+    pub fn set_commit_interval(&mut self, commit_interval: usize) -> &mut BulkLoader<'env> {
+        self.commit_interval = commit_interval.max(1);
+        self
+    }
+
+    /// Sets whether pairs may be loaded in any order.
+    ///
+    /// When set, a pair is appended if its key sorts after every key loaded so far and every key
+    /// already in the database. Other pairs are written with an ordinary put, which replaces the
+    /// data of an existing key, or adds a data item to it in databases with
+    /// `DatabaseFlags::DUP_SORT`. Sorted runs of pairs keep most of the speed of appending, and
+    /// the database does not need to be empty. Defaults to false.
+    pub fn set_detect_order(&mut self, detect_order: bool) -> &mut BulkLoader<'env> {
+        self.detect_order = detect_order;
+        self
+    }
+
+    /// Sets a function which is called with the total number of pairs loaded after each batch
+    /// is committed.
+    pub fn set_progress<F>(&mut self, progress: F) -> &mut BulkLoader<'env> where F: FnMut(usize) + 'env {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Appends a key/data pair, committing the batch if it is full.
+    pub fn put<K, D>(&mut self, key: &K, data: &D) -> Result<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let key = key.as_ref();
+        if self.txn.is_none() {
+            self.txn = Some(self.env.begin_rw_txn()?);
+        }
+        if self.detect_order && self.prev_key.is_none() {
+            let cursor = self.txn.as_ref().unwrap().open_ro_cursor(self.db)?;
+            self.prev_key = cursor.last()?.map(|(last, _)| last.to_vec());
+        }
+        let flags = match self.prev_key {
+            Some(ref prev_key) if self.detect_order && &prev_key[..] >= key => WriteFlags::empty(),
+            Some(ref prev_key) if self.dup_sort && &prev_key[..] == key => WriteFlags::APPEND_DUP,
+            _ => WriteFlags::APPEND,
+        };
+        self.txn.as_mut().unwrap().put(self.db, &key, data, flags)?;
+        if (self.dup_sort || self.detect_order) && flags == WriteFlags::APPEND {
+            let prev_key = self.prev_key.get_or_insert_with(Vec::new);
+            prev_key.clear();
+            prev_key.extend_from_slice(key);
+        }
+        self.uncommitted += 1;
+        if self.uncommitted == self.commit_interval {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the key/data pairs.
+    pub fn put_iter<I, K, D>(&mut self, pairs: I) -> Result<()>
+    where I: IntoIterator<Item = (K, D)>, K: AsRef<[u8]>, D: AsRef<[u8]> {
+        for (key, data) in pairs {
+            self.put(&key, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of pairs loaded so far, including pairs in the current uncommitted
+    /// batch.
+    pub fn loaded(&self) -> usize {
+        self.loaded + self.uncommitted
+    }
+
+    /// Commits the last batch, and returns the number of pairs loaded.
+    pub fn finish(mut self) -> Result<usize> {
+        self.commit()?;
+        Ok(self.loaded)
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if let Some(txn) = self.txn.take() {
+            txn.commit()?;
+            self.loaded += self.uncommitted;
+            self.uncommitted = 0;
+            if let Some(ref mut progress) = self.progress {
+                progress(self.loaded);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl <'env> Drop for BulkLoader<'env> {
+    fn drop(&mut self) {
+        // Discarding the current batch is documented, so it is not a dropped transaction.
+        if let Some(txn) = self.txn.take() {
+            txn.abort();
+        }
+    }
+}
+
+/// Loads key/data pairs read from another store into the database, in any order, and returns
+/// the number of pairs loaded.
+///
+/// Pairs are loaded by a `BulkLoader` with `BulkLoader::set_detect_order`, so they are appended
+/// while they are sorted, and committed every 100,000 pairs. Stores which iterate in byte order
+/// are loaded almost as fast as with `WriteFlags::APPEND` alone.
+pub fn import_pairs<I, K, D>(env: &Environment, db: Database, pairs: I) -> Result<usize>
+where I: IntoIterator<Item = (K, D)>, K: AsRef<[u8]>, D: AsRef<[u8]> {
+    let mut loader = BulkLoader::new(env, db)?;
+    loader.set_detect_order(true);
+    loader.put_iter(pairs)?;
+    loader.finish()
+}
+
+/// Iterates over the key/data pairs of the database in key order, to be written to another
+/// store.
+pub fn export_pairs<'txn, T>(txn: &'txn T, db: Database) -> Result<ExportIter<'txn>> where T: Transaction {
+    let iter = CursorIter::new(txn.open_ro_cursor(db)?, |cursor| cursor.iter_start());
+    Ok(ExportIter { iter })
+}
+
+/// An iterator over the key/data pairs of a database, returned by `export_pairs`.
+pub struct ExportIter<'txn> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+}
+
+impl <'txn> fmt::Debug for ExportIter<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("ExportIter").finish()
+    }
+}
+
+impl <'txn> Iterator for ExportIter<'txn> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        self.iter.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::cell::RefCell;
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let progress = RefCell::new(Vec::new());
+        let mut loader = BulkLoader::new(&env, db).unwrap();
+        loader.set_commit_interval(300).set_progress(|loaded| progress.borrow_mut().push(loaded));
+        loader.put_iter((0..1000u32).map(|i| (i.to_be_bytes(), format!("value{}", i)))).unwrap();
+        assert_eq!(1000, loader.loaded());
+        assert_eq!(1000, loader.finish().unwrap());
+        assert_eq!(vec![300, 600, 900, 1000], *progress.borrow());
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        for (i, (key, value)) in cursor.iter_start().enumerate() {
+            assert_eq!(&(i as u32).to_be_bytes()[..], key);
+            assert_eq!(format!("value{}", i).as_bytes(), value);
+        }
+        assert_eq!(1000, txn.len(db).unwrap());
+    }
+
+    #[test]
+    fn test_load_unsorted() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut loader = BulkLoader::new(&env, db).unwrap();
+        loader.set_commit_interval(2);
+        loader.put(b"a", b"1").unwrap();
+        loader.put(b"b", b"1").unwrap();
+        loader.put(b"c", b"1").unwrap();
+        assert_eq!(Err(Error::KeyExist), loader.put(b"c", b"2"));
+        assert_eq!(Err(Error::KeyExist), loader.put(b"b", b"1"));
+        drop(loader);
+
+        // The committed batch remains.
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(2, txn.len(db).unwrap());
+    }
+
+    #[test]
+    fn test_load_detect_order() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let dup = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"m", b"existing", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        // Keys before the existing key, out of order, and repeated are put.
+        let pairs = vec![(b"a", b"1"), (b"b", b"1"), (b"n", b"1"), (b"o", b"1"), (b"c", b"1"), (b"o", b"2"), (b"p", b"1")];
+        let mut loader = BulkLoader::new(&env, db).unwrap();
+        loader.set_detect_order(true).set_commit_interval(3);
+        loader.put_iter(pairs.clone()).unwrap();
+        assert_eq!(7, loader.finish().unwrap());
+        assert_eq!(7, import_pairs(&env, dup, pairs).unwrap());
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(vec![(&b"a"[..], &b"1"[..]), (b"b", b"1"), (b"c", b"1"), (b"m", b"existing"), (b"n", b"1"),
+                        (b"o", b"2"), (b"p", b"1")],
+                   export_pairs(&txn, db).unwrap().collect::<Vec<_>>());
+        assert_eq!(vec![(&b"a"[..], &b"1"[..]), (b"b", b"1"), (b"c", b"1"), (b"n", b"1"), (b"o", b"1"),
+                        (b"o", b"2"), (b"p", b"1")],
+                   export_pairs(&txn, dup).unwrap().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_load_dup_sort() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+        let int = env.create_db(Some("int"), DatabaseFlags::INTEGER_KEY).unwrap();
+        assert_eq!(Error::Incompatible, BulkLoader::new(&env, int).unwrap_err());
+
+        let mut loader = BulkLoader::new(&env, db).unwrap();
+        loader.set_commit_interval(1);
+        loader.put_iter(vec![(b"a", b"1"), (b"a", b"2"), (b"b", b"1")]).unwrap();
+        assert_eq!(Err(Error::KeyExist), loader.put(b"b", b"0"));
+        assert_eq!(3, loader.finish().unwrap());
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![(&b"a"[..], &b"1"[..]), (b"a", b"2"), (b"b", b"1")],
+                   cursor.iter_start().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::error::Error as StdError;
+use std::convert::TryInto;
+use std::{fmt, result, str};
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use environment::{Environment, MAIN_DBI};
+use error::{Error, Result};
+use flags::{DatabaseFlags, WriteFlags};
+use transaction::{RwTransaction, Transaction};
+
+/// The tag of a logged put.
+const PUT: u8 = 0;
+
+/// The tag of a logged delete of every data item of a key.
+const DEL: u8 = 1;
+
+/// The tag of a logged delete of one data item of a key.
+const DEL_DATA: u8 = 2;
+
+/// The length recorded in place of the name of the main database.
+const MAIN_NAME: u32 = u32::MAX;
+
+/// The length of the header of an entry of a change batch: its sequence number and the length of
+/// its record.
+const BATCH_HEADER: usize = 12;
+
+/// An ordered log of the writes made through it, for replication.
+///
+/// Writes made with `ChangeLog::put` and `ChangeLog::del` are applied to their database, and
+/// appended to a log database in the same transaction, under a sequence number one greater than
+/// the last, stored as an 8-byte big-endian key. The log therefore records every committed write in
+/// commit order, and nothing else. Consumers read the entries after the last sequence number they
+/// applied with `ChangeLog::tail`, for example when notified by `Environment::commit_channel`,
+/// and the log is trimmed with `ChangeLog::truncate` once every consumer has read it.
+///
+/// Entries name their database, so the written databases must be the main database, or opened by
+/// name with `Environment::open_db` or `Environment::create_db`.
+///
+/// A replica is kept in sync by sending it the batches returned by `ChangeLog::batch`, which it
+/// applies with `ChangeLog::apply_changes`.
+#[derive(Clone, Copy, Debug)]
+pub struct ChangeLog {
+    log: Database,
+}
+
+/// The kind of a logged write.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogOp {
+    /// A key/data pair was stored.
+    Put,
+    /// The items of a key were deleted, or only one if the entry has data.
+    Del,
+}
+
+/// A write recorded in a `ChangeLog`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LogEntry<'txn> {
+    seq: u64,
+    op: LogOp,
+    db_name: Option<&'txn str>,
+    key: &'txn [u8],
+    data: Option<&'txn [u8]>,
+}
+
+impl <'txn> LogEntry<'txn> {
+
+    /// The sequence number of the write.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The kind of the write.
+    pub fn op(&self) -> LogOp {
+        self.op
+    }
+
+    /// The name of the written database, or `None` for the main database.
+    pub fn db_name(&self) -> Option<&'txn str> {
+        self.db_name
+    }
+
+    /// The key written.
+    pub fn key(&self) -> &'txn [u8] {
+        self.key
+    }
+
+    /// The data stored by a put, or the data item deleted if only one of the duplicate data items
+    /// of the key was.
+    pub fn data(&self) -> Option<&'txn [u8]> {
+        self.data
+    }
+
+    /// Decodes an entry of the log.
+    fn decode(seq: u64, bytes: &'txn [u8]) -> Option<LogEntry<'txn>> {
+        fn field<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            if bytes.len() < len {
+                return None;
+            }
+            let (field, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Some(field)
+        }
+        fn len(bytes: &mut &[u8]) -> Option<u32> {
+            let mut len = [0; 4];
+            len.copy_from_slice(field(bytes, 4)?);
+            Some(u32::from_le_bytes(len))
+        }
+
+        let (tag, mut rest) = bytes.split_first()?;
+        let db_name = match len(&mut rest)? {
+            MAIN_NAME => None,
+            name_len => Some(str::from_utf8(field(&mut rest, name_len as usize)?).ok()?),
+        };
+        let key_len = len(&mut rest)? as usize;
+        let key = field(&mut rest, key_len)?;
+        let (op, data) = match *tag {
+            PUT => (LogOp::Put, Some(rest)),
+            DEL if rest.is_empty() => (LogOp::Del, None),
+            DEL_DATA => (LogOp::Del, Some(rest)),
+            _ => return None,
+        };
+        Some(LogEntry { seq, op, db_name, key, data })
+    }
+}
+
+impl ChangeLog {
+
+    /// Opens the log database with the given name, creating it if it doesn't exist.
+    ///
+    /// Fails with `Error::Incompatible` if the database exists with `DatabaseFlags::INTEGER_KEY`
+    /// or `DatabaseFlags::REVERSE_KEY`, which would not keep the log in sequence order.
+    pub fn open(env: &Environment, name: &str) -> Result<ChangeLog> {
+        let db = env.create_db(Some(name), DatabaseFlags::empty())?;
+        if env.get_db_flags(db)?.intersects(DatabaseFlags::INTEGER_KEY | DatabaseFlags::REVERSE_KEY) {
+            return Err(Error::Incompatible);
+        }
+        Ok(ChangeLog { log: db })
+    }
+
+    /// Returns the handle of the log database.
+    pub fn database(&self) -> Database {
+        self.log
+    }
+
+    /// Stores an item into the database, and logs it. Returns the sequence number of the write.
+    ///
+    /// See `RwTransaction::put`.
+    pub fn put<K, D>(&self, txn: &mut RwTransaction, db: Database, key: &K, data: &D, flags: WriteFlags)
+                     -> Result<u64>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let name = self.db_name(txn, db)?;
+        txn.put(db, key, data, flags)?;
+        self.append(txn, PUT, name.as_deref(), key.as_ref(), data.as_ref())
+    }
+
+    /// Deletes an item from the database, and logs it. Returns the sequence number of the write.
+    ///
+    /// See `RwTransaction::del`.
+    pub fn del<K>(&self, txn: &mut RwTransaction, db: Database, key: &K, data: Option<&[u8]>) -> Result<u64>
+    where K: AsRef<[u8]> {
+        let name = self.db_name(txn, db)?;
+        txn.del(db, key, data)?;
+        match data {
+            Some(data) => self.append(txn, DEL_DATA, name.as_deref(), key.as_ref(), data),
+            None => self.append(txn, DEL, name.as_deref(), key.as_ref(), &[]),
+        }
+    }
+
+    /// Returns the sequence number of the last logged write, or zero if none was logged.
+    ///
+    /// Sequence numbers are not reused after the log is truncated, unless it is emptied.
+    pub fn last_seq<T>(&self, txn: &T) -> Result<u64> where T: Transaction {
+        let cursor = txn.open_ro_cursor(self.log)?;
+        match cursor.last()? {
+            Some((key, _)) => decode_seq(key).ok_or(Error::BadValSize),
+            None => Ok(0),
+        }
+    }
+
+    /// Iterates over the logged writes with sequence numbers greater than `after`, in order.
+    ///
+    /// Passing zero reads the whole log.
+    pub fn tail<'txn, T>(&self, txn: &'txn T, after: u64) -> Result<LogTail<'txn>> where T: Transaction {
+        Ok(LogTail { iter: self.iter_from(txn, after)? })
+    }
+
+    /// Deletes the logged writes with sequence numbers up to and including `through`, returning
+    /// the number deleted.
+    ///
+    /// The last entry is kept, so that the sequence numbers of later writes keep increasing.
+    pub fn truncate(&self, txn: &mut RwTransaction, through: u64) -> Result<usize> {
+        let through = through.min(self.last_seq(txn)?.saturating_sub(1));
+        let seqs = self.iter_from(txn, 0)?
+            .map(|(key, _)| decode_seq(key).ok_or(Error::BadValSize))
+            .take_while(|seq| seq.as_ref().map_or(true, |&seq| seq <= through))
+            .collect::<Result<Vec<_>>>()?;
+        for &seq in &seqs {
+            txn.del(self.log, &seq.to_be_bytes(), None)?;
+        }
+        Ok(seqs.len())
+    }
+
+    /// Serializes the logged writes with sequence numbers greater than `after` into a change batch,
+    /// up to `limit` writes, for `ChangeLog::apply_changes`.
+    ///
+    /// A batch is the concatenation of its entries in order, each being its sequence number and
+    /// the length of its record as little-endian `u64` and `u32`, followed by the record as stored
+    /// in the log.
+    pub fn batch<T>(&self, txn: &T, after: u64, limit: usize) -> Result<Vec<u8>> where T: Transaction {
+        let mut batch = Vec::new();
+        for (key, record) in self.iter_from(txn, after)?.take(limit) {
+            let seq = decode_seq(key).ok_or(Error::BadValSize)?;
+            batch.extend_from_slice(&seq.to_le_bytes());
+            batch.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            batch.extend_from_slice(record);
+        }
+        Ok(batch)
+    }
+
+    /// Applies a change batch of another environment's log to the environment in one
+    /// transaction, returning the sequence number of the last write applied.
+    ///
+    /// The writes are logged in this log under their original sequence numbers, so the last
+    /// sequence number of this log is the position of the replica, to request the next batch
+    /// from, and the replica can itself be replicated. Writes already applied are skipped, so a
+    /// batch may be applied again, but the batch must continue this log without a gap, or
+    /// `ApplyError::Gap` is returned and nothing is applied. The written databases must exist in
+    /// the environment, with the flags of the original databases.
+    pub fn apply_changes(&self, env: &Environment, batch: &[u8]) -> result::Result<u64, ApplyError> {
+        let entries = decode_batch(batch).ok_or(Error::Corrupted)?;
+        let mut dbs = HashMap::new();
+        for entry in &entries {
+            if let Entry::Vacant(vacant) = dbs.entry(entry.db_name) {
+                vacant.insert(env.open_db(entry.db_name)?);
+            }
+        }
+
+        let mut txn = env.begin_rw_txn()?;
+        match self.apply_entries(&mut txn, &dbs, entries) {
+            Ok(last) => {
+                txn.commit()?;
+                Ok(last)
+            },
+            Err(error) => {
+                txn.abort();
+                Err(error)
+            },
+        }
+    }
+
+    /// Writes the entries of a batch which are not yet applied, returning the sequence number
+    /// of the last one.
+    fn apply_entries(&self,
+                     txn: &mut RwTransaction,
+                     dbs: &HashMap<Option<&str>, Database>,
+                     entries: Vec<LogEntry>)
+                     -> result::Result<u64, ApplyError> {
+        let applied = self.last_seq(txn)?;
+        let mut last = applied;
+        for entry in entries.into_iter().skip_while(|entry| entry.seq <= applied) {
+            if entry.seq != last + 1 {
+                return Err(ApplyError::Gap { expected: last + 1, found: entry.seq });
+            }
+            let db = dbs[&entry.db_name];
+            last = match (entry.op, entry.data) {
+                (LogOp::Put, data) => self.put(txn, db, &entry.key, &data.unwrap_or(&[]), WriteFlags::empty())?,
+                (LogOp::Del, data) => self.del(txn, db, &entry.key, data)?,
+            };
+        }
+        Ok(last)
+    }
+
+    /// Returns the name recorded for the database, failing with `Error::Incompatible` if it is
+    /// not known.
+    fn db_name(&self, txn: &RwTransaction, db: Database) -> Result<Option<String>> {
+        match txn.env().db_name(db.dbi()) {
+            Some(name) => Ok(Some(name)),
+            None if db.dbi() == MAIN_DBI => Ok(None),
+            None => Err(Error::Incompatible),
+        }
+    }
+
+    fn append(&self, txn: &mut RwTransaction, tag: u8, name: Option<&str>, key: &[u8], data: &[u8]) -> Result<u64> {
+        let seq = self.last_seq(txn)? + 1;
+        let name_len = name.map_or(0, str::len);
+        let mut entry = Vec::with_capacity(9 + name_len + key.len() + data.len());
+        entry.push(tag);
+        match name {
+            Some(name) => {
+                entry.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                entry.extend_from_slice(name.as_bytes());
+            },
+            None => entry.extend_from_slice(&MAIN_NAME.to_le_bytes()),
+        }
+        entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        entry.extend_from_slice(key);
+        entry.extend_from_slice(data);
+        txn.put(self.log, &seq.to_be_bytes(), &entry, WriteFlags::APPEND)?;
+        Ok(seq)
+    }
+
+    /// Iterates over the items of the log with sequence numbers greater than `after`.
+    fn iter_from<'txn, T>(&self, txn: &'txn T, after: u64) -> Result<CursorIter<'txn, Iter<'txn>>>
+    where T: Transaction {
+        let start = after.saturating_add(1).to_be_bytes();
+        Ok(CursorIter::new(txn.open_ro_cursor(self.log)?, |cursor| cursor.iter_from(start)))
+    }
+}
+
+/// Decodes the sequence number of a log key, or returns `None` if it is not 8 bytes long.
+fn decode_seq(key: &[u8]) -> Option<u64> {
+    key.try_into().ok().map(u64::from_be_bytes)
+}
+
+/// Decodes the entries of a change batch, or returns `None` if it is malformed or out of order.
+fn decode_batch(mut batch: &[u8]) -> Option<Vec<LogEntry<'_>>> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    while !batch.is_empty() {
+        if batch.len() < BATCH_HEADER {
+            return None;
+        }
+        let (header, rest) = batch.split_at(BATCH_HEADER);
+        let mut seq = [0; 8];
+        seq.copy_from_slice(&header[..8]);
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[8..]);
+        let len = u32::from_le_bytes(len) as usize;
+        if rest.len() < len {
+            return None;
+        }
+        let (record, rest) = rest.split_at(len);
+        let entry = LogEntry::decode(u64::from_le_bytes(seq), record)?;
+        if entries.last().is_some_and(|last| last.seq >= entry.seq) {
+            return None;
+        }
+        entries.push(entry);
+        batch = rest;
+    }
+    Some(entries)
+}
+
+/// An error which aborted `ChangeLog::apply_changes`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ApplyError {
+    /// The batch does not continue the log: its first write not yet applied is not the one after
+    /// the last write of the log.
+    Gap {
+        /// The sequence number of the next write of the log.
+        expected: u64,
+        /// The sequence number of the first write of the batch not yet applied.
+        found: u64,
+    },
+    /// An error returned by LMDB. A malformed batch fails with `Error::Corrupted`.
+    Lmdb(Error),
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApplyError::Gap { expected, found } =>
+                write!(fmt, "change batch skips from sequence number {} to {}", expected, found),
+            ApplyError::Lmdb(ref error) => error.fmt(fmt),
+        }
+    }
+}
+
+impl StdError for ApplyError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ApplyError::Gap { .. } => None,
+            ApplyError::Lmdb(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for ApplyError {
+    fn from(error: Error) -> ApplyError {
+        ApplyError::Lmdb(error)
+    }
+}
+
+/// An iterator over the entries of a `ChangeLog`.
+///
+/// Yields `Error::Corrupted` for entries which can't be decoded.
+pub struct LogTail<'txn> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+}
+
+impl <'txn> fmt::Debug for LogTail<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("LogTail").finish()
+    }
+}
+
+impl <'txn> Iterator for LogTail<'txn> {
+
+    type Item = Result<LogEntry<'txn>>;
+
+    fn next(&mut self) -> Option<Result<LogEntry<'txn>>> {
+        self.iter.next().map(|(key, bytes)| {
+            let seq = decode_seq(key).ok_or(Error::Corrupted)?;
+            LogEntry::decode(seq, bytes).ok_or(Error::Corrupted)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use super::*;
+
+    #[test]
+    fn test_change_log() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(5).open(dir.path()).unwrap();
+        let main = env.open_db(None).unwrap();
+        let tags = env.create_db(Some("tags"), DatabaseFlags::DUP_SORT).unwrap();
+        let log = ChangeLog::open(&env, "log").unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(0, log.last_seq(&txn).unwrap());
+        assert_eq!(1, log.put(&mut txn, main, b"key", b"val", WriteFlags::empty()).unwrap());
+        assert_eq!(2, log.put(&mut txn, tags, b"tag", b"a", WriteFlags::empty()).unwrap());
+        assert_eq!(3, log.put(&mut txn, tags, b"tag", b"b", WriteFlags::empty()).unwrap());
+        // Failed writes are not logged.
+        assert_eq!(Err(Error::KeyExist), log.put(&mut txn, main, b"key", b"val", WriteFlags::NO_OVERWRITE));
+        assert_eq!(Err(Error::NotFound), log.del(&mut txn, main, b"missing", None));
+        txn.commit().unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(4, log.del(&mut txn, tags, b"tag", Some(b"a")).unwrap());
+        assert_eq!(5, log.del(&mut txn, main, b"key", None).unwrap());
+        // Aborted writes are not logged.
+        drop(txn);
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(4, log.del(&mut txn, tags, b"tag", Some(b"a")).unwrap());
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let entries = log.tail(&txn, 0).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(vec![(1, LogOp::Put, None, &b"key"[..], Some(&b"val"[..])),
+                        (2, LogOp::Put, Some("tags"), b"tag", Some(b"a")),
+                        (3, LogOp::Put, Some("tags"), b"tag", Some(b"b")),
+                        (4, LogOp::Del, Some("tags"), b"tag", Some(b"a"))],
+                   entries.iter()
+                          .map(|entry| (entry.seq(), entry.op(), entry.db_name(), entry.key(), entry.data()))
+                          .collect::<Vec<_>>());
+        assert_eq!(vec![4], log.tail(&txn, 3).unwrap().map(|entry| entry.unwrap().seq()).collect::<Vec<_>>());
+        assert_eq!(0, log.tail(&txn, 4).unwrap().count());
+        drop(txn);
+
+        // Truncating keeps the last entry, so that sequence numbers keep increasing.
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(3, log.truncate(&mut txn, 10).unwrap());
+        assert_eq!(5, log.del(&mut txn, main, b"key", None).unwrap());
+        let entry = log.tail(&txn, 4).unwrap().next().unwrap().unwrap();
+        assert_eq!((LogOp::Del, None, None), (entry.op(), entry.db_name(), entry.data()));
+        assert_eq!(2, log.tail(&txn, 0).unwrap().count());
+
+        // Sequence numbers are big-endian keys, so that they sort in order on every platform.
+        let mut cursor = txn.open_ro_cursor(log.database()).unwrap();
+        assert_eq!(vec![&4u64.to_be_bytes()[..], &5u64.to_be_bytes()],
+                   cursor.iter_start().map(|(key, _)| key).collect::<Vec<_>>());
+        drop(cursor);
+
+        // Databases must be known by name.
+        let unnamed = unsafe { txn.create_db(Some("unnamed"), DatabaseFlags::empty()).unwrap() };
+        assert_eq!(Err(Error::Incompatible), log.put(&mut txn, unnamed, b"key", b"val", WriteFlags::empty()));
+        txn.commit().unwrap();
+
+        env.create_db(Some("integer"), DatabaseFlags::INTEGER_KEY).unwrap();
+        assert_eq!(Error::Incompatible, ChangeLog::open(&env, "integer").unwrap_err());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let open = || {
+            let dir = TempDir::new("test").unwrap();
+            let env = Environment::new().set_max_dbs(2)
+                                        .set_dropped_writes(DroppedWrites::Panic)
+                                        .open(dir.path())
+                                        .unwrap();
+            env.create_db(Some("tags"), DatabaseFlags::DUP_SORT).unwrap();
+            let log = ChangeLog::open(&env, "log").unwrap();
+            (dir, env, log)
+        };
+        let (_leader_dir, leader, leader_log) = open();
+        let (_follower_dir, follower, follower_log) = open();
+        let main = leader.open_db(None).unwrap();
+        let tags = leader.open_db(Some("tags")).unwrap();
+
+        let mut txn = leader.begin_rw_txn().unwrap();
+        leader_log.put(&mut txn, main, b"key", b"val", WriteFlags::empty()).unwrap();
+        leader_log.put(&mut txn, tags, b"tag", b"a", WriteFlags::empty()).unwrap();
+        leader_log.put(&mut txn, tags, b"tag", b"b", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        let mut txn = leader.begin_rw_txn().unwrap();
+        leader_log.del(&mut txn, tags, b"tag", Some(b"a")).unwrap();
+        leader_log.del(&mut txn, main, b"key", None).unwrap();
+        txn.commit().unwrap();
+
+        let txn = leader.begin_ro_txn().unwrap();
+        let first = leader_log.batch(&txn, 0, 3).unwrap();
+        let rest = leader_log.batch(&txn, 3, 10).unwrap();
+        assert_eq!(Vec::<u8>::new(), leader_log.batch(&txn, 5, 10).unwrap());
+        drop(txn);
+
+        // Batches must continue the log.
+        assert_eq!(Err(ApplyError::Gap { expected: 1, found: 4 }), follower_log.apply_changes(&follower, &rest));
+        // A gap after the first writes of the batch aborts them.
+        let gapped = [&leader_log.batch(&leader.begin_ro_txn().unwrap(), 0, 1).unwrap()[..], &rest].concat();
+        assert_eq!(Err(ApplyError::Gap { expected: 2, found: 4 }), follower_log.apply_changes(&follower, &gapped));
+        assert_eq!(Err(Error::NotFound), follower.begin_ro_txn().unwrap().get(main, b"key"));
+        assert_eq!(Ok(3), follower_log.apply_changes(&follower, &first));
+        {
+            let txn = follower.begin_ro_txn().unwrap();
+            assert_eq!(b"val", txn.get(main, b"key").unwrap());
+            let mut cursor = txn.open_ro_cursor(tags).unwrap();
+            assert_eq!(vec![&b"a"[..], b"b"], cursor.iter_dup_of(b"tag").map(|(_, data)| data).collect::<Vec<_>>());
+        }
+        assert_eq!(Ok(5), follower_log.apply_changes(&follower, &rest));
+        // Applied writes are skipped.
+        assert_eq!(Ok(5), follower_log.apply_changes(&follower, &rest));
+        assert_eq!(Ok(5), follower_log.apply_changes(&follower, &[]));
+
+        let txn = follower.begin_ro_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.get(main, b"key"));
+        assert_eq!(b"b", txn.get(tags, b"tag").unwrap());
+        assert_eq!(5, follower_log.last_seq(&txn).unwrap());
+        drop(txn);
+
+        assert_eq!(Err(ApplyError::Lmdb(Error::Corrupted)), follower_log.apply_changes(&follower, &first[..20]));
+    }
+}
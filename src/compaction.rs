@@ -0,0 +1,315 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{fmt, fs, io, result};
+
+use environment::{Environment, EnvironmentBuilder};
+use error::{io_error, Result};
+use flags::{CopyFlags, EnvironmentFlags};
+
+/// How long a compaction waits for the other handles to the environment to be dropped.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Compacts an environment on a background thread when too much of its data file is free.
+///
+/// LMDB reuses freed pages, but never shrinks its data file. Every interval, the scheduler
+/// compares the number of free pages to the size of the file, and when their ratio reaches the
+/// threshold and the maintenance window predicate returns true, it makes a compacted copy of the
+/// environment next to it, and swaps it in place of the original.
+///
+/// The scheduler owns the environment, and hands out shared handles to it through
+/// `CompactionScheduler::env`. A compaction pauses the environment for as long as it takes to
+/// copy it: no handles are handed out meanwhile, and the compaction waits up to five seconds for
+/// the handles already handed out to be dropped, so they must not be held longer than a
+/// transaction. If they aren't dropped in time, the compaction is skipped until the next
+/// interval. The environment must not be opened by other processes, which would go on using
+/// the original file.
+///
+/// Dropping the scheduler stops the thread, waiting for a compaction in progress to finish.
+pub struct CompactionScheduler {
+    shared: Arc<Shared>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    env: RwLock<Option<Arc<Environment>>>,
+    builder: EnvironmentBuilder,
+    path: PathBuf,
+    threshold: f64,
+    window: Box<dyn Fn() -> bool + Send + Sync>,
+    compactions: AtomicUsize,
+}
+
+impl fmt::Debug for CompactionScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("CompactionScheduler").field("path", &self.shared.path).finish()
+    }
+}
+
+impl CompactionScheduler {
+
+    /// Opens the environment at the path, and starts a thread which compacts it when the ratio
+    /// of free pages reaches `threshold`, between 0 and 1, while `window` returns true.
+    ///
+    /// The environment is reopened with the builder after each compaction.
+    pub fn new<W>(builder: &EnvironmentBuilder,
+                  path: &Path,
+                  threshold: f64,
+                  interval: Duration,
+                  window: W)
+                  -> Result<CompactionScheduler>
+    where W: Fn() -> bool + Send + Sync + 'static {
+        let env = builder.open(path)?;
+        let shared = Arc::new(Shared {
+            env: RwLock::new(Some(Arc::new(env))),
+            builder: *builder,
+            path: path.to_path_buf(),
+            threshold,
+            window: Box::new(window),
+            compactions: AtomicUsize::new(0),
+        });
+        let (stop, stopped) = mpsc::channel();
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("lmdb-compaction".to_owned())
+                .spawn(move || {
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        // Errors, including failures to reopen the environment, are retried at
+                        // the next interval.
+                        if let Ok(true) = shared.is_due() {
+                            let _ = shared.compact();
+                        }
+                    }
+                })
+                .expect("failed to spawn the compaction thread")
+        };
+        Ok(CompactionScheduler { shared, stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// Returns a handle to the environment.
+    ///
+    /// Blocks while a compaction is in progress. If the environment failed to reopen after a
+    /// compaction, it is reopened first.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the environment failed to reopen after a compaction, and fails to reopen again.
+    pub fn env(&self) -> Arc<Environment> {
+        self.shared.env().expect("the environment failed to reopen after a compaction")
+    }
+
+    /// Compacts the environment now, regardless of the threshold and the window.
+    ///
+    /// Returns false if the handles to the environment were not dropped in time.
+    pub fn compact(&self) -> Result<bool> {
+        self.shared.compact()
+    }
+
+    /// Returns the number of compactions completed since the scheduler started.
+    pub fn compactions(&self) -> usize {
+        self.shared.compactions.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for CompactionScheduler {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+
+    /// Returns a handle to the environment, reopening it if it failed to reopen after a
+    /// compaction.
+    fn env(&self) -> Result<Arc<Environment>> {
+        if let Some(ref env) = *self.env.read().unwrap_or_else(PoisonError::into_inner) {
+            return Ok(env.clone());
+        }
+        let mut guard = self.env.write().unwrap_or_else(PoisonError::into_inner);
+        if guard.is_none() {
+            *guard = Some(Arc::new(self.builder.open(&self.path)?));
+        }
+        Ok(guard.clone().unwrap())
+    }
+
+    /// Returns whether the ratio of free pages has reached the threshold within the window.
+    fn is_due(&self) -> Result<bool> {
+        let env = self.env()?;
+        let free = env.free_pages()? as f64;
+        let total = (env.info()?.last_pgno() + 1) as f64;
+        Ok(free / total >= self.threshold && (self.window)())
+    }
+
+    fn compact(&self) -> Result<bool> {
+        let mut guard = self.env.write().unwrap_or_else(PoisonError::into_inner);
+        let env = match guard.take() {
+            Some(env) => env,
+            None => Arc::new(self.builder.open(&self.path)?),
+        };
+        let no_sub_dir = match env.get_flags() {
+            Ok(flags) => flags.contains(EnvironmentFlags::NO_SUB_DIR),
+            Err(error) => {
+                *guard = Some(env);
+                return Err(error);
+            },
+        };
+        let env = match Environment::drain(env, DRAIN_TIMEOUT) {
+            Ok(env) => env,
+            Err(error) => {
+                *guard = Some(error.into_env());
+                return Ok(false);
+            },
+        };
+
+        let mut copy = OsString::from(self.path.as_os_str());
+        copy.push(".compact");
+        let copy = PathBuf::from(copy);
+        let (from, to) = if no_sub_dir {
+            (copy.clone(), self.path.clone())
+        } else {
+            (copy.join("data.mdb"), self.path.join("data.mdb"))
+        };
+        let copied = remove_copy(&copy, no_sub_dir)
+            .and_then(|()| if no_sub_dir { Ok(()) } else { fs::create_dir(&copy).map_err(io_error) })
+            .and_then(|()| env.copy(&copy, CopyFlags::COMPACT));
+        if let Err(error) = copied {
+            *guard = Some(Arc::new(env));
+            let _ = remove_copy(&copy, no_sub_dir);
+            return Err(error);
+        }
+
+        // The environment is closed before the copy replaces its data file, which is not
+        // possible while the file is mapped on Windows.
+        drop(env);
+        let renamed = fs::rename(&from, &to).map_err(io_error);
+        let _ = remove_copy(&copy, no_sub_dir);
+        *guard = Some(Arc::new(self.builder.open(&self.path)?));
+        renamed?;
+        self.compactions.fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+}
+
+/// Removes a leftover compacted copy.
+fn remove_copy(copy: &Path, no_sub_dir: bool) -> Result<()> {
+    let removed = if no_sub_dir { fs::remove_file(copy) } else { fs::remove_dir_all(copy) };
+    match removed {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(io_error(error)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::atomic::AtomicBool;
+    use std::time::Instant;
+
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    fn data_size(dir: &Path) -> u64 {
+        fs::metadata(dir.join("data.mdb")).unwrap().len()
+    }
+
+    #[test]
+    fn test_compaction_scheduler() {
+        let dir = TempDir::new("test").unwrap();
+        let open = Arc::new(AtomicBool::new(false));
+        let window = open.clone();
+        let scheduler = CompactionScheduler::new(Environment::new().set_map_size(16 * 1024 * 1024),
+                                                 dir.path(),
+                                                 0.5,
+                                                 Duration::from_millis(10),
+                                                 move || window.load(Ordering::SeqCst)).unwrap();
+
+        {
+            let env = scheduler.env();
+            let db = env.open_db(None).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            for i in 0..1000u32 {
+                txn.put(db, &i.to_be_bytes(), &[0; 1024], WriteFlags::empty()).unwrap();
+            }
+            txn.commit().unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            for i in 10..1000u32 {
+                txn.del(db, &i.to_be_bytes(), None).unwrap();
+            }
+            txn.commit().unwrap();
+            assert!(env.free_pages().unwrap() > 500);
+        }
+        let size = data_size(dir.path());
+
+        // Nothing is compacted outside of the window.
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(0, scheduler.compactions());
+
+        open.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while scheduler.compactions() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(1, scheduler.compactions());
+        assert!(data_size(dir.path()) < size / 4);
+
+        let env = scheduler.env();
+        let db = env.open_db(None).unwrap();
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(10, txn.open_ro_cursor(db).unwrap().iter_start().count());
+        assert_eq!(&[0; 1024][..], txn.get(db, &9u32.to_be_bytes()).unwrap());
+        drop(txn);
+        drop(env);
+        assert!(!PathBuf::from(format!("{}.compact", dir.path().display())).exists());
+    }
+
+    #[test]
+    fn test_compact() {
+        let dir = TempDir::new("test").unwrap();
+        let scheduler = CompactionScheduler::new(&Environment::new(),
+                                                 dir.path(),
+                                                 1.0,
+                                                 Duration::from_secs(60),
+                                                 || false).unwrap();
+        assert!(scheduler.compact().unwrap());
+        assert_eq!(1, scheduler.compactions());
+    }
+
+    #[test]
+    fn test_reopen_after_failed_reopen() {
+        let dir = TempDir::new("test").unwrap();
+        let scheduler = CompactionScheduler::new(&Environment::new(),
+                                                 dir.path(),
+                                                 1.0,
+                                                 Duration::from_secs(60),
+                                                 || false).unwrap();
+        // As left by a compaction whose reopen failed.
+        *scheduler.shared.env.write().unwrap() = None;
+        assert_eq!(Ok(false), scheduler.shared.is_due());
+
+        *scheduler.shared.env.write().unwrap() = None;
+        let env = scheduler.env();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        drop(env);
+        assert!(scheduler.compact().unwrap());
+
+        let env = scheduler.env();
+        let db = env.open_db(None).unwrap();
+        assert_eq!(b"val", env.begin_ro_txn().unwrap().get(db, b"key").unwrap());
+    }
+}
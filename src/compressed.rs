@@ -0,0 +1,244 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::{fmt, mem, result};
+
+use lz4_flex::block;
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use error::{Error, Result, lmdb_result};
+use ffi;
+use flags::{DatabaseFlags, WriteFlags};
+use stat::EnvironmentInfo;
+use transact::DatabaseSet;
+use transaction::{RwTransaction, Transaction};
+
+/// The prefix of compressed values.
+const MAGIC: [u8; 4] = [0xfa, b'L', b'Z', b'4'];
+
+/// The length of the header of compressed values: the prefix and the uncompressed length.
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// A database handle which compresses values with LZ4 when they are written, and decompresses
+/// them when they are read.
+///
+/// A compressed value is stored with an 8 byte header: a 4 byte prefix, followed by the length of
+/// the uncompressed value. Values shorter than the minimum length, or which don't shrink, are
+/// stored as they are, so a database may mix compressed values with values written without the
+/// adapter, and can be migrated to it gradually. A value written without the adapter which
+/// happens to begin with the prefix `fa 4c 5a 34` is misread as compressed, so it must be
+/// rewritten through the adapter.
+///
+/// Decompression allocates, so values are returned as `Cow`, which borrows from the database
+/// when the value is stored uncompressed. Values which fail to decompress, or whose header gives
+/// an uncompressed length larger than the map size of the environment, are reported as
+/// `Error::MALFORMED_VALUE`, so a damaged header cannot make a read allocate up to 4 GiB.
+///
+/// Compressed values are written with `RwTransaction::put_vectored`, which reserves space for
+/// them, so databases with duplicate data items (`DatabaseFlags::DUP_SORT`) are not supported.
+///
+/// Requires the `compression` feature.
+#[derive(Clone, Copy)]
+pub struct CompressedDatabase {
+    db: Database,
+    min_len: usize,
+}
+
+impl fmt::Debug for CompressedDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("CompressedDatabase").field("db", &self.db).field("min_len", &self.min_len).finish()
+    }
+}
+
+impl DatabaseSet for CompressedDatabase {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.push(self.db);
+    }
+}
+
+impl CompressedDatabase {
+
+    /// Wraps a database handle, compressing values of at least 64 bytes.
+    pub fn new(db: Database) -> CompressedDatabase {
+        CompressedDatabase { db, min_len: 64 }
+    }
+
+    /// Sets the minimum length of the values which are compressed.
+    pub fn set_min_len(&mut self, min_len: usize) -> &mut CompressedDatabase {
+        self.min_len = min_len;
+        self
+    }
+
+    /// Returns the underlying database handle.
+    pub fn database(&self) -> Database {
+        self.db
+    }
+
+    /// Gets the decompressed value associated with the key.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the database.
+    pub fn get<'txn, T, K>(&self, txn: &'txn T, key: &K) -> Result<Cow<'txn, [u8]>>
+    where T: Transaction, K: AsRef<[u8]> {
+        decompress(txn.get(self.db, key)?, map_size(txn)?)
+    }
+
+    /// Compresses the value if it is worthwhile, and stores it into the database.
+    ///
+    /// Fails with `Error::Incompatible` if the database has `DatabaseFlags::DUP_SORT`, and with
+    /// `Error::BadValSize` if the value is larger than the map size of the environment, since it
+    /// could not be read back.
+    pub fn put<K, D>(&self, txn: &mut RwTransaction, key: &K, data: &D, flags: WriteFlags) -> Result<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let data = data.as_ref();
+        if txn.db_flags(self.db)?.contains(DatabaseFlags::DUP_SORT) {
+            return Err(Error::Incompatible);
+        }
+        if data.len() > map_size(txn)? {
+            return Err(Error::BadValSize);
+        }
+        // Values beginning with the prefix are always compressed, so that they aren't misread.
+        if data.len() < self.min_len && !data.starts_with(&MAGIC) {
+            return txn.put(self.db, key, &data, flags);
+        }
+        let compressed = block::compress(data);
+        if HEADER_LEN + compressed.len() >= data.len() && !data.starts_with(&MAGIC) {
+            return txn.put(self.db, key, &data, flags);
+        }
+        let len = u32::try_from(data.len()).map_err(|_| Error::BadValSize)?;
+        txn.put_vectored(self.db, key, &[&MAGIC, &len.to_be_bytes(), &compressed], flags)
+    }
+
+    /// Deletes the key from the database.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the database.
+    pub fn del<K>(&self, txn: &mut RwTransaction, key: &K) -> Result<()> where K: AsRef<[u8]> {
+        txn.del(self.db, key, None)
+    }
+
+    /// Iterates over the decompressed items of the database, starting from the beginning.
+    pub fn iter<'txn, T>(&self, txn: &'txn T) -> Result<CompressedIter<'txn>> where T: Transaction {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_start());
+        Ok(CompressedIter { iter, max_len: map_size(txn)? })
+    }
+
+    /// Iterates over the decompressed items of the database, starting from the given key.
+    pub fn iter_from<'txn, T, K>(&self, txn: &'txn T, key: &K) -> Result<CompressedIter<'txn>>
+    where T: Transaction, K: AsRef<[u8]> {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_from(key));
+        Ok(CompressedIter { iter, max_len: map_size(txn)? })
+    }
+}
+
+/// Returns the map size of the environment of the transaction, which bounds the length of the
+/// values written through the adapter.
+fn map_size<T>(txn: &T) -> Result<usize> where T: Transaction {
+    unsafe {
+        let mut info = EnvironmentInfo(mem::zeroed());
+        lmdb_result(ffi::mdb_env_info(ffi::mdb_txn_env(txn.txn()), &mut info.0))?;
+        Ok(info.map_size())
+    }
+}
+
+/// Decompresses a value if it has the header of a compressed value, whose uncompressed length
+/// must not exceed `max_len`.
+fn decompress(data: &[u8], max_len: usize) -> Result<Cow<'_, [u8]>> {
+    if !data.starts_with(&MAGIC) {
+        return Ok(Cow::Borrowed(data));
+    }
+    if data.len() < HEADER_LEN {
+        return Err(Error::MALFORMED_VALUE);
+    }
+    let (header, compressed) = data.split_at(HEADER_LEN);
+    let len = u32::from_be_bytes(<[u8; 4]>::try_from(&header[MAGIC.len()..]).unwrap()) as usize;
+    if len > max_len {
+        return Err(Error::MALFORMED_VALUE);
+    }
+    match block::decompress(compressed, len) {
+        Ok(value) if value.len() == len => Ok(Cow::Owned(value)),
+        _ => Err(Error::MALFORMED_VALUE),
+    }
+}
+
+/// An iterator over the decompressed items of a `CompressedDatabase`.
+pub struct CompressedIter<'txn> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+    max_len: usize,
+}
+
+impl <'txn> fmt::Debug for CompressedIter<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("CompressedIter").finish()
+    }
+}
+
+impl <'txn> Iterator for CompressedIter<'txn> {
+
+    type Item = Result<(&'txn [u8], Cow<'txn, [u8]>)>;
+
+    fn next(&mut self) -> Option<Result<(&'txn [u8], Cow<'txn, [u8]>)>> {
+        let max_len = self.max_len;
+        self.iter.next().map(|(key, data)| decompress(data, max_len).map(|value| (key, value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use super::*;
+
+    #[test]
+    fn test_compressed_database() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let raw = env.open_db(None).unwrap();
+        let db = CompressedDatabase::new(raw);
+        let json = br#"{"name": "value", "name": "value", "name": "value", "name": "value", "name": "value"}"#;
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(raw, b"legacy", json, WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"large", json, WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"small", b"value", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"tricky", &MAGIC, WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert!(txn.get(raw, b"large").unwrap().len() < json.len());
+        assert_eq!(b"value", txn.get(raw, b"small").unwrap());
+        assert_eq!(Cow::Borrowed(&b"value"[..]), db.get(&txn, b"small").unwrap());
+        assert_eq!(&json[..], &*db.get(&txn, b"large").unwrap());
+        assert_eq!(&MAGIC[..], &*db.get(&txn, b"tricky").unwrap());
+
+        // Values written without the adapter are read as they are.
+        let items = db.iter(&txn).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(vec![(&b"large"[..], &json[..]), (b"legacy", json), (b"small", b"value"), (b"tricky", &MAGIC)],
+                   items.iter().map(|(key, value)| (*key, &value[..])).collect::<Vec<_>>());
+        assert_eq!(2, db.iter_from(&txn, b"m").unwrap().count());
+        drop(txn);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(raw, b"corrupt", &[0xfa, b'L', b'Z', b'4', 0, 0, 0, 9, 1], WriteFlags::empty()).unwrap();
+        assert_eq!(Error::MALFORMED_VALUE, db.get(&txn, b"corrupt").unwrap_err());
+        // The length in the header is checked before allocating.
+        txn.put(raw, b"huge", &[0xfa, b'L', b'Z', b'4', 0xff, 0xff, 0xff, 0xff, 1], WriteFlags::empty()).unwrap();
+        assert_eq!(Error::MALFORMED_VALUE, db.get(&txn, b"huge").unwrap_err());
+        db.del(&mut txn, b"huge").unwrap();
+        assert_eq!(Err(Error::NotFound), db.del(&mut txn, b"huge"));
+        let map_size = env.info().unwrap().map_size();
+        assert_eq!(Err(Error::BadValSize), db.put(&mut txn, b"huge", &vec![0; map_size + 1], WriteFlags::empty()));
+    }
+
+    #[test]
+    fn test_compressed_dup_sort() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = CompressedDatabase::new(env.create_db(None, DatabaseFlags::DUP_SORT).unwrap());
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        // Rejected even when the value would be stored uncompressed, so that the outcome does
+        // not depend on the value.
+        assert_eq!(Err(Error::Incompatible), db.put(&mut txn, b"key", b"value", WriteFlags::empty()));
+    }
+}
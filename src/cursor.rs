@@ -1,13 +1,92 @@
+use std::cmp::Ordering;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::{fmt, mem, ptr, result, slice};
 
 use libc::{EINVAL, c_void, size_t, c_uint};
 
-use database::Database;
-use error::{Error, Result, lmdb_result};
+use bookmark::Bookmark;
+use database::{Database, DatabaseLimits};
+use environment::{CheckLevel, Environment};
+use error::{Error, Result, lmdb_result, with_context};
 use ffi;
-use flags::WriteFlags;
-use transaction::Transaction;
+use flags::{DatabaseFlags, WriteFlags};
+use metrics::{self, Operation};
+use quota::{Quota, check_quota};
+use tracking::{HandleKind, Tracked};
+use transaction::{Transaction, check_write};
+
+/// A cursor operation, used to position a cursor with `Cursor::get`.
+///
+/// Mirrors LMDB's `MDB_cursor_op`. Operations ending in `Dup` or `Nodup` and the `GetBoth` and
+/// `Multiple` operations require a database with duplicate data items (`DatabaseFlags::DUP_SORT`),
+/// and the `Multiple` operations additionally require `DatabaseFlags::DUP_FIXED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorOp {
+    /// Position at the first item.
+    First,
+    /// Position at the first data item of the current key.
+    FirstDup,
+    /// Position at the given key/data pair.
+    GetBoth,
+    /// Position at the given key, and the first data item greater than or equal to the given
+    /// data.
+    GetBothRange,
+    /// Return the item at the cursor position.
+    GetCurrent,
+    /// Return up to a page of duplicate data items from the current cursor position.
+    GetMultiple,
+    /// Position at the last item.
+    Last,
+    /// Position at the last data item of the current key.
+    LastDup,
+    /// Position at the next item.
+    Next,
+    /// Position at the next data item of the current key.
+    NextDup,
+    /// Return up to a page of duplicate data items from the next cursor position.
+    NextMultiple,
+    /// Position at the first data item of the next key.
+    NextNodup,
+    /// Position at the previous item.
+    Prev,
+    /// Position at the previous data item of the current key.
+    PrevDup,
+    /// Position at the last data item of the previous key.
+    PrevNodup,
+    /// Position at the given key.
+    Set,
+    /// Position at the given key, and return the key.
+    SetKey,
+    /// Position at the first key greater than or equal to the given key.
+    SetRange,
+}
+
+impl From<CursorOp> for ffi::MDB_cursor_op {
+
+    fn from(op: CursorOp) -> ffi::MDB_cursor_op {
+        match op {
+            CursorOp::First => ffi::MDB_FIRST,
+            CursorOp::FirstDup => ffi::MDB_FIRST_DUP,
+            CursorOp::GetBoth => ffi::MDB_GET_BOTH,
+            CursorOp::GetBothRange => ffi::MDB_GET_BOTH_RANGE,
+            CursorOp::GetCurrent => ffi::MDB_GET_CURRENT,
+            CursorOp::GetMultiple => ffi::MDB_GET_MULTIPLE,
+            CursorOp::Last => ffi::MDB_LAST,
+            CursorOp::LastDup => ffi::MDB_LAST_DUP,
+            CursorOp::Next => ffi::MDB_NEXT,
+            CursorOp::NextDup => ffi::MDB_NEXT_DUP,
+            CursorOp::NextMultiple => ffi::MDB_NEXT_MULTIPLE,
+            CursorOp::NextNodup => ffi::MDB_NEXT_NODUP,
+            CursorOp::Prev => ffi::MDB_PREV,
+            CursorOp::PrevDup => ffi::MDB_PREV_DUP,
+            CursorOp::PrevNodup => ffi::MDB_PREV_NODUP,
+            CursorOp::Set => ffi::MDB_SET,
+            CursorOp::SetKey => ffi::MDB_SET_KEY,
+            CursorOp::SetRange => ffi::MDB_SET_RANGE,
+        }
+    }
+}
 
 /// An LMDB cursor.
 pub trait Cursor<'txn> {
@@ -18,20 +97,115 @@ pub trait Cursor<'txn> {
     /// lifetime of the cursor.
     fn cursor(&self) -> *mut ffi::MDB_cursor;
 
+    /// Returns the environment of the cursor's transaction, which records its metrics, or `None`
+    /// for cursors not opened in a transaction begun by an `Environment`.
+    #[doc(hidden)]
+    fn environment(&self) -> Option<&'txn Environment> {
+        None
+    }
+
     /// Retrieves a key/data pair from the cursor. Depending on the cursor op,
     /// the current key may be returned.
-    fn get(&self, key: Option<&[u8]>, data: Option<&[u8]>, op: c_uint) -> Result<(Option<&'txn [u8]>, &'txn [u8])> {
-        unsafe {
-            let mut key_val = slice_to_val(key);
-            let mut data_val = slice_to_val(data);
-            let key_ptr = key_val.mv_data;
-            lmdb_result(ffi::mdb_cursor_get(self.cursor(), &mut key_val, &mut data_val, op))?;
-            let key_out = if key_ptr != key_val.mv_data { Some(val_to_slice(key_val)) } else { None };
-            let data_out = val_to_slice(data_val);
-            Ok((key_out, data_out))
+    ///
+    /// The op is a `CursorOp`, or a raw `MDB_cursor_op`. The positioning methods, such as
+    /// `Cursor::first` and `Cursor::seek_range`, cover the common ops.
+    fn get<O>(&self, key: Option<&[u8]>, data: Option<&[u8]>, op: O) -> Result<(Option<&'txn [u8]>, &'txn [u8])>
+    where O: Into<ffi::MDB_cursor_op> {
+        unsafe { cursor_get(RawCursor::of(self), key, data, op.into()) }
+    }
+
+    /// Returns the item the cursor is positioned at.
+    ///
+    /// Returns `None` if the cursor is not positioned, or its item was deleted.
+    fn current(&self) -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+        match unsafe { position(RawCursor::of(self), None, None, ffi::MDB_GET_CURRENT) } {
+            Err(Error::Other(EINVAL)) => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Positions the cursor at the first item of the database, and returns it.
+    ///
+    /// Returns `None` if the database is empty.
+    fn first(&self) -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+        unsafe { position(RawCursor::of(self), None, None, ffi::MDB_FIRST) }
+    }
+
+    /// Positions the cursor at the last item of the database, and returns it.
+    ///
+    /// Returns `None` if the database is empty.
+    fn last(&self) -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+        unsafe { position(RawCursor::of(self), None, None, ffi::MDB_LAST) }
+    }
+
+    /// Moves the cursor to the next item, or to the first item if the cursor is not positioned,
+    /// and returns it.
+    ///
+    /// Returns `None` if there is no next item.
+    fn next(&self) -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+        unsafe { position(RawCursor::of(self), None, None, ffi::MDB_NEXT) }
+    }
+
+    /// Moves the cursor to the previous item, or to the last item if the cursor is not
+    /// positioned, and returns it.
+    ///
+    /// Returns `None` if there is no previous item.
+    fn prev(&self) -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+        unsafe { position(RawCursor::of(self), None, None, ffi::MDB_PREV) }
+    }
+
+    /// Positions the cursor at the given key, and returns its item.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the cursor is
+    /// positioned at the first data item of the key. Returns `None` if the key is not in the
+    /// database.
+    ///
+    /// Equivalent to `Cursor::seek_key`.
+    fn seek<K>(&self, key: &K) -> Result<Option<(&'txn [u8], &'txn [u8])>> where K: AsRef<[u8]> {
+        self.seek_key(key)
+    }
+
+    /// Positions the cursor at the given key, and returns its data item only.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the cursor is
+    /// positioned at the first data item of the key. Returns `None` if the key is not in the
+    /// database. To position the cursor at a key/data pair, use `Cursor::seek_both`.
+    fn seek_exact<K>(&self, key: &K) -> Result<Option<&'txn [u8]>> where K: AsRef<[u8]> {
+        match unsafe { cursor_get(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET) } {
+            Ok((_, data)) => Ok(Some(data)),
+            Err(Error::NotFound) => Ok(None),
+            Err(error) => Err(error),
         }
     }
 
+    /// Positions the cursor at the given key, and returns its item, with the key as stored in
+    /// the database.
+    ///
+    /// The returned key points into the database rather than at the given key, so it outlives
+    /// it. For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the cursor is
+    /// positioned at the first data item of the key. Returns `None` if the key is not in the
+    /// database.
+    fn seek_key<K>(&self, key: &K) -> Result<Option<(&'txn [u8], &'txn [u8])>> where K: AsRef<[u8]> {
+        unsafe { position(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET_KEY) }
+    }
+
+    /// Positions the cursor at the first key greater than or equal to the given key, and returns
+    /// its item.
+    ///
+    /// Returns `None` if there is no such key.
+    fn seek_range<K>(&self, key: &K) -> Result<Option<(&'txn [u8], &'txn [u8])>> where K: AsRef<[u8]> {
+        unsafe { position(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET_RANGE) }
+    }
+
+    /// Positions the cursor at the given key/data pair of a database with duplicate data items
+    /// (`DatabaseFlags::DUP_SORT`), and returns it.
+    ///
+    /// Returns `None` if the pair is not in the database.
+    fn seek_both<K, D>(&self, key: &K, data: &D) -> Result<Option<(&'txn [u8], &'txn [u8])>>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        unsafe { position(RawCursor::of(self), Some(key.as_ref()), Some(data.as_ref()), ffi::MDB_GET_BOTH) }
+    }
+
     /// Iterate over database items. The iterator will begin with item next
     /// after the cursor, and continue until the end of the database. For new
     /// cursors, the iterator will begin with the first item in the database.
@@ -40,7 +214,7 @@ pub trait Cursor<'txn> {
     /// duplicate data items of each key will be returned before moving on to
     /// the next key.
     fn iter(&mut self) -> Iter<'txn> {
-        Iter::new(self.cursor(), ffi::MDB_NEXT, ffi::MDB_NEXT)
+        Iter::new(RawCursor::of(self), ffi::MDB_NEXT, ffi::MDB_NEXT)
     }
 
     /// Iterate over database items starting from the beginning of the database.
@@ -49,7 +223,7 @@ pub trait Cursor<'txn> {
     /// duplicate data items of each key will be returned before moving on to
     /// the next key.
     fn iter_start(&mut self) -> Iter<'txn> {
-        Iter::new(self.cursor(), ffi::MDB_FIRST, ffi::MDB_NEXT)
+        Iter::new(RawCursor::of(self), ffi::MDB_FIRST, ffi::MDB_NEXT)
     }
 
     /// Iterate over database items starting from the given key.
@@ -58,56 +232,267 @@ pub trait Cursor<'txn> {
     /// duplicate data items of each key will be returned before moving on to
     /// the next key.
     fn iter_from<K>(&mut self, key: K) -> Iter<'txn> where K: AsRef<[u8]> {
-        match self.get(Some(key.as_ref()), None, ffi::MDB_SET_RANGE) {
+        match unsafe { cursor_get(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET_RANGE) } {
             Ok(_) | Err(Error::NotFound) => (),
             Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
         };
-        Iter::new(self.cursor(), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT)
+        Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT)
+    }
+
+    /// Iterate over the keys of database items, without reading their data items. The iterator
+    /// will begin with the item next after the cursor, and continue until the end of the
+    /// database.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the key is returned
+    /// once for each of its duplicate data items.
+    fn iter_keys(&mut self) -> IterKeys<'txn> {
+        self.iter().keys()
+    }
+
+    /// Iterate over the data items of database items, without returning their keys. The
+    /// iterator will begin with the item next after the cursor, and continue until the end of
+    /// the database.
+    fn iter_values(&mut self) -> IterValues<'txn> {
+        self.iter().values()
+    }
+
+    /// Iterate over the distinct prefixes of `len` bytes of the keys in the database, from its
+    /// start.
+    ///
+    /// After each prefix, the cursor seeks to the first key beyond it rather than visiting the
+    /// other keys sharing it, so enumerating namespaces, such as tenant or table identifiers,
+    /// costs a seek per namespace rather than a step per key. Keys shorter than `len` are
+    /// returned whole. Prefixes are compared bytewise, so the database must use the default key
+    /// order, without `DatabaseFlags::REVERSE_KEY` or `DatabaseFlags::INTEGER_KEY`.
+    fn iter_distinct_prefixes(&mut self, len: usize) -> DistinctPrefixes<'txn> {
+        DistinctPrefixes { cursor: RawCursor::of(self), len, next: PrefixSeek::First, _marker: PhantomData }
     }
 
     /// Iterate over duplicate database items. The iterator will begin with the
     /// item next after the cursor, and continue until the end of the database.
     /// Each item will be returned as an iterator of its duplicates.
     fn iter_dup(&mut self) -> IterDup<'txn> {
-        IterDup::new(self.cursor(), ffi::MDB_NEXT)
+        IterDup::new(RawCursor::of(self), ffi::MDB_NEXT)
     }
 
     /// Iterate over duplicate database items starting from the beginning of the
     /// database. Each item will be returned as an iterator of its duplicates.
     fn iter_dup_start(&mut self) -> IterDup<'txn> {
-        IterDup::new(self.cursor(), ffi::MDB_FIRST)
+        IterDup::new(RawCursor::of(self), ffi::MDB_FIRST)
     }
 
     /// Iterate over duplicate items in the database starting from the given
     /// key. Each item will be returned as an iterator of its duplicates.
     fn iter_dup_from<K>(&mut self, key: &K) -> IterDup<'txn> where K: AsRef<[u8]> {
-        match self.get(Some(key.as_ref()), None, ffi::MDB_SET_RANGE) {
+        match unsafe { cursor_get(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET_RANGE) } {
             Ok(_) | Err(Error::NotFound) => (),
             Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
         };
-        IterDup::new(self.cursor(), ffi::MDB_GET_CURRENT)
+        IterDup::new(RawCursor::of(self), ffi::MDB_GET_CURRENT)
     }
 
     /// Iterate over the duplicates of the item in the database with the given key.
+    ///
+    /// The iterator will be empty if the key is not in the database.
     fn iter_dup_of<K>(&mut self, key: &K) -> Iter<'txn> where K: AsRef<[u8]> {
-        match self.get(Some(key.as_ref()), None, ffi::MDB_SET) {
+        match unsafe { cursor_get(RawCursor::of(self), Some(key.as_ref()), None, ffi::MDB_SET) } {
             Ok(_) | Err(Error::NotFound) => (),
             Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
         };
-        Iter::new(self.cursor(), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP)
+        Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP)
+    }
+
+    /// Iterate over the duplicates of the item in the database with the given key, whose data
+    /// items are in the given range.
+    ///
+    /// The database must have duplicate data items (`DatabaseFlags::DUP_SORT`). The cursor is
+    /// positioned at the first data item in the range, and iteration stops at the first data
+    /// item beyond its end, so a range query, such as over the values with an embedded timestamp
+    /// in a time window, visits only the matching items. Data items are compared in the order of
+    /// the database. The iterator will be empty if the key is not in the database.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the database has `DatabaseFlags::INTEGER_DUP` and a bound is not 4 or 8 bytes
+    /// long, since LMDB would read past its end.
+    fn iter_dup_range<K, D, R>(&mut self, key: &K, range: R) -> IterDupRange<'txn>
+    where K: AsRef<[u8]>, D: AsRef<[u8]>, R: RangeBounds<D> {
+        let key = key.as_ref();
+        check_integer_bounds(self.cursor(), ffi::MDB_INTEGERDUP, &range);
+        let positioned = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => unsafe {
+                cursor_get(RawCursor::of(self), Some(key), Some(start.as_ref()), ffi::MDB_GET_BOTH_RANGE)
+            },
+            Bound::Unbounded => unsafe { cursor_get(RawCursor::of(self), Some(key), None, ffi::MDB_SET) },
+        };
+        let done = match positioned {
+            Ok(_) => false,
+            Err(Error::NotFound) => true,
+            Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
+        };
+        let skip = match range.start_bound() {
+            Bound::Excluded(start) => Some(start.as_ref().to_vec()),
+            _ => None,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(end.as_ref().to_vec()),
+            Bound::Excluded(end) => Bound::Excluded(end.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        IterDupRange {
+            iter: Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP),
+            skip,
+            end,
+            order: Order::of_data(self.cursor()),
+            done,
+        }
+    }
+
+    /// Iterate over the database items with keys in the given range.
+    ///
+    /// The cursor is positioned at the start of the range, and iteration stops at the first key
+    /// beyond its end. Keys are compared in the order of the database, so for databases with
+    /// `DatabaseFlags::REVERSE_KEY` or `DatabaseFlags::INTEGER_KEY` the bounds are compared
+    /// with keys from their last byte, or as integers.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the
+    /// duplicate data items of each key will be returned before moving on to
+    /// the next key.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the database has `DatabaseFlags::INTEGER_KEY` and a bound is not 4 or 8 bytes
+    /// long, since LMDB would read past its end.
+    fn iter_range<K, R>(&mut self, range: R) -> IterRange<'txn>
+    where K: AsRef<[u8]>, R: RangeBounds<K> {
+        check_integer_bounds(self.cursor(), ffi::MDB_INTEGERKEY, &range);
+        // LMDB rejects empty keys, which would sort before all others.
+        let iter = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) if !start.as_ref().is_empty() =>
+                self.iter_from(start),
+            _ => self.iter_start(),
+        };
+        let skip = match range.start_bound() {
+            Bound::Excluded(start) => Some(start.as_ref().to_vec()),
+            _ => None,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(end.as_ref().to_vec()),
+            Bound::Excluded(end) => Bound::Excluded(end.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        IterRange { iter, skip, end, order: Order::of_keys(self.cursor()), done: false }
+    }
+
+    /// Returns a bookmark of the item the cursor is positioned at.
+    ///
+    /// The bookmark includes the data item if the database was opened with
+    /// `DatabaseFlags::DUP_SORT`.
+    fn bookmark(&self) -> Result<Bookmark> {
+        let (key, data) = match unsafe { cursor_get(RawCursor::of(self), None, None, ffi::MDB_GET_CURRENT)? } {
+            (Some(key), data) => (key, data),
+            (None, _) => return Err(Error::NotFound),
+        };
+        let dup_sort = unsafe { db_flags(self.cursor())? } & ffi::MDB_DUPSORT != 0;
+        Ok(Bookmark::new(key, if dup_sort { Some(data) } else { None }))
+    }
+
+    /// Iterate over the database items after the bookmarked item.
+    ///
+    /// Iteration resumes at the next item even if the bookmarked item has
+    /// since been deleted. If the bookmark has no data item, all duplicate
+    /// data items of the bookmarked key are skipped.
+    fn iter_after(&mut self, bookmark: &Bookmark) -> Iter<'txn> {
+        let key = bookmark.key();
+        if let Some(data) = bookmark.data() {
+            match unsafe { cursor_get(RawCursor::of(self), Some(key), Some(data), ffi::MDB_GET_BOTH_RANGE) } {
+                Ok((_, found)) if found == data => {
+                    return Iter::new(RawCursor::of(self), ffi::MDB_NEXT, ffi::MDB_NEXT)
+                },
+                Ok(_) => return Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT),
+                Err(Error::NotFound) => (),
+                Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
+            }
+        }
+        match unsafe { cursor_get(RawCursor::of(self), Some(key), None, ffi::MDB_SET_RANGE) } {
+            Ok((Some(found), _)) if found == key => {
+                Iter::new(RawCursor::of(self), ffi::MDB_NEXT_NODUP, ffi::MDB_NEXT)
+            },
+            Ok(_) | Err(Error::NotFound) => Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT),
+            Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
+        }
+    }
+
+    /// Iterate over the database items with keys starting with the given prefix.
+    ///
+    /// The cursor is positioned at the first key greater than or equal to the prefix, and
+    /// iteration stops at the first key which does not start with the prefix. An empty prefix
+    /// matches every key.
+    ///
+    /// For databases with `DatabaseFlags::REVERSE_KEY` or `DatabaseFlags::INTEGER_KEY`, the keys
+    /// with a prefix are not adjacent, so the whole database is scanned for them. In a database
+    /// with `DatabaseFlags::REVERSE_KEY`, the keys which are adjacent are those with a common
+    /// suffix instead: iterate over them with `Cursor::iter_from` the suffix, until a key does
+    /// not end with it.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the
+    /// duplicate data items of each key will be returned before moving on to
+    /// the next key.
+    fn iter_prefix<K>(&mut self, prefix: K) -> IterPrefix<'txn> where K: AsRef<[u8]> {
+        let prefix = prefix.as_ref();
+        let scan = unsafe { db_flags(self.cursor()) }
+            .is_ok_and(|flags| flags & (ffi::MDB_REVERSEKEY | ffi::MDB_INTEGERKEY) != 0);
+        let iter = if prefix.is_empty() || scan { self.iter_start() } else { self.iter_from(prefix) };
+        IterPrefix { iter, prefix: prefix.to_vec(), scan, dup: false, done: false }
+    }
+
+    /// Iterate over the duplicates of the item in the database with the given key, whose data
+    /// items start with the given prefix.
+    ///
+    /// The database must have duplicate data items (`DatabaseFlags::DUP_SORT`). The cursor is
+    /// positioned at the first data item greater than or equal to the prefix, and iteration stops
+    /// at the first data item which does not start with it, so the rest of the duplicates are not
+    /// scanned. An empty prefix matches every data item of the key. The iterator will be empty if
+    /// the key is not in the database.
+    ///
+    /// For databases with `DatabaseFlags::REVERSE_DUP` or `DatabaseFlags::INTEGER_DUP`, the data
+    /// items with a prefix are not adjacent, so every data item of the key is scanned for them, as
+    /// for keys in `Cursor::iter_prefix`.
+    fn iter_dup_prefix<K, D>(&mut self, key: &K, prefix: D) -> IterPrefix<'txn>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let (key, prefix) = (key.as_ref(), prefix.as_ref());
+        let scan = unsafe { db_flags(self.cursor()) }
+            .is_ok_and(|flags| flags & (ffi::MDB_REVERSEDUP | ffi::MDB_INTEGERDUP) != 0);
+        let positioned = if prefix.is_empty() || scan {
+            unsafe { cursor_get(RawCursor::of(self), Some(key), None, ffi::MDB_SET) }
+        } else {
+            unsafe { cursor_get(RawCursor::of(self), Some(key), Some(prefix), ffi::MDB_GET_BOTH_RANGE) }
+        };
+        let done = match positioned {
+            Ok(_) => false,
+            Err(Error::NotFound) => true,
+            Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
+        };
+        let iter = Iter::new(RawCursor::of(self), ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP);
+        IterPrefix { iter, prefix: prefix.to_vec(), scan, dup: true, done }
     }
 }
 
 /// A read-only cursor for navigating the items within a database.
 pub struct RoCursor<'txn> {
     cursor: *mut ffi::MDB_cursor,
-    _marker: PhantomData<fn() -> &'txn ()>,
+    env: Option<&'txn Environment>,
+    _tracked: Tracked<'txn>,
 }
 
 impl <'txn> Cursor<'txn> for RoCursor<'txn> {
     fn cursor(&self) -> *mut ffi::MDB_cursor {
         self.cursor
     }
+
+    fn environment(&self) -> Option<&'txn Environment> {
+        self.env
+    }
 }
 
 impl <'txn> fmt::Debug for RoCursor<'txn> {
@@ -131,7 +516,8 @@ impl <'txn> RoCursor<'txn> {
         unsafe { lmdb_result(ffi::mdb_cursor_open(txn.txn(), db.dbi(), &mut cursor))?; }
         Ok(RoCursor {
             cursor: cursor,
-            _marker: PhantomData,
+            env: txn.environment(),
+            _tracked: Tracked::open(txn.environment(), HandleKind::Cursor),
         })
     }
 }
@@ -139,13 +525,24 @@ impl <'txn> RoCursor<'txn> {
 /// A read-write cursor for navigating items within a database.
 pub struct RwCursor<'txn> {
     cursor: *mut ffi::MDB_cursor,
-    _marker: PhantomData<fn() -> &'txn ()>,
+    check_level: CheckLevel,
+    quota: Option<Quota>,
+    env: Option<&'txn Environment>,
+    db: Database,
+    /// The databases written by the transaction of the cursor, which its writes are added to,
+    /// or null.
+    dirty: *mut Vec<Database>,
+    _tracked: Tracked<'txn>,
 }
 
 impl <'txn> Cursor<'txn> for RwCursor<'txn> {
     fn cursor(&self) -> *mut ffi::MDB_cursor {
         self.cursor
     }
+
+    fn environment(&self) -> Option<&'txn Environment> {
+        self.env
+    }
 }
 
 impl <'txn> fmt::Debug for RwCursor<'txn> {
@@ -164,10 +561,29 @@ impl <'txn> RwCursor<'txn> {
 
     /// Creates a new read-only cursor in the given database and transaction.
     /// Prefer using `RwTransaction::open_rw_cursor`.
-    pub(crate) fn new<T>(txn: &'txn T, db: Database) -> Result<RwCursor<'txn>> where T: Transaction {
+    ///
+    /// `dirty` is null, or points to the databases written by the transaction, which must not be
+    /// accessed otherwise while the cursor is open.
+    pub(crate) fn new<T>(txn: &'txn T,
+                         db: Database,
+                         check_level: CheckLevel,
+                         quota: Option<Quota>,
+                         dirty: *mut Vec<Database>)
+                         -> Result<RwCursor<'txn>>
+    where T: Transaction {
         let mut cursor: *mut ffi::MDB_cursor = ptr::null_mut();
         unsafe { lmdb_result(ffi::mdb_cursor_open(txn.txn(), db.dbi(), &mut cursor))?; }
-        Ok(RwCursor { cursor: cursor, _marker: PhantomData })
+        let _tracked = Tracked::open(txn.environment(), HandleKind::Cursor);
+        Ok(RwCursor { cursor, check_level, quota, env: txn.environment(), db, dirty, _tracked })
+    }
+
+    /// Marks the database of the cursor as written by its transaction.
+    fn mark_dirty(&mut self) {
+        if let Some(dirty) = unsafe { self.dirty.as_mut() } {
+            if !dirty.contains(&self.db) {
+                dirty.push(self.db);
+            }
+        }
     }
 
     /// Puts a key/data pair into the database. The cursor will be positioned at
@@ -180,12 +596,70 @@ impl <'txn> RwCursor<'txn> {
                                                        mv_data: key.as_ptr() as *mut c_void };
         let mut data_val: ffi::MDB_val = ffi::MDB_val { mv_size: data.len() as size_t,
                                                         mv_data: data.as_ptr() as *mut c_void };
-        unsafe {
+        let (txn, dbi) = unsafe { (ffi::mdb_cursor_txn(self.cursor()), ffi::mdb_cursor_dbi(self.cursor())) };
+        let put = || unsafe {
+            if self.check_level == CheckLevel::Strict {
+                check_write(txn, dbi, key, data, flags.bits())?;
+            }
+            if let Some(ref quota) = self.quota {
+                check_quota(txn, dbi, quota, key, 1, data.len())?;
+            }
             lmdb_result(ffi::mdb_cursor_put(self.cursor(),
                                             &mut key_val,
                                             &mut data_val,
                                             flags.bits()))
+        };
+        let result = metrics::timed(self.env, Operation::Put, put);
+        if result.is_ok() {
+            self.mark_dirty();
+        }
+        with_context(result, "cursor put", txn, dbi, key)
+    }
+
+    /// Puts many fixed-size data items for a single key into a database opened with
+    /// `DatabaseFlags::DUP_FIXED`, in one call.
+    ///
+    /// `values` is the concatenation of the items, each `item_size` bytes long. Returns the
+    /// number of items written; the cursor is positioned at the last of them.
+    pub fn put_multiple<K>(&mut self, key: &K, values: &[u8], item_size: usize, flags: WriteFlags) -> Result<usize>
+    where K: AsRef<[u8]> {
+        if item_size == 0 || !values.len().is_multiple_of(item_size) {
+            return Err(Error::BadValSize);
+        }
+        if values.is_empty() {
+            return Ok(0);
+        }
+        let key = key.as_ref();
+        let mut key_val: ffi::MDB_val = ffi::MDB_val { mv_size: key.len() as size_t,
+                                                       mv_data: key.as_ptr() as *mut c_void };
+        let mut data_vals: [ffi::MDB_val; 2] = [
+            ffi::MDB_val { mv_size: item_size as size_t, mv_data: values.as_ptr() as *mut c_void },
+            ffi::MDB_val { mv_size: (values.len() / item_size) as size_t, mv_data: ptr::null_mut() },
+        ];
+        unsafe {
+            if self.check_level == CheckLevel::Strict {
+                let txn = ffi::mdb_cursor_txn(self.cursor());
+                let dbi = ffi::mdb_cursor_dbi(self.cursor());
+                if !DatabaseLimits::from_raw(txn, dbi)?.flags().contains(DatabaseFlags::DUP_FIXED) {
+                    return Err(Error::Incompatible);
+                }
+                check_write(txn, dbi, key, &values[..item_size], flags.bits())?;
+            }
+            if let Some(ref quota) = self.quota {
+                check_quota(ffi::mdb_cursor_txn(self.cursor()),
+                            ffi::mdb_cursor_dbi(self.cursor()),
+                            quota,
+                            key,
+                            values.len() / item_size,
+                            values.len())?;
+            }
+            lmdb_result(ffi::mdb_cursor_put(self.cursor(),
+                                            &mut key_val,
+                                            data_vals.as_mut_ptr(),
+                                            flags.bits() | ffi::MDB_MULTIPLE))?;
         }
+        self.mark_dirty();
+        Ok(data_vals[1].mv_size as usize)
     }
 
     /// Deletes the current key/data pair.
@@ -195,7 +669,207 @@ impl <'txn> RwCursor<'txn> {
     /// `WriteFlags::NO_DUP_DATA` may be used to delete all data items for the
     /// current key, if the database was opened with `DatabaseFlags::DUP_SORT`.
     pub fn del(&mut self, flags: WriteFlags) -> Result<()> {
-        unsafe { lmdb_result(ffi::mdb_cursor_del(self.cursor(), flags.bits())) }
+        unsafe { lmdb_result(ffi::mdb_cursor_del(self.cursor(), flags.bits()))? };
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Positions the cursor at the given key, and returns a guard which updates or deletes its
+    /// item in place.
+    ///
+    /// The guard borrows the cursor, so it cannot be moved between positioning and modifying the
+    /// item. For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the cursor is
+    /// positioned at the first data item of the key. Returns `None` if the key is not in the
+    /// database.
+    pub fn seek_exact_for_update<'c, K>(&'c mut self, key: &K) -> Result<Option<UpdateGuard<'c, 'txn>>>
+    where K: AsRef<[u8]> {
+        let key = key.as_ref();
+        match unsafe { position(RawCursor::of(self), Some(key), None, ffi::MDB_SET_KEY)? } {
+            Some((_, data)) => Ok(Some(UpdateGuard { cursor: self, key: key.to_vec(), data })),
+            None => Ok(None),
+        }
+    }
+}
+
+/// An item of a database which a write cursor is positioned at, returned by
+/// `RwCursor::seek_exact_for_update`.
+pub struct UpdateGuard<'c, 'txn: 'c> {
+    cursor: &'c mut RwCursor<'txn>,
+    key: Vec<u8>,
+    data: &'c [u8],
+}
+
+impl <'c, 'txn> fmt::Debug for UpdateGuard<'c, 'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("UpdateGuard").field("key", &self.key).finish()
+    }
+}
+
+impl <'c, 'txn> UpdateGuard<'c, 'txn> {
+
+    /// Returns the key of the item.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns the data of the item.
+    pub fn value(&self) -> &[u8] {
+        self.data
+    }
+
+    /// Replaces the data of the item, using `WriteFlags::CURRENT`.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the new data must
+    /// sort into the same position as the data it replaces.
+    pub fn update<D>(self, data: &D) -> Result<()> where D: AsRef<[u8]> {
+        self.cursor.put(&self.key, data, WriteFlags::CURRENT)
+    }
+
+    /// Deletes the item.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), only this data item
+    /// is deleted.
+    pub fn delete(self) -> Result<()> {
+        self.cursor.del(WriteFlags::empty())
+    }
+}
+
+/// Returns the raw flags of the database of the cursor.
+unsafe fn db_flags(cursor: *mut ffi::MDB_cursor) -> Result<c_uint> {
+    let mut flags: c_uint = 0;
+    lmdb_result(ffi::mdb_dbi_flags(ffi::mdb_cursor_txn(cursor), ffi::mdb_cursor_dbi(cursor), &mut flags))?;
+    Ok(flags)
+}
+
+/// Panics if the database of the cursor has the integer flag, `MDB_INTEGERKEY` or
+/// `MDB_INTEGERDUP`, and a bound of the range is not the size of an integer, since LMDB compares
+/// integers by reading as many bytes as the size of the item in the database.
+fn check_integer_bounds<T, R>(cursor: *mut ffi::MDB_cursor, flag: c_uint, range: &R)
+where T: AsRef<[u8]>, R: RangeBounds<T> {
+    if unsafe { db_flags(cursor) }.map_or(true, |flags| flags & flag == 0) {
+        return;
+    }
+    for bound in &[range.start_bound(), range.end_bound()] {
+        if let Bound::Included(bound) | Bound::Excluded(bound) = *bound {
+            let len = bound.as_ref().len();
+            if len != mem::size_of::<c_uint>() && len != mem::size_of::<size_t>() {
+                panic!("a bound of a range of integers must be 4 or 8 bytes long, not {}", len);
+            }
+        }
+    }
+}
+
+/// The order of the keys or duplicate data items of a database, used to compare them with the
+/// bounds of a range.
+#[derive(Clone, Copy)]
+enum Order {
+    /// Ordered as byte strings.
+    Bytes,
+    /// Keys ordered by the key comparison function of the database.
+    Keys(*mut ffi::MDB_txn, ffi::MDB_dbi),
+    /// Data items ordered by the data comparison function of the database.
+    Data(*mut ffi::MDB_txn, ffi::MDB_dbi),
+}
+
+impl Order {
+
+    /// Returns the key order of the database of the cursor.
+    fn of_keys(cursor: *mut ffi::MDB_cursor) -> Order {
+        match unsafe { db_flags(cursor) } {
+            Ok(flags) if flags & (ffi::MDB_REVERSEKEY | ffi::MDB_INTEGERKEY) != 0 => unsafe {
+                Order::Keys(ffi::mdb_cursor_txn(cursor), ffi::mdb_cursor_dbi(cursor))
+            },
+            _ => Order::Bytes,
+        }
+    }
+
+    /// Returns the order of the duplicate data items of the database of the cursor.
+    fn of_data(cursor: *mut ffi::MDB_cursor) -> Order {
+        match unsafe { db_flags(cursor) } {
+            Ok(flags) if flags & (ffi::MDB_REVERSEDUP | ffi::MDB_INTEGERDUP) != 0 => unsafe {
+                Order::Data(ffi::mdb_cursor_txn(cursor), ffi::mdb_cursor_dbi(cursor))
+            },
+            _ => Order::Bytes,
+        }
+    }
+
+    fn cmp(self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            Order::Bytes => a.cmp(b),
+            Order::Keys(txn, dbi) => unsafe {
+                let a = slice_to_val(Some(a));
+                let b = slice_to_val(Some(b));
+                ffi::mdb_cmp(txn, dbi, &a, &b).cmp(&0)
+            },
+            Order::Data(txn, dbi) => unsafe {
+                let a = slice_to_val(Some(a));
+                let b = slice_to_val(Some(b));
+                ffi::mdb_dcmp(txn, dbi, &a, &b).cmp(&0)
+            },
+        }
+    }
+
+    /// Returns whether the value is before the end bound.
+    fn before_end(self, value: &[u8], end: &Bound<Vec<u8>>) -> bool {
+        match *end {
+            Bound::Included(ref end) => self.cmp(value, end) != Ordering::Greater,
+            Bound::Excluded(ref end) => self.cmp(value, end) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+/// A raw cursor, with the environment which records the metrics of its operations, or null.
+/// Like the cursor, the environment must not be used after the lifetime of the cursor.
+#[derive(Clone, Copy)]
+struct RawCursor {
+    cursor: *mut ffi::MDB_cursor,
+    env: *const Environment,
+}
+
+impl RawCursor {
+    fn of<'txn, C>(cursor: &C) -> RawCursor where C: Cursor<'txn> + ?Sized {
+        RawCursor { cursor: cursor.cursor(), env: cursor.environment().map_or(ptr::null(), |env| env) }
+    }
+
+    unsafe fn env<'env>(&self) -> Option<&'env Environment> {
+        self.env.as_ref()
+    }
+}
+
+/// Retrieves a key/data pair from the cursor with a raw cursor op. Depending on the op, the
+/// current key may be returned.
+unsafe fn cursor_get<'txn>(cursor: RawCursor,
+                           key: Option<&[u8]>,
+                           data: Option<&[u8]>,
+                           op: c_uint)
+                           -> Result<(Option<&'txn [u8]>, &'txn [u8])> {
+    let mut key_val = slice_to_val(key);
+    let mut data_val = slice_to_val(data);
+    let key_ptr = key_val.mv_data;
+    let get = || lmdb_result(ffi::mdb_cursor_get(cursor.cursor, &mut key_val, &mut data_val, op));
+    metrics::timed(cursor.env(), Operation::Cursor, get)?;
+    let key_out = if key_ptr != key_val.mv_data { Some(val_to_slice(key_val)) } else { None };
+    let data_out = val_to_slice(data_val);
+    Ok((key_out, data_out))
+}
+
+/// Positions the cursor with a raw cursor op, and returns the item at the new position, or
+/// `None` if there is no such item.
+unsafe fn position<'txn>(cursor: RawCursor,
+                         key: Option<&[u8]>,
+                         data: Option<&[u8]>,
+                         op: c_uint)
+                         -> Result<Option<(&'txn [u8], &'txn [u8])>> {
+    match cursor_get(cursor, key, data, op) {
+        Ok((Some(key), data)) => Ok(Some((key, data))),
+        // The op does not return the key, so read the item in the database.
+        Ok((None, _)) => match cursor_get(cursor, None, None, ffi::MDB_GET_CURRENT)? {
+            (Some(key), data) => Ok(Some((key, data))),
+            (None, _) => Err(Error::NotFound),
+        },
+        Err(Error::NotFound) => Ok(None),
+        Err(error) => Err(error),
     }
 }
 
@@ -216,7 +890,7 @@ unsafe fn val_to_slice<'a>(val: ffi::MDB_val) -> &'a [u8] {
 
 /// An iterator over the values in an LMDB database.
 pub struct Iter<'txn> {
-    cursor: *mut ffi::MDB_cursor,
+    cursor: RawCursor,
     op: c_uint,
     next_op: c_uint,
     _marker: PhantomData<fn(&'txn ())>,
@@ -225,28 +899,36 @@ pub struct Iter<'txn> {
 impl <'txn> Iter<'txn> {
 
     /// Creates a new iterator backed by the given cursor.
-    fn new<'t>(cursor: *mut ffi::MDB_cursor, op: c_uint, next_op: c_uint) -> Iter<'t> {
+    fn new<'t>(cursor: RawCursor, op: c_uint, next_op: c_uint) -> Iter<'t> {
         Iter { cursor: cursor, op: op, next_op: next_op, _marker: PhantomData }
     }
-}
 
-impl <'txn> fmt::Debug for Iter<'txn> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-        f.debug_struct("Iter").finish()
+    /// Limits the iterator to yielding about `budget` bytes of keys and values.
+    ///
+    /// See `IterBudget` for details.
+    pub fn with_byte_budget(self, budget: usize) -> IterBudget<'txn, Iter<'txn>> {
+        IterBudget::new(self, budget)
     }
-}
 
-impl <'txn> Iterator for Iter<'txn> {
+    /// Yields only the keys of the items, without reading their data items.
+    pub fn keys(self) -> IterKeys<'txn> {
+        IterKeys { iter: self }
+    }
 
-    type Item = (&'txn [u8], &'txn [u8]);
+    /// Yields only the data items of the items.
+    pub fn values(self) -> IterValues<'txn> {
+        IterValues { iter: self }
+    }
 
-    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+    /// Moves the cursor to the next item, and returns its key. The data item is read into
+    /// `data`.
+    fn advance(&mut self, data: &mut ffi::MDB_val) -> Option<&'txn [u8]> {
         let mut key = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
-        let mut data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
         let op = mem::replace(&mut self.op, self.next_op);
         unsafe {
-            match ffi::mdb_cursor_get(self.cursor, &mut key, &mut data, op) {
-                ffi::MDB_SUCCESS => Some((val_to_slice(key), val_to_slice(data))),
+            let get = || ffi::mdb_cursor_get(self.cursor.cursor, &mut key, data, op);
+            match metrics::timed(self.cursor.env(), Operation::Cursor, get) {
+                ffi::MDB_SUCCESS => Some(val_to_slice(key)),
                 // EINVAL can occur when the cursor was previously seeked to a non-existent value,
                 // e.g. iter_from with a key greater than all values in the database.
                 ffi::MDB_NOTFOUND | EINVAL => None,
@@ -256,60 +938,588 @@ impl <'txn> Iterator for Iter<'txn> {
     }
 }
 
-/// An iterator over the keys and duplicate values in an LMDB database.
-///
-/// The yielded items of the iterator are themselves iterators over the duplicate values for a
-/// specific key.
-pub struct IterDup<'txn> {
-    cursor: *mut ffi::MDB_cursor,
-    op: c_uint,
-    _marker: PhantomData<fn(&'txn ())>,
+impl <'txn> fmt::Debug for Iter<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("Iter").finish()
+    }
 }
 
-impl <'txn> IterDup<'txn> {
+impl <'txn> Iterator for Iter<'txn> {
 
-    /// Creates a new iterator backed by the given cursor.
-    fn new<'t>(cursor: *mut ffi::MDB_cursor, op: c_uint) -> IterDup<'t> {
-        IterDup { cursor: cursor, op: op, _marker: PhantomData }
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        let mut data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+        let key = self.advance(&mut data)?;
+        Some((key, unsafe { val_to_slice(data) }))
     }
 }
 
-impl <'txn> fmt::Debug for IterDup<'txn> {
+/// An iterator over the keys in an LMDB database.
+///
+/// The data items are not returned, so their pages, such as the overflow pages of large values,
+/// are not read.
+pub struct IterKeys<'txn> {
+    iter: Iter<'txn>,
+}
+
+impl <'txn> fmt::Debug for IterKeys<'txn> {
     fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-        f.debug_struct("IterDup").finish()
+        f.debug_struct("IterKeys").finish()
     }
 }
 
-impl <'txn> Iterator for IterDup<'txn> {
+impl <'txn> Iterator for IterKeys<'txn> {
 
-    type Item = Iter<'txn>;
+    type Item = &'txn [u8];
 
-    fn next(&mut self) -> Option<Iter<'txn>> {
-        let mut key = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+    fn next(&mut self) -> Option<&'txn [u8]> {
+        // LMDB writes the data item even when it is not wanted, such as for duplicates stored
+        // in `DatabaseFlags::DUP_FIXED` pages, so it must not be passed a null pointer.
         let mut data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
-        let op = mem::replace(&mut self.op, ffi::MDB_NEXT_NODUP);
-        let err_code = unsafe {
-            ffi::mdb_cursor_get(self.cursor, &mut key, &mut data, op)
-        };
+        self.iter.advance(&mut data)
+    }
+}
 
-        if err_code == ffi::MDB_SUCCESS {
-            Some(Iter::new(self.cursor, ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP))
-        } else {
-            None
-        }
+/// An iterator over the data items in an LMDB database.
+pub struct IterValues<'txn> {
+    iter: Iter<'txn>,
+}
+
+impl <'txn> fmt::Debug for IterValues<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterValues").finish()
     }
 }
 
-#[cfg(test)]
-mod test {
+impl <'txn> Iterator for IterValues<'txn> {
 
-    use std::ptr;
-    use test::{Bencher, black_box};
+    type Item = &'txn [u8];
 
-    use tempdir::TempDir;
+    fn next(&mut self) -> Option<&'txn [u8]> {
+        self.iter.next().map(|(_, data)| data)
+    }
+}
 
-    use environment::*;
-    use ffi::*;
+/// An iterator over the distinct key prefixes of an LMDB database.
+///
+/// Returned by `Cursor::iter_distinct_prefixes`.
+pub struct DistinctPrefixes<'txn> {
+    cursor: RawCursor,
+    len: usize,
+    next: PrefixSeek,
+    _marker: PhantomData<fn(&'txn ())>,
+}
+
+/// How `DistinctPrefixes` finds the key with the next prefix.
+enum PrefixSeek {
+    First,
+    /// Seeks to the first key not less than the successor of the last prefix.
+    Range(Vec<u8>),
+    /// Steps to the next key, after a key shorter than the prefix length.
+    Next,
+    Done,
+}
+
+impl <'txn> fmt::Debug for DistinctPrefixes<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("DistinctPrefixes").field("len", &self.len).finish()
+    }
+}
+
+impl <'txn> Iterator for DistinctPrefixes<'txn> {
+
+    type Item = &'txn [u8];
+
+    fn next(&mut self) -> Option<&'txn [u8]> {
+        let found = unsafe {
+            match mem::replace(&mut self.next, PrefixSeek::Done) {
+                PrefixSeek::First => position(self.cursor, None, None, ffi::MDB_FIRST),
+                PrefixSeek::Range(key) => position(self.cursor, Some(&key), None, ffi::MDB_SET_RANGE),
+                PrefixSeek::Next => position(self.cursor, None, None, ffi::MDB_NEXT_NODUP),
+                PrefixSeek::Done => return None,
+            }
+        };
+        let key = match found {
+            Ok(Some((key, _))) => key,
+            Ok(None) => return None,
+            Err(error) => panic!("mdb_cursor_get returned an unexpected error: {}", error),
+        };
+        if key.len() < self.len {
+            self.next = PrefixSeek::Next;
+            return Some(key);
+        }
+        let prefix = &key[..self.len];
+        // The successor of the prefix increments its last byte which is not 0xff, dropping the
+        // bytes after it. Prefixes of only 0xff bytes have no successor.
+        if let Some(last) = prefix.iter().rposition(|&byte| byte != 0xff) {
+            let mut successor = prefix[..=last].to_vec();
+            successor[last] += 1;
+            self.next = PrefixSeek::Range(successor);
+        }
+        Some(prefix)
+    }
+}
+
+/// An iterator over the values in a range of keys in an LMDB database.
+pub struct IterRange<'txn> {
+    iter: Iter<'txn>,
+    skip: Option<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    order: Order,
+    done: bool,
+}
+
+impl <'txn> IterRange<'txn> {
+
+    /// Limits the iterator to yielding about `budget` bytes of keys and values.
+    ///
+    /// See `IterBudget` for details.
+    pub fn with_byte_budget(self, budget: usize) -> IterBudget<'txn, IterRange<'txn>> {
+        IterBudget::new(self, budget)
+    }
+}
+
+impl <'txn> fmt::Debug for IterRange<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterRange").finish()
+    }
+}
+
+impl <'txn> Iterator for IterRange<'txn> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let (key, data) = match self.iter.next() {
+                Some(item) => item,
+                None => {
+                    self.done = true;
+                    return None;
+                },
+            };
+            if let Some(ref skip) = self.skip {
+                if key == &skip[..] {
+                    continue;
+                }
+            }
+            self.skip = None;
+            if !self.order.before_end(key, &self.end) {
+                self.done = true;
+                return None;
+            }
+            return Some((key, data));
+        }
+    }
+}
+
+/// An iterator over the duplicate data items in a range of a key in an LMDB database.
+pub struct IterDupRange<'txn> {
+    iter: Iter<'txn>,
+    skip: Option<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    order: Order,
+    done: bool,
+}
+
+impl <'txn> fmt::Debug for IterDupRange<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterDupRange").finish()
+    }
+}
+
+impl <'txn> Iterator for IterDupRange<'txn> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let (key, data) = match self.iter.next() {
+                Some(item) => item,
+                None => {
+                    self.done = true;
+                    return None;
+                },
+            };
+            if let Some(skip) = self.skip.take() {
+                if data == &skip[..] {
+                    continue;
+                }
+            }
+            if !self.order.before_end(data, &self.end) {
+                self.done = true;
+                return None;
+            }
+            return Some((key, data));
+        }
+    }
+}
+
+/// An iterator over the values with keys starting with a prefix in an LMDB database, or over the
+/// duplicate data items of a key starting with a prefix.
+pub struct IterPrefix<'txn> {
+    iter: Iter<'txn>,
+    prefix: Vec<u8>,
+    /// Whether the items with the prefix may not be adjacent, so that every item is scanned.
+    scan: bool,
+    /// Whether the prefix is matched against data items instead of keys.
+    dup: bool,
+    done: bool,
+}
+
+impl <'txn> fmt::Debug for IterPrefix<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterPrefix").field("prefix", &self.prefix).finish()
+    }
+}
+
+impl <'txn> Iterator for IterPrefix<'txn> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        if self.done {
+            return None;
+        }
+        for (key, data) in &mut self.iter {
+            if (if self.dup { data } else { key }).starts_with(&self.prefix) {
+                return Some((key, data));
+            }
+            if !self.scan {
+                break;
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// An iterator which owns the cursor it reads with, so that it can be created straight from a
+/// transaction.
+///
+/// Returned by `Transaction::iter_from` and `Transaction::iter_prefix`, and wrapped by the
+/// iterators of the database adapters, such as `IntegerIter`.
+pub struct CursorIter<'txn, I> {
+    iter: I,
+    // Declared after `iter` so that the cursor outlives it.
+    _cursor: RoCursor<'txn>,
+}
+
+impl <'txn, I> CursorIter<'txn, I> {
+
+    /// Creates an iterator with the cursor it reads with.
+    pub(crate) fn new<F>(mut cursor: RoCursor<'txn>, f: F) -> CursorIter<'txn, I>
+    where F: FnOnce(&mut RoCursor<'txn>) -> I {
+        let iter = f(&mut cursor);
+        CursorIter { iter, _cursor: cursor }
+    }
+}
+
+impl <'txn, I> fmt::Debug for CursorIter<'txn, I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("CursorIter").finish()
+    }
+}
+
+impl <'txn, I> Iterator for CursorIter<'txn, I> where I: Iterator {
+
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.iter.next()
+    }
+}
+
+/// An iterator over all of the items of a database, which can be iterated from both ends.
+///
+/// Returned by `Transaction::iter`. The front and the back are read with separate cursors, and
+/// the number of items is taken from the statistics of the database when the iterator is
+/// created, so that the iterator knows its exact length and where the two ends meet.
+pub struct Items<'txn> {
+    front: RoCursor<'txn>,
+    back: RoCursor<'txn>,
+    remaining: usize,
+    /// Whether the front cursor may be positioned at an item which was not yielded yet, after
+    /// `Items::resume_after` or a failed read.
+    front_pending: bool,
+    /// The last item yielded from the front, to tell whether a failed read moved the cursor.
+    front_last: Option<(&'txn [u8], &'txn [u8])>,
+}
+
+impl <'txn> Items<'txn> {
+
+    /// Creates an iterator over the items of the database.
+    pub(crate) fn new<T>(txn: &'txn T, db: Database) -> Result<Items<'txn>> where T: Transaction {
+        Ok(Items {
+            front: RoCursor::new(txn, db)?,
+            back: RoCursor::new(txn, db)?,
+            remaining: txn.stat(db)?.entries(),
+            front_pending: false,
+            front_last: None,
+        })
+    }
+
+    /// Returns the next item, or the error reading it rather than panicking like `next`.
+    ///
+    /// After an error, calling `try_next` again retries the failed item, and
+    /// `Items::resume_after` skips it. An item which was already yielded is not yielded again,
+    /// whether or not the failed read moved the cursor past it.
+    pub fn try_next(&mut self) -> Option<Result<(&'txn [u8], &'txn [u8])>> {
+        self.try_step(false)
+    }
+
+    /// Skips the items with keys up to and including the key, so that the scan continues from
+    /// the next key, such as after `Items::try_next` failed to read the item of the key.
+    ///
+    /// The skipped items are stepped over without returning their data items. Keys are compared
+    /// in the order of the database. Items already yielded from the back are not yielded again.
+    pub fn resume_after<K>(&mut self, key: &K) -> Result<()> where K: AsRef<[u8]> {
+        let key = key.as_ref();
+        let cursor = self.front.cursor();
+        let order = Order::of_keys(cursor);
+        while self.remaining > 0 {
+            let op = if self.front_pending { ffi::MDB_GET_CURRENT } else { ffi::MDB_NEXT };
+            let mut next = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+            // LMDB may write the data item, such as of duplicates on `DatabaseFlags::DUP_FIXED`
+            // pages, so it must not be passed a null pointer.
+            let mut data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+            match unsafe { lmdb_result(ffi::mdb_cursor_get(cursor, &mut next, &mut data, op)) } {
+                Ok(()) => (),
+                Err(Error::NotFound) => {
+                    self.remaining = 0;
+                    break;
+                },
+                // The cursor is not positioned if reading the first item failed.
+                Err(Error::Other(EINVAL)) if self.front_pending => {
+                    self.front_pending = false;
+                    continue;
+                },
+                Err(error) => return Err(error),
+            }
+            let (next, data) = unsafe { (val_to_slice(next), val_to_slice(data)) };
+            if self.front_pending && self.is_front_last((next, data)) {
+                // A failed read did not move the cursor past the last item yielded.
+                self.front_pending = false;
+                continue;
+            }
+            if order.cmp(next, key) == Ordering::Greater {
+                self.front_pending = true;
+                break;
+            }
+            self.front_pending = false;
+            self.remaining -= 1;
+        }
+        Ok(())
+    }
+
+    /// Moves the front or the back cursor, which start at the first and the last item.
+    fn step(&mut self, back: bool) -> Option<(&'txn [u8], &'txn [u8])> {
+        self.try_step(back).map(|item| {
+            item.unwrap_or_else(|error| panic!("mdb_cursor_get returned an unexpected error: {}", error))
+        })
+    }
+
+    fn try_step(&mut self, back: bool) -> Option<Result<(&'txn [u8], &'txn [u8])>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let item = if back {
+            self.back.prev()
+        } else if self.front_pending {
+            // The cursor is not positioned if reading the first item failed, and is still at the
+            // last item yielded if a read failed before moving it.
+            match self.front.current() {
+                Ok(Some(item)) if !self.is_front_last(item) => Ok(Some(item)),
+                Ok(_) => self.front.next(),
+                Err(error) => Err(error),
+            }
+        } else {
+            self.front.next()
+        };
+        match item {
+            Ok(Some(item)) => {
+                self.remaining -= 1;
+                if !back {
+                    self.front_pending = false;
+                    self.front_last = Some(item);
+                }
+                Some(Ok(item))
+            },
+            Ok(None) => {
+                self.remaining = 0;
+                None
+            },
+            Err(error) => {
+                if !back {
+                    self.front_pending = true;
+                }
+                Some(Err(error))
+            },
+        }
+    }
+
+    /// Returns whether the item, read at the position of the front cursor, is the last item
+    /// yielded from the front. Items are compared by address, which tells apart duplicates.
+    fn is_front_last(&self, (key, data): (&[u8], &[u8])) -> bool {
+        self.front_last.is_some_and(|(last_key, last_data)| {
+            ptr::eq(last_key, key) && ptr::eq(last_data, data)
+        })
+    }
+}
+
+impl <'txn> fmt::Debug for Items<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("Items").field("remaining", &self.remaining).finish()
+    }
+}
+
+impl <'txn> Iterator for Items<'txn> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        self.step(false)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl <'txn> DoubleEndedIterator for Items<'txn> {
+    fn next_back(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        self.step(true)
+    }
+}
+
+impl <'txn> ExactSizeIterator for Items<'txn> {}
+
+/// An iterator adaptor which stops once the yielded keys and values exhaust a byte budget.
+///
+/// The first item is always yielded, so that a scan makes progress even if a single item exceeds
+/// the budget. After that, iteration stops before the first item which would exceed the budget,
+/// and the key of that item becomes the continuation key. A scan may be resumed in a later
+/// transaction by passing the continuation key to `Cursor::iter_from` or `Cursor::iter_range`.
+///
+/// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), the scan may stop partway
+/// through the duplicates of a key, and a resumed scan yields the duplicates of the continuation
+/// key from the beginning.
+pub struct IterBudget<'txn, I> {
+    iter: I,
+    remaining: usize,
+    started: bool,
+    continuation: Option<&'txn [u8]>,
+}
+
+impl <'txn, I> IterBudget<'txn, I> where I: Iterator<Item = (&'txn [u8], &'txn [u8])> {
+
+    /// Limits the iterator to yielding about `budget` bytes of keys and values.
+    pub fn new(iter: I, budget: usize) -> IterBudget<'txn, I> {
+        IterBudget { iter, remaining: budget, started: false, continuation: None }
+    }
+
+    /// Returns the key of the first item not yielded because the budget was exhausted.
+    ///
+    /// Returns `None` while the budget lasts, and after the underlying iterator is exhausted.
+    pub fn continuation(&self) -> Option<&'txn [u8]> {
+        self.continuation
+    }
+
+    /// Returns the number of bytes remaining in the budget.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl <'txn, I> fmt::Debug for IterBudget<'txn, I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterBudget").field("remaining", &self.remaining).finish()
+    }
+}
+
+impl <'txn, I> Iterator for IterBudget<'txn, I> where I: Iterator<Item = (&'txn [u8], &'txn [u8])> {
+
+    type Item = (&'txn [u8], &'txn [u8]);
+
+    fn next(&mut self) -> Option<(&'txn [u8], &'txn [u8])> {
+        if self.continuation.is_some() {
+            return None;
+        }
+        let (key, data) = self.iter.next()?;
+        let len = key.len() + data.len();
+        if self.started && len > self.remaining {
+            self.continuation = Some(key);
+            return None;
+        }
+        self.started = true;
+        self.remaining = self.remaining.saturating_sub(len);
+        Some((key, data))
+    }
+}
+
+/// An iterator over the keys and duplicate values in an LMDB database.
+///
+/// The yielded items of the iterator are themselves iterators over the duplicate values for a
+/// specific key.
+pub struct IterDup<'txn> {
+    cursor: RawCursor,
+    op: c_uint,
+    _marker: PhantomData<fn(&'txn ())>,
+}
+
+impl <'txn> IterDup<'txn> {
+
+    /// Creates a new iterator backed by the given cursor.
+    fn new<'t>(cursor: RawCursor, op: c_uint) -> IterDup<'t> {
+        IterDup { cursor: cursor, op: op, _marker: PhantomData }
+    }
+}
+
+impl <'txn> fmt::Debug for IterDup<'txn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IterDup").finish()
+    }
+}
+
+impl <'txn> Iterator for IterDup<'txn> {
+
+    type Item = Iter<'txn>;
+
+    fn next(&mut self) -> Option<Iter<'txn>> {
+        let mut key = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+        let mut data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+        let op = mem::replace(&mut self.op, ffi::MDB_NEXT_NODUP);
+        let err_code = unsafe {
+            ffi::mdb_cursor_get(self.cursor.cursor, &mut key, &mut data, op)
+        };
+
+        if err_code == ffi::MDB_SUCCESS {
+            Some(Iter::new(self.cursor, ffi::MDB_GET_CURRENT, ffi::MDB_NEXT_DUP))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::convert::TryFrom;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::ptr;
+    use test::{Bencher, black_box};
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use ffi::*;
     use flags::*;
     use super::*;
     use test_utils::*;
@@ -342,6 +1552,12 @@ mod test {
                    cursor.get(Some(&b"key3"[..]), None, MDB_SET_KEY).unwrap());
         assert_eq!((Some(&b"key3"[..]), &b"val3"[..]),
                    cursor.get(Some(&b"key2\0"[..]), None, MDB_SET_RANGE).unwrap());
+
+        // Typed ops position the cursor like the raw ones.
+        assert_eq!((Some(&b"key1"[..]), &b"val1"[..]),
+                   cursor.get(None, None, CursorOp::First).unwrap());
+        assert_eq!((Some(&b"key2"[..]), &b"val2"[..]),
+                   cursor.get(Some(&b"key2"[..]), None, CursorOp::SetKey).unwrap());
     }
 
     #[test]
@@ -390,6 +1606,152 @@ mod test {
                    cursor.get(Some(&b"key2"[..]), Some(&b"val"[..]), MDB_GET_BOTH_RANGE).unwrap());
     }
 
+    #[test]
+    fn test_positioning() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &[(b"key1", b"val1"), (b"key2", b"val2"), (b"key3", b"val3")] {
+            txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+            txn.put(dup_db, b"key", &data, WriteFlags::empty()).unwrap();
+        }
+
+        let cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(None, cursor.current().unwrap());
+        assert_eq!(Some((&b"key1"[..], &b"val1"[..])), cursor.next().unwrap());
+        assert_eq!(Some((&b"key1"[..], &b"val1"[..])), cursor.first().unwrap());
+        assert_eq!(Some((&b"key1"[..], &b"val1"[..])), cursor.current().unwrap());
+        assert_eq!(Some((&b"key2"[..], &b"val2"[..])), cursor.next().unwrap());
+        assert_eq!(Some((&b"key1"[..], &b"val1"[..])), cursor.prev().unwrap());
+        assert_eq!(None, cursor.prev().unwrap());
+        assert_eq!(Some((&b"key3"[..], &b"val3"[..])), cursor.last().unwrap());
+        assert_eq!(None, cursor.next().unwrap());
+        assert_eq!(Some((&b"key2"[..], &b"val2"[..])), cursor.seek(b"key2").unwrap());
+        assert_eq!(None, cursor.seek(b"key").unwrap());
+        assert_eq!(Some((&b"key3"[..], &b"val3"[..])), cursor.seek_range(b"key2\0").unwrap());
+        assert_eq!(None, cursor.seek_range(b"key4").unwrap());
+
+        let cursor = txn.open_ro_cursor(dup_db).unwrap();
+        assert_eq!(Some((&b"key"[..], &b"val1"[..])), cursor.seek(b"key").unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val2"[..])), cursor.seek_both(b"key", b"val2").unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val3"[..])), cursor.next().unwrap());
+        assert_eq!(None, cursor.seek_both(b"key", b"val4").unwrap());
+    }
+
+    #[test]
+    fn test_iter_distinct_prefixes() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for key in &[&b"a"[..], b"a1:x", b"a1:y", b"a2:x", b"a\xff\xff:x", b"a\xff\xff:y", b"b", b"b1:x", b"\xff\xff"] {
+            txn.put(db, key, b"1", WriteFlags::empty()).unwrap();
+            txn.put(db, key, b"2", WriteFlags::empty()).unwrap();
+        }
+
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![&b"a"[..], b"a1", b"a2", b"a\xff", b"b", b"b1", b"\xff\xff"],
+                   cursor.iter_distinct_prefixes(2).collect::<Vec<_>>());
+        assert_eq!(vec![&b"a"[..], b"b", b"\xff"], cursor.iter_distinct_prefixes(1).collect::<Vec<_>>());
+        assert_eq!(vec![&b""[..]], cursor.iter_distinct_prefixes(0).collect::<Vec<_>>());
+        drop(cursor);
+        txn.clear_db(db).unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(0, cursor.iter_distinct_prefixes(1).count());
+    }
+
+    #[test]
+    fn test_iter_keys_and_values() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let large = vec![7; 64 * 1024];
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", &large, WriteFlags::empty()).unwrap();
+        txn.put(db, b"key3", b"val3", WriteFlags::empty()).unwrap();
+        for &(key, data) in &[(b"a", b"1"), (b"a", b"2"), (b"b", b"1")] {
+            txn.put(dup_db, key, data, WriteFlags::empty()).unwrap();
+        }
+
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![&b"key1"[..], b"key2", b"key3"], cursor.iter_keys().collect::<Vec<_>>());
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![&b"val1"[..], &large, b"val3"], cursor.iter_values().collect::<Vec<_>>());
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![&b"key2"[..], b"key3"], cursor.iter_from(b"key2").keys().collect::<Vec<_>>());
+        assert_eq!(vec![&b"val1"[..]], cursor.iter_start().values().take(1).collect::<Vec<_>>());
+        // The cursor is left at the last key returned.
+        assert_eq!(vec![&b"key2"[..], b"key3"], cursor.iter_keys().collect::<Vec<_>>());
+
+        let mut cursor = txn.open_ro_cursor(dup_db).unwrap();
+        assert_eq!(vec![&b"a"[..], b"a", b"b"], cursor.iter_keys().collect::<Vec<_>>());
+        let mut cursor = txn.open_ro_cursor(dup_db).unwrap();
+        assert_eq!(vec![&b"1"[..], b"2", b"1"], cursor.iter_values().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_keys_dup_fixed() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let db = env.create_db(Some("fixed"), DatabaseFlags::DUP_SORT | DatabaseFlags::DUP_FIXED).unwrap();
+
+        // LMDB writes the data item of keys with several fixed-size duplicates, which are stored
+        // on their own pages, even when only keys are iterated.
+        let mut txn = env.begin_rw_txn().unwrap();
+        for key in 0..50u8 {
+            for data in 0..50u32 {
+                txn.put(db, &[b'k', key], &data.to_be_bytes(), WriteFlags::empty()).unwrap();
+            }
+        }
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let keys = cursor.iter_keys().collect::<Vec<_>>();
+        assert_eq!(2500, keys.len());
+        assert_eq!((&[b'k', 0][..], &[b'k', 49][..]), (keys[0], keys[2499]));
+    }
+
+    #[test]
+    fn test_seek_exact_and_seek_key() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        for data in &[b"val3", b"val1", b"val2"] {
+            txn.put(dup_db, b"key", data, WriteFlags::empty()).unwrap();
+        }
+        txn.put(dup_db, b"later", b"val", WriteFlags::empty()).unwrap();
+
+        let cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(Some(&b"val1"[..]), cursor.seek_exact(b"key1").unwrap());
+        assert_eq!(None, cursor.seek_exact(b"key").unwrap());
+        let key = b"key1".to_vec();
+        let (found, data) = cursor.seek_key(&key).unwrap().unwrap();
+        assert_eq!((&b"key1"[..], &b"val1"[..]), (found, data));
+        // The key is echoed from the database, not from the argument.
+        assert_ne!(key.as_ptr(), found.as_ptr());
+        assert_eq!(None, cursor.seek_key(b"key2").unwrap());
+
+        // Both position the cursor at the first duplicate of the key.
+        let cursor = txn.open_ro_cursor(dup_db).unwrap();
+        assert_eq!(Some(&b"val1"[..]), cursor.seek_exact(b"key").unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val2"[..])), cursor.next().unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val1"[..])), cursor.seek_key(b"key").unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val1"[..])), cursor.current().unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val2"[..])), cursor.next().unwrap());
+        assert_eq!(Some((&b"key"[..], &b"val3"[..])), cursor.next().unwrap());
+        assert_eq!(Some((&b"later"[..], &b"val"[..])), cursor.next().unwrap());
+        assert_eq!(None, cursor.seek_exact(b"kez").unwrap());
+    }
+
     #[test]
     fn test_get_dupfixed() {
         let dir = TempDir::new("test").unwrap();
@@ -412,6 +1774,74 @@ mod test {
         assert!(cursor.get(None, None, MDB_NEXT_MULTIPLE).is_err());
     }
 
+    #[test]
+    fn test_seek_exact_for_update() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &[(b"key1", b"val1"), (b"key2", b"val2"), (b"key3", b"val3")] {
+            txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+            txn.put(dup_db, b"key", &data, WriteFlags::empty()).unwrap();
+        }
+        {
+            let mut cursor = txn.open_rw_cursor(db).unwrap();
+            assert!(cursor.seek_exact_for_update(b"key0").unwrap().is_none());
+            let guard = cursor.seek_exact_for_update(b"key1").unwrap().unwrap();
+            assert_eq!((&b"key1"[..], &b"val1"[..]), (guard.key(), guard.value()));
+            guard.update(b"updated1").unwrap();
+            cursor.seek_exact_for_update(b"key2").unwrap().unwrap().delete().unwrap();
+            assert_eq!(Some((&b"key3"[..], &b"val3"[..])), cursor.current().unwrap());
+        }
+        {
+            let mut cursor = txn.open_rw_cursor(dup_db).unwrap();
+            let guard = cursor.seek_exact_for_update(b"key").unwrap().unwrap();
+            assert_eq!(b"val1", guard.value());
+            guard.delete().unwrap();
+        }
+        assert_eq!(b"updated1", txn.get(db, b"key1").unwrap());
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"key2"));
+        let mut cursor = txn.open_ro_cursor(dup_db).unwrap();
+        assert_eq!(vec![(&b"key"[..], &b"val2"[..]), (b"key", b"val3")],
+                   cursor.iter_start().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_put_multiple() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2)
+                                    .set_check_level(CheckLevel::Strict)
+                                    .open(dir.path())
+                                    .unwrap();
+        let db = env.create_db(Some("fixed"), DatabaseFlags::DUP_SORT | DatabaseFlags::DUP_FIXED).unwrap();
+        let plain = env.create_db(Some("plain"), DatabaseFlags::empty()).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        {
+            let mut cursor = txn.open_rw_cursor(db).unwrap();
+            assert_eq!(3, cursor.put_multiple(b"key1", b"val3val1val2", 4, WriteFlags::empty()).unwrap());
+            assert_eq!(0, cursor.put_multiple(b"key2", b"", 4, WriteFlags::empty()).unwrap());
+            assert_eq!(Err(Error::BadValSize),
+                       cursor.put_multiple(b"key2", b"val", 4, WriteFlags::empty()));
+            assert_eq!(Err(Error::BadValSize),
+                       cursor.put_multiple(b"key2", b"val", 0, WriteFlags::empty()));
+        }
+        {
+            let mut cursor = txn.open_rw_cursor(plain).unwrap();
+            assert_eq!(Err(Error::Incompatible),
+                       cursor.put_multiple(b"key1", b"val1val2", 4, WriteFlags::empty()));
+        }
+
+        let cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!((Some(&b"key1"[..]), &b"val1"[..]),
+                   cursor.get(None, None, MDB_FIRST).unwrap());
+        assert_eq!((None, &b"val1val2val3"[..]),
+                   cursor.get(None, None, MDB_GET_MULTIPLE).unwrap());
+        assert!(cursor.get(None, None, MDB_NEXT_MULTIPLE).is_err());
+    }
+
     #[test]
     fn test_iter() {
         let dir = TempDir::new("test").unwrap();
@@ -451,6 +1881,333 @@ mod test {
                    cursor.iter_from(b"key6").collect::<Vec<_>>());
     }
 
+    #[test]
+    fn test_items_resume_after() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("dups"), DatabaseFlags::DUP_SORT).unwrap();
+        let fixed = env.create_db(Some("fixed"), DatabaseFlags::DUP_SORT | DatabaseFlags::DUP_FIXED).unwrap();
+
+        let items: Vec<(&[u8], &[u8])> = vec!((b"key1", b"val1"),
+                                              (b"key2", b"val1"),
+                                              (b"key2", b"val2"),
+                                              (b"key3", b"val1"),
+                                              (b"key5", b"val1"));
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &items {
+            txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+        }
+
+        for key in 0..50u8 {
+            for data in 0..50u32 {
+                txn.put(fixed, &[b'k', key], &data.to_be_bytes(), WriteFlags::empty()).unwrap();
+            }
+        }
+
+        let mut iter = txn.iter(db).unwrap();
+        assert_eq!(Some(Ok(items[0])), iter.try_next());
+        iter.resume_after(b"key1").unwrap();
+        assert_eq!(Some(items[1]), iter.next());
+        // The remaining duplicates of the key are skipped too.
+        iter.resume_after(b"key2").unwrap();
+        assert_eq!(2, iter.len());
+        iter.resume_after(b"key2").unwrap();
+        assert_eq!(items[3..].to_vec(), iter.collect::<Vec<_>>());
+
+        let mut iter = txn.iter(db).unwrap();
+        iter.resume_after(b"key4").unwrap();
+        assert_eq!(1, iter.len());
+        assert_eq!(Some(Ok(items[4])), iter.try_next());
+        assert_eq!(None, iter.try_next());
+
+        // Items yielded from the back are not yielded again.
+        let mut iter = txn.iter(db).unwrap();
+        assert_eq!(Some(items[4]), iter.next_back());
+        iter.resume_after(b"key9").unwrap();
+        assert_eq!(0, iter.len());
+        assert_eq!(None, iter.next());
+
+        // Keys with many fixed-size duplicates are stored on their own pages.
+        let mut iter = txn.iter(fixed).unwrap();
+        assert_eq!(Some(Ok((&[b'k', 0][..], &[0, 0, 0, 0][..]))), iter.try_next());
+        iter.resume_after(&[b'k', 30]).unwrap();
+        assert_eq!(19 * 50, iter.len());
+        assert_eq!(Some((&[b'k', 31][..], &[0, 0, 0, 0][..])), iter.next());
+        iter.resume_after(&[b'k', 31]).unwrap();
+        assert_eq!(Some((&[b'k', 32][..], &[0, 0, 0, 0][..])), iter.next());
+        assert_eq!(18 * 50 - 1, iter.len());
+    }
+
+    #[test]
+    fn test_iter_range() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let items: Vec<(&[u8], &[u8])> = vec!((b"key1", b"val1"),
+                                              (b"key2", b"val2"),
+                                              (b"key3", b"val3"),
+                                              (b"key5", b"val5"));
+
+        {
+            let mut txn = env.begin_rw_txn().unwrap();
+            for &(key, data) in &items {
+                txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+            }
+            txn.commit().unwrap();
+        }
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(items[1..3].to_vec(),
+                   cursor.iter_range(&b"key2"[..]..&b"key5"[..]).collect::<Vec<_>>());
+        assert_eq!(items[1..].to_vec(),
+                   cursor.iter_range(&b"key2"[..]..=&b"key5"[..]).collect::<Vec<_>>());
+        assert_eq!(items[..2].to_vec(),
+                   cursor.iter_range(..&b"key3"[..]).collect::<Vec<_>>());
+        assert_eq!(items[3..].to_vec(),
+                   cursor.iter_range(&b"key4"[..]..).collect::<Vec<_>>());
+        assert_eq!(items, cursor.iter_range::<&[u8], _>(..).collect::<Vec<_>>());
+        assert_eq!(items[2..].to_vec(),
+                   cursor.iter_range::<&[u8], _>((Bound::Excluded(&b"key2"[..]), Bound::Unbounded))
+                         .collect::<Vec<_>>());
+        assert_eq!(items, cursor.iter_range(&b""[..]..).collect::<Vec<_>>());
+        assert_eq!(0, cursor.iter_range(&b"key6"[..]..).count());
+        assert_eq!(0, cursor.iter_range(&b"key3"[..]..&b"key3"[..]).count());
+    }
+
+    #[test]
+    fn test_iter_range_key_order() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let reverse = env.create_db(Some("reverse"), DatabaseFlags::REVERSE_KEY).unwrap();
+        let integer = env.create_db(Some("integer"), DatabaseFlags::INTEGER_KEY).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for key in &[&b"1a"[..], b"2a", b"1b", b"2b", b"3b"] {
+            txn.put(reverse, key, key, WriteFlags::empty()).unwrap();
+        }
+        for key in &[1u32, 2, 256, 65536] {
+            txn.put(integer, &key.to_ne_bytes(), &key.to_ne_bytes(), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(reverse).unwrap();
+        let keys = |iter: IterRange| iter.map(|(key, _)| key).collect::<Vec<_>>();
+        // Keys are ordered from their last byte.
+        assert_eq!(vec![&b"1a"[..], b"2a", b"1b", b"2b", b"3b"],
+                   keys(cursor.iter_range::<&[u8], _>(..)));
+        assert_eq!(vec![&b"2a"[..], b"1b", b"2b"], keys(cursor.iter_range(&b"2a"[..]..=&b"2b"[..])));
+        assert_eq!(vec![&b"2a"[..], b"1b"], keys(cursor.iter_range(&b"2a"[..]..&b"2b"[..])));
+
+        let mut cursor = txn.open_ro_cursor(integer).unwrap();
+        let keys = cursor.iter_range(2u32.to_ne_bytes()..65536u32.to_ne_bytes())
+                         .map(|(key, _)| key.to_vec())
+                         .collect::<Vec<_>>();
+        assert_eq!(vec![2u32.to_ne_bytes().to_vec(), 256u32.to_ne_bytes().to_vec()], keys);
+    }
+
+    #[test]
+    fn test_iter_dup_range() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        // Values embed a big-endian timestamp followed by a payload.
+        let value = |ts: u32, payload: &str| [&ts.to_be_bytes()[..], payload.as_bytes()].concat();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &ts in &[10u32, 20, 30, 40] {
+            txn.put(db, b"sensor1", &value(ts, "a"), WriteFlags::empty()).unwrap();
+        }
+        txn.put(db, b"sensor0", &value(25, "b"), WriteFlags::empty()).unwrap();
+        txn.put(db, b"sensor2", &value(25, "c"), WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let timestamps = |iter: IterDupRange| {
+            iter.map(|(key, data)| {
+                assert_eq!(b"sensor1", key);
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+            }).collect::<Vec<_>>()
+        };
+        let ts = |ts: u32| ts.to_be_bytes();
+        assert_eq!(vec![20, 30], timestamps(cursor.iter_dup_range(b"sensor1", ts(15)..ts(40))));
+        assert_eq!(vec![20, 30, 40], timestamps(cursor.iter_dup_range(b"sensor1", ts(20)..)));
+        assert_eq!(vec![10, 20], timestamps(cursor.iter_dup_range(b"sensor1", ..ts(30))));
+        assert_eq!(vec![10, 20, 30, 40],
+                   timestamps(cursor.iter_dup_range::<_, &[u8], _>(b"sensor1", ..)));
+        assert_eq!(vec![30, 40], timestamps(cursor.iter_dup_range(
+            b"sensor1", (Bound::Excluded(value(20, "a")), Bound::Unbounded))));
+        assert_eq!(0, cursor.iter_dup_range(b"sensor1", ts(50)..).count());
+        assert_eq!(0, cursor.iter_dup_range(b"sensor3", ts(0)..).count());
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let items: Vec<(&[u8], &[u8])> = vec!((b"a", b"val0"),
+                                              (b"user:1", b"val1"),
+                                              (b"user:1", b"val2"),
+                                              (b"user:2", b"val3"),
+                                              (b"users", b"val4"));
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &items {
+            txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(items[1..4].to_vec(), cursor.iter_prefix(b"user:").collect::<Vec<_>>());
+        assert_eq!(items[1..3].to_vec(), cursor.iter_prefix(b"user:1").collect::<Vec<_>>());
+        assert_eq!(items[1..].to_vec(), cursor.iter_prefix(b"user").collect::<Vec<_>>());
+        assert_eq!(items, cursor.iter_prefix(b"").collect::<Vec<_>>());
+        assert_eq!(0, cursor.iter_prefix(b"b").count());
+        assert_eq!(0, cursor.iter_prefix(b"z").count());
+    }
+
+    #[test]
+    fn test_iter_dup_prefix() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+        let reverse = env.create_db(Some("reverse"), DatabaseFlags::DUP_SORT | DatabaseFlags::REVERSE_DUP).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for data in &[&b"a:1"[..], b"b:1", b"b:2", b"bc:1", b"c:1"] {
+            txn.put(db, b"key1", data, WriteFlags::empty()).unwrap();
+            txn.put(reverse, b"key1", data, WriteFlags::empty()).unwrap();
+        }
+        txn.put(db, b"key2", b"b:3", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let data = |iter: IterPrefix| iter.map(|(key, data)| {
+            assert_eq!(b"key1", key);
+            data
+        }).collect::<Vec<_>>();
+        assert_eq!(vec![&b"b:1"[..], b"b:2"], data(cursor.iter_dup_prefix(b"key1", b"b:")));
+        assert_eq!(vec![&b"b:1"[..], b"b:2", b"bc:1"], data(cursor.iter_dup_prefix(b"key1", b"b")));
+        assert_eq!(5, cursor.iter_dup_prefix(b"key1", b"").count());
+        assert_eq!(0, cursor.iter_dup_prefix(b"key1", b"d").count());
+        assert_eq!(0, cursor.iter_dup_prefix(b"key1", b"ab").count());
+        assert_eq!(0, cursor.iter_dup_prefix(b"key3", b"b").count());
+
+        // Data items are ordered from their last byte, so those with a prefix are found by a scan.
+        let mut cursor = txn.open_ro_cursor(reverse).unwrap();
+        assert_eq!(vec![&b"b:1"[..], b"bc:1", b"b:2"], data(cursor.iter_dup_prefix(b"key1", b"b")));
+        assert_eq!(0, cursor.iter_dup_prefix(b"key1", b":1").count());
+    }
+
+    #[test]
+    fn test_iter_prefix_reverse_key() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::REVERSE_KEY).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for key in &[&b"a.com"[..], b"b.org", b"mail.a.com", b"x.com"] {
+            txn.put(db, key, b"", WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let keys = |iter: IterPrefix| iter.map(|(key, _)| key).collect::<Vec<_>>();
+        // Keys are ordered from their last byte, so those with a prefix are found by a scan.
+        assert_eq!(vec![&b"b.org"[..], b"a.com", b"mail.a.com", b"x.com"],
+                   keys(cursor.iter_prefix(b"")));
+        assert_eq!(vec![&b"a.com"[..]], keys(cursor.iter_prefix(b"a.")));
+        assert_eq!(vec![&b"mail.a.com"[..]], keys(cursor.iter_prefix(b"m")));
+        assert_eq!(0, cursor.iter_prefix(b".com").count());
+        assert_eq!(0, cursor.iter_prefix(b"z").count());
+
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let suffixed = cursor.iter_from(b"a.com").take_while(|&(key, _)| key.ends_with(b"a.com"));
+        assert_eq!(vec![&b"a.com"[..], b"mail.a.com"], suffixed.map(|(key, _)| key).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_integer_bounds() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let integer = env.create_db(Some("integer"), DatabaseFlags::INTEGER_KEY).unwrap();
+        let dup = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT | DatabaseFlags::INTEGER_DUP).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for value in &[1u32, 2, 256] {
+            txn.put(integer, &value.to_ne_bytes(), b"", WriteFlags::empty()).unwrap();
+            txn.put(dup, b"key", &value.to_ne_bytes(), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        // Prefixes are matched by a scan, without comparing them to the integers.
+        let mut cursor = txn.open_ro_cursor(integer).unwrap();
+        assert_eq!(vec![2u32.to_ne_bytes()], cursor.iter_prefix([2])
+                                                  .map(|(key, _)| <[u8; 4]>::try_from(key).unwrap())
+                                                  .collect::<Vec<_>>());
+        assert_eq!(1, cursor.iter_range(2u32.to_ne_bytes()..256u32.to_ne_bytes()).count());
+        let mut cursor = txn.open_ro_cursor(dup).unwrap();
+        assert_eq!(3, cursor.iter_dup_prefix(b"key", []).count());
+        assert_eq!(1, cursor.iter_dup_prefix(b"key", [1]).count());
+
+        // Bounds of the wrong size are rejected before they reach LMDB.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            txn.open_ro_cursor(integer).unwrap().iter_range(&b"ab"[..]..).count()
+        }));
+        assert!(result.is_err());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            txn.open_ro_cursor(dup).unwrap().iter_dup_range(b"key", ..=&b"abc"[..]).count()
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_iter_byte_budget() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let items: Vec<(&[u8], &[u8])> = vec!((b"key1", b"val1"),
+                                              (b"key2", b"val2"),
+                                              (b"key3", b"val3"),
+                                              (b"key4", b"val4"));
+
+        {
+            let mut txn = env.begin_rw_txn().unwrap();
+            for &(key, data) in &items {
+                txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+            }
+            txn.commit().unwrap();
+        }
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+
+        // Each item is 8 bytes, so a budget of 20 bytes fits two items.
+        let mut iter = cursor.iter_start().with_byte_budget(20);
+        assert_eq!(items[..2].to_vec(), iter.by_ref().collect::<Vec<_>>());
+        assert_eq!(Some(&b"key3"[..]), iter.continuation());
+        assert_eq!(4, iter.remaining());
+        assert_eq!(None, iter.next());
+
+        let mut iter = cursor.iter_from(b"key3").with_byte_budget(20);
+        assert_eq!(items[2..].to_vec(), iter.by_ref().collect::<Vec<_>>());
+        assert_eq!(None, iter.continuation());
+
+        // The first item is yielded even if it exceeds the budget.
+        let mut iter = cursor.iter_range(&b"key2"[..]..).with_byte_budget(0);
+        assert_eq!(items[1..2].to_vec(), iter.by_ref().collect::<Vec<_>>());
+        assert_eq!(Some(&b"key3"[..]), iter.continuation());
+    }
+
     #[test]
     fn test_iter_empty_database() {
         let dir = TempDir::new("test").unwrap();
@@ -535,6 +2292,10 @@ mod test {
                    cursor.iter_dup_of(b"b").collect::<Vec<_>>());
 
         assert_eq!(0, cursor.iter_dup_of(b"foo").count());
+        assert_eq!(0, cursor.iter_dup_of(b"bb").count());
+        cursor.seek(b"a").unwrap();
+        assert_eq!(0, cursor.iter_dup_of(b"d").count());
+        assert_eq!(0, cursor.iter_dup_of(b"f").count());
     }
 
     #[test]
@@ -1,10 +1,12 @@
-use libc::c_uint;
+use libc::{c_uint, size_t};
 use std::ffi::CString;
-use std::ptr;
+use std::{mem, ptr, result};
 
 use ffi;
 
-use error::{Result, lmdb_result};
+use error::{EntryError, Result, lmdb_result};
+use flags::DatabaseFlags;
+use transaction::Transaction;
 
 /// A handle to an individual database in an environment.
 ///
@@ -31,6 +33,11 @@ impl Database {
         Ok(Database { dbi: dbi })
     }
 
+    /// Returns a handle to LMDB's internal database of free pages, which is always open.
+    pub(crate) fn freelist() -> Database {
+        Database { dbi: 0 }
+    }
+
     /// Returns the underlying LMDB database handle.
     ///
     /// The caller **must** ensure that the handle is not used after the lifetime of the
@@ -38,7 +45,139 @@ impl Database {
     pub fn dbi(&self) -> ffi::MDB_dbi {
         self.dbi
     }
+
+    /// Retrieves the effective key and value size limits of the database in the given
+    /// transaction.
+    pub fn limits<T>(&self, txn: &T) -> Result<DatabaseLimits> where T: Transaction {
+        unsafe { DatabaseLimits::from_raw(txn.txn(), self.dbi) }
+    }
+}
+
+/// The key and value size limits of a database.
+///
+/// LMDB rejects oversized entries with `Error::BadValSize` at write time. `DatabaseLimits`
+/// allows callers to check entries ahead of time, and to find out why they would be rejected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DatabaseLimits {
+    max_key_size: usize,
+    flags: DatabaseFlags,
+}
+
+impl DatabaseLimits {
+
+    /// Retrieves the limits of the database handle in the raw transaction.
+    pub(crate) unsafe fn from_raw(txn: *mut ffi::MDB_txn, dbi: ffi::MDB_dbi) -> Result<DatabaseLimits> {
+        let mut flags: c_uint = 0;
+        lmdb_result(ffi::mdb_dbi_flags(txn, dbi, &mut flags))?;
+        let env = ffi::mdb_txn_env(txn);
+        Ok(DatabaseLimits {
+            max_key_size: ffi::mdb_env_get_maxkeysize(env) as usize,
+            flags: DatabaseFlags::from_bits_truncate(flags),
+        })
+    }
+
+    /// The maximum size of a key in the database, in bytes.
+    ///
+    /// This is a property of the environment, and is the same for all databases.
+    pub fn max_key_size(&self) -> usize {
+        self.max_key_size
+    }
+
+    /// The maximum size of a value in the database, in bytes, if it is limited.
+    ///
+    /// Values in `DatabaseFlags::DUP_SORT` databases are stored as keys of a nested B-tree,
+    /// and so are subject to the maximum key size. Values in other databases are only limited
+    /// by the map size.
+    pub fn max_value_size(&self) -> Option<usize> {
+        if self.flags.contains(DatabaseFlags::DUP_SORT) {
+            Some(self.max_key_size)
+        } else {
+            None
+        }
+    }
+
+    /// The flags the database was opened with.
+    pub fn flags(&self) -> DatabaseFlags {
+        self.flags
+    }
+
+    /// Checks that the key/value pair may be written to the database.
+    pub fn validate_entry(&self, key: &[u8], value: &[u8]) -> result::Result<(), EntryError> {
+        if key.is_empty() {
+            return Err(EntryError::EmptyKey);
+        }
+        if key.len() > self.max_key_size {
+            return Err(EntryError::KeyTooLarge { len: key.len(), max: self.max_key_size });
+        }
+        if self.flags.contains(DatabaseFlags::INTEGER_KEY)
+            && key.len() != mem::size_of::<c_uint>()
+            && key.len() != mem::size_of::<size_t>() {
+            return Err(EntryError::IntegerKeySize { len: key.len() });
+        }
+        if let Some(max) = self.max_value_size() {
+            if value.len() > max {
+                return Err(EntryError::DupValueTooLarge { len: value.len(), max });
+            }
+        }
+        Ok(())
+    }
 }
 
 unsafe impl Sync for Database {}
 unsafe impl Send for Database {}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+
+    #[test]
+    fn test_limits() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let limits = db.limits(&txn).unwrap();
+        let dup_limits = dup_db.limits(&txn).unwrap();
+        let max = limits.max_key_size();
+        assert!(max > 0);
+        assert_eq!(None, limits.max_value_size());
+        assert_eq!(Some(max), dup_limits.max_value_size());
+
+        let big = vec![0u8; max + 1];
+        assert_eq!(Ok(()), limits.validate_entry(b"key", &big));
+        assert_eq!(Err(EntryError::EmptyKey), limits.validate_entry(b"", b"val"));
+        assert_eq!(Err(EntryError::KeyTooLarge { len: max + 1, max }),
+                   limits.validate_entry(&big, b"val"));
+        assert_eq!(Err(EntryError::DupValueTooLarge { len: max + 1, max }),
+                   dup_limits.validate_entry(b"key", &big));
+    }
+
+    #[test]
+    fn test_validate_entry_matches_put() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let limits = db.limits(&txn).unwrap();
+        let max = limits.max_key_size();
+        let at_max = vec![1u8; max];
+        let over_max = vec![1u8; max + 1];
+
+        assert!(limits.validate_entry(&at_max, &at_max).is_ok());
+        assert!(txn.put(db, &at_max, &at_max, WriteFlags::empty()).is_ok());
+
+        assert!(limits.validate_entry(&over_max, b"val").is_err());
+        assert_eq!(Err(Error::BadValSize), txn.put(db, &over_max, b"val", WriteFlags::empty()));
+
+        assert!(limits.validate_entry(b"key", &over_max).is_err());
+        assert_eq!(Err(Error::BadValSize), txn.put(db, b"key", &over_max, WriteFlags::empty()));
+    }
+}
@@ -0,0 +1,395 @@
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::{fmt, result};
+
+use siphasher::sip::SipHasher13;
+
+use cursor::Cursor;
+use database::Database;
+use error::{Error, Result};
+use flags::WriteFlags;
+use transaction::{RwTransaction, Transaction};
+
+/// The hasher of `DigestTree::new`: SipHash-1-3 with both keys zero.
+///
+/// Unlike `std::collections::hash_map::DefaultHasher`, whose algorithm may change between Rust
+/// releases, it produces the same hashes in every build, so its digests can be stored and
+/// compared between environments opened by different builds.
+#[derive(Clone, Debug)]
+pub struct DigestHasher(SipHasher13);
+
+impl Default for DigestHasher {
+    fn default() -> DigestHasher {
+        DigestHasher(SipHasher13::new_with_keys(0, 0))
+    }
+}
+
+impl Hasher for DigestHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// Feeds a key/value pair to a hasher, prefixing the key and value with their lengths so that
+/// distinct databases can't produce the same stream of bytes.
+pub(crate) fn hash_item<H>(hasher: &mut H, key: &[u8], data: &[u8]) where H: Hasher {
+    hasher.write(&(key.len() as u64).to_le_bytes());
+    hasher.write(key);
+    hasher.write(&(data.len() as u64).to_le_bytes());
+    hasher.write(data);
+}
+
+/// Computes a digest of the contents of a database by streaming every key/value pair, in database
+/// order, through the hasher.
+///
+/// Two databases with the same contents and the same key ordering produce the same digest, so
+/// the digests of a primary and a replica or backup can be compared to verify that they are
+/// equal without transferring their data. Both sides must use the same hasher implementation and
+/// seed, such as `DigestHasher`; the algorithm of `std::collections::hash_map::DefaultHasher` may
+/// change between Rust releases.
+pub fn digest<T, H>(txn: &T, db: Database, mut hasher: H) -> Result<u64> where T: Transaction, H: Hasher {
+    let mut cursor = txn.open_ro_cursor(db)?;
+    for (key, data) in cursor.iter_start() {
+        hash_item(&mut hasher, key, data);
+    }
+    Ok(hasher.finish())
+}
+
+/// The size of a bucket record: the tag of the hash algorithm, followed by the XOR of the item
+/// hashes and the item count, both as little-endian `u64`s.
+const BUCKET_LEN: usize = 17;
+
+/// The tag of buckets hashed with `DigestHasher`.
+const SIPHASH_1_3: u8 = 1;
+/// The tag of buckets hashed with a hasher given to `DigestTree::with_hasher`.
+const CUSTOM_HASHER: u8 = 0;
+
+/// A database with a maintained digest tree, allowing two environments to find which key prefixes
+/// differ without comparing every item.
+///
+/// Items are grouped into buckets by the first `prefix_len` bytes of their keys. The tree database
+/// maps each bucket prefix to a combination of the hashes of its items, which is updated
+/// incrementally as items are written through `DigestTree`, in the same transaction. Writes made
+/// directly to the data database leave the tree stale until `DigestTree::rebuild` is called.
+///
+/// Each bucket records the hash algorithm it was built with: `DigestHasher` for trees created
+/// with `DigestTree::new`, or a caller-chosen hasher for trees created with
+/// `DigestTree::with_hasher`. Such a hasher must be deterministic and the same for every
+/// environment being compared. Buckets written with the other kind of hasher, or by an earlier
+/// version of the tree, fail with `Error::Incompatible` until the tree is rebuilt. The data
+/// database must not be opened with `DatabaseFlags::DUP_SORT`.
+pub struct DigestTree<S = BuildHasherDefault<DigestHasher>> {
+    data: Database,
+    tree: Database,
+    prefix_len: usize,
+    hasher: S,
+    algorithm: u8,
+}
+
+impl <S> fmt::Debug for DigestTree<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("DigestTree")
+         .field("data", &self.data)
+         .field("tree", &self.tree)
+         .field("prefix_len", &self.prefix_len)
+         .finish()
+    }
+}
+
+impl DigestTree {
+
+    /// Creates a digest tree over the data database, stored in the tree database, with buckets of
+    /// keys sharing their first `prefix_len` bytes.
+    ///
+    /// Panics if `prefix_len` is zero, since the prefixes are the keys of the tree database, which
+    /// LMDB requires to be non-empty.
+    pub fn new(data: Database, tree: Database, prefix_len: usize) -> DigestTree {
+        let tree = DigestTree::with_hasher(data, tree, prefix_len, BuildHasherDefault::default());
+        DigestTree { algorithm: SIPHASH_1_3, ..tree }
+    }
+
+    /// Returns the prefixes of the buckets which differ between two sorted lists of buckets, as
+    /// returned by `DigestTree::buckets`, including buckets which are only in one of the lists.
+    pub fn diff<'a>(ours: &[(&'a [u8], u64)], theirs: &[(&'a [u8], u64)]) -> Vec<&'a [u8]> {
+        let mut prefixes = Vec::new();
+        let (mut ours, mut theirs) = (ours.iter().peekable(), theirs.iter().peekable());
+        loop {
+            match (ours.peek(), theirs.peek()) {
+                (Some(&&(a, a_digest)), Some(&&(b, b_digest))) => {
+                    if a < b {
+                        prefixes.push(a);
+                        ours.next();
+                    } else if b < a {
+                        prefixes.push(b);
+                        theirs.next();
+                    } else {
+                        if a_digest != b_digest {
+                            prefixes.push(a);
+                        }
+                        ours.next();
+                        theirs.next();
+                    }
+                },
+                (Some(&&(a, _)), None) => { prefixes.push(a); ours.next(); },
+                (None, Some(&&(b, _))) => { prefixes.push(b); theirs.next(); },
+                (None, None) => return prefixes,
+            }
+        }
+    }
+}
+
+impl <S> DigestTree<S> where S: BuildHasher {
+
+    /// Creates a digest tree which hashes items with the given hasher.
+    ///
+    /// Panics if `prefix_len` is zero, like `DigestTree::new`.
+    pub fn with_hasher(data: Database, tree: Database, prefix_len: usize, hasher: S) -> DigestTree<S> {
+        assert!(prefix_len > 0, "the prefix length of a digest tree must not be zero");
+        DigestTree { data, tree, prefix_len, hasher, algorithm: CUSTOM_HASHER }
+    }
+
+    /// Returns the data database.
+    pub fn data(&self) -> Database {
+        self.data
+    }
+
+    /// Returns the tree database.
+    pub fn tree(&self) -> Database {
+        self.tree
+    }
+
+    /// Stores an item into the data database and updates the digest of its bucket.
+    pub fn put<K, D>(&self, txn: &mut RwTransaction, key: &K, data: &D, flags: WriteFlags) -> Result<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let (key, data) = (key.as_ref(), data.as_ref());
+        let old = match txn.get(self.data, &key) {
+            Ok(old) => Some(self.hash_item(key, old)),
+            Err(Error::NotFound) => None,
+            Err(error) => return Err(error),
+        };
+        txn.put(self.data, &key, &data, flags)?;
+        let new = self.hash_item(key, data);
+        match old {
+            Some(old) => self.update_bucket(txn, key, old ^ new, 0),
+            None => self.update_bucket(txn, key, new, 1),
+        }
+    }
+
+    /// Deletes an item from the data database and updates the digest of its bucket.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the data database.
+    pub fn del<K>(&self, txn: &mut RwTransaction, key: &K) -> Result<()> where K: AsRef<[u8]> {
+        let key = key.as_ref();
+        let old = self.hash_item(key, txn.get(self.data, &key)?);
+        txn.del(self.data, &key, None)?;
+        self.update_bucket(txn, key, old, -1)
+    }
+
+    /// Clears the tree and rebuilds it from the items of the data database.
+    pub fn rebuild(&self, txn: &mut RwTransaction) -> Result<()> {
+        txn.clear_db(self.tree)?;
+        let mut buckets: Vec<(Vec<u8>, [u8; BUCKET_LEN])> = Vec::new();
+        {
+            let mut cursor = txn.open_ro_cursor(self.data)?;
+            for (key, data) in cursor.iter_start() {
+                let hash = self.hash_item(key, data);
+                let prefix = self.prefix(key);
+                match buckets.last_mut() {
+                    Some(&mut (ref last, ref mut bucket)) if &last[..] == prefix => {
+                        *bucket = combine(bucket, hash, 1);
+                    },
+                    _ => buckets.push((prefix.to_vec(), combine(&self.empty_bucket(), hash, 1))),
+                }
+            }
+        }
+        txn.put_many(self.tree, buckets, WriteFlags::APPEND)
+    }
+
+    /// Returns the digest of every bucket, in prefix order.
+    ///
+    /// The buckets of two environments can be compared with `DigestTree::diff`.
+    pub fn buckets<'txn, T>(&self, txn: &'txn T) -> Result<Vec<(&'txn [u8], u64)>> where T: Transaction {
+        let mut cursor = txn.open_ro_cursor(self.tree)?;
+        cursor.iter_start().map(|(prefix, bucket)| {
+            self.check_bucket(bucket)?;
+            Ok((prefix, self.hash_bucket(bucket)))
+        }).collect()
+    }
+
+    /// Returns the digest of the whole data database, combining the digests of the buckets.
+    pub fn root<T>(&self, txn: &T) -> Result<u64> where T: Transaction {
+        let mut hasher = self.hasher.build_hasher();
+        for (prefix, digest) in self.buckets(txn)? {
+            hash_item(&mut hasher, prefix, &digest.to_le_bytes());
+        }
+        Ok(hasher.finish())
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..key.len().min(self.prefix_len)]
+    }
+
+    fn hash_item(&self, key: &[u8], data: &[u8]) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        hash_item(&mut hasher, key, data);
+        hasher.finish()
+    }
+
+    fn empty_bucket(&self) -> [u8; BUCKET_LEN] {
+        let mut bucket = [0; BUCKET_LEN];
+        bucket[0] = self.algorithm;
+        bucket
+    }
+
+    /// Returns `Error::Incompatible` if the bucket was not written with the hasher of the tree.
+    fn check_bucket(&self, bucket: &[u8]) -> Result<()> {
+        if bucket.len() == BUCKET_LEN && bucket[0] == self.algorithm {
+            Ok(())
+        } else {
+            Err(Error::Incompatible)
+        }
+    }
+
+    fn hash_bucket(&self, bucket: &[u8]) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        hasher.write(bucket);
+        hasher.finish()
+    }
+
+    /// XORs the hash into the bucket of the key and adjusts its item count, removing the bucket
+    /// once it is empty.
+    fn update_bucket(&self, txn: &mut RwTransaction, key: &[u8], hash: u64, count: i64) -> Result<()> {
+        let prefix = self.prefix(key);
+        let bucket = match txn.get(self.tree, &prefix) {
+            Ok(bucket) => {
+                self.check_bucket(bucket)?;
+                let mut buf = [0; BUCKET_LEN];
+                buf.copy_from_slice(bucket);
+                combine(&buf, hash, count)
+            },
+            Err(Error::NotFound) => combine(&self.empty_bucket(), hash, count),
+            Err(error) => return Err(error),
+        };
+        if bucket[9..] == [0; 8] {
+            txn.del(self.tree, &prefix, None)
+        } else {
+            txn.put(self.tree, &prefix, &bucket, WriteFlags::empty())
+        }
+    }
+}
+
+/// Returns the bucket record with the hash XORed in and the count adjusted.
+fn combine(bucket: &[u8; BUCKET_LEN], hash: u64, count: i64) -> [u8; BUCKET_LEN] {
+    let mut b8 = [0u8; 8];
+    b8.copy_from_slice(&bucket[1..9]);
+    let xor = u64::from_le_bytes(b8) ^ hash;
+    b8.copy_from_slice(&bucket[9..]);
+    let n = u64::from_le_bytes(b8).wrapping_add(count as u64);
+    let mut out = [0u8; BUCKET_LEN];
+    out[0] = bucket[0];
+    out[1..9].copy_from_slice(&xor.to_le_bytes());
+    out[9..].copy_from_slice(&n.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).open(dir.path()).unwrap();
+        let a = env.create_db(Some("a"), DatabaseFlags::empty()).unwrap();
+        let b = env.create_db(Some("b"), DatabaseFlags::empty()).unwrap();
+        let c = env.create_db(Some("c"), DatabaseFlags::empty()).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        // The same items, written in different orders.
+        for i in 0..100u32 {
+            txn.put(a, &i.to_be_bytes(), &format!("value{}", i), WriteFlags::empty()).unwrap();
+            txn.put(b, &(99 - i).to_be_bytes(), &format!("value{}", 99 - i), WriteFlags::empty()).unwrap();
+        }
+        // The same bytes, split differently between key and value.
+        txn.put(c, b"ab", b"c", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let digest_a = digest(&txn, a, DigestHasher::default()).unwrap();
+        // The digest is the same in every build.
+        assert_eq!(15654787582477274433, digest_a);
+        assert_eq!(digest_a, digest(&txn, b, DigestHasher::default()).unwrap());
+
+        txn.put(b, &5u32.to_be_bytes(), b"changed", WriteFlags::empty()).unwrap();
+        assert!(digest_a != digest(&txn, b, DigestHasher::default()).unwrap());
+
+        let digest_c = digest(&txn, c, DigestHasher::default()).unwrap();
+        txn.clear_db(c).unwrap();
+        txn.put(c, b"a", b"bc", WriteFlags::empty()).unwrap();
+        assert!(digest_c != digest(&txn, c, DigestHasher::default()).unwrap());
+    }
+
+    #[test]
+    fn test_digest_tree() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(4).open(dir.path()).unwrap();
+        let open = |name: &str| env.create_db(Some(name), DatabaseFlags::empty()).unwrap();
+        let (ours, theirs) = (DigestTree::new(open("a"), open("a-tree"), 2),
+                              DigestTree::new(open("b"), open("b-tree"), 2));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for tree in &[&ours, &theirs] {
+            for key in &["aa1", "aa2", "ab1", "b", "bb1"] {
+                tree.put(&mut txn, key, key, WriteFlags::empty()).unwrap();
+            }
+        }
+        assert_eq!(ours.root(&txn).unwrap(), theirs.root(&txn).unwrap());
+        assert_eq!(4, ours.buckets(&txn).unwrap().len());
+
+        theirs.put(&mut txn, b"ab1", b"changed", WriteFlags::empty()).unwrap();
+        theirs.del(&mut txn, b"b").unwrap();
+        theirs.put(&mut txn, b"cc1", b"new", WriteFlags::empty()).unwrap();
+        assert!(ours.root(&txn).unwrap() != theirs.root(&txn).unwrap());
+        {
+            let (a, b) = (ours.buckets(&txn).unwrap(), theirs.buckets(&txn).unwrap());
+            assert_eq!(vec![&b"ab"[..], b"b", b"cc"], DigestTree::diff(&a, &b));
+        }
+
+        // Undoing the changes restores the digest.
+        theirs.put(&mut txn, b"ab1", b"ab1", WriteFlags::empty()).unwrap();
+        theirs.put(&mut txn, b"b", b"b", WriteFlags::empty()).unwrap();
+        theirs.del(&mut txn, b"cc1").unwrap();
+        assert_eq!(ours.root(&txn).unwrap(), theirs.root(&txn).unwrap());
+        assert_eq!(Err(Error::NotFound), theirs.del(&mut txn, b"cc1"));
+
+        // Rebuilding picks up direct writes.
+        txn.put(ours.data(), b"zz", b"direct", WriteFlags::empty()).unwrap();
+        theirs.put(&mut txn, b"zz", b"direct", WriteFlags::empty()).unwrap();
+        assert!(ours.root(&txn).unwrap() != theirs.root(&txn).unwrap());
+        ours.rebuild(&mut txn).unwrap();
+        assert_eq!(ours.root(&txn).unwrap(), theirs.root(&txn).unwrap());
+
+        // A tree built with another hasher must be rebuilt.
+        let custom = BuildHasherDefault::<DefaultHasher>::default();
+        let other = DigestTree::with_hasher(ours.data(), ours.tree(), 2, custom);
+        assert_eq!(Err(Error::Incompatible), other.buckets(&txn));
+        assert_eq!(Err(Error::Incompatible), other.put(&mut txn, b"aa1", b"other", WriteFlags::empty()));
+        other.rebuild(&mut txn).unwrap();
+        assert_eq!(Err(Error::Incompatible), ours.root(&txn));
+
+        // Buckets must have non-empty prefixes.
+        let (data, tree) = (ours.data(), ours.tree());
+        assert!(::std::panic::catch_unwind(|| DigestTree::new(data, tree, 0)).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Reading and writing databases in the text format of the `mdb_dump` and `mdb_load` tools.
+
+use std::io::{self, BufRead, Write};
+use std::result;
+
+use database::Database;
+use environment::{Environment, MAIN_DBI};
+use error::{Error, LoadError};
+use flags::{DatabaseFlags, WriteFlags};
+use cursor::Cursor;
+use transaction::{RwTransaction, Transaction};
+
+/// The database flags recorded in the header, with their names.
+const DB_FLAGS: &[(DatabaseFlags, &str)] = &[
+    (DatabaseFlags::REVERSE_KEY, "reversekey"),
+    (DatabaseFlags::DUP_SORT, "dupsort"),
+    (DatabaseFlags::INTEGER_KEY, "integerkey"),
+    (DatabaseFlags::DUP_FIXED, "dupfixed"),
+    (DatabaseFlags::INTEGER_DUP, "integerdup"),
+    (DatabaseFlags::REVERSE_DUP, "reversedup"),
+];
+
+/// The number of items loaded per transaction, as with `mdb_load`.
+const COMMIT_INTERVAL: usize = 100;
+
+/// Writes the databases in the format of `mdb_dump`, as seen by the transaction.
+///
+/// Each database is written as a header, holding its name, its flags and the sizes of the
+/// environment, followed by its items as lines of hex-encoded keys and data items, as with the
+/// default `bytevalue` format. The output can be read by `load` and `mdb_load`.
+///
+/// The databases must be the main database, or opened by name with `Environment::open_db` or
+/// `Environment::create_db`, or `Error::Incompatible` is returned. The records of the given named
+/// databases in the main database are not written, since loading the databases recreates them.
+pub fn dump<T, W>(env: &Environment, txn: &T, dbs: &[Database], writer: &mut W) -> result::Result<(), LoadError>
+where T: Transaction, W: Write {
+    let page_size = env.stat()?.page_size();
+    let info = env.info()?;
+    let mut named = Vec::with_capacity(dbs.len());
+    for &db in dbs {
+        match env.db_name(db.dbi()) {
+            Some(name) => named.push((Some(name), db)),
+            None if db.dbi() == MAIN_DBI => named.push((None, db)),
+            None => return Err(LoadError::Lmdb(Error::Incompatible)),
+        }
+    }
+    let names = named.iter().filter_map(|(name, _)| name.clone()).collect::<Vec<_>>();
+
+    for (name, db) in &named {
+        let flags = txn.db_flags(*db)?;
+        writeln!(writer, "VERSION=3")?;
+        writeln!(writer, "format=bytevalue")?;
+        if let Some(name) = name {
+            writeln!(writer, "database={}", name)?;
+        }
+        writeln!(writer, "type=btree")?;
+        writeln!(writer, "mapsize={}", info.map_size())?;
+        writeln!(writer, "maxreaders={}", info.max_readers())?;
+        for &(flag, flag_name) in DB_FLAGS {
+            if flags.contains(flag) {
+                writeln!(writer, "{}=1", flag_name)?;
+            }
+        }
+        writeln!(writer, "db_pagesize={}", page_size)?;
+        writeln!(writer, "HEADER=END")?;
+        let mut cursor = txn.open_ro_cursor(*db)?;
+        for (key, data) in cursor.iter_start() {
+            if name.is_none() && names.iter().any(|name| name.as_bytes() == key) {
+                continue;
+            }
+            write_hex(writer, key)?;
+            write_hex(writer, data)?;
+        }
+        writeln!(writer, "DATA=END")?;
+    }
+    Ok(())
+}
+
+/// Reads databases in the format of `mdb_dump` into the environment, returning them in the
+/// order they were read.
+///
+/// Each database is created with the flags recorded in its header if it doesn't exist, and its
+/// items are written in transactions of 100 items, as `mdb_load` does, so that loading a large
+/// database does not hold all of its dirty pages in one transaction. If loading fails, the items
+/// of the transactions committed before remain. A database is loaded under its recorded
+/// name, or under `name` if it is given, as with `mdb_load -s`. If the environment resizes its
+/// own map, with `EnvironmentBuilder::set_resize_policy` or
+/// `EnvironmentBuilder::set_adopt_map_resize`, the map is first grown to the size recorded in the
+/// header, as `mdb_load` does. Only the default `bytevalue` format is supported. Malformed input
+/// fails with an I/O error of kind `InvalidData`, naming the line.
+pub fn load<R>(env: &Environment, reader: R, name: Option<&str>) -> result::Result<Vec<Database>, LoadError>
+where R: BufRead {
+    let mut lines = reader.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut dbs = Vec::new();
+    loop {
+        // Header.
+        let mut db_name = name.map(str::to_owned);
+        let mut flags = DatabaseFlags::empty();
+        let mut map_size = None;
+        let mut saw_header = false;
+        loop {
+            let (number, line) = match lines.next() {
+                Some((number, line)) => (number, line?),
+                None if saw_header => return Err(invalid(None, "unexpected end of input in header")),
+                None => return Ok(dbs),
+            };
+            saw_header = true;
+            if line == "HEADER=END" {
+                break;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Err(invalid(Some(number), "malformed header")),
+            };
+            match key {
+                "format" if value != "bytevalue" => return Err(invalid(Some(number), "unsupported format")),
+                "database" if name.is_none() => db_name = Some(value.to_owned()),
+                "mapsize" => map_size = Some(value.parse::<usize>()
+                                                  .map_err(|_| invalid(Some(number), "invalid mapsize"))?),
+                // Written by older versions of mdb_dump for DUP_SORT databases.
+                "duplicates" if value == "1" => flags |= DatabaseFlags::DUP_SORT,
+                _ => match DB_FLAGS.iter().find(|&&(_, flag_name)| flag_name == key) {
+                    Some(&(flag, _)) if value == "1" => flags |= flag,
+                    _ => (),
+                },
+            }
+        }
+        if let Some(map_size) = map_size {
+            env.grow_map_to(map_size)?;
+        }
+
+        // Data.
+        let db = env.create_db(db_name.as_deref(), flags)?;
+        loop {
+            let mut txn = env.begin_rw_txn()?;
+            match load_data(&mut txn, db, &mut lines) {
+                Ok(ended) => {
+                    txn.commit()?;
+                    if ended {
+                        break;
+                    }
+                },
+                Err(error) => {
+                    txn.abort();
+                    return Err(error);
+                },
+            }
+        }
+        dbs.push(db);
+    }
+}
+
+/// Writes up to `COMMIT_INTERVAL` items of a database, returning whether the `DATA=END` line was
+/// reached.
+fn load_data<I>(txn: &mut RwTransaction, db: Database, lines: &mut I) -> result::Result<bool, LoadError>
+where I: Iterator<Item = (usize, io::Result<String>)> {
+    for _ in 0..COMMIT_INTERVAL {
+        let (number, line) = match lines.next() {
+            Some((number, line)) => (number, line?),
+            None => return Err(invalid(None, "unexpected end of input in data")),
+        };
+        if line == "DATA=END" {
+            return Ok(true);
+        }
+        let key = read_hex(&line, number)?;
+        let data = match lines.next() {
+            Some((number, line)) => read_hex(&line?, number)?,
+            None => return Err(invalid(Some(number), "missing value")),
+        };
+        txn.put(db, &key, &data, WriteFlags::empty())?;
+    }
+    Ok(false)
+}
+
+/// Decodes a string of hex digits, or returns `None` if it has an odd length or any other
+/// character.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes().chunks(2).map(|pair| {
+        match *pair {
+            [high, low] => Some(((char::from(high).to_digit(16)? << 4) | char::from(low).to_digit(16)?) as u8),
+            _ => None,
+        }
+    }).collect()
+}
+
+fn write_hex<W>(writer: &mut W, bytes: &[u8]) -> io::Result<()> where W: Write {
+    writer.write_all(b" ")?;
+    for byte in bytes {
+        write!(writer, "{:02x}", byte)?;
+    }
+    writer.write_all(b"\n")
+}
+
+fn read_hex(line: &str, number: usize) -> result::Result<Vec<u8>, LoadError> {
+    let line = line.trim_end_matches('\r');
+    if !line.starts_with(' ') {
+        return Err(invalid(Some(number), "malformed data line"));
+    }
+    decode_hex(&line[1..]).ok_or_else(|| invalid(Some(number), "invalid hex"))
+}
+
+/// Returns the error of malformed input, at the line if it is known.
+fn invalid(number: Option<usize>, message: &str) -> LoadError {
+    let message = match number {
+        Some(number) => format!("line {}: {}", number, message),
+        None => message.to_owned(),
+    };
+    LoadError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::DroppedWrites;
+    use super::*;
+
+    #[test]
+    fn test_dump_and_load() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).set_map_size(1 << 20).open(dir.path()).unwrap();
+        let main = env.open_db(None).unwrap();
+        let tags = env.create_db(Some("tags"), DatabaseFlags::DUP_SORT).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(main, b"key", b"\x00\xff", WriteFlags::empty()).unwrap();
+        txn.put(tags, b"tag", b"a", WriteFlags::empty()).unwrap();
+        txn.put(tags, b"tag", b"b", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut out = Vec::new();
+        dump(&env, &txn, &[main, tags], &mut out).unwrap();
+        let page_size = env.stat().unwrap().page_size();
+        let expected = format!("VERSION=3\nformat=bytevalue\ntype=btree\nmapsize=1048576\nmaxreaders=126\n\
+                                db_pagesize={0}\nHEADER=END\n 6b6579\n 00ff\nDATA=END\n\
+                                VERSION=3\nformat=bytevalue\ndatabase=tags\ntype=btree\nmapsize=1048576\n\
+                                maxreaders=126\ndupsort=1\ndb_pagesize={0}\nHEADER=END\n 746167\n 61\n\
+                                \x20746167\n 62\nDATA=END\n",
+                               page_size);
+        assert_eq!(expected, String::from_utf8(out.clone()).unwrap());
+        drop(txn);
+
+        let load_dir = TempDir::new("load").unwrap();
+        let loaded = Environment::new().set_max_dbs(3)
+                                       .set_dropped_writes(DroppedWrites::Panic)
+                                       .open(load_dir.path())
+                                       .unwrap();
+        let dbs = load(&loaded, &out[..], None).unwrap();
+        let txn = loaded.begin_ro_txn().unwrap();
+        assert_eq!(b"\x00\xff", txn.get(dbs[0], b"key").unwrap());
+        assert_eq!(DatabaseFlags::DUP_SORT, txn.db_flags(dbs[1]).unwrap());
+        let mut cursor = txn.open_ro_cursor(dbs[1]).unwrap();
+        assert_eq!(vec![(&b"tag"[..], &b"a"[..]), (b"tag", b"b")], cursor.iter_start().collect::<Vec<_>>());
+        drop(cursor);
+        drop(txn);
+
+        // The name given replaces the recorded names.
+        let mut out = Vec::new();
+        dump(&env, &env.begin_ro_txn().unwrap(), &[tags], &mut out).unwrap();
+        let db = load(&loaded, &out[..], Some("copy")).unwrap()[0];
+        assert_eq!(b"a", loaded.begin_ro_txn().unwrap().get(db, b"tag").unwrap());
+        assert_eq!(Some("copy".to_owned()), loaded.db_name(db.dbi()));
+
+        match load(&loaded, &b"VERSION=3\nHEADER=END\n 6b6579\nvalue\n"[..], None) {
+            Err(LoadError::Io(error)) => assert_eq!("line 4: malformed data line", error.to_string()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // Only hex digits are accepted, although `u8::from_str_radix` would take a sign.
+        match load(&loaded, &b"VERSION=3\nHEADER=END\n 6b6579\n +f\nDATA=END\n"[..], None) {
+            Err(LoadError::Io(error)) => assert_eq!("line 4: invalid hex", error.to_string()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // The items written before a malformed line are aborted.
+        match load(&loaded, &b"VERSION=3\nHEADER=END\n 6b6579\n 00\n 6b\nvalue\n"[..], None) {
+            Err(LoadError::Io(error)) => assert_eq!("line 6: malformed data line", error.to_string()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(b"\x00\xff", loaded.begin_ro_txn().unwrap().get(dbs[0], b"key").unwrap());
+
+        // Items are committed in batches, which remain if a later batch fails.
+        let mut input = b"VERSION=3\ndatabase=batches\nHEADER=END\n".to_vec();
+        for i in 0..250u32 {
+            input.extend_from_slice(format!(" {:08x}\n 00\n", i).as_bytes());
+        }
+        let last_txn_id = loaded.info().unwrap().last_txnid();
+        let db = load(&loaded, &[&input[..], b"DATA=END\n"].concat()[..], None).unwrap()[0];
+        // The database is created in a transaction of its own.
+        assert_eq!(last_txn_id + 4, loaded.info().unwrap().last_txnid());
+        assert_eq!(250, loaded.begin_ro_txn().unwrap().open_ro_cursor(db).unwrap().iter_start().count());
+        let mut txn = loaded.begin_rw_txn().unwrap();
+        txn.clear_db(db).unwrap();
+        txn.commit().unwrap();
+        assert!(load(&loaded, &input[..], None).is_err());
+        assert_eq!(200, loaded.begin_ro_txn().unwrap().open_ro_cursor(db).unwrap().iter_start().count());
+
+        // Databases must be known by name.
+        let txn = env.begin_rw_txn().unwrap();
+        let unnamed = unsafe { txn.create_db(Some("unnamed"), DatabaseFlags::empty()).unwrap() };
+        match dump(&env, &txn, &[unnamed], &mut Vec::new()) {
+            Err(LoadError::Lmdb(Error::Incompatible)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
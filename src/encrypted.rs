@@ -0,0 +1,248 @@
+use std::error::Error as StdError;
+use std::{fmt, result};
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use error::Error;
+use flags::WriteFlags;
+use transact::DatabaseSet;
+use transaction::{RwTransaction, Transaction};
+
+/// An error from an encrypted database operation.
+#[derive(Debug)]
+pub enum EncryptedError {
+    /// An error returned by LMDB.
+    Lmdb(Error),
+    /// The value of the item with the key failed to decrypt, because it was tampered with, moved
+    /// from another key, or encrypted with another key.
+    Decrypt {
+        /// The key of the item.
+        key: Vec<u8>,
+    },
+}
+
+impl fmt::Display for EncryptedError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncryptedError::Lmdb(ref error) => error.fmt(fmt),
+            EncryptedError::Decrypt { ref key } => {
+                fmt.write_str("failed to decrypt the value of key ")?;
+                for byte in key {
+                    write!(fmt, "{:02x}", byte)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl StdError for EncryptedError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            EncryptedError::Lmdb(ref error) => Some(error),
+            EncryptedError::Decrypt { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for EncryptedError {
+    fn from(error: Error) -> EncryptedError {
+        EncryptedError::Lmdb(error)
+    }
+}
+
+/// An encrypted database result.
+pub type EncryptedResult<T> = result::Result<T, EncryptedError>;
+
+/// An authenticated cipher which encrypts the values of an `EncryptedDatabase`.
+///
+/// Implementations typically wrap an AEAD such as AES-GCM or ChaCha20-Poly1305, generating a
+/// fresh nonce for each value and storing it in front of the ciphertext. The key of the item is
+/// passed as associated data, so that a value copied to another key fails to decrypt.
+pub trait Cipher {
+
+    /// Encrypts the value of the item with the key.
+    fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Vec<u8>;
+
+    /// Decrypts the value of the item with the key, returning `None` if it fails to
+    /// authenticate.
+    fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// A database handle which encrypts values with a `Cipher` before they are written, and decrypts
+/// them when they are read.
+///
+/// Keys are stored in plain text, so that they remain ordered and can be looked up. Since
+/// encryption is not deterministic, the data items of a key in a database with
+/// `DatabaseFlags::DUP_SORT` are ordered by their ciphertext, and can't be deleted individually.
+///
+/// Requires the `encryption` feature.
+pub struct EncryptedDatabase<C> {
+    db: Database,
+    cipher: C,
+}
+
+impl <C> fmt::Debug for EncryptedDatabase<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("EncryptedDatabase").field("db", &self.db).finish()
+    }
+}
+
+impl <C> DatabaseSet for EncryptedDatabase<C> {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.push(self.db);
+    }
+}
+
+impl <C> EncryptedDatabase<C> where C: Cipher {
+
+    /// Wraps a database handle.
+    pub fn new(db: Database, cipher: C) -> EncryptedDatabase<C> {
+        EncryptedDatabase { db, cipher }
+    }
+
+    /// Returns the underlying database handle.
+    pub fn database(&self) -> Database {
+        self.db
+    }
+
+    /// Gets the decrypted value associated with the key.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the database.
+    pub fn get<T, K>(&self, txn: &T, key: &K) -> EncryptedResult<Vec<u8>>
+    where T: Transaction, K: AsRef<[u8]> {
+        let key = key.as_ref();
+        let ciphertext = txn.get(self.db, &key)?;
+        self.decrypt(key, ciphertext)
+    }
+
+    /// Encrypts the value, and stores it into the database.
+    pub fn put<K, D>(&self, txn: &mut RwTransaction, key: &K, data: &D, flags: WriteFlags) -> EncryptedResult<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let key = key.as_ref();
+        let ciphertext = self.cipher.encrypt(key, data.as_ref());
+        Ok(txn.put(self.db, &key, &ciphertext, flags)?)
+    }
+
+    /// Deletes the key, and all of its data items, from the database.
+    pub fn del<K>(&self, txn: &mut RwTransaction, key: &K) -> EncryptedResult<()> where K: AsRef<[u8]> {
+        Ok(txn.del(self.db, key, None)?)
+    }
+
+    /// Iterates over the decrypted items of the database, starting from the beginning.
+    pub fn iter<'txn, T>(&'txn self, txn: &'txn T) -> EncryptedResult<EncryptedIter<'txn, C>>
+    where T: Transaction {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_start());
+        Ok(EncryptedIter { iter, db: self })
+    }
+
+    /// Iterates over the decrypted items of the database, starting from the given key.
+    pub fn iter_from<'txn, T, K>(&'txn self, txn: &'txn T, key: &K) -> EncryptedResult<EncryptedIter<'txn, C>>
+    where T: Transaction, K: AsRef<[u8]> {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_from(key));
+        Ok(EncryptedIter { iter, db: self })
+    }
+
+    fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> EncryptedResult<Vec<u8>> {
+        self.cipher.decrypt(key, ciphertext).ok_or_else(|| EncryptedError::Decrypt { key: key.to_vec() })
+    }
+}
+
+/// An iterator over the decrypted items of an `EncryptedDatabase`.
+pub struct EncryptedIter<'txn, C: 'txn> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+    db: &'txn EncryptedDatabase<C>,
+}
+
+impl <'txn, C> fmt::Debug for EncryptedIter<'txn, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("EncryptedIter").finish()
+    }
+}
+
+impl <'txn, C> Iterator for EncryptedIter<'txn, C> where C: Cipher {
+
+    type Item = EncryptedResult<(&'txn [u8], Vec<u8>)>;
+
+    fn next(&mut self) -> Option<EncryptedResult<(&'txn [u8], Vec<u8>)>> {
+        self.iter.next().map(|(key, ciphertext)| {
+            self.db.decrypt(key, ciphertext).map(|plaintext| (key, plaintext))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use super::*;
+
+    /// A toy cipher, which XORs values with a keystream derived from the item key and appends a
+    /// checksum.
+    struct XorCipher(u8);
+
+    impl XorCipher {
+        fn apply(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+            let seed = key.iter().fold(self.0, |seed, &byte| seed.wrapping_mul(31).wrapping_add(byte));
+            data.iter().enumerate().map(|(i, &byte)| byte ^ seed.wrapping_add(i as u8)).collect()
+        }
+    }
+
+    impl Cipher for XorCipher {
+        fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Vec<u8> {
+            let mut ciphertext = self.apply(key, plaintext);
+            ciphertext.push(plaintext.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+            ciphertext
+        }
+
+        fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+            let (&checksum, ciphertext) = ciphertext.split_last()?;
+            let plaintext = self.apply(key, ciphertext);
+            if plaintext.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == checksum {
+                Some(plaintext)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypted_database() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let raw = env.open_db(None).unwrap();
+        let db = EncryptedDatabase::new(raw, XorCipher(7));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        db.put(&mut txn, b"key1", b"secret1", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"key2", b"secret2", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"key3", b"secret3", WriteFlags::empty()).unwrap();
+        assert_eq!(b"secret1".to_vec(), db.get(&txn, b"key1").unwrap());
+        assert!(txn.get(raw, b"key1").unwrap() != b"secret1");
+        db.del(&mut txn, b"key3").unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let items = db.iter(&txn).unwrap().collect::<EncryptedResult<Vec<_>>>().unwrap();
+        assert_eq!(vec![(&b"key1"[..], b"secret1".to_vec()), (b"key2", b"secret2".to_vec())], items);
+        let keys = db.iter_from(&txn, b"key2").unwrap().map(|item| item.unwrap().0).collect::<Vec<_>>();
+        assert_eq!(vec![b"key2"], keys);
+        match db.get(&txn, b"key3") {
+            Err(EncryptedError::Lmdb(Error::NotFound)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        drop(txn);
+
+        // A value copied to another key fails to decrypt.
+        let mut txn = env.begin_rw_txn().unwrap();
+        let ciphertext = txn.get(raw, b"key1").unwrap().to_vec();
+        txn.put(raw, b"key2", &ciphertext, WriteFlags::empty()).unwrap();
+        match db.get(&txn, b"key2") {
+            Err(EncryptedError::Decrypt { key }) => assert_eq!(b"key2", &key[..]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(db.iter(&txn).unwrap().nth(1).unwrap().is_err());
+    }
+}
@@ -1,38 +1,90 @@
-use libc::{c_uint, size_t};
-use std::{fmt, ptr, result, mem};
-use std::ffi::CString;
+use libc::{c_char, c_int, c_uint, c_void, size_t, EINVAL};
+use std::{fmt, process, ptr, result, mem};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::convert::{TryFrom, TryInto};
+use std::ffi::{CStr, CString};
+#[cfg(unix)]
+use std::ffi::OsStr;
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(unix)]
+use std::io::Write;
 #[cfg(windows)]
-use std::ffi::OsStr;
-use std::path::Path;
-use std::sync::Mutex;
+use std::str;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard};
+use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use ffi;
 
-use error::{Result, lmdb_result};
+use error::{Error, LoadError, Result, env_closed, lmdb_result};
+#[cfg(feature = "error-context")]
+use error::{ErrorContext, take_context};
+use cursor::Cursor;
 use database::Database;
-use transaction::{RoTransaction, RwTransaction, Transaction};
-use flags::{DatabaseFlags, EnvironmentFlags};
-
-#[cfg(windows)]
-/// Adding a 'missing' trait from windows OsStrExt
-trait OsStrExtLmdb {
-    fn as_bytes(&self) -> &[u8];
-}
-#[cfg(windows)]
-impl OsStrExtLmdb for OsStr {
-    fn as_bytes(&self) -> &[u8] {
-        &self.to_str().unwrap().as_bytes()
-    }
-}
+#[cfg(feature = "metrics")]
+use metrics::{Metrics, Recorder};
+use metrics::{self, Operation};
+use mirror::{MirrorSlot, MirroredRead};
+use notify::{CommitEvent, CommitSubscription, Subscribers};
+use quota::{Quota, Quotas};
+use readahead::SequentialScan;
+use stat::{DbiStats, EnvironmentInfo, ReaderInfo, ReaderLag, Stat};
+use trace;
+use tracking::{HandleKind, OpenHandle, Tracker};
+use transact::{DatabaseSet, TransactContext, TransactResult};
+use transaction::{RoTransaction, RwTransaction, SendableRoTransaction, Snapshot, Transaction};
+use weak::WeakEnvironment;
+use flags::{CopyFlags, DatabaseFlags, EnvironmentFlags, Instrumentation};
 
 /// An LMDB environment.
 ///
 /// An environment supports multiple databases, all residing in the same shared-memory map.
 pub struct Environment {
     env: *mut ffi::MDB_env,
-    dbi_open_mutex: Mutex<()>,
+    /// Serializes opening database handles.
+    open_lock: Mutex<()>,
+    /// Named database handles opened through the environment, with their names.
+    open_dbis: Mutex<HashMap<ffi::MDB_dbi, String>>,
+    /// The database recording the generation of each database, if generations are tracked.
+    generations: Option<Database>,
+    check_level: CheckLevel,
+    dropped_writes: DroppedWrites,
+    max_dbs: Option<c_uint>,
+    write_map: bool,
+    quotas: Mutex<Arc<Quotas>>,
+    sequential_scans: Mutex<usize>,
+    instrumentation: AtomicU32,
+    resize_policy: Option<ResizePolicy>,
+    adopt_map_resize: bool,
+    sync_period: Option<SyncPeriod>,
+    /// The number of stale reader slots cleared when the environment was opened, if checked.
+    stale_readers_cleared: Option<usize>,
+    /// The commits since the environment was last synced by the sync period or a barrier, and
+    /// when it was.
+    sync_state: Mutex<(u32, Instant)>,
+    /// The error of the last flush by the sync period, if it failed and no flush succeeded since.
+    sync_error: Mutex<Option<Error>>,
+    /// Held exclusively while a commit sets `EnvironmentFlags::NO_SYNC`, and shared by other
+    /// commits, so that they are not committed without syncing meanwhile.
+    no_sync_lock: RwLock<()>,
+    /// Held exclusively while the map is resized, and shared while transactions are begun.
+    resize_lock: RwLock<()>,
+    mirror: MirrorSlot,
+    #[cfg(feature = "metrics")]
+    recorder: Recorder,
+    tracker: Tracker,
+    /// Whether the handle was inherited by a forked process, so that it must not be closed.
+    inherited: AtomicBool,
+    subscribers: Subscribers,
 }
 
 impl Environment {
@@ -43,7 +95,18 @@ impl Environment {
             flags: EnvironmentFlags::empty(),
             max_readers: None,
             max_dbs: None,
-            map_size: None
+            map_size: None,
+            check_level: CheckLevel::Permissive,
+            dropped_writes: DroppedWrites::Abort,
+            track_generations: false,
+            resize_policy: None,
+            adopt_map_resize: false,
+            check_readers_on_open: false,
+            sync_period: None,
+            #[cfg(feature = "pagesize")]
+            page_size: None,
+            #[cfg(feature = "prevsnapshot")]
+            prev_snapshot: false,
         }
     }
 
@@ -55,6 +118,232 @@ impl Environment {
         self.env
     }
 
+    /// Returns the level of checking performed on writes in the environment.
+    pub fn check_level(&self) -> CheckLevel {
+        self.check_level
+    }
+
+    /// Returns what happens when a write transaction with writes is dropped without being
+    /// committed or aborted.
+    pub(crate) fn dropped_writes(&self) -> DroppedWrites {
+        self.dropped_writes
+    }
+
+    /// Returns whether the environment was opened with `EnvironmentFlags::WRITE_MAP`, which
+    /// does not support nested transactions.
+    pub(crate) fn write_map(&self) -> bool {
+        self.write_map
+    }
+
+    /// Returns a guard to hold while beginning a transaction, which prevents the map from being
+    /// resized meanwhile, or `None` if the map is never resized by the environment itself.
+    fn resize_guard(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        if self.resize_policy.is_some() || self.adopt_map_resize {
+            Some(self.resize_lock.read().unwrap_or_else(PoisonError::into_inner))
+        } else {
+            None
+        }
+    }
+
+    /// Begins a top-level transaction with the flags, adopting the size of the map and retrying
+    /// once if it was grown by another process and the environment is set to adopt it.
+    pub(crate) fn begin_txn(&self, flags: c_uint) -> Result<*mut ffi::MDB_txn> {
+        let begin = || {
+            let mut txn: *mut ffi::MDB_txn = ptr::null_mut();
+            let _guard = self.resize_guard();
+            unsafe { lmdb_result(ffi::mdb_txn_begin(self.env(), ptr::null_mut(), flags, &mut txn))? };
+            Ok(txn)
+        };
+        let operation = if flags & ffi::MDB_RDONLY != 0 { Operation::BeginRo } else { Operation::BeginRw };
+        metrics::timed(Some(self), operation, || match begin() {
+            Err(Error::MapResized) if self.adopt_map_resize => {
+                if !self.resize_map(0)? {
+                    return Err(Error::MapResized);
+                }
+                begin()
+            },
+            result => result,
+        })
+    }
+
+    /// Opens a new, independent handle to the environment in read-only mode.
+    ///
+    /// The new environment is opened at the same path with the flags, reader and database limits,
+    /// map size and check level of this environment, plus `EnvironmentFlags::READ_ONLY`. This is
+    /// intended for worker processes forked from the process which opened this environment: LMDB
+    /// does not support using an environment across `fork`, nor opening the same environment
+    /// twice in one process, so a forked worker should call this and use only the returned
+    /// environment.
+    pub fn reopen_readonly(&self) -> Result<Environment> {
+        let flags = (self.get_flags()?
+                     - EnvironmentFlags::WRITE_MAP
+                     - EnvironmentFlags::MAP_ASYNC)
+                    | EnvironmentFlags::READ_ONLY;
+        self.reopen_builder(flags)?.open(&self.path()?)
+    }
+
+    /// Opens a new handle to the environment in a process forked from the process which opened
+    /// this handle.
+    ///
+    /// The new environment is opened at the same path with the flags, reader and database limits,
+    /// map size and options of this environment. A forked process must not use the handle it
+    /// inherited, nor close it: closing it would release the reader slots of the parent process.
+    /// Once the environment is reopened, this handle is therefore marked so that dropping it
+    /// leaks it instead of closing it, and the child should use only the returned environment.
+    /// The reopened environment is independent of the parent's, so handles of named databases
+    /// must be opened again.
+    ///
+    /// Must only be called in a forked process, since the environment may not be opened twice
+    /// in one process.
+    pub fn reopen_after_fork(&self) -> Result<Environment> {
+        let env = self.reopen_builder(self.get_flags()?)?.open(&self.path()?)?;
+        self.inherited.store(true, Ordering::SeqCst);
+        Ok(env)
+    }
+
+    /// Returns a builder for reopening the environment with the flags.
+    fn reopen_builder(&self, flags: EnvironmentFlags) -> Result<EnvironmentBuilder> {
+        let mut builder = Environment::new();
+        builder.set_flags(flags)
+               .set_max_readers(self.max_readers()?)
+               .set_map_size(self.info()?.map_size())
+               .set_check_level(self.check_level)
+               .set_adopt_map_resize(self.adopt_map_resize)
+               .set_check_readers_on_open(self.stale_readers_cleared.is_some());
+        if let Some(max_dbs) = self.max_dbs {
+            builder.set_max_dbs(max_dbs);
+        }
+        if let Some(policy) = self.resize_policy {
+            builder.set_resize_policy(policy);
+        }
+        if let Some(period) = self.sync_period {
+            builder.set_sync_period(period);
+        }
+        builder.set_dropped_writes(self.dropped_writes);
+        builder.set_track_generations(self.generations.is_some());
+        Ok(builder)
+    }
+
+    /// Returns the path which the environment was opened with.
+    ///
+    /// This is the directory containing the data and lock files, or with
+    /// `EnvironmentFlags::NO_SUB_DIR` the data file itself.
+    pub fn path(&self) -> Result<PathBuf> {
+        let mut path: *const c_char = ptr::null();
+        let bytes = unsafe {
+            lmdb_result(ffi::mdb_env_get_path(self.env(), &mut path))?;
+            CStr::from_ptr(path).to_bytes()
+        };
+        #[cfg(unix)]
+        let path = OsStr::from_bytes(bytes);
+        #[cfg(windows)]
+        let path = str::from_utf8(bytes).map_err(|_| Error::Invalid)?;
+        Ok(PathBuf::from(path))
+    }
+
+    /// Hints that the environment is about to be read sequentially, until the returned guard is
+    /// dropped.
+    ///
+    /// See `SequentialScan` for details.
+    pub fn sequential_scan<'env>(&'env self) -> Result<SequentialScan<'env>> {
+        SequentialScan::new(self)
+    }
+
+    /// Returns the number of live `SequentialScan` guards.
+    pub(crate) fn sequential_scans(&self) -> &Mutex<usize> {
+        &self.sequential_scans
+    }
+
+    /// Sets the quota of a database, replacing any previous quota.
+    ///
+    /// Writes through `RwTransaction` and `RwCursor` which would exceed the quota fail with
+    /// `Error::QUOTA_EXCEEDED`. The quota applies to write transactions begun after the call.
+    pub fn set_quota(&self, db: Database, quota: Quota) {
+        let mut quotas = self.quotas.lock().unwrap();
+        Arc::make_mut(&mut quotas).insert(db.dbi(), quota);
+    }
+
+    /// Removes the quota of a database.
+    pub fn remove_quota(&self, db: Database) {
+        let mut quotas = self.quotas.lock().unwrap();
+        Arc::make_mut(&mut quotas).remove(&db.dbi());
+    }
+
+    /// Returns the quota of a database, if it has one.
+    pub fn quota(&self, db: Database) -> Option<Quota> {
+        self.quotas.lock().unwrap().get(&db.dbi()).cloned()
+    }
+
+    /// Mirrors every `every`th key read with `Transaction::get` to the callback, replacing any
+    /// previous mirror.
+    ///
+    /// Only keys are mirrored, with the handle of the database they were read from, including
+    /// keys which are not found. This lets an application replay production access patterns
+    /// against a shadow environment, for instance while migrating to a new data layout. The
+    /// callback is run on the reading thread while its transaction is open, so it should only
+    /// hand the read off, for example through a channel. While the environment does not mirror
+    /// reads, each read costs a single atomic load.
+    pub fn set_read_mirror<F>(&self, every: u32, callback: F)
+    where F: Fn(MirroredRead) + Send + Sync + 'static {
+        self.mirror.set(every, Box::new(callback));
+    }
+
+    /// Stops mirroring reads.
+    pub fn remove_read_mirror(&self) {
+        self.mirror.remove();
+    }
+
+    /// Returns the read mirror of the environment, which transactions pass their reads to.
+    pub(crate) fn read_mirror(&self) -> &MirrorSlot {
+        &self.mirror
+    }
+
+    /// Returns the recorder of the metrics of the environment's transactions and cursors.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    /// Returns the registry of the environment's open transactions and cursors.
+    pub(crate) fn tracker(&self) -> &Tracker {
+        &self.tracker
+    }
+
+    /// Calls the callback after each write transaction commits, with the databases it wrote.
+    ///
+    /// The callback is run on the committing thread, after the write lock is released, so it
+    /// should be quick, for example invalidating the cached data of the databases. Commits of
+    /// nested transactions, which may still be aborted with their parent, are not notified, and
+    /// their writes are not included in the notification of their parent.
+    pub fn subscribe_commits<F>(&self, callback: F) -> CommitSubscription
+    where F: Fn(&CommitEvent) + Send + Sync + 'static {
+        self.subscribers.subscribe(callback)
+    }
+
+    /// Returns a channel receiving an event after each write transaction commits.
+    ///
+    /// The channel is unsubscribed by the first commit after the receiver is dropped. See
+    /// `Environment::subscribe_commits`.
+    pub fn commit_channel(&self) -> Receiver<CommitEvent> {
+        self.subscribers.channel()
+    }
+
+    /// Removes a subscriber added with `Environment::subscribe_commits`, returning whether it
+    /// was subscribed.
+    pub fn unsubscribe_commits(&self, subscription: CommitSubscription) -> bool {
+        self.subscribers.unsubscribe(subscription)
+    }
+
+    pub(crate) fn commit_subscribers(&self) -> &Subscribers {
+        &self.subscribers
+    }
+
+    /// Returns the quotas for a new write transaction, or `None` if there are none.
+    pub(crate) fn quotas(&self) -> Option<Arc<Quotas>> {
+        let quotas = self.quotas.lock().unwrap();
+        if quotas.is_empty() { None } else { Some(quotas.clone()) }
+    }
+
     /// Opens a handle to an LMDB database.
     ///
     /// If `name` is `None`, then the returned handle will be for the default database.
@@ -70,11 +359,13 @@ impl Environment {
     ///
     /// The database name may not contain the null character.
     pub fn open_db<'env>(&'env self, name: Option<&str>) -> Result<Database> {
-        let mutex = self.dbi_open_mutex.lock();
+        let _open_lock = self.open_lock.lock().unwrap();
         let txn = self.begin_ro_txn()?;
         let db = unsafe { txn.open_db(name)? };
         txn.commit()?;
-        drop(mutex);
+        if let Some(name) = name {
+            self.opened_dbi(db, name);
+        }
         Ok(db)
     }
 
@@ -96,14 +387,100 @@ impl Environment {
                            name: Option<&str>,
                            flags: DatabaseFlags)
                            -> Result<Database> {
-        let mutex = self.dbi_open_mutex.lock();
+        let _open_lock = self.open_lock.lock().unwrap();
         let txn = self.begin_rw_txn()?;
         let db = unsafe { txn.create_db(name, flags)? };
         txn.commit()?;
-        drop(mutex);
+        if let Some(name) = name {
+            self.opened_dbi(db, name);
+        }
         Ok(db)
     }
 
+    /// Records the name of a database handle opened through the environment, and warns once
+    /// few handles remain.
+    fn opened_dbi(&self, db: Database, name: &str) {
+        let mut open_dbis = self.open_dbis.lock().unwrap_or_else(PoisonError::into_inner);
+        if open_dbis.insert(db.dbi(), name.to_owned()).is_none() {
+            let stats = DbiStats { open: open_dbis.len(), max: self.max_dbs.unwrap_or(0) as usize };
+            trace::dbi_opened(self.instrumentation().contains(Instrumentation::LOGGING), stats);
+        }
+    }
+
+    /// Returns the generation of the database, a counter which is incremented by every committed
+    /// write transaction which writes to the database.
+    ///
+    /// Caches of the database can record its generation, and later check cheaply whether it has
+    /// changed since. The generation of a database which was never written is 0. Generations are
+    /// only tracked for the main database and databases opened by name with
+    /// `Environment::open_db` or `Environment::create_db`, and for writes made through this
+    /// crate's safe APIs, including writes through a write cursor.
+    ///
+    /// Fails with `Error::Incompatible` if the environment was not opened with
+    /// `EnvironmentBuilder::set_track_generations`, with `Error::NotFound` if the generation of
+    /// the database is not tracked, and with `Error::BadRslot` if called by a thread which has an
+    /// ongoing transaction.
+    pub fn db_generation(&self, db: Database) -> Result<u64> {
+        let generations = self.generations.ok_or(Error::Incompatible)?;
+        let key = generation_key(db.dbi(), self.db_name(db.dbi()).as_deref())
+            .ok_or(Error::NotFound)?;
+        let txn = self.begin_ro_txn()?;
+        match txn.get(generations, &key) {
+            Ok(generation) => decode_generation(generation),
+            Err(Error::NotFound) => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the database recording generations, if they are tracked.
+    pub(crate) fn generations(&self) -> Option<Database> {
+        self.generations
+    }
+
+    /// Returns the name of the database handle, if it was opened by name through the environment.
+    pub(crate) fn db_name(&self, dbi: ffi::MDB_dbi) -> Option<String> {
+        self.open_dbis.lock().unwrap_or_else(PoisonError::into_inner).get(&dbi).cloned()
+    }
+
+    /// Returns the names of the named databases in the environment, in order.
+    ///
+    /// Named databases are recorded as items of the main database, which may also contain
+    /// regular data. Items whose values are not database records, or whose keys are not valid
+    /// database names, are skipped. Each candidate is confirmed by opening it in a short-lived
+    /// read-only transaction, so the returned names do not consume database handles.
+    ///
+    /// Checking a candidate which is not already open requires a free database handle, so this
+    /// function will fail with `Error::DbsFull` if every handle allowed by
+    /// `EnvironmentBuilder::set_max_dbs` is in use, or if the environment was not configured to
+    /// allow named databases. It will fail with `Error::BadRslot` if called by a thread which has
+    /// an ongoing transaction.
+    pub fn list_dbs(&self) -> Result<Vec<String>> {
+        // The size of an LMDB `MDB_db` record: two u16 flags and depth after a u32 of padding,
+        // four page and entry counts, and the root page number.
+        let record_size = 8 + 5 * mem::size_of::<size_t>();
+        let _open_lock = self.open_lock.lock();
+        let candidates = {
+            let txn = self.begin_ro_txn()?;
+            let main = unsafe { txn.open_db(None)? };
+            let mut cursor = txn.open_ro_cursor(main)?;
+            cursor.iter_start()
+                  .filter(|&(key, data)| data.len() == record_size && !key.contains(&0))
+                  .filter_map(|(key, _)| String::from_utf8(key.to_vec()).ok())
+                  .collect::<Vec<_>>()
+        };
+        let mut names = Vec::new();
+        for name in candidates {
+            // Handles opened in an aborted read-only transaction are closed.
+            let txn = self.begin_ro_txn()?;
+            match unsafe { txn.open_db(Some(&name)) } {
+                Ok(..) => names.push(name),
+                Err(Error::Incompatible) | Err(Error::NotFound) => (),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(names)
+    }
+
     /// Retrieves the set of flags which the database is opened with.
     ///
     /// The database must belong to to this environment.
@@ -121,12 +498,174 @@ impl Environment {
         RoTransaction::new(self)
     }
 
+    /// Create a read-only transaction which may be sent to another thread.
+    ///
+    /// Fails with `Error::Incompatible` unless the environment was opened with
+    /// `EnvironmentFlags::NO_TLS`.
+    pub fn begin_sendable_ro_txn<'env>(&'env self) -> Result<SendableRoTransaction<'env>> {
+        SendableRoTransaction::new(self)
+    }
+
+    /// Creates a long-lived read-only view of the latest committed state of the environment,
+    /// which can be refreshed without acquiring a new reader slot.
+    pub fn snapshot<'env>(&'env self) -> Result<Snapshot<'env>> {
+        Snapshot::new(self)
+    }
+
     /// Create a read-write transaction for use with the environment. This method will block while
     /// there are any other read-write transactions open on the environment.
     pub fn begin_rw_txn<'env>(&'env self) -> Result<RwTransaction<'env>> {
         RwTransaction::new(self)
     }
 
+    /// Runs `f` in a new read-write transaction and commits it, returning the result of `f`
+    /// and the ID of the committed transaction.
+    ///
+    /// Values computed by `f`, such as data read back after writing it, reflect the state the
+    /// transaction commits. The ID can be passed to `Environment::wait_for_txn` by readers, in
+    /// this or another process, which must not start reading until the write is visible. If `f`
+    /// fails, the transaction is aborted.
+    pub fn write_and_read<F, T>(&self, f: F) -> Result<(T, usize)>
+    where F: FnOnce(&mut RwTransaction) -> Result<T> {
+        let mut txn = self.begin_rw_txn()?;
+        let value = match f(&mut txn) {
+            Ok(value) => value,
+            Err(error) => {
+                txn.abort();
+                return Err(error);
+            },
+        };
+        let id = txn.id();
+        txn.commit()?;
+        Ok((value, id))
+    }
+
+    /// Runs `f` in a new read-write transaction and commits it, growing the memory map and
+    /// retrying when the transaction fails with `Error::MapFull`.
+    ///
+    /// The map is grown according to the policy set with `EnvironmentBuilder::set_resize_policy`.
+    /// Since the map may only be resized while no transaction is active in this process, the
+    /// failed transaction is aborted first, and the beginning of other transactions is held off
+    /// while the map is grown. If another transaction of this process is still active, including
+    /// a read-only transaction of the calling thread, the map is left as it is and `MapFull` is
+    /// returned. Without a resize policy, `f` is run once.
+    ///
+    /// `f` may be run several times, so it should not have side effects outside of the
+    /// transaction.
+    pub fn with_write_txn<F, T>(&self, mut f: F) -> Result<T>
+    where F: FnMut(&mut RwTransaction) -> Result<T> {
+        loop {
+            let mut txn = self.begin_rw_txn()?;
+            let result = match f(&mut txn) {
+                Ok(value) => txn.commit().map(|()| value),
+                Err(error) => {
+                    txn.abort();
+                    Err(error)
+                },
+            };
+            match (result, self.resize_policy) {
+                (Err(Error::MapFull), Some(policy)) => {
+                    if !self.grow_map(policy)? {
+                        return Err(Error::MapFull);
+                    }
+                },
+                (result, _) => return result,
+            }
+        }
+    }
+
+    /// Grows the map according to the policy, returning false if it can't be grown because a
+    /// transaction is active in this process.
+    fn grow_map(&self, policy: ResizePolicy) -> Result<bool> {
+        let map_size = self.info()?.map_size();
+        let page_size = self.stat()?.page_size() as usize;
+        let size = match policy {
+            ResizePolicy::GrowBy(bytes) => map_size.saturating_add(bytes),
+            ResizePolicy::GrowFactor(factor) => (map_size as f64 * factor) as usize,
+        };
+        let size = size.div_ceil(page_size).saturating_mul(page_size);
+        if size <= map_size {
+            return Ok(false);
+        }
+        self.resize_map(size)
+    }
+
+    /// Sets the size of the map, returning false if it can't be resized because a transaction
+    /// is active in this process.
+    fn resize_map(&self, size: size_t) -> Result<bool> {
+        let _guard = self.resize_lock.write().unwrap_or_else(PoisonError::into_inner);
+        let pid = process::id() as i32;
+        if self.reader_list()?.iter().any(|reader| reader.pid() == pid && reader.txn_id().is_some()) {
+            return Ok(false);
+        }
+        // Safe, since no read-only transaction is active in this process, none can begin while
+        // the lock is held, and LMDB fails with EINVAL if a write transaction is active.
+        match unsafe { self.set_map_size(size) } {
+            Ok(()) => Ok(true),
+            Err(Error::Other(EINVAL)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Grows the map to at least `size` if the environment resizes its map itself, so that the
+    /// map may be resized safely.
+    pub(crate) fn grow_map_to(&self, size: size_t) -> Result<()> {
+        if (self.resize_policy.is_some() || self.adopt_map_resize) && size > self.info()?.map_size() {
+            self.resize_map(size)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `f` in a new read-write transaction over the databases and commits it, reporting
+    /// which database and operation failed if it is aborted.
+    ///
+    /// The writes of `f` to every database commit together, or not at all. `f` is given the
+    /// handles in `dbs`, such as a tuple of typed handles, and accesses the databases through the
+    /// `TransactContext`, whose errors name the database and the operation, and which rejects
+    /// databases that are not in `dbs` with `Error::UNREGISTERED_DB`. If `f` or the commit fails,
+    /// the transaction is aborted.
+    pub fn transact_all<S, F, T>(&self, dbs: S, f: F) -> TransactResult<T>
+    where S: DatabaseSet, F: FnOnce(&mut TransactContext, S) -> TransactResult<T> {
+        let mut databases = Vec::new();
+        dbs.databases(&mut databases);
+        let mut ctx = TransactContext::new(self.begin_rw_txn()?, self, databases);
+        match f(&mut ctx, dbs) {
+            Ok(value) => ctx.commit().map(|()| value),
+            Err(error) => {
+                ctx.abort();
+                Err(error)
+            },
+        }
+    }
+
+    /// Returns the ID of the last committed transaction of the environment.
+    ///
+    /// Read-only transactions begun afterwards see the writes of every transaction up to this ID.
+    pub fn last_committed_txn_id(&self) -> Result<usize> {
+        Ok(self.info()?.last_txnid())
+    }
+
+    /// Waits up to `timeout` for the transaction with the given ID to be committed, and returns
+    /// whether it was.
+    ///
+    /// Once this returns true, read-only transactions begun afterwards see the writes of the
+    /// transaction.
+    pub fn wait_for_txn(&self, txn_id: usize, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            if self.last_committed_txn_id()? >= txn_id {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(Duration::from_millis(50));
+        }
+    }
+
     /// Flush data buffers to disk.
     ///
     /// Data is always written to disk when `Transaction::commit` is called, but the operating
@@ -138,6 +677,190 @@ impl Environment {
         }
     }
 
+    /// Makes every transaction committed before the call durable, including those committed
+    /// without syncing because of the sync period or with `RwTransaction::commit_no_sync`.
+    ///
+    /// This allows group durability, such as syncing once per batch of requests rather than per
+    /// commit: commits made with `RwTransaction::commit_no_sync` are acknowledged after a barrier.
+    /// The barrier restarts the sync period.
+    pub fn barrier(&self) -> Result<()> {
+        let mut state = self.sync_state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.sync(true)?;
+        *state = (0, Instant::now());
+        *self.sync_error.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        Ok(())
+    }
+
+    /// Returns the error of the last flush made by a commit at the end of the sync period, if it
+    /// failed and the environment has not been flushed since.
+    ///
+    /// A failed flush does not fail the commit which made it: the transaction is committed, but
+    /// not durable after a crash of the operating system until a flush succeeds. The flush is
+    /// retried by the next commit, and by `Environment::barrier`.
+    pub fn sync_error(&self) -> Option<Error> {
+        *self.sync_error.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Commits the top-level write transaction, without syncing the environment if `no_sync`.
+    pub(crate) fn commit_txn(&self, txn: *mut ffi::MDB_txn, no_sync: bool) -> Result<()> {
+        if !no_sync || self.sync_period.is_some() {
+            let _commit = self.no_sync_lock.read().unwrap_or_else(PoisonError::into_inner);
+            return unsafe { lmdb_result(ffi::mdb_txn_commit(txn)) };
+        }
+        let _commit = self.no_sync_lock.write().unwrap_or_else(PoisonError::into_inner);
+        let set = self.get_flags().and_then(|flags| {
+            let set = !flags.contains(EnvironmentFlags::NO_SYNC);
+            if set {
+                self.set_flags(EnvironmentFlags::NO_SYNC, true)?;
+            }
+            Ok(set)
+        });
+        let set = match set {
+            Ok(set) => set,
+            Err(error) => {
+                unsafe { ffi::mdb_txn_abort(txn) };
+                return Err(error);
+            },
+        };
+        let result = unsafe { lmdb_result(ffi::mdb_txn_commit(txn)) };
+        if set {
+            result.and(self.set_flags(EnvironmentFlags::NO_SYNC, false))
+        } else {
+            result
+        }
+    }
+
+    /// Counts a committed write transaction towards the sync period, flushing the environment
+    /// if the period has elapsed and the transaction was not committed with `no_sync`. A failed
+    /// flush is recorded for `Environment::sync_error`, and retried by the next commit.
+    pub(crate) fn committed(&self, no_sync: bool) {
+        let period = match self.sync_period {
+            Some(period) => period,
+            None if no_sync => {
+                self.sync_state.lock().unwrap_or_else(PoisonError::into_inner).0 += 1;
+                return;
+            },
+            None => return,
+        };
+        // The lock is held while flushing, so that concurrent commits don't flush again.
+        let mut state = self.sync_state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 += 1;
+        let due = match period {
+            SyncPeriod::Commits(commits) => state.0 >= commits,
+            SyncPeriod::Interval(interval) => state.1.elapsed() >= interval,
+        };
+        if due && !no_sync {
+            let mut sync_error = self.sync_error.lock().unwrap_or_else(PoisonError::into_inner);
+            match self.sync(true) {
+                Ok(()) => {
+                    *state = (0, Instant::now());
+                    *sync_error = None;
+                },
+                Err(error) => *sync_error = Some(error),
+            }
+        }
+    }
+
+    /// Sets or clears environment flags at runtime.
+    ///
+    /// Only `EnvironmentFlags::NO_SYNC`, `EnvironmentFlags::NO_META_SYNC`,
+    /// `EnvironmentFlags::MAP_ASYNC` and `EnvironmentFlags::NO_MEM_INIT` may be changed after the
+    /// environment is opened; LMDB fails with `EINVAL` for any other flag. The flags are set if
+    /// `onoff` is true, and cleared otherwise. They affect only this environment handle, not other
+    /// processes using the same environment.
+    pub fn set_flags(&self, flags: EnvironmentFlags, onoff: bool) -> Result<()> {
+        unsafe {
+            lmdb_result(ffi::mdb_env_set_flags(self.env(), flags.bits(), if onoff { 1 } else { 0 }))
+        }
+    }
+
+    /// Returns the flags of the environment, including those set with `Environment::set_flags`.
+    pub fn get_flags(&self) -> Result<EnvironmentFlags> {
+        let mut flags: c_uint = 0;
+        unsafe { lmdb_result(ffi::mdb_env_get_flags(self.env(), &mut flags))?; }
+        Ok(EnvironmentFlags::from_bits_truncate(flags))
+    }
+
+    /// Enables the given instrumentation subsystems, and disables the others.
+    ///
+    /// Takes effect immediately for every thread using the environment, including transactions
+    /// which are already open, so that visibility can be turned on temporarily, for example during
+    /// an incident, without reopening the environment.
+    pub fn set_instrumentation(&self, instrumentation: Instrumentation) {
+        self.instrumentation.store(instrumentation.bits(), Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.recorder.set_enabled(instrumentation.contains(Instrumentation::METRICS));
+    }
+
+    /// Returns the enabled instrumentation subsystems.
+    pub fn instrumentation(&self) -> Instrumentation {
+        Instrumentation::from_bits_truncate(self.instrumentation.load(Ordering::Relaxed))
+    }
+
+    /// Returns the transactions and cursors open in the environment, in the order they were
+    /// opened, with the threads which opened them.
+    ///
+    /// Only handles opened while `Instrumentation::TRACKING` is enabled are listed, such as to
+    /// find which thread holds the write lock, or which keeps an old snapshot alive. Unlike
+    /// `Environment::reader_list`, write transactions and cursors are listed too, but not the
+    /// transactions of other processes.
+    pub fn open_handles(&self) -> Vec<OpenHandle> {
+        self.tracker.handles()
+    }
+
+    /// Flushes the environment to disk and closes it.
+    ///
+    /// Dropping an environment closes it without reporting errors; this makes the final flush
+    /// explicit. Since transactions borrow the environment, none can be outstanding. To close an
+    /// environment shared between threads, first reclaim it with `Environment::drain`, whose
+    /// `DrainError` reports what still uses the environment if it can't be reclaimed.
+    pub fn close(self) -> Result<()> {
+        let mut flags: c_uint = 0;
+        unsafe { lmdb_result(ffi::mdb_env_get_flags(self.env(), &mut flags))?; }
+        if flags & ffi::MDB_RDONLY == 0 {
+            self.sync(true)?;
+        }
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for every other reference to a shared environment to be dropped,
+    /// for example by worker threads finishing their transactions, and returns the environment.
+    ///
+    /// If other references remain after the timeout, a `DrainError` is returned with the shared
+    /// environment and a report of what is still active, so that the caller can log it, and retry
+    /// or give up.
+    ///
+    /// Transactions borrow the environment, so a transaction of another thread keeps its clone of
+    /// the `Arc` alive; but a clone held without any open transaction, such as by a
+    /// `WriteScheduler`, a `CompactionScheduler` or an upgraded `WeakEnvironment`, delays the
+    /// drain just the same, until the timeout. The report lists the open transactions and cursors
+    /// only while `Instrumentation::TRACKING` is enabled. Transactions of other processes using the
+    /// same environment are not waited for.
+    pub fn drain(env: Arc<Environment>, timeout: Duration) -> result::Result<Environment, DrainError> {
+        let deadline = Instant::now() + timeout;
+        let mut env = env;
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            env = match Arc::try_unwrap(env) {
+                Ok(env) => return Ok(env),
+                Err(env) => env,
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DrainError::new(env));
+            }
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Returns a weak reference to a shared environment, which does not keep it open.
+    ///
+    /// See `WeakEnvironment` for details.
+    pub fn downgrade(env: &Arc<Environment>) -> WeakEnvironment {
+        WeakEnvironment::new(env)
+    }
+
     /// Closes the database handle. Normally unnecessary.
     ///
     /// Closing a database handle is not necessary, but lets `Transaction::open_database` reuse the
@@ -153,6 +876,64 @@ impl Environment {
     /// `Error::BadValSize` (since the DB name is gone).
     pub unsafe fn close_db(&mut self, db: Database) {
         ffi::mdb_dbi_close(self.env, db.dbi());
+        self.open_dbis.get_mut().unwrap_or_else(PoisonError::into_inner).remove(&db.dbi());
+    }
+
+    /// Returns the usage of the named database handles of the environment.
+    ///
+    /// Once every handle allowed by `EnvironmentBuilder::set_max_dbs` is in use, opening another
+    /// named database fails with `Error::DbsFull`, so `DbiStats::remaining` can be monitored to
+    /// detect handle leaks before then. Only handles opened with `Environment::open_db` and
+    /// `Environment::create_db` are counted. While `Instrumentation::LOGGING` is enabled, opening
+    /// a handle which leaves a tenth of them or fewer remaining also emits a `tracing` warning.
+    pub fn dbi_stats(&self) -> DbiStats {
+        DbiStats {
+            open: self.open_dbis.lock().unwrap_or_else(PoisonError::into_inner).len(),
+            max: self.max_dbs.unwrap_or(0) as usize,
+        }
+    }
+
+    /// Returns the context of the most recent operation in this environment which failed on the
+    /// current thread, and clears it.
+    ///
+    /// Returns `None` if no operation has failed since the context was last taken, or if the most
+    /// recent failure was in another environment. Failures of `Transaction::get`,
+    /// `RwTransaction::put`, `RwTransaction::reserve`, `RwTransaction::del` and `RwCursor::put`
+    /// are recorded, including `Error::NotFound`.
+    ///
+    /// Closing any environment discards the contexts recorded before it was closed, since an
+    /// environment opened later may reuse the address of the closed one.
+    #[cfg(feature = "error-context")]
+    pub fn last_error_context(&self) -> Option<ErrorContext> {
+        let open_dbis = self.open_dbis.lock().unwrap_or_else(PoisonError::into_inner);
+        take_context(self.env, |dbi| open_dbis.get(&dbi).cloned())
+    }
+
+    /// Returns the counts and latencies of the operations in this environment, recorded while
+    /// `Instrumentation::METRICS` is enabled.
+    ///
+    /// Transactions, `Transaction::get`, `RwTransaction::put`, `RwCursor::put`, and cursor
+    /// positioning and iteration are recorded, from every thread using the environment.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Metrics {
+        self.recorder.snapshot()
+    }
+
+    /// Returns the maximum size of a key, and of a data item in a database with duplicate data
+    /// items (`DatabaseFlags::DUP_SORT`).
+    ///
+    /// Writing a larger key fails with `Error::BadValSize`. The limit is fixed when LMDB is
+    /// compiled, and is 511 bytes by default.
+    pub fn max_key_size(&self) -> usize {
+        unsafe { ffi::mdb_env_get_maxkeysize(self.env()) as usize }
+    }
+
+    /// Returns the maximum number of concurrent read transactions, as set with
+    /// `EnvironmentBuilder::set_max_readers` by the process which created the environment.
+    pub fn max_readers(&self) -> Result<c_uint> {
+        let mut max_readers: c_uint = 0;
+        unsafe { lmdb_result(ffi::mdb_env_get_maxreaders(self.env(), &mut max_readers))?; }
+        Ok(max_readers)
     }
 
     /// Retrieves statistics about this environment.
@@ -163,49 +944,181 @@ impl Environment {
             Ok(stat)
         }
     }
-}
 
-/// Environment statistics.
-///
-/// Contains information about the size and layout of an LMDB environment.
-pub struct Stat(ffi::MDB_stat);
+    /// Returns the number of pages in the freelist, which are free for reuse by later writes.
+    ///
+    /// LMDB never shrinks its data file, so the ratio of free pages to
+    /// `EnvironmentInfo::last_pgno` is the fraction of the file which a copy made with
+    /// `CopyFlags::COMPACT` would leave out. This opens a read-only transaction.
+    pub fn free_pages(&self) -> Result<usize> {
+        let txn = self.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(Database::freelist())?;
+        // Each value is a list of page numbers, prefixed with its length.
+        Ok(cursor.iter_start().filter_map(|(_, data)| {
+            data.get(..mem::size_of::<usize>())
+                .and_then(|len| <[u8; mem::size_of::<usize>()]>::try_from(len).ok())
+                .map(usize::from_ne_bytes)
+        }).sum())
+    }
+
+    /// Retrieves information about this environment.
+    pub fn info(&self) -> Result<EnvironmentInfo> {
+        unsafe {
+            let mut info = EnvironmentInfo(mem::zeroed());
+            lmdb_try!(ffi::mdb_env_info(self.env(), &mut info.0));
+            Ok(info)
+        }
+    }
+
+    /// Clears stale entries from the reader lock table, returning the number of entries cleared.
+    ///
+    /// Entries are left behind by processes which exit without closing their read-only
+    /// transactions, for example after a crash. Stale entries prevent the reuse of pages
+    /// referenced by their snapshots, so the database grows until they are cleared.
+    pub fn reader_check(&self) -> Result<usize> {
+        let mut dead: c_int = 0;
+        unsafe { lmdb_result(ffi::mdb_reader_check(self.env(), &mut dead))? };
+        Ok(dead as usize)
+    }
+
+    /// Returns the number of stale entries cleared from the reader lock table when the
+    /// environment was opened, or `None` if it was opened without
+    /// `EnvironmentBuilder::set_check_readers_on_open`.
+    pub fn stale_readers_cleared(&self) -> Option<usize> {
+        self.stale_readers_cleared
+    }
+
+    /// Lists the entries of the reader lock table.
+    pub fn reader_list(&self) -> Result<Vec<ReaderInfo>> {
+        let mut lines: Vec<String> = Vec::new();
+        let func = reader_list_line as ffi::MDB_msg_func;
+        unsafe {
+            lmdb_result(ffi::mdb_reader_list(self.env(),
+                                             func as *mut ffi::MDB_msg_func,
+                                             &mut lines as *mut Vec<String> as *mut c_void))?;
+        }
+        // Each entry is formatted as "<pid> <thread in hex> <txn id or ->". Other lines, such as
+        // the header, are skipped.
+        Ok(lines.iter().filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse().ok()?;
+            let thread = usize::from_str_radix(fields.next()?, 16).ok()?;
+            let txn_id = match fields.next()? {
+                "-" => None,
+                txn_id => Some(txn_id.parse().ok()?),
+            };
+            Some(ReaderInfo { pid, thread, txn_id })
+        }).collect())
+    }
 
-impl Stat {
-    /// Size of a database page. This is the same for all databases in the environment.
-    #[inline]
-    pub fn page_size(&self) -> u32 {
-        self.0.ms_psize
+    /// Returns the lag of the oldest active reader behind the last committed transaction, from
+    /// every process using the environment.
+    ///
+    /// This is the metric to alert on before the freelist starves: see `ReaderLag`. Stale
+    /// entries left by crashed processes count as readers until they are cleared by
+    /// `Environment::reader_check`.
+    pub fn reader_lag(&self) -> Result<ReaderLag> {
+        // The reader list is read first, so that its snapshots are no newer than the last
+        // transaction.
+        let readers = self.reader_list()?;
+        let last_txn_id = self.info()?.last_txnid();
+        let oldest_txn_id = readers.iter().filter_map(ReaderInfo::txn_id).min();
+        Ok(ReaderLag { oldest_txn_id, last_txn_id })
     }
 
-    /// Depth (height) of the B-tree.
-    #[inline]
-    pub fn depth(&self) -> u32 {
-        self.0.ms_depth
+    /// Sets the size of the memory map of the open environment.
+    ///
+    /// The size should be a multiple of the OS page size. It may be used to grow the map after
+    /// `Error::MapFull`, without reopening the environment. A size of zero adopts the current size
+    /// of the environment, which is required after another process has grown the map and
+    /// operations in this process fail with `Error::MapResized`.
+    ///
+    /// Any attempt to set a size smaller than the space already consumed by the environment will
+    /// be silently changed to the current size of the used space. The new size is persisted when
+    /// the next write transaction commits.
+    ///
+    /// ## Safety
+    ///
+    /// The memory map is moved, so this **must not** be called while any transaction is active
+    /// in this process, including read-only transactions and any data borrowed from them. LMDB
+    /// only detects an active write transaction, which fails with `Error::Other(EINVAL)`.
+    pub unsafe fn set_map_size(&self, size: size_t) -> Result<()> {
+        lmdb_result(ffi::mdb_env_set_mapsize(self.env(), size))
     }
 
-    /// Number of internal (non-leaf) pages.
-    #[inline]
-    pub fn branch_pages(&self) -> usize {
-        self.0.ms_branch_pages
+    /// Copies the environment to the given path.
+    ///
+    /// The path must be an existing, empty directory, or the path of the new data file if the
+    /// environment was opened with `EnvironmentFlags::NO_SUB_DIR`. The copy is a consistent
+    /// snapshot taken with a read-only transaction, so it may be made while the environment is
+    /// in use.
+    pub fn copy(&self, path: &Path, flags: CopyFlags) -> Result<()> {
+        let path = path_to_cstring(path)?;
+        unsafe {
+            lmdb_result(ffi::mdb_env_copy2(self.env(), path.as_ptr(), flags.bits()))
+        }
     }
 
-    /// Number of leaf pages.
-    #[inline]
-    pub fn leaf_pages(&self) -> usize {
-        self.0.ms_leaf_pages
+    /// Copies the environment into the writer, returning the number of bytes written.
+    ///
+    /// The bytes are those of the data file of a copy made with `Environment::copy`, so a backup
+    /// may be streamed to a socket or an upload without a temporary file. LMDB writes the copy
+    /// into a pipe from another thread, and the bytes are read from the pipe and written into
+    /// the writer on this thread, as they are produced.
+    ///
+    /// If the writer fails, the rest of the copy is still read from the pipe and discarded before
+    /// the error is returned.
+    #[cfg(unix)]
+    pub fn copy_to_writer<W>(&self, writer: &mut W, flags: CopyFlags) -> result::Result<u64, LoadError>
+    where W: Write {
+        let (mut source, sink) = cloexec_pipe().map_err(LoadError::Io)?;
+        thread::scope(|scope| {
+            let copy = scope.spawn(move || {
+                // The write end is closed once the copy is done, ending the read.
+                unsafe { lmdb_result(ffi::mdb_env_copyfd2(self.env(), sink.as_raw_fd(), flags.bits())) }
+            });
+            let written = io::copy(&mut source, writer);
+            if written.is_err() {
+                // Closing the read end instead would raise SIGPIPE in the copying thread, which
+                // terminates the process unless the signal is ignored.
+                let _ = io::copy(&mut source, &mut io::sink());
+            }
+            let copied = copy.join().unwrap_or_else(|panic| ::std::panic::resume_unwind(panic));
+            let written = written.map_err(LoadError::Io)?;
+            copied.map_err(LoadError::Lmdb)?;
+            Ok(written)
+        })
     }
+}
 
-    /// Number of overflow pages.
-    #[inline]
-    pub fn overflow_pages(&self) -> usize {
-        self.0.ms_overflow_pages
+/// Creates a pipe, returning its read and write ends, which are closed in child processes on
+/// exec, so that a child spawned during a copy does not hold the write end open.
+#[cfg(unix)]
+fn cloexec_pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    // Apple platforms lack `pipe2`, so the flag is set once the pipe is created.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let result = unsafe {
+        match libc::pipe(fds.as_mut_ptr()) {
+            0 => fds.iter().map(|&fd| libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC)).min().unwrap(),
+            result => result,
+        }
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
     }
+    unsafe { Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))) }
+}
 
-    /// Number of data items.
-    #[inline]
-    pub fn entries(&self) -> usize {
-        self.0.ms_entries
+/// Collects the lines written by `mdb_reader_list` into the `Vec<String>` passed as context.
+extern "C" fn reader_list_line(msg: *const c_char, ctx: *mut c_void) -> c_int {
+    unsafe {
+        let lines = &mut *(ctx as *mut Vec<String>);
+        lines.push(CStr::from_ptr(msg).to_string_lossy().into_owned());
     }
+    0
 }
 
 unsafe impl Send for Environment {}
@@ -217,47 +1130,162 @@ impl fmt::Debug for Environment {
     }
 }
 
-impl Drop for Environment {
-    fn drop(&mut self) {
-        unsafe { ffi::mdb_env_close(self.env) }
-    }
+/// The error of `Environment::drain` when other references to the environment remain after the
+/// timeout, with the shared environment and a report of what still uses it.
+#[derive(Debug)]
+pub struct DrainError {
+    env: Arc<Environment>,
+    strong_refs: usize,
+    readers: Vec<ReaderInfo>,
+    open_handles: Vec<OpenHandle>,
+    write_txn_held: Option<bool>,
 }
 
-///////////////////////////////////////////////////////////////////////////////////////////////////
-//// Environment Builder
-///////////////////////////////////////////////////////////////////////////////////////////////////
+impl DrainError {
 
-/// Options for opening or creating an environment.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-pub struct EnvironmentBuilder {
-    flags: EnvironmentFlags,
-    max_readers: Option<c_uint>,
-    max_dbs: Option<c_uint>,
-    map_size: Option<size_t>,
-}
+    fn new(env: Arc<Environment>) -> DrainError {
+        let tracking = env.instrumentation().contains(Instrumentation::TRACKING);
+        let open_handles = env.open_handles();
+        DrainError {
+            strong_refs: Arc::strong_count(&env) - 1,
+            readers: env.reader_list().unwrap_or_default(),
+            write_txn_held: if tracking {
+                Some(open_handles.iter().any(|handle| handle.kind() == HandleKind::RwTransaction))
+            } else {
+                None
+            },
+            open_handles,
+            env,
+        }
+    }
 
-impl EnvironmentBuilder {
+    /// The shared environment, to retry with.
+    pub fn env(&self) -> &Arc<Environment> {
+        &self.env
+    }
 
-    /// Open an environment.
-    ///
-    /// On UNIX, the database files will be opened with 644 permissions.
-    ///
-    /// The path may not contain the null character, Windows UNC (Uniform Naming Convention)
-    /// paths are not supported either.
-    pub fn open(&self, path: &Path) -> Result<Environment> {
+    /// Returns the shared environment, to retry with.
+    pub fn into_env(self) -> Arc<Environment> {
+        self.env
+    }
+
+    /// The number of other references to the environment at the timeout.
+    pub fn strong_refs(&self) -> usize {
+        self.strong_refs
+    }
+
+    /// The entries of the reader lock table at the timeout, including those of other processes,
+    /// or none if it could not be read.
+    pub fn readers(&self) -> &[ReaderInfo] {
+        &self.readers
+    }
+
+    /// The transactions and cursors open at the timeout, as listed by
+    /// `Environment::open_handles`.
+    pub fn open_handles(&self) -> &[OpenHandle] {
+        &self.open_handles
+    }
+
+    /// Whether a write transaction of this process was open at the timeout, or `None` if
+    /// `Instrumentation::TRACKING` was disabled, so that it is not known.
+    pub fn write_txn_held(&self) -> Option<bool> {
+        self.write_txn_held
+    }
+}
+
+impl fmt::Display for DrainError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt,
+               "environment still has {} other references, {} readers and {} tracked handles",
+               self.strong_refs,
+               self.readers.len(),
+               self.open_handles.len())?;
+        if self.write_txn_held == Some(true) {
+            write!(fmt, ", including a write transaction")?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for DrainError {}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        env_closed(self.env);
+        if !self.inherited.load(Ordering::SeqCst) {
+            let pending = self.sync_state.get_mut().unwrap_or_else(PoisonError::into_inner).0;
+            if pending > 0 {
+                let _ = self.sync(true);
+            }
+            unsafe { ffi::mdb_env_close(self.env) }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+//// Environment Builder
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Options for opening or creating an environment.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct EnvironmentBuilder {
+    flags: EnvironmentFlags,
+    max_readers: Option<c_uint>,
+    max_dbs: Option<c_uint>,
+    map_size: Option<size_t>,
+    check_level: CheckLevel,
+    dropped_writes: DroppedWrites,
+    track_generations: bool,
+    resize_policy: Option<ResizePolicy>,
+    adopt_map_resize: bool,
+    check_readers_on_open: bool,
+    sync_period: Option<SyncPeriod>,
+    #[cfg(feature = "pagesize")]
+    page_size: Option<u32>,
+    #[cfg(feature = "prevsnapshot")]
+    prev_snapshot: bool,
+}
+
+impl EnvironmentBuilder {
+
+    /// Open an environment.
+    ///
+    /// On UNIX, the database files will be opened with 644 permissions. Use
+    /// `EnvironmentBuilder::open_with_permissions` to choose other permissions.
+    ///
+    /// The path may not contain the null character, Windows UNC (Uniform Naming Convention)
+    /// paths are not supported either. On Windows, the path must be valid Unicode.
+    pub fn open(&self, path: &Path) -> Result<Environment> {
         self.open_with_permissions(path, 0o644)
     }
 
     /// Open an environment with the provided UNIX permissions.
     ///
-    /// On Windows, the permissions will be ignored.
+    /// On Windows, the permissions will be ignored, so `EnvironmentBuilder::open` is equivalent.
     ///
     /// The path may not contain the null character, Windows UNC (Uniform Naming Convention)
-    /// paths are not supported either.
+    /// paths are not supported either. On Windows, the path must be valid Unicode.
     pub fn open_with_permissions(&self, path: &Path, mode: ffi::mode_t) -> Result<Environment> {
+        #[cfg(feature = "pagesize")]
+        {
+            if let Some(page_size) = self.page_size {
+                if !page_size.is_power_of_two()
+                    || page_size < MIN_PAGE_SIZE
+                    || page_size > MAX_PAGE_SIZE {
+                    return Err(::Error::Other(EINVAL));
+                }
+            }
+        }
         let mut env: *mut ffi::MDB_env = ptr::null_mut();
         unsafe {
             lmdb_try!(ffi::mdb_env_create(&mut env));
+            #[cfg(feature = "pagesize")]
+            {
+                if let Some(page_size) = self.page_size {
+                    lmdb_try_with_cleanup!(ffi::mdb_env_set_pagesize(env, page_size as c_int),
+                                           ffi::mdb_env_close(env))
+                }
+            }
             if let Some(max_readers) = self.max_readers {
                 lmdb_try_with_cleanup!(ffi::mdb_env_set_maxreaders(env, max_readers),
                                        ffi::mdb_env_close(env))
@@ -270,15 +1298,93 @@ impl EnvironmentBuilder {
                 lmdb_try_with_cleanup!(ffi::mdb_env_set_mapsize(env, map_size),
                                        ffi::mdb_env_close(env))
             }
-            let path = match CString::new(path.as_os_str().as_bytes()) {
+            let path = match path_to_cstring(path) {
                 Ok(path) => path,
-                Err(..) => return Err(::Error::Invalid),
+                Err(error) => {
+                    ffi::mdb_env_close(env);
+                    return Err(error);
+                },
             };
-            lmdb_try_with_cleanup!(ffi::mdb_env_open(env, path.as_ptr(), self.flags.bits(), mode),
+            #[cfg_attr(not(feature = "prevsnapshot"), allow(unused_mut))]
+            let mut flags = self.flags.bits();
+            if self.sync_period.is_some() {
+                flags |= ffi::MDB_NOSYNC;
+            }
+            #[cfg(feature = "prevsnapshot")]
+            {
+                if self.prev_snapshot {
+                    flags |= ffi::MDB_PREVSNAPSHOT | ffi::MDB_RDONLY;
+                }
+            }
+            lmdb_try_with_cleanup!(ffi::mdb_env_open(env, path.as_ptr(), flags, mode),
                                    ffi::mdb_env_close(env));
         }
-        Ok(Environment { env: env, dbi_open_mutex: Mutex::new(()) })
+        let mut env = Environment {
+            env: env,
+            open_lock: Mutex::new(()),
+            open_dbis: Mutex::new(HashMap::new()),
+            generations: None,
+            check_level: self.check_level,
+            dropped_writes: self.dropped_writes,
+            max_dbs: self.max_dbs,
+            write_map: self.flags.contains(EnvironmentFlags::WRITE_MAP),
+            quotas: Mutex::new(Arc::new(Quotas::new())),
+            sequential_scans: Mutex::new(0),
+            instrumentation: AtomicU32::new(Instrumentation::empty().bits()),
+            resize_policy: self.resize_policy,
+            adopt_map_resize: self.adopt_map_resize,
+            sync_period: self.sync_period,
+            stale_readers_cleared: None,
+            sync_state: Mutex::new((0, Instant::now())),
+            sync_error: Mutex::new(None),
+            no_sync_lock: RwLock::new(()),
+            resize_lock: RwLock::new(()),
+            mirror: MirrorSlot::default(),
+            #[cfg(feature = "metrics")]
+            recorder: Recorder::default(),
+            tracker: Tracker::default(),
+            inherited: AtomicBool::new(false),
+            subscribers: Subscribers::default(),
+        };
+        if self.check_readers_on_open {
+            env.stale_readers_cleared = Some(env.reader_check()?);
+        }
+        if self.track_generations {
+            env.generations = Some(if self.flags.contains(EnvironmentFlags::READ_ONLY) {
+                env.open_db(Some(GENERATIONS_DB))?
+            } else {
+                env.create_db(Some(GENERATIONS_DB), DatabaseFlags::empty())?
+            });
+        }
+        Ok(env)
+    }
 
+    /// Restores an environment from a backup read from the reader, such as one written by
+    /// `Environment::copy_to_writer`, and opens it.
+    ///
+    /// The data file is written into the directory at the path, or to the path itself with
+    /// `EnvironmentFlags::NO_SUB_DIR`, and must not exist yet. It is synced to disk, and its meta
+    /// pages are validated before the environment is opened: a backup which is not an LMDB data
+    /// file, or which was truncated within its meta pages, fails with `Error::Invalid`, and the
+    /// data file is removed.
+    pub fn restore_from_reader<R>(&self, reader: &mut R, path: &Path) -> result::Result<Environment, LoadError>
+    where R: Read {
+        let data_path = if self.flags.contains(EnvironmentFlags::NO_SUB_DIR) {
+            path.to_path_buf()
+        } else {
+            path.join("data.mdb")
+        };
+        let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&data_path)?;
+        let restored = io::copy(reader, &mut file)
+            .and_then(|_| file.sync_all())
+            .map_err(LoadError::Io)
+            .and_then(|()| validate_meta_pages(&mut file));
+        drop(file);
+        if let Err(error) = restored {
+            let _ = fs::remove_file(&data_path);
+            return Err(error);
+        }
+        Ok(self.open(path)?)
     }
 
     /// Sets the provided options in the environment.
@@ -327,6 +1433,318 @@ impl EnvironmentBuilder {
         self.map_size = Some(map_size);
         self
     }
+
+    /// Sets the level of checking performed on writes before they are passed to LMDB.
+    ///
+    /// Defaults to `CheckLevel::Permissive`.
+    pub fn set_check_level(&mut self, check_level: CheckLevel) -> &mut EnvironmentBuilder {
+        self.check_level = check_level;
+        self
+    }
+
+    /// Sets what happens in debug builds when a write transaction which made writes is dropped
+    /// without calling `Transaction::commit` or `Transaction::abort`, which silently discards
+    /// the writes.
+    ///
+    /// Defaults to `DroppedWrites::Abort`. In release builds, such transactions are always
+    /// aborted silently.
+    pub fn set_dropped_writes(&mut self, dropped_writes: DroppedWrites) -> &mut EnvironmentBuilder {
+        self.dropped_writes = dropped_writes;
+        self
+    }
+
+    /// Sets whether to track the generation of each database, returned by
+    /// `Environment::db_generation`.
+    ///
+    /// The generations are stored in a named database called `__generations`, which is created
+    /// when the environment is opened, so the environment must allow a named database for it
+    /// through `EnvironmentBuilder::set_max_dbs`. Defaults to false.
+    pub fn set_track_generations(&mut self, track_generations: bool) -> &mut EnvironmentBuilder {
+        self.track_generations = track_generations;
+        self
+    }
+
+    /// Sets how `Environment::with_write_txn` grows the memory map when a write transaction
+    /// fails with `Error::MapFull`.
+    ///
+    /// Setting a policy makes beginning a transaction take a shared lock, which is held off
+    /// while the map is grown. By default there is no policy, and the map is never grown.
+    pub fn set_resize_policy(&mut self, policy: ResizePolicy) -> &mut EnvironmentBuilder {
+        self.resize_policy = Some(policy);
+        self
+    }
+
+    /// Sets whether beginning a transaction adopts the size of the map after another process
+    /// has grown it, rather than failing with `Error::MapResized`.
+    ///
+    /// The new size is adopted as with `Environment::set_map_size(0)`, and the transaction is
+    /// begun again. Since the map may only be resized while no transaction is active in this
+    /// process, beginning a transaction takes a shared lock which is held off while the map is
+    /// resized, and `MapResized` is still returned while other transactions of this process are
+    /// active. Defaults to false.
+    pub fn set_adopt_map_resize(&mut self, adopt_map_resize: bool) -> &mut EnvironmentBuilder {
+        self.adopt_map_resize = adopt_map_resize;
+        self
+    }
+
+    /// Sets whether stale entries are cleared from the reader lock table when the environment is
+    /// opened, as with `Environment::reader_check`.
+    ///
+    /// A process restarting after a crash thereby clears the entries it left behind, as well as
+    /// those of other crashed processes, which otherwise stay until every process closes the
+    /// environment, since LMDB resets the reader lock table when the environment is opened by the
+    /// only process using it. The number cleared is returned by
+    /// `Environment::stale_readers_cleared`. Defaults to false.
+    pub fn set_check_readers_on_open(&mut self, check_readers_on_open: bool) -> &mut EnvironmentBuilder {
+        self.check_readers_on_open = check_readers_on_open;
+        self
+    }
+
+    /// Sets how often the environment is flushed to disk by write transactions, trading
+    /// durability for commit throughput.
+    ///
+    /// The environment is opened with `EnvironmentFlags::NO_SYNC`, and a commit flushes it with
+    /// `Environment::sync` when the period has elapsed. The commits since the last flush are
+    /// durable after a crash of the application, but may be lost on a crash of the operating
+    /// system or a power failure, without corrupting the environment. Dropping the environment
+    /// flushes the commits made since the last flush. By default, every commit is flushed.
+    ///
+    /// Only commits flush the environment; there is no background timer. With
+    /// `SyncPeriod::Interval`, the last commits before a pause in writing stay unflushed until
+    /// the next commit, so an application which needs them flushed within the interval should
+    /// also call `Environment::barrier` periodically. A failed flush does not fail the commit
+    /// which made it, and is reported by `Environment::sync_error` instead.
+    pub fn set_sync_period(&mut self, period: SyncPeriod) -> &mut EnvironmentBuilder {
+        self.sync_period = Some(period);
+        self
+    }
+
+    /// Sets the page size to use when creating the environment.
+    ///
+    /// The page size must be a power of two between `MIN_PAGE_SIZE` and `MAX_PAGE_SIZE`,
+    /// otherwise opening the environment will fail with `EINVAL`. The default is the OS page
+    /// size. Larger pages reduce the number of overflow pages needed to store large values.
+    ///
+    /// The page size is stored persistently when the environment is created. When opening an
+    /// existing environment this setting has no effect; use `Environment::stat` to retrieve the
+    /// page size actually in use.
+    ///
+    /// Requires the `pagesize` feature and a liblmdb build providing `mdb_env_set_pagesize`.
+    #[cfg(feature = "pagesize")]
+    pub fn set_page_size(&mut self, page_size: u32) -> &mut EnvironmentBuilder {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Opens the environment at its previous snapshot instead of the latest one.
+    ///
+    /// LMDB keeps two meta pages, the latest committed transaction and the one before it.
+    /// Opening the previous snapshot makes the state before the last commit visible, which can
+    /// be used to recover data after an accidental destructive write, or to work around some
+    /// kinds of corruption of the latest meta page.
+    ///
+    /// The environment is always opened with `EnvironmentFlags::READ_ONLY` when this option is
+    /// set, so the latest snapshot is left intact.
+    ///
+    /// ## Warning
+    ///
+    /// Only the state as of the transaction committed before the latest one is recoverable.
+    /// Every further commit to the environment, including by other processes, replaces the
+    /// previous snapshot. Stop all writers and copy the environment before attempting recovery.
+    ///
+    /// Requires the `prevsnapshot` feature and a liblmdb build supporting `MDB_PREVSNAPSHOT`.
+    #[cfg(feature = "prevsnapshot")]
+    pub fn set_prev_snapshot(&mut self, prev_snapshot: bool) -> &mut EnvironmentBuilder {
+        self.prev_snapshot = prev_snapshot;
+        self
+    }
+}
+
+/// How thoroughly writes are checked before they are passed to LMDB.
+///
+/// LMDB trusts its callers with some invariants, such as the size of keys in
+/// `DatabaseFlags::INTEGER_KEY` databases, and silently ignores write flags which don't apply to
+/// the database. Depending on the liblmdb version, the ordering of keys written with
+/// `WriteFlags::APPEND` may not be checked either. Strict checking verifies these invariants on
+/// every write, at the cost of extra lookups, so it is best suited to development and testing.
+/// Thread affinity of transactions needs no runtime check, since transactions and cursors are
+/// not `Send`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CheckLevel {
+    /// Writes are passed straight to LMDB.
+    #[default]
+    Permissive,
+    /// Before each write through `RwTransaction::put`, `RwTransaction::reserve` or
+    /// `RwCursor::put`, checks that:
+    ///
+    /// * the write flags are compatible with the database flags, failing with
+    ///   `Error::Incompatible` otherwise,
+    /// * the entry is within the limits of `DatabaseLimits::validate_entry`, failing with
+    ///   `Error::BadValSize` otherwise, and
+    /// * keys written with `WriteFlags::APPEND` sort after the last key of the database, failing
+    ///   with `Error::KeyExist` otherwise.
+    Strict,
+}
+
+/// What happens in debug builds when a write transaction which made writes is dropped without
+/// being committed or aborted explicitly.
+///
+/// Dropped transactions are aborted, so that writes are lost without an error if a commit was
+/// forgotten, for example on an early return. Reporting them catches such bugs during
+/// development, with the backtrace of where the transaction was begun. Transactions dropped while
+/// the thread is panicking are not reported.
+///
+/// See `EnvironmentBuilder::set_dropped_writes`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DroppedWrites {
+    /// The transaction is aborted silently.
+    #[default]
+    Abort,
+    /// The transaction is aborted, and reported to the standard error stream.
+    Log,
+    /// The transaction is aborted, and the thread panics.
+    Panic,
+}
+
+/// How often write transactions flush the environment to disk.
+///
+/// See `EnvironmentBuilder::set_sync_period`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyncPeriod {
+    /// Every `n`th commit flushes the environment.
+    Commits(u32),
+    /// The first commit after the interval has elapsed since the last flush flushes the
+    /// environment. Commits are not flushed until the next commit or until the environment is
+    /// dropped, however long ago they were made, unless `Environment::sync` is called.
+    Interval(Duration),
+}
+
+/// How the memory map is grown by `Environment::with_write_txn`.
+///
+/// The new size is rounded up to a multiple of the page size. Factors are compared bitwise.
+#[derive(Clone, Copy, Debug)]
+pub enum ResizePolicy {
+    /// Grows the map by the number of bytes.
+    GrowBy(usize),
+    /// Multiplies the size of the map by the factor, which should be greater than one.
+    GrowFactor(f64),
+}
+
+impl PartialEq for ResizePolicy {
+    fn eq(&self, other: &ResizePolicy) -> bool {
+        match (*self, *other) {
+            (ResizePolicy::GrowBy(a), ResizePolicy::GrowBy(b)) => a == b,
+            (ResizePolicy::GrowFactor(a), ResizePolicy::GrowFactor(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ResizePolicy {}
+
+/// The smallest page size accepted by `EnvironmentBuilder::set_page_size`.
+#[cfg(feature = "pagesize")]
+pub const MIN_PAGE_SIZE: u32 = 512;
+
+/// The largest page size accepted by `EnvironmentBuilder::set_page_size`.
+#[cfg(feature = "pagesize")]
+pub const MAX_PAGE_SIZE: u32 = 0x8000;
+
+/// Converts a path to the null-terminated form LMDB expects.
+///
+/// LMDB converts paths from UTF-8 to UTF-16 on Windows, so they must be valid Unicode there.
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::Invalid)
+}
+
+#[cfg(windows)]
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    let path = path.to_str().ok_or(Error::Invalid)?;
+    CString::new(path).map_err(|_| Error::Invalid)
+}
+
+/// The magic number at the start of the meta pages of an LMDB data file.
+const META_MAGIC: u32 = 0xBEEF_C0DE;
+
+/// The version of the data file format in the meta pages.
+const META_VERSION: u32 = 1;
+
+/// The flag of a meta page in its page header.
+const P_META: u16 = 0x08;
+
+/// Checks the page headers, magic number and format version of the two meta pages at the start
+/// of the data file, which are native-endian and sized for the pointer width.
+fn validate_meta_pages(file: &mut File) -> result::Result<(), LoadError> {
+    const WORD: usize = mem::size_of::<usize>();
+    // The page header is the page number, padding, the flags, and the bounds of the free space.
+    const PAGE_HEADER: usize = WORD + 8;
+    // The meta data are the magic number, the version, the address and size of the map, and the
+    // free and main databases, the first field of which is the page size in the free database.
+    const PAGE_SIZE_OFFSET: usize = PAGE_HEADER + 8 + 2 * WORD;
+
+    fn read_meta(file: &mut File, offset: u64) -> io::Result<[u8; PAGE_SIZE_OFFSET + 4]> {
+        let mut meta = [0; PAGE_SIZE_OFFSET + 4];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut meta)?;
+        Ok(meta)
+    }
+    fn valid(meta: &[u8], pgno: usize) -> bool {
+        let word = |offset: usize| usize::from_ne_bytes(meta[offset..offset + WORD].try_into().unwrap());
+        let u16_at = |offset: usize| u16::from_ne_bytes(meta[offset..offset + 2].try_into().unwrap());
+        let u32_at = |offset: usize| u32::from_ne_bytes(meta[offset..offset + 4].try_into().unwrap());
+        word(0) == pgno
+            && u16_at(WORD + 2) & P_META != 0
+            && u32_at(PAGE_HEADER) == META_MAGIC
+            && u32_at(PAGE_HEADER + 4) == META_VERSION
+    }
+    let invalid = |error: io::Error| if error.kind() == io::ErrorKind::UnexpectedEof {
+        LoadError::Lmdb(Error::Invalid)
+    } else {
+        LoadError::Io(error)
+    };
+
+    let first = read_meta(file, 0).map_err(invalid)?;
+    if !valid(&first, 0) {
+        return Err(LoadError::Lmdb(Error::Invalid));
+    }
+    let page_size = u32::from_ne_bytes(first[PAGE_SIZE_OFFSET..].try_into().unwrap());
+    if !page_size.is_power_of_two() {
+        return Err(LoadError::Lmdb(Error::Invalid));
+    }
+    let second = read_meta(file, u64::from(page_size)).map_err(invalid)?;
+    if !valid(&second, 1) {
+        return Err(LoadError::Lmdb(Error::Invalid));
+    }
+    Ok(())
+}
+
+/// The name of the database recording the generation of each database.
+const GENERATIONS_DB: &str = "__generations";
+
+/// The handle of the main database, which is the same in every environment.
+pub(crate) const MAIN_DBI: ffi::MDB_dbi = 1;
+
+/// Returns the key of the generation of a database in the generations database, if it is
+/// tracked. Database names may not contain the null character, so it is the key of the main
+/// database. LMDB rejects the empty string as a database name, and as a key.
+pub(crate) fn generation_key(dbi: ffi::MDB_dbi, name: Option<&str>) -> Option<Vec<u8>> {
+    match name {
+        Some("") => None,
+        Some(name) => Some(name.as_bytes().to_vec()),
+        None if dbi == MAIN_DBI => Some(vec![0]),
+        None => None,
+    }
+}
+
+/// Decodes a generation stored in the generations database.
+pub(crate) fn decode_generation(bytes: &[u8]) -> Result<u64> {
+    let mut buf = [0; 8];
+    if bytes.len() != buf.len() {
+        return Err(Error::Corrupted);
+    }
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_ne_bytes(buf))
 }
 
 #[cfg(test)]
@@ -334,6 +1752,10 @@ mod test {
 
     extern crate byteorder;
 
+    use std::fs;
+    use std::sync::mpsc;
+
+    use libc::{EINVAL, EIO};
     use tempdir::TempDir;
     use self::byteorder::{ByteOrder, LittleEndian};
 
@@ -414,6 +1836,250 @@ mod test {
         assert!(env.open_db(Some("db")).is_ok());
     }
 
+    #[test]
+    fn test_dbi_stats() {
+        let dir = TempDir::new("test").unwrap();
+        let mut env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let stats = env.dbi_stats();
+        assert_eq!((0, 2, 2), (stats.open(), stats.max(), stats.remaining()));
+
+        env.open_db(None).unwrap();
+        let db1 = env.create_db(Some("db1"), DatabaseFlags::empty()).unwrap();
+        // Reopening a database returns the same handle.
+        env.open_db(Some("db1")).unwrap();
+        assert_eq!(1, env.dbi_stats().open());
+        env.create_db(Some("db2"), DatabaseFlags::empty()).unwrap();
+        assert_eq!(0, env.dbi_stats().remaining());
+        assert_eq!(Err(Error::DbsFull), env.create_db(Some("db3"), DatabaseFlags::empty()));
+
+        unsafe { env.close_db(db1); }
+        assert_eq!(1, env.dbi_stats().remaining());
+    }
+
+    #[test]
+    fn test_set_flags() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_flags(EnvironmentFlags::NO_META_SYNC).open(dir.path()).unwrap();
+        assert!(env.get_flags().unwrap().contains(EnvironmentFlags::NO_META_SYNC));
+        assert!(!env.get_flags().unwrap().contains(EnvironmentFlags::NO_SYNC));
+
+        env.set_flags(EnvironmentFlags::NO_SYNC | EnvironmentFlags::NO_MEM_INIT, true).unwrap();
+        env.set_flags(EnvironmentFlags::NO_META_SYNC, false).unwrap();
+        let flags = env.get_flags().unwrap();
+        assert!(flags.contains(EnvironmentFlags::NO_SYNC | EnvironmentFlags::NO_MEM_INIT));
+        assert!(!flags.contains(EnvironmentFlags::NO_META_SYNC));
+
+        assert_eq!(Err(Error::Other(EINVAL)), env.set_flags(EnvironmentFlags::READ_ONLY, true));
+        assert!(!env.get_flags().unwrap().contains(EnvironmentFlags::READ_ONLY));
+    }
+
+    #[test]
+    fn test_set_instrumentation() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        assert_eq!(Instrumentation::empty(), env.instrumentation());
+
+        env.set_instrumentation(Instrumentation::METRICS | Instrumentation::TRACKING);
+        let other = env.clone();
+        thread::spawn(move || {
+            assert_eq!(Instrumentation::METRICS | Instrumentation::TRACKING, other.instrumentation());
+            other.set_instrumentation(Instrumentation::LOGGING);
+        }).join().unwrap();
+        assert_eq!(Instrumentation::LOGGING, env.instrumentation());
+    }
+
+    #[test]
+    fn test_path() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        assert_eq!(dir.path(), env.path().unwrap());
+
+        let file = dir.path().join("data.mdb");
+        let env = Environment::new().set_flags(EnvironmentFlags::NO_SUB_DIR).open(&file).unwrap();
+        assert_eq!(file, env.path().unwrap());
+
+        let invalid = dir.path().join("data\0.mdb");
+        assert_eq!(Error::Invalid, Environment::new().open(&invalid).err().unwrap());
+        assert_eq!(Err(Error::Invalid), env.copy(&invalid, CopyFlags::empty()));
+    }
+
+    #[test]
+    fn test_db_generation() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(4).set_track_generations(true).open(dir.path()).unwrap();
+        let main = env.open_db(None).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let other = env.create_db(Some("other"), DatabaseFlags::empty()).unwrap();
+        assert_eq!(Ok(0), env.db_generation(main));
+        assert_eq!(Ok(0), env.db_generation(db));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"", WriteFlags::empty()).unwrap();
+        txn.put(main, b"key", b"", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(Ok(1), env.db_generation(main));
+        assert_eq!(Ok(1), env.db_generation(db));
+        assert_eq!(Ok(0), env.db_generation(other));
+
+        // Aborted and failed writes don't bump the generation.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(other, b"key", b"", WriteFlags::empty()).unwrap();
+        txn.abort();
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.del(other, b"key", None));
+        txn.open_rw_cursor(other).unwrap();
+        txn.open_rw_cursor(db).unwrap().put(b"key3", b"", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(Ok(2), env.db_generation(db));
+        assert_eq!(Ok(0), env.db_generation(other));
+
+        // The writes of nested transactions are counted once, when the top-level one commits.
+        let mut txn = env.begin_rw_txn().unwrap();
+        for _ in 0..2 {
+            let mut nested = txn.begin_nested_txn().unwrap();
+            nested.put(db, b"key4", b"", WriteFlags::empty()).unwrap();
+            nested.commit().unwrap();
+        }
+        txn.commit().unwrap();
+        assert_eq!(Ok(3), env.db_generation(db));
+
+        // The empty string is not a database name, nor the key of a generation.
+        assert_eq!(Err(Error::BadValSize), env.create_db(Some(""), DatabaseFlags::empty()));
+        assert_eq!(None, generation_key(MAIN_DBI + 1, Some("")));
+
+        // An environment must not be opened twice by the same process.
+        drop(env);
+        let untracked = Environment::new().open(dir.path()).unwrap();
+        assert_eq!(Err(Error::Incompatible), untracked.db_generation(main));
+    }
+
+    #[test]
+    #[cfg(feature = "error-context")]
+    fn test_last_error_context() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let other_dir = TempDir::new("test").unwrap();
+        let other = Environment::new().open(other_dir.path()).unwrap();
+        let db = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+        let main = other.open_db(None).unwrap();
+        assert_eq!(None, env.last_error_context());
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"user:1", b"", WriteFlags::empty()).unwrap();
+        assert_eq!(Err(Error::KeyExist), txn.put(db, b"user:1", b"", WriteFlags::NO_OVERWRITE));
+        txn.commit().unwrap();
+        let context = env.last_error_context().unwrap();
+        assert_eq!((Error::KeyExist, "put", Some("users"), &b"user:1"[..]),
+                   (context.error(), context.op(), context.db_name(), context.key_excerpt()));
+        assert_eq!(None, env.last_error_context());
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.get(db, &[b'k'; 20]));
+        txn.abort();
+        let context = env.last_error_context().unwrap();
+        assert_eq!((16, 20), (context.key_excerpt().len(), context.key_len()));
+        assert_eq!(format!("get on database \"users\" with key {}... (20 bytes) failed: {}",
+                           "6b".repeat(16), Error::NotFound),
+                   context.to_string());
+
+        // Contexts of other environments are not returned.
+        let txn = other.begin_ro_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.get(main, b"key"));
+        txn.abort();
+        assert_eq!(None, env.last_error_context());
+        assert_eq!("get on database 1 with key 6b6579 (3 bytes) failed: MDB_NOTFOUND: No matching key/data pair found",
+                   other.last_error_context().unwrap().to_string());
+
+        // Closing an environment discards its context, even if another reuses its address.
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"user:2"));
+        txn.abort();
+        drop(env);
+        let env = Environment::new().open(dir.path()).unwrap();
+        assert_eq!(None, env.last_error_context());
+        // As are the contexts of other environments.
+        let txn = other.begin_ro_txn().unwrap();
+        assert_eq!(Err(Error::NotFound), txn.get(main, b"key"));
+        txn.abort();
+        drop(env);
+        assert_eq!(None, other.last_error_context());
+    }
+
+    #[test]
+    fn test_limits() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_readers(7).open(dir.path()).unwrap();
+        assert_eq!(7, env.max_readers().unwrap());
+
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        let key = vec![0; env.max_key_size()];
+        txn.put(db, &key, b"", WriteFlags::empty()).unwrap();
+        let key = vec![0; env.max_key_size() + 1];
+        assert_eq!(Err(Error::BadValSize), txn.put(db, &key, b"", WriteFlags::empty()));
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        let last = env.last_committed_txn_id().unwrap();
+
+        let (value, id) = env.write_and_read(|txn| {
+            txn.put(db, b"counter", b"1", WriteFlags::empty())?;
+            Ok(txn.get(db, b"counter")?.to_vec())
+        }).unwrap();
+        assert_eq!((b"1".to_vec(), last + 1), (value, id));
+        assert_eq!(id, env.last_committed_txn_id().unwrap());
+        assert!(env.wait_for_txn(id, Duration::from_secs(0)).unwrap());
+        assert_eq!(id, env.begin_ro_txn().unwrap().id());
+
+        // A failed write is aborted.
+        assert_eq!(Err(Error::NotFound), env.write_and_read(|txn| {
+            txn.put(db, b"counter", b"2", WriteFlags::empty())?;
+            txn.get(db, b"missing").map(|_| ())
+        }));
+        assert_eq!(id, env.last_committed_txn_id().unwrap());
+        assert!(!env.wait_for_txn(id + 1, Duration::from_millis(10)).unwrap());
+
+        let writer = env.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.write_and_read(|txn| txn.put(db, b"counter", b"2", WriteFlags::empty())).unwrap().1
+        });
+        assert!(env.wait_for_txn(id + 1, Duration::from_secs(10)).unwrap());
+        assert_eq!(id + 1, handle.join().unwrap());
+        assert_eq!(b"2", env.begin_ro_txn().unwrap().get(db, b"counter").unwrap());
+    }
+
+    #[test]
+    fn test_begin_sendable_ro_txn() {
+        let dir = TempDir::new("test").unwrap();
+        {
+            let env = Environment::new().open(dir.path()).unwrap();
+            assert_eq!(Error::Incompatible, env.begin_sendable_ro_txn().unwrap_err());
+        }
+        let env = Environment::new().set_flags(EnvironmentFlags::NO_TLS).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val1", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        // The snapshot is read on another thread, after a later write.
+        let snapshot = env.begin_sendable_ro_txn().unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val2", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                assert_eq!(b"val1", snapshot.get(db, b"key").unwrap());
+                snapshot.commit().unwrap();
+            });
+        });
+    }
+
     #[test]
     fn test_sync() {
         let dir = TempDir::new("test").unwrap();
@@ -428,6 +2094,138 @@ mod test {
         }
     }
 
+    #[cfg(feature = "prevsnapshot")]
+    #[test]
+    fn test_prev_snapshot() {
+        let dir = TempDir::new("test").unwrap();
+        {
+            let env = Environment::new().open(dir.path()).unwrap();
+            let db = env.open_db(None).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", b"val1", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", b"val2", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let env = Environment::new().set_prev_snapshot(true).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        assert!(env.begin_rw_txn().is_err());
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(b"val1", txn.get(db, b"key").unwrap());
+    }
+
+    #[test]
+    fn test_copy() {
+        let dir = TempDir::new("test").unwrap();
+        let copy_dir = TempDir::new("copy").unwrap();
+        let compact_dir = TempDir::new("compact").unwrap();
+        {
+            let env = Environment::new().open(dir.path()).unwrap();
+            let db = env.open_db(None).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+
+            env.copy(copy_dir.path(), CopyFlags::empty()).unwrap();
+            env.copy(compact_dir.path(), CopyFlags::COMPACT).unwrap();
+        }
+
+        for dir in &[copy_dir, compact_dir] {
+            let env = Environment::new().open(dir.path()).unwrap();
+            let db = env.open_db(None).unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            assert_eq!(b"val", txn.get(db, b"key").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_copy_to_writer() {
+        struct Failing;
+        impl io::Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for i in 0..1000u32 {
+            txn.put(db, &i.to_be_bytes(), &[0; 256], WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        for &flags in &[CopyFlags::empty(), CopyFlags::COMPACT] {
+            let mut backup = Vec::new();
+            let written = env.copy_to_writer(&mut backup, flags).unwrap();
+            assert_eq!(backup.len() as u64, written);
+
+            let copy_dir = TempDir::new("copy").unwrap();
+            fs::write(copy_dir.path().join("data.mdb"), &backup).unwrap();
+            let copy = Environment::new().open(copy_dir.path()).unwrap();
+            let copy_db = copy.open_db(None).unwrap();
+            let txn = copy.begin_ro_txn().unwrap();
+            assert_eq!(&[0; 256][..], txn.get(copy_db, &999u32.to_be_bytes()).unwrap());
+            assert_eq!(1000, copy.stat().unwrap().entries());
+        }
+
+        match env.copy_to_writer(&mut Failing, CopyFlags::empty()) {
+            Err(LoadError::Io(error)) => assert_eq!(io::ErrorKind::BrokenPipe, error.kind()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let (source, sink) = cloexec_pipe().unwrap();
+        for file in &[source, sink] {
+            assert_eq!(libc::FD_CLOEXEC, unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFD) });
+        }
+    }
+
+    #[test]
+    fn test_restore_from_reader() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        let mut backup = Vec::new();
+        env.copy_to_writer(&mut backup, CopyFlags::COMPACT).unwrap();
+
+        let restore_dir = TempDir::new("restore").unwrap();
+        let restored = Environment::new().restore_from_reader(&mut &backup[..], restore_dir.path()).unwrap();
+        let restored_db = restored.open_db(None).unwrap();
+        assert_eq!(b"val", restored.begin_ro_txn().unwrap().get(restored_db, b"key").unwrap());
+        // An existing environment is not overwritten.
+        match Environment::new().restore_from_reader(&mut &backup[..], restore_dir.path()) {
+            Err(LoadError::Io(error)) => assert_eq!(io::ErrorKind::AlreadyExists, error.kind()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let file = restore_dir.path().join("file.mdb");
+        let restored = Environment::new().set_flags(EnvironmentFlags::NO_SUB_DIR)
+                                         .restore_from_reader(&mut &backup[..], &file)
+                                         .unwrap();
+        let restored_db = restored.open_db(None).unwrap();
+        assert_eq!(b"val", restored.begin_ro_txn().unwrap().get(restored_db, b"key").unwrap());
+
+        // Invalid and truncated backups are removed.
+        let page_size = env.stat().unwrap().page_size() as usize;
+        for invalid in &[&[0; 8192][..], &backup[..100], &backup[..page_size + 10]] {
+            let invalid_dir = TempDir::new("invalid").unwrap();
+            match Environment::new().restore_from_reader(&mut &invalid[..], invalid_dir.path()) {
+                Err(LoadError::Lmdb(Error::Invalid)) => (),
+                result => panic!("unexpected result: {:?}", result),
+            }
+            assert!(!invalid_dir.path().join("data.mdb").exists());
+        }
+    }
+
     #[test]
     fn test_stat() {
         let dir = TempDir::new("test").unwrap();
@@ -462,4 +2260,312 @@ mod test {
         assert_eq!(stat.overflow_pages(), 0);
         assert_eq!(stat.entries(), 64);
     }
+
+    #[test]
+    fn test_set_map_size() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_map_size(64 * 1024).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let value = vec![0u8; 16 * 1024];
+
+        let fill = || -> Result<()> {
+            let mut txn = env.begin_rw_txn()?;
+            for i in 0..16u32 {
+                txn.put(db, &i.to_be_bytes(), &value, WriteFlags::empty())?;
+            }
+            txn.commit()
+        };
+        assert_eq!(Err(Error::MapFull), fill());
+
+        unsafe { env.set_map_size(1024 * 1024).unwrap() };
+        assert_eq!(1024 * 1024, env.info().unwrap().map_size());
+        fill().unwrap();
+    }
+
+    #[test]
+    fn test_with_write_txn() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_map_size(64 * 1024)
+                                    .set_resize_policy(ResizePolicy::GrowFactor(2.0))
+                                    .open(dir.path())
+                                    .unwrap();
+        let db = env.open_db(None).unwrap();
+        let value = vec![0u8; 16 * 1024];
+
+        let mut attempts = 0;
+        let written = env.with_write_txn(|txn| {
+            attempts += 1;
+            for i in 0..16u32 {
+                txn.put(db, &i.to_be_bytes(), &value, WriteFlags::empty())?;
+            }
+            Ok(16)
+        }).unwrap();
+        assert_eq!(16, written);
+        assert!(attempts > 1);
+        assert!(env.info().unwrap().map_size() > 256 * 1024);
+
+        // The map is not grown while a transaction is active.
+        let map_size = env.info().unwrap().map_size();
+        let txn = env.begin_ro_txn().unwrap();
+        let fill = |txn: &mut RwTransaction| -> Result<()> {
+            for i in 0..64u32 {
+                txn.put(db, &(i + 16).to_be_bytes(), &value, WriteFlags::empty())?;
+            }
+            Ok(())
+        };
+        assert_eq!(Err(Error::MapFull), env.with_write_txn(fill));
+        assert_eq!(map_size, env.info().unwrap().map_size());
+        drop(txn);
+        env.with_write_txn(fill).unwrap();
+
+        // Errors other than `MapFull` are returned without retrying.
+        attempts = 0;
+        let result: Result<()> = env.with_write_txn(|_| {
+            attempts += 1;
+            Err(Error::NotFound)
+        });
+        assert_eq!(Err(Error::NotFound), result);
+        assert_eq!(1, attempts);
+    }
+
+    #[test]
+    fn test_reopen_after_fork_failed() {
+        // Reopening after a fork is tested by the `multiprocess-tests` feature; a failed reopen
+        // leaves the handle to be closed when dropped.
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+        assert!(env.reopen_after_fork().is_err());
+        assert!(!env.inherited.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_list_dbs() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).open(dir.path()).unwrap();
+        assert_eq!(Vec::<String>::new(), env.list_dbs().unwrap());
+
+        let db = env.create_db(Some("db1"), DatabaseFlags::empty()).unwrap();
+        env.create_db(Some("db2"), DatabaseFlags::DUP_SORT).unwrap();
+        let main = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(main, b"data", b"value", WriteFlags::empty()).unwrap();
+        txn.put(main, b"fake", &[0u8; 8 + 5 * mem::size_of::<size_t>()], WriteFlags::empty()).unwrap();
+        txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        // The handles opened to check candidates are released.
+        assert_eq!(vec!["db1".to_owned(), "db2".to_owned()], env.list_dbs().unwrap());
+        assert_eq!(vec!["db1".to_owned(), "db2".to_owned()], env.list_dbs().unwrap());
+        drop(env);
+
+        let env = Environment::new().open(dir.path()).unwrap();
+        assert_eq!(Err(Error::DbsFull), env.list_dbs());
+    }
+
+    #[test]
+    fn test_close() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        env.set_instrumentation(Instrumentation::TRACKING);
+
+        let (begun_tx, begun_rx) = mpsc::channel();
+        let (commit_tx, commit_rx) = mpsc::channel();
+        let worker = {
+            let env = env.clone();
+            thread::Builder::new().name("worker".to_owned()).spawn(move || {
+                let mut txn = env.begin_rw_txn().unwrap();
+                txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+                begun_tx.send(()).unwrap();
+                commit_rx.recv().unwrap();
+                txn.commit().unwrap();
+            }).unwrap()
+        };
+        begun_rx.recv().unwrap();
+        // The report names the write transaction holding the environment.
+        let error = Environment::drain(env, Duration::from_millis(10)).unwrap_err();
+        assert_eq!((1, Some(true)), (error.strong_refs(), error.write_txn_held()));
+        let handles = error.open_handles();
+        assert_eq!(vec![(HandleKind::RwTransaction, Some("worker"))],
+                   handles.iter().map(|handle| (handle.kind(), handle.thread_name())).collect::<Vec<_>>());
+        let env = error.into_env();
+        env.set_instrumentation(Instrumentation::empty());
+        let error = Environment::drain(env, Duration::from_millis(10)).unwrap_err();
+        assert_eq!((1, None), (error.strong_refs(), error.write_txn_held()));
+
+        commit_tx.send(()).unwrap();
+        let env = Environment::drain(error.into_env(), Duration::from_secs(10)).unwrap();
+        worker.join().unwrap();
+        env.close().unwrap();
+
+        let env = Environment::new().set_flags(EnvironmentFlags::READ_ONLY).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        assert_eq!(b"value", env.begin_ro_txn().unwrap().get(db, b"key").unwrap());
+        env.close().unwrap();
+    }
+
+    #[test]
+    fn test_reader_list() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        assert_eq!(0, env.reader_check().unwrap());
+        assert_eq!(Vec::<ReaderInfo>::new(), env.reader_list().unwrap());
+
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let readers = env.reader_list().unwrap();
+        assert_eq!(1, readers.len());
+        assert_eq!(::std::process::id() as i32, readers[0].pid());
+        assert_eq!(Some(1), readers[0].txn_id());
+
+        // The slot stays tied to the thread after the transaction ends.
+        drop(txn);
+        let readers = env.reader_list().unwrap();
+        assert_eq!(1, readers.len());
+        assert_eq!(None, readers[0].txn_id());
+        assert_eq!(0, env.reader_check().unwrap());
+    }
+
+    #[test]
+    fn test_reader_lag() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let write = |value: &[u8]| {
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", &value, WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        };
+        write(b"1");
+        assert_eq!(ReaderLag { oldest_txn_id: None, last_txn_id: 1 }, env.reader_lag().unwrap());
+        assert_eq!(0, env.reader_lag().unwrap().lag());
+
+        let txn = env.begin_ro_txn().unwrap();
+        // The writes are made by another thread, which has no reader slot of its own.
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                write(b"2");
+                write(b"3");
+            });
+        });
+        let lag = env.reader_lag().unwrap();
+        assert_eq!((Some(1), 3, 2), (lag.oldest_txn_id(), lag.last_txn_id(), lag.lag()));
+
+        drop(txn);
+        assert_eq!(0, env.reader_lag().unwrap().lag());
+    }
+
+    #[test]
+    fn test_sync_period() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_sync_period(SyncPeriod::Commits(3)).open(dir.path()).unwrap();
+        assert!(env.get_flags().unwrap().contains(EnvironmentFlags::NO_SYNC));
+        let commit = |env: &Environment| {
+            let db = env.open_db(None).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        };
+        let pending = |env: &Environment| env.sync_state.lock().unwrap().0;
+
+        commit(&env);
+        commit(&env);
+        assert_eq!(2, pending(&env));
+        commit(&env);
+        assert_eq!(0, pending(&env));
+        // Nested transactions are not counted.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.begin_nested_txn().unwrap().commit().unwrap();
+        txn.commit().unwrap();
+        assert_eq!(1, pending(&env));
+
+        let builder = env.reopen_builder(env.get_flags().unwrap()).unwrap();
+        assert_eq!(Some(SyncPeriod::Commits(3)), builder.sync_period);
+
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_sync_period(SyncPeriod::Interval(Duration::from_secs(3600)))
+                                    .open(dir.path())
+                                    .unwrap();
+        commit(&env);
+        commit(&env);
+        assert_eq!(2, pending(&env));
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_sync_period(SyncPeriod::Interval(Duration::ZERO)).open(dir.path()).unwrap();
+        commit(&env);
+        assert_eq!(0, pending(&env));
+
+        // A recorded flush failure is cleared by the next successful flush.
+        assert_eq!(None, env.sync_error());
+        *env.sync_error.lock().unwrap() = Some(Error::Other(EIO));
+        assert_eq!(Some(Error::Other(EIO)), env.sync_error());
+        commit(&env);
+        assert_eq!(None, env.sync_error());
+    }
+
+    #[test]
+    fn test_barrier() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let pending = |env: &Environment| env.sync_state.lock().unwrap().0;
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.commit_no_sync().unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        txn.commit_no_sync().unwrap();
+        assert_eq!(2, pending(&env));
+        // The environment syncs other commits.
+        assert!(!env.get_flags().unwrap().contains(EnvironmentFlags::NO_SYNC));
+        env.begin_rw_txn().unwrap().commit().unwrap();
+        assert_eq!(2, pending(&env));
+        env.barrier().unwrap();
+        assert_eq!(0, pending(&env));
+        assert_eq!(b"val2", env.begin_ro_txn().unwrap().get(db, b"key2").unwrap());
+
+        // Commits without syncing don't flush the environment at the end of the sync period.
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_sync_period(SyncPeriod::Commits(2)).open(dir.path()).unwrap();
+        env.begin_rw_txn().unwrap().commit_no_sync().unwrap();
+        env.begin_rw_txn().unwrap().commit_no_sync().unwrap();
+        assert_eq!(2, pending(&env));
+        env.begin_rw_txn().unwrap().commit().unwrap();
+        assert_eq!(0, pending(&env));
+    }
+
+    #[test]
+    fn test_info() {
+        let map_size = 1024 * 1024;
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_map_size(map_size)
+                                    .set_max_readers(16)
+                                    .open(dir.path())
+                                    .unwrap();
+
+        let info = env.info().unwrap();
+        assert_eq!(info.map_size(), map_size);
+        assert_eq!(info.last_txnid(), 0);
+        assert_eq!(info.max_readers(), 16);
+        assert_eq!(info.num_readers(), 0);
+        let last_pgno = info.last_pgno();
+
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let _txn = env.begin_ro_txn().unwrap();
+        let info = env.info().unwrap();
+        assert_eq!(info.last_txnid(), 1);
+        assert!(info.last_pgno() > last_pgno);
+        assert_eq!(info.num_readers(), 1);
+    }
 }
@@ -1,8 +1,8 @@
-use libc::c_int;
+use libc::{c_int, EIO};
 use std::error::Error as StdError;
 use std::ffi::CStr;
 use std::os::raw::c_char;
-use std::{fmt, result, str};
+use std::{fmt, io, result, str};
 
 use ffi;
 
@@ -53,8 +53,43 @@ pub enum Error {
     Other(c_int),
 }
 
+/// The error code of `Error::QUOTA_EXCEEDED`, which is raised by this crate rather than LMDB. It
+/// is well below the range of LMDB error codes.
+const QUOTA_EXCEEDED: c_int = -30900;
+/// The error code of `Error::NESTED_TXN_UNSUPPORTED`, which is raised by this crate rather than
+/// LMDB.
+const NESTED_TXN_UNSUPPORTED: c_int = -30901;
+/// The error code of `Error::MALFORMED_VALUE`, which is raised by this crate rather than LMDB.
+const MALFORMED_VALUE: c_int = -30902;
+/// The error code of `Error::UNREGISTERED_DB`, which is raised by this crate rather than LMDB.
+const UNREGISTERED_DB: c_int = -30903;
+
 impl Error {
 
+    /// A write would exceed the quota of the database.
+    ///
+    /// The error is raised by this crate rather than LMDB, so it is an `Error::Other` with a code
+    /// outside the range of LMDB error codes. It may be compared against, or matched as a
+    /// constant pattern.
+    pub const QUOTA_EXCEEDED: Error = Error::Other(QUOTA_EXCEEDED);
+
+    /// Nested transactions are not supported in environments opened with
+    /// `EnvironmentFlags::WRITE_MAP`.
+    ///
+    /// Like `Error::QUOTA_EXCEEDED`, the error is an `Error::Other` raised by this crate.
+    pub const NESTED_TXN_UNSUPPORTED: Error = Error::Other(NESTED_TXN_UNSUPPORTED);
+
+    /// A stored value does not have the layout it was read as, such as a value shorter than its
+    /// header, or a header which fails to decode.
+    ///
+    /// Unlike `Error::Corrupted`, which LMDB raises for a damaged database file, the database is
+    /// intact: the value was written in another format, or by another writer.
+    pub const MALFORMED_VALUE: Error = Error::Other(MALFORMED_VALUE);
+
+    /// A database was accessed through the context of `Environment::transact_all` without being
+    /// one of the databases it was given.
+    pub const UNREGISTERED_DB: Error = Error::Other(UNREGISTERED_DB);
+
     /// Converts a raw error code to an `Error`.
     pub fn from_err_code(err_code: c_int) -> Error {
         match err_code {
@@ -118,6 +153,13 @@ impl fmt::Display for Error {
 
 impl StdError for Error {
     fn description(&self) -> &str {
+        match *self {
+            Error::QUOTA_EXCEEDED => return "Database quota exceeded",
+            Error::NESTED_TXN_UNSUPPORTED => return "Nested transactions are not supported with WRITE_MAP",
+            Error::MALFORMED_VALUE => return "Stored value is malformed",
+            Error::UNREGISTERED_DB => return "Database is not registered with the transaction",
+            _ => (),
+        }
         unsafe {
             // This is safe since the error messages returned from mdb_strerror are static.
             let err: *const c_char = ffi::mdb_strerror(self.to_err_code()) as *const c_char;
@@ -129,6 +171,280 @@ impl StdError for Error {
 /// An LMDB result.
 pub type Result<T> = result::Result<T, Error>;
 
+/// The reason a key/data pair would be rejected by a database.
+///
+/// Returned by `DatabaseLimits::validate_entry`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum EntryError {
+    /// The key is empty. LMDB does not allow zero-length keys.
+    EmptyKey,
+    /// The key is longer than the environment's maximum key size.
+    KeyTooLarge {
+        /// The length of the key.
+        len: usize,
+        /// The maximum key size.
+        max: usize,
+    },
+    /// The key is not the size of a native `c_uint` or `size_t`, as required by
+    /// `DatabaseFlags::INTEGER_KEY`.
+    IntegerKeySize {
+        /// The length of the key.
+        len: usize,
+    },
+    /// The value is longer than the maximum key size, which limits values stored in a
+    /// `DatabaseFlags::DUP_SORT` database.
+    DupValueTooLarge {
+        /// The length of the value.
+        len: usize,
+        /// The maximum value size.
+        max: usize,
+    },
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntryError::EmptyKey => write!(fmt, "key is empty"),
+            EntryError::KeyTooLarge { len, max } =>
+                write!(fmt, "key length {} exceeds the maximum key size {}", len, max),
+            EntryError::IntegerKeySize { len } =>
+                write!(fmt, "key length {} is not a valid integer key size", len),
+            EntryError::DupValueTooLarge { len, max } =>
+                write!(fmt, "duplicate value length {} exceeds the maximum size {}", len, max),
+        }
+    }
+}
+
+impl StdError for EntryError {
+    fn description(&self) -> &str {
+        match *self {
+            EntryError::EmptyKey => "key is empty",
+            EntryError::KeyTooLarge { .. } => "key too large",
+            EntryError::IntegerKeySize { .. } => "invalid integer key size",
+            EntryError::DupValueTooLarge { .. } => "duplicate value too large",
+        }
+    }
+}
+
+/// An error from moving data between a database and an external source or destination, such as
+/// a file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// An error returned by LMDB.
+    Lmdb(Error),
+    /// An I/O error from the external source or destination.
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Lmdb(ref error) => error.fmt(fmt),
+            LoadError::Io(ref error) => error.fmt(fmt),
+        }
+    }
+}
+
+impl StdError for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Lmdb(..) => "LMDB error",
+            LoadError::Io(..) => "I/O error",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            LoadError::Lmdb(ref error) => Some(error),
+            LoadError::Io(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for LoadError {
+    fn from(error: Error) -> LoadError {
+        LoadError::Lmdb(error)
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+/// The number of leading key bytes kept by an `ErrorContext`.
+#[cfg(feature = "error-context")]
+const KEY_EXCERPT_LEN: usize = 16;
+
+/// Describes the operation which failed with an `Error`: its name, the database and an excerpt of
+/// the key.
+///
+/// The context of the most recent failed operation on the current thread is retrieved with
+/// `Environment::last_error_context`. Recording it does not allocate, so the error types and
+/// signatures of the safe APIs remain unchanged.
+#[cfg(feature = "error-context")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorContext {
+    error: Error,
+    op: &'static str,
+    dbi: ffi::MDB_dbi,
+    db_name: Option<String>,
+    key: [u8; KEY_EXCERPT_LEN],
+    key_len: usize,
+}
+
+#[cfg(feature = "error-context")]
+impl ErrorContext {
+
+    /// Returns the error the operation failed with.
+    pub fn error(&self) -> Error {
+        self.error
+    }
+
+    /// Returns the name of the operation, such as `"get"` or `"put"`.
+    pub fn op(&self) -> &'static str {
+        self.op
+    }
+
+    /// Returns the name of the database, if it was opened by name with `Environment::open_db` or
+    /// `Environment::create_db`.
+    pub fn db_name(&self) -> Option<&str> {
+        self.db_name.as_deref()
+    }
+
+    /// Returns up to the first 16 bytes of the key.
+    pub fn key_excerpt(&self) -> &[u8] {
+        &self.key[..self.key_len.min(KEY_EXCERPT_LEN)]
+    }
+
+    /// Returns the length of the whole key.
+    pub fn key_len(&self) -> usize {
+        self.key_len
+    }
+}
+
+#[cfg(feature = "error-context")]
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} on database ", self.op)?;
+        match self.db_name {
+            Some(ref name) => write!(fmt, "{:?}", name)?,
+            None => write!(fmt, "{}", self.dbi)?,
+        }
+        write!(fmt, " with key ")?;
+        for byte in self.key_excerpt() {
+            write!(fmt, "{:02x}", byte)?;
+        }
+        if self.key_len > KEY_EXCERPT_LEN {
+            write!(fmt, "...")?;
+        }
+        write!(fmt, " ({} bytes) failed: {}", self.key_len, self.error)
+    }
+}
+
+#[cfg(feature = "error-context")]
+impl StdError for ErrorContext {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(feature = "error-context")]
+thread_local! {
+    /// The environment, the count of closed environments, and the context of the most recent
+    /// failed operation on this thread.
+    static LAST_ERROR_CONTEXT: ::std::cell::RefCell<Option<(*mut ffi::MDB_env, usize, ErrorContext)>> =
+        const { ::std::cell::RefCell::new(None) };
+}
+
+/// The number of environments closed. A context recorded before an environment was closed is not
+/// returned, since a newly opened environment may reuse the address of the closed one.
+#[cfg(feature = "error-context")]
+static CLOSED_ENVS: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+
+/// Records the context of the operation if it failed, and returns its result.
+#[cfg(feature = "error-context")]
+pub(crate) fn with_context<T>(result: Result<T>,
+                              op: &'static str,
+                              txn: *mut ffi::MDB_txn,
+                              dbi: ffi::MDB_dbi,
+                              key: &[u8])
+                              -> Result<T> {
+    if let Err(error) = result {
+        let mut context = ErrorContext {
+            error,
+            op,
+            dbi,
+            db_name: None,
+            key: [0; KEY_EXCERPT_LEN],
+            key_len: key.len(),
+        };
+        let excerpt = context.key_len.min(KEY_EXCERPT_LEN);
+        context.key[..excerpt].copy_from_slice(&key[..excerpt]);
+        let env = unsafe { ffi::mdb_txn_env(txn) };
+        let closed = CLOSED_ENVS.load(::std::sync::atomic::Ordering::SeqCst);
+        LAST_ERROR_CONTEXT.with(|last| *last.borrow_mut() = Some((env, closed, context)));
+    }
+    result
+}
+
+/// Returns the result of the operation; error contexts are only recorded with the `error-context`
+/// feature.
+#[cfg(not(feature = "error-context"))]
+#[inline]
+pub(crate) fn with_context<T>(result: Result<T>,
+                              _op: &'static str,
+                              _txn: *mut ffi::MDB_txn,
+                              _dbi: ffi::MDB_dbi,
+                              _key: &[u8])
+                              -> Result<T> {
+    result
+}
+
+/// Takes the context of the most recent failed operation on this thread, if it was in the given
+/// environment, naming its database with `db_name`.
+#[cfg(feature = "error-context")]
+pub(crate) fn take_context<F>(env: *mut ffi::MDB_env, db_name: F) -> Option<ErrorContext>
+where F: FnOnce(ffi::MDB_dbi) -> Option<String> {
+    LAST_ERROR_CONTEXT.with(|last| {
+        let mut last = last.borrow_mut();
+        match last.take() {
+            Some((last_env, closed, mut context)) if last_env == env => {
+                if closed != CLOSED_ENVS.load(::std::sync::atomic::Ordering::SeqCst) {
+                    return None;
+                }
+                context.db_name = db_name(context.dbi);
+                Some(context)
+            },
+            other => {
+                *last = other;
+                None
+            },
+        }
+    })
+}
+
+/// Discards the contexts recorded in the environment, which is being closed, on this thread, and
+/// those of all environments on other threads, which can not be told apart from the contexts of
+/// an environment later opened at the same address.
+#[cfg(feature = "error-context")]
+pub(crate) fn env_closed(env: *mut ffi::MDB_env) {
+    CLOSED_ENVS.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    // The thread-local is already destroyed if the environment is dropped by another one.
+    let _ = LAST_ERROR_CONTEXT.try_with(|last| {
+        let mut last = last.borrow_mut();
+        if last.as_ref().is_some_and(|&(last_env, ..)| last_env == env) {
+            *last = None;
+        }
+    });
+}
+
+/// Error contexts are only recorded with the `error-context` feature.
+#[cfg(not(feature = "error-context"))]
+#[inline]
+pub(crate) fn env_closed(_env: *mut ffi::MDB_env) {}
+
 pub fn lmdb_result(err_code: c_int) -> Result<()> {
     if err_code == ffi::MDB_SUCCESS {
         Ok(())
@@ -137,6 +453,12 @@ pub fn lmdb_result(err_code: c_int) -> Result<()> {
     }
 }
 
+/// Converts an I/O error of a file operation into an `Error::Other` with its OS error code, or
+/// `EIO` if it has none.
+pub(crate) fn io_error(error: io::Error) -> Error {
+    Error::Other(error.raw_os_error().unwrap_or(EIO))
+}
+
 #[cfg(test)]
 mod test {
 
@@ -150,5 +472,18 @@ mod test {
                    Error::from_err_code(13).description());
         assert_eq!("MDB_NOTFOUND: No matching key/data pair found",
                    Error::NotFound.description());
+        assert_eq!("Database quota exceeded",
+                   Error::from_err_code(Error::QUOTA_EXCEEDED.to_err_code()).to_string());
+        assert_eq!("Nested transactions are not supported with WRITE_MAP",
+                   Error::from_err_code(Error::NESTED_TXN_UNSUPPORTED.to_err_code()).to_string());
+        assert_eq!("Stored value is malformed", Error::MALFORMED_VALUE.to_string());
+        assert_eq!("Database is not registered with the transaction", Error::UNREGISTERED_DB.to_string());
+    }
+
+    #[test]
+    fn test_io_error() {
+        assert_eq!(Error::Other(13), io_error(io::Error::from_raw_os_error(13)));
+        // Errors without an OS error code must not map to `MDB_SUCCESS`.
+        assert_eq!(Error::Other(EIO), io_error(io::Error::other("failed")));
     }
 }
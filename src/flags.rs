@@ -171,3 +171,35 @@ bitflags! {
         const APPEND_DUP = MDB_APPENDDUP;
     }
 }
+
+bitflags! {
+    #[doc="Environment copy options."]
+    #[derive(Default)]
+    pub struct CopyFlags: c_uint {
+
+        #[doc="Perform compaction while copying: omit free pages and sequentially renumber all"]
+        #[doc="pages in output. This option consumes more CPU and runs more slowly than the"]
+        #[doc="default."]
+        const COMPACT = MDB_CP_COMPACT;
+    }
+}
+
+bitflags! {
+    #[doc="Instrumentation subsystems of an environment, which may be enabled and disabled at"]
+    #[doc="runtime with `Environment::set_instrumentation`. All are disabled by default, and a"]
+    #[doc="disabled subsystem costs a single atomic load per instrumented operation."]
+    #[derive(Default)]
+    pub struct Instrumentation: u32 {
+
+        #[doc="Record counts and latencies of transaction and cursor operations."]
+        const METRICS = 0x01;
+
+        #[doc="Log transaction lifetimes and failed operations, as `tracing` spans and events with"]
+        #[doc="the `tracing` feature."]
+        const LOGGING = 0x02;
+
+        #[doc="Track open transactions and cursors and the threads which opened them, such as which"]
+        #[doc="thread holds the write lock, for `Environment::open_handles`."]
+        const TRACKING = 0x04;
+    }
+}
@@ -0,0 +1,112 @@
+/// A fixed-size header stored in front of the body of a value.
+///
+/// See `RwTransaction::put_with_header` and `Transaction::get_with_header`. Implemented for byte
+/// arrays, and for unsigned integers in big-endian byte order. Implement it for a struct to store
+/// a typed header, such as a version and a timestamp.
+pub trait Header: Sized {
+
+    /// The length of the encoded header.
+    const LEN: usize;
+
+    /// Encodes the header into a buffer of `LEN` bytes.
+    fn encode(&self, buf: &mut [u8]);
+
+    /// Decodes the header from a buffer of `LEN` bytes, returning `None` if it is invalid, which
+    /// `Transaction::get_with_header` reports as `Error::MALFORMED_VALUE`.
+    fn decode(buf: &[u8]) -> Option<Self>;
+}
+
+impl <const N: usize> Header for [u8; N] {
+
+    const LEN: usize = N;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self);
+    }
+
+    fn decode(buf: &[u8]) -> Option<[u8; N]> {
+        let mut header = [0; N];
+        header.copy_from_slice(buf);
+        Some(header)
+    }
+}
+
+macro_rules! integer_header {
+    ($ty:ty) => {
+        impl Header for $ty {
+
+            const LEN: usize = ::std::mem::size_of::<$ty>();
+
+            fn encode(&self, buf: &mut [u8]) {
+                buf.copy_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode(buf: &[u8]) -> Option<$ty> {
+                <[u8; ::std::mem::size_of::<$ty>()]>::decode(buf).map(<$ty>::from_be_bytes)
+            }
+        }
+    }
+}
+
+integer_header!(u8);
+integer_header!(u16);
+integer_header!(u32);
+integer_header!(u64);
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    /// A header with a version and a timestamp.
+    #[derive(Debug, PartialEq)]
+    struct Versioned {
+        version: u16,
+        timestamp: u64,
+    }
+
+    impl Header for Versioned {
+
+        const LEN: usize = 10;
+
+        fn encode(&self, buf: &mut [u8]) {
+            self.version.encode(&mut buf[..2]);
+            self.timestamp.encode(&mut buf[2..]);
+        }
+
+        fn decode(buf: &[u8]) -> Option<Versioned> {
+            let version = u16::decode(&buf[..2])?;
+            if version != 1 {
+                return None;
+            }
+            Some(Versioned { version, timestamp: u64::decode(&buf[2..])? })
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let header = Versioned { version: 1, timestamp: 1234 };
+        txn.put_with_header(db, b"key1", &header, b"body", WriteFlags::empty()).unwrap();
+        txn.put_with_header(db, b"key2", &Versioned { version: 2, timestamp: 0 }, b"", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key3", b"short", WriteFlags::empty()).unwrap();
+
+        assert_eq!(Ok((header, &b"body"[..])), txn.get_with_header(db, b"key1"));
+        assert_eq!(Ok((&[0, 1][..], &b"body"[..])), txn.get_split(db, b"key1", 2).map(|(h, b)| (h, &b[8..])));
+        assert_eq!(Ok((*b"sh", &b"ort"[..])), txn.get_with_header::<[u8; 2], _>(db, b"key3"));
+        assert_eq!(Err(Error::MALFORMED_VALUE), txn.get_with_header::<Versioned, _>(db, b"key2"));
+        assert_eq!(Err(Error::MALFORMED_VALUE), txn.get_with_header::<Versioned, _>(db, b"key3"));
+        assert_eq!(Err(Error::MALFORMED_VALUE), txn.get_split(db, b"key3", 6));
+        assert_eq!(Err(Error::NotFound), txn.get_split(db, b"key4", 0));
+    }
+}
@@ -0,0 +1,210 @@
+use std::marker::PhantomData;
+use std::{fmt, mem, result};
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use environment::Environment;
+use error::{Error, Result};
+use flags::{DatabaseFlags, WriteFlags};
+use transact::DatabaseSet;
+use transaction::{RwTransaction, Transaction};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// An unsigned integer type which LMDB supports as the key of a database with
+/// `DatabaseFlags::INTEGER_KEY`: the native `c_uint` and `size_t` types.
+///
+/// Implemented for `u32`, `usize`, and `u64` on 64-bit targets.
+pub trait IntegerKey: Copy + private::Sealed {
+
+    #[doc(hidden)]
+    type Bytes: AsRef<[u8]>;
+
+    #[doc(hidden)]
+    fn encode(self) -> Self::Bytes;
+
+    #[doc(hidden)]
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! integer_key {
+    ($ty:ty) => {
+        impl private::Sealed for $ty {}
+
+        impl IntegerKey for $ty {
+
+            type Bytes = [u8; mem::size_of::<$ty>()];
+
+            fn encode(self) -> Self::Bytes {
+                self.to_ne_bytes()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<$ty> {
+                let mut buf = [0u8; mem::size_of::<$ty>()];
+                if bytes.len() != buf.len() {
+                    return None;
+                }
+                buf.copy_from_slice(bytes);
+                Some(<$ty>::from_ne_bytes(buf))
+            }
+        }
+    }
+}
+
+integer_key!(u32);
+integer_key!(usize);
+#[cfg(target_pointer_width = "64")]
+integer_key!(u64);
+
+/// A database handle with `DatabaseFlags::INTEGER_KEY`, whose keys are encoded from and decoded
+/// to integers in native byte order.
+pub struct IntegerDatabase<K> {
+    db: Database,
+    _marker: PhantomData<fn() -> K>,
+}
+
+impl <K> Clone for IntegerDatabase<K> {
+    fn clone(&self) -> IntegerDatabase<K> {
+        *self
+    }
+}
+
+impl <K> Copy for IntegerDatabase<K> {}
+
+impl <K> fmt::Debug for IntegerDatabase<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IntegerDatabase").field("db", &self.db).finish()
+    }
+}
+
+impl <K> DatabaseSet for IntegerDatabase<K> {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.push(self.db);
+    }
+}
+
+impl <K> IntegerDatabase<K> where K: IntegerKey {
+
+    /// Wraps a database handle.
+    ///
+    /// Fails with `Error::Incompatible` if the database was not opened with
+    /// `DatabaseFlags::INTEGER_KEY`, and with `Error::BadRslot` if called by a thread which has an
+    /// ongoing transaction.
+    pub fn new(env: &Environment, db: Database) -> Result<IntegerDatabase<K>> {
+        if !env.get_db_flags(db)?.contains(DatabaseFlags::INTEGER_KEY) {
+            return Err(Error::Incompatible);
+        }
+        Ok(IntegerDatabase { db, _marker: PhantomData })
+    }
+
+    /// Returns the underlying database handle.
+    pub fn database(&self) -> Database {
+        self.db
+    }
+
+    /// Gets the data associated with the key.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the database.
+    pub fn get<'txn, T>(&self, txn: &'txn T, key: K) -> Result<&'txn [u8]> where T: Transaction {
+        txn.get(self.db, &key.encode())
+    }
+
+    /// Stores an item into the database.
+    pub fn put<D>(&self, txn: &mut RwTransaction, key: K, data: &D, flags: WriteFlags) -> Result<()>
+    where D: AsRef<[u8]> {
+        txn.put(self.db, &key.encode(), data, flags)
+    }
+
+    /// Deletes an item from the database.
+    ///
+    /// If `data` is `Some` and the database supports duplicates, only the matching item is
+    /// deleted.
+    pub fn del(&self, txn: &mut RwTransaction, key: K, data: Option<&[u8]>) -> Result<()> {
+        txn.del(self.db, &key.encode(), data)
+    }
+
+    /// Iterates over the items of the database in key order, starting from the beginning.
+    pub fn iter<'txn, T>(&self, txn: &'txn T) -> Result<IntegerIter<'txn, K>> where T: Transaction {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_start());
+        Ok(IntegerIter { iter, _marker: PhantomData })
+    }
+
+    /// Iterates over the items of the database in key order, starting from the given key.
+    pub fn iter_from<'txn, T>(&self, txn: &'txn T, key: K) -> Result<IntegerIter<'txn, K>>
+    where T: Transaction {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_from(key.encode()));
+        Ok(IntegerIter { iter, _marker: PhantomData })
+    }
+}
+
+/// An iterator over the items of an `IntegerDatabase`.
+///
+/// Yields `Error::BadValSize` for keys which are not the size of `K`.
+pub struct IntegerIter<'txn, K> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+    _marker: PhantomData<fn() -> K>,
+}
+
+impl <'txn, K> fmt::Debug for IntegerIter<'txn, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("IntegerIter").finish()
+    }
+}
+
+impl <'txn, K> Iterator for IntegerIter<'txn, K> where K: IntegerKey {
+
+    type Item = Result<(K, &'txn [u8])>;
+
+    fn next(&mut self) -> Option<Result<(K, &'txn [u8])>> {
+        self.iter.next().map(|(key, data)| {
+            K::decode(key).map(|key| (key, data)).ok_or(Error::BadValSize)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_integer_database() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let raw = env.create_db(Some("int"), DatabaseFlags::INTEGER_KEY).unwrap();
+        let plain = env.create_db(Some("plain"), DatabaseFlags::empty()).unwrap();
+        assert_eq!(Error::Incompatible, IntegerDatabase::<u32>::new(&env, plain).unwrap_err());
+        let db = IntegerDatabase::<u32>::new(&env, raw).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &key in &[256u32, 1, 65536, 2] {
+            db.put(&mut txn, key, &key.to_string(), WriteFlags::empty()).unwrap();
+        }
+        assert_eq!(b"256", db.get(&txn, 256).unwrap());
+        db.del(&mut txn, 2, None).unwrap();
+        assert_eq!(Err(Error::NotFound), db.get(&txn, 2));
+        assert_eq!(Err(Error::NotFound), db.del(&mut txn, 2, None));
+        txn.commit().unwrap();
+
+        // Keys are ordered numerically, regardless of byte order.
+        let txn = env.begin_ro_txn().unwrap();
+        let items = db.iter(&txn).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(vec![(1, &b"1"[..]), (256, b"256"), (65536, b"65536")], items);
+        let keys = db.iter_from(&txn, 2).unwrap().map(|item| item.unwrap().0).collect::<Vec<_>>();
+        assert_eq!(vec![256, 65536], keys);
+
+        // Keys of another size can't be decoded.
+        #[cfg(target_pointer_width = "64")]
+        {
+            let wide = IntegerDatabase::<u64> { db: raw, _marker: PhantomData };
+            assert_eq!(Some(Err(Error::BadValSize)), wide.iter(&txn).unwrap().next());
+        }
+    }
+}
@@ -6,30 +6,101 @@
 #![doc(html_root_url = "https://docs.rs/lmdb/0.8.0")]
 
 extern crate libc;
+extern crate siphasher;
 extern crate lmdb_sys as ffi;
 
+#[cfg(feature = "arrow")] extern crate arrow_array;
+#[cfg(feature = "arrow")] extern crate arrow_schema;
+#[cfg(feature = "serde")] extern crate bincode;
+#[cfg(feature = "compression")] extern crate lz4_flex;
+#[cfg(feature = "sqlite")] extern crate rusqlite;
+#[cfg(feature = "serde")] extern crate serde;
 #[cfg(test)] extern crate rand;
-#[cfg(test)] extern crate tempdir;
+#[cfg(any(test, feature = "testing"))] extern crate tempdir;
+#[cfg(feature = "tracing")] extern crate tracing;
 #[cfg(test)] extern crate test;
 #[macro_use] extern crate bitflags;
 
+#[cfg(feature = "arrow")]
+pub use arrow::{export_arrow, ArrowExport, ColumnType, ExportError, ExportResult, SchemaMapping};
+pub use bookmark::Bookmark;
+pub use bulk::{export_pairs, import_pairs, BulkLoader, ExportIter};
+pub use changelog::{ApplyError, ChangeLog, LogEntry, LogOp, LogTail};
+pub use compaction::CompactionScheduler;
 pub use cursor::{
     Cursor,
+    CursorIter,
+    CursorOp,
+    DistinctPrefixes,
     RoCursor,
     RwCursor,
+    UpdateGuard,
     Iter,
+    IterBudget,
     IterDup,
+    IterDupRange,
+    IterKeys,
+    IterPrefix,
+    IterRange,
+    IterValues,
+    Items,
 };
-pub use database::Database;
-pub use environment::{Environment, Stat, EnvironmentBuilder};
-pub use error::{Error, Result};
+#[cfg(feature = "compression")]
+pub use compressed::{CompressedDatabase, CompressedIter};
+pub use database::{Database, DatabaseLimits};
+pub use environment::{
+    CheckLevel,
+    DrainError,
+    DroppedWrites,
+    Environment,
+    EnvironmentBuilder,
+    ResizePolicy,
+    SyncPeriod,
+};
+#[cfg(feature = "pagesize")]
+pub use environment::{MAX_PAGE_SIZE, MIN_PAGE_SIZE};
+pub use digest::{digest, DigestHasher, DigestTree};
+pub use dump::{dump, load};
+#[cfg(feature = "encryption")]
+pub use encrypted::{Cipher, EncryptedDatabase, EncryptedError, EncryptedIter, EncryptedResult};
+pub use error::{EntryError, Error, LoadError, Result};
+#[cfg(feature = "error-context")]
+pub use error::ErrorContext;
 pub use flags::*;
+pub use header::Header;
+pub use integer::{IntegerDatabase, IntegerIter, IntegerKey};
+#[cfg(feature = "metrics")]
+pub use metrics::{Metrics, OperationMetrics};
+pub use mirror::MirroredRead;
+pub use normalized::NormalizedDatabase;
+pub use notify::{CommitEvent, CommitSubscription};
+pub use parallel::parallel_scan;
+pub use pool::{pool_stats, PoolStats, PooledBuf};
+pub use quota::Quota;
+pub use pretty::{Format, Pretty, Printer, PrinterRegistry};
+pub use readahead::SequentialScan;
+pub use registry::{open_registered, FromDatabase};
+pub use scheduler::WriteScheduler;
+pub use sort::ExternalSorter;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{decode_row, export_sqlite, import_sqlite, SqliteError, SqliteResult};
+pub use stat::{DbiStats, EnvironmentInfo, ReaderInfo, ReaderLag, Stat};
+#[cfg(feature = "serde")]
+pub use typed::{TypedDatabase, TypedError, TypedIter, TypedResult};
+pub use transact::{DatabaseSet, TransactContext, TransactError, TransactResult};
+pub use tracking::{HandleKind, OpenHandle};
+pub use usage::{usage_by_prefix, PrefixUsage};
 pub use transaction::{
+    Change,
     InactiveTransaction,
     RoTransaction,
     RwTransaction,
+    SendableRoTransaction,
+    Snapshot,
     Transaction,
 };
+pub use version::{version, version_string};
+pub use weak::WeakEnvironment;
 
 macro_rules! lmdb_try {
     ($expr:expr) => ({
@@ -53,11 +124,53 @@ macro_rules! lmdb_try_with_cleanup {
 }
 
 mod flags;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod bookmark;
+mod bulk;
+mod changelog;
+mod compaction;
+#[cfg(feature = "compression")]
+mod compressed;
 mod cursor;
 mod database;
+mod digest;
+mod dump;
+#[cfg(feature = "encryption")]
+mod encrypted;
 mod environment;
 mod error;
+mod header;
+mod integer;
+mod metrics;
+mod mirror;
+#[cfg(all(test, feature = "multiprocess-tests"))]
+mod multiprocess;
+mod normalized;
+mod notify;
+mod parallel;
+mod pool;
+mod pretty;
+mod quota;
+mod readahead;
+#[macro_use]
+mod registry;
+mod scheduler;
+mod sort;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stat;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod transact;
+mod trace;
+mod tracking;
 mod transaction;
+mod usage;
+mod version;
+mod weak;
+#[cfg(feature = "serde")]
+mod typed;
 
 #[cfg(test)]
 mod test_utils {
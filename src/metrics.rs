@@ -0,0 +1,232 @@
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+use environment::Environment;
+
+/// An operation whose count and latency are recorded.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Operation {
+    BeginRo,
+    BeginRw,
+    Commit,
+    Abort,
+    Get,
+    Put,
+    Cursor,
+}
+
+/// The count and total latency of an operation.
+///
+/// See `Metrics`.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OperationMetrics {
+    count: u64,
+    total_time: Duration,
+}
+
+#[cfg(feature = "metrics")]
+impl OperationMetrics {
+    /// Number of operations, including failed ones.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Total time spent in the operations.
+    #[inline]
+    pub fn total_time(&self) -> Duration {
+        self.total_time
+    }
+
+    /// Mean time spent in an operation, or `None` if there were none.
+    #[inline]
+    pub fn mean_time(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => Some(Duration::from_nanos((self.total_time.as_nanos() / u128::from(count)) as u64)),
+        }
+    }
+}
+
+/// A snapshot of the operations recorded by an environment while `Instrumentation::METRICS` was
+/// enabled, returned by `Environment::metrics`.
+///
+/// The counts and times only grow, so that they can be exported as counters, such as the count
+/// and sum of a Prometheus summary, and rates derived from the difference of two snapshots.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Metrics {
+    operations: [OperationMetrics; OPERATIONS],
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Read-only transactions begun.
+    #[inline]
+    pub fn begin_ro(&self) -> OperationMetrics {
+        self.operations[Operation::BeginRo as usize]
+    }
+
+    /// Read-write transactions begun, including the time spent waiting for the write lock.
+    #[inline]
+    pub fn begin_rw(&self) -> OperationMetrics {
+        self.operations[Operation::BeginRw as usize]
+    }
+
+    /// Transactions committed, including the time spent syncing to disk.
+    #[inline]
+    pub fn commit(&self) -> OperationMetrics {
+        self.operations[Operation::Commit as usize]
+    }
+
+    /// Transactions aborted or dropped without commit.
+    #[inline]
+    pub fn abort(&self) -> OperationMetrics {
+        self.operations[Operation::Abort as usize]
+    }
+
+    /// Items read with `Transaction::get`.
+    #[inline]
+    pub fn get(&self) -> OperationMetrics {
+        self.operations[Operation::Get as usize]
+    }
+
+    /// Items written with `RwTransaction::put` and `RwCursor::put`.
+    #[inline]
+    pub fn put(&self) -> OperationMetrics {
+        self.operations[Operation::Put as usize]
+    }
+
+    /// Cursor positioning and iteration steps.
+    #[inline]
+    pub fn cursor(&self) -> OperationMetrics {
+        self.operations[Operation::Cursor as usize]
+    }
+}
+
+#[cfg(feature = "metrics")]
+const OPERATIONS: usize = Operation::Cursor as usize + 1;
+
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct Counter {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
+
+/// The metrics of an environment, found by transactions and cursors through the environment
+/// they were begun or opened in.
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+pub(crate) struct Recorder {
+    enabled: AtomicBool,
+    counters: [Counter; OPERATIONS],
+}
+
+#[cfg(feature = "metrics")]
+impl Recorder {
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let mut metrics = Metrics::default();
+        for (metrics, counter) in metrics.operations.iter_mut().zip(&self.counters) {
+            metrics.count = counter.count.load(Ordering::Relaxed);
+            metrics.total_time = Duration::from_nanos(counter.nanos.load(Ordering::Relaxed));
+        }
+        metrics
+    }
+
+    fn record(&self, operation: Operation, elapsed: Duration) {
+        let counter = &self.counters[operation as usize];
+        counter.count.fetch_add(1, Ordering::Relaxed);
+        counter.nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Runs the operation, recording its count and latency if its environment records metrics.
+/// Operations of transactions and cursors not begun by an `Environment` are not recorded.
+#[cfg(feature = "metrics")]
+#[inline]
+pub(crate) fn timed<F, T>(env: Option<&Environment>, operation: Operation, f: F) -> T
+where F: FnOnce() -> T {
+    let recorder = match env {
+        Some(env) if env.recorder().enabled.load(Ordering::Relaxed) => env.recorder(),
+        _ => return f(),
+    };
+    let start = Instant::now();
+    let result = f();
+    recorder.record(operation, start.elapsed());
+    result
+}
+
+/// Runs the operation; metrics are only recorded with the `metrics` feature.
+#[cfg(not(feature = "metrics"))]
+#[inline]
+pub(crate) fn timed<F, T>(_env: Option<&Environment>, _operation: Operation, f: F) -> T where F: FnOnce() -> T {
+    f()
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+
+    use std::ptr;
+
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use error::Error;
+    use ffi;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        // Nothing is recorded until metrics are enabled.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(Metrics::default(), env.metrics());
+
+        // The user context of the environment is left to the application.
+        unsafe { ffi::mdb_env_set_userctx(env.env(), ptr::null_mut()) };
+        env.set_instrumentation(Instrumentation::METRICS);
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        {
+            let mut cursor = txn.open_rw_cursor(db).unwrap();
+            cursor.put(b"key3", b"val3", WriteFlags::empty()).unwrap();
+        }
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"missing"));
+        txn.commit().unwrap();
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(b"val1", txn.get(db, b"key1").unwrap());
+        assert_eq!(3, txn.open_ro_cursor(db).unwrap().iter_start().count());
+        drop(txn);
+        env.begin_rw_txn().unwrap().abort();
+
+        let metrics = env.metrics();
+        assert_eq!((1, 2, 1, 2), (metrics.begin_ro().count(), metrics.begin_rw().count(),
+                                   metrics.commit().count(), metrics.abort().count()));
+        assert_eq!((2, 2), (metrics.get().count(), metrics.put().count()));
+        // Stepping past the last item counts too.
+        assert_eq!(4, metrics.cursor().count());
+        assert!(metrics.commit().mean_time().is_some());
+        assert_eq!(None, Metrics::default().get().mean_time());
+
+        env.set_instrumentation(Instrumentation::empty());
+        env.begin_ro_txn().unwrap();
+        assert_eq!(metrics, env.metrics());
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use ffi;
+
+/// A read mirrored by `Environment::set_read_mirror`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MirroredRead {
+    dbi: ffi::MDB_dbi,
+    key: Vec<u8>,
+}
+
+impl MirroredRead {
+
+    /// The handle of the database which was read, as returned by `Database::dbi`.
+    pub fn dbi(&self) -> ffi::MDB_dbi {
+        self.dbi
+    }
+
+    /// The key which was read.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Takes the key which was read.
+    pub fn into_key(self) -> Vec<u8> {
+        self.key
+    }
+}
+
+type Callback = Box<dyn Fn(MirroredRead) + Send + Sync>;
+
+struct Mirror {
+    every: u64,
+    reads: AtomicU64,
+    callback: Callback,
+}
+
+/// The read mirror of an environment.
+#[derive(Default)]
+pub(crate) struct MirrorSlot {
+    enabled: AtomicBool,
+    mirror: RwLock<Option<Arc<Mirror>>>,
+}
+
+impl MirrorSlot {
+
+    pub(crate) fn set(&self, every: u32, callback: Callback) {
+        let mirror = Mirror { every: u64::from(every.max(1)), reads: AtomicU64::new(0), callback };
+        *self.mirror.write().unwrap() = Some(Arc::new(mirror));
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn remove(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        *self.mirror.write().unwrap() = None;
+    }
+
+    /// Passes a read of the key to the mirror, if there is one and the read is sampled.
+    pub(crate) fn record(&self, dbi: ffi::MDB_dbi, key: &[u8]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mirror = match *self.mirror.read().unwrap() {
+            Some(ref mirror) => mirror.clone(),
+            None => return,
+        };
+        if mirror.reads.fetch_add(1, Ordering::Relaxed) % mirror.every == 0 {
+            (mirror.callback)(MirroredRead { dbi, key: key.to_vec() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+
+    #[test]
+    fn test_read_mirror() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        env.set_read_mirror(2, move |read| {
+            let _ = sender.lock().unwrap().send(read);
+        });
+        let txn = env.begin_ro_txn().unwrap();
+        for key in &[&b"key1"[..], b"key2", b"missing", b"key2"] {
+            let _ = txn.get(db, key);
+        }
+        let reads = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(vec![b"key1".to_vec(), b"missing".to_vec()],
+                   reads.iter().map(|read| read.key().to_vec()).collect::<Vec<_>>());
+        assert!(reads.iter().all(|read| read.dbi() == db.dbi()));
+
+        env.remove_read_mirror();
+        txn.get(db, b"key1").unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+}
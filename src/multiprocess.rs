@@ -0,0 +1,236 @@
+// Tests of environments shared between processes. Child processes rerun the test binary with
+// only the `child` test, which performs the role passed in its environment variables.
+
+#[cfg(unix)]
+use libc;
+use std::{env, mem};
+use std::path::Path;
+use std::process::{self, Child, Command};
+
+use tempdir::TempDir;
+
+use environment::Environment;
+use error::Error;
+use flags::*;
+use transaction::Transaction;
+
+/// The variable naming the role of a child process.
+const ROLE: &str = "LMDB_TEST_ROLE";
+
+/// The variable holding the path of the environment of a child process.
+const PATH: &str = "LMDB_TEST_PATH";
+
+/// The variable holding the ID of the parent of a child process.
+const PARENT: &str = "LMDB_TEST_PARENT";
+
+/// The number of transactions committed by each writer.
+const WRITES: u32 = 100;
+
+fn open(path: &Path) -> Environment {
+    Environment::new().set_max_readers(16)
+                      .set_map_size(16 * 1024 * 1024)
+                      .open(path)
+                      .unwrap()
+}
+
+/// Spawns a child process which performs the role on the environment at the path.
+fn spawn(role: &str, path: &Path) -> Child {
+    Command::new(env::current_exe().unwrap())
+        .args(["--exact", "multiprocess::child", "--test-threads", "1", "--quiet"])
+        .env(ROLE, role)
+        .env(PATH, path)
+        .env(PARENT, process::id().to_string())
+        .spawn()
+        .unwrap()
+}
+
+/// Writes keys prefixed with the name, each in its own transaction.
+fn write(env: &Environment, name: &str) {
+    let db = env.open_db(None).unwrap();
+    for i in 0..WRITES {
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, &format!("{}:{:03}", name, i), b"value", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+    }
+}
+
+/// Performs the role of a child process, if this is one.
+#[test]
+fn child() {
+    let role = match env::var(ROLE) {
+        Ok(role) => role,
+        Err(_) => return,
+    };
+    let path = env::var_os(PATH).unwrap();
+    if role == "check-readers" {
+        let env = Environment::new().set_check_readers_on_open(true).open(Path::new(&path)).unwrap();
+        assert_eq!(Some(1), env.stale_readers_cleared());
+        return;
+    }
+    if role == "grow" {
+        // Opening with a larger map grows it, and writes more than fits in the original map.
+        let env = Environment::new().set_map_size(1024 * 1024).open(Path::new(&path)).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for i in 0..16u32 {
+            txn.put(db, &i.to_be_bytes(), &[0; 16 * 1024], WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+        return;
+    }
+    let env = open(Path::new(&path));
+    match role.as_str() {
+        "reader" => {
+            // The parent holds a read-only transaction open while the child runs.
+            let parent: i32 = env::var(PARENT).unwrap().parse().unwrap();
+            assert!(env.reader_list().unwrap().iter().any(|reader| {
+                reader.pid() == parent && reader.txn_id().is_some()
+            }));
+            let db = env.open_db(None).unwrap();
+            let txn = env.begin_ro_txn().unwrap();
+            assert_eq!(b"parent", txn.get(db, b"written-by").unwrap());
+        },
+        "stale-reader" => {
+            // Exit with a read-only transaction open, leaving its slot behind, as a crash does.
+            mem::forget(env.begin_ro_txn().unwrap());
+            process::exit(0);
+        },
+        writer => write(&env, writer),
+    }
+}
+
+#[test]
+fn test_concurrent_writers() {
+    let dir = TempDir::new("test").unwrap();
+    let env = open(dir.path());
+    let children = (0..4).map(|i| spawn(&format!("writer{}", i), dir.path())).collect::<Vec<_>>();
+    write(&env, "parent");
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let db = env.open_db(None).unwrap();
+    let txn = env.begin_ro_txn().unwrap();
+    assert_eq!(5 * WRITES as usize, txn.stat(db).unwrap().entries());
+    assert_eq!(b"value", txn.get(db, b"writer3:099").unwrap());
+    drop(txn);
+    // The children closed their environments, leaving no stale reader slots.
+    assert_eq!(0, env.reader_check().unwrap());
+}
+
+#[test]
+fn test_cross_process_reader() {
+    let dir = TempDir::new("test").unwrap();
+    let env = open(dir.path());
+    let db = env.open_db(None).unwrap();
+    let mut txn = env.begin_rw_txn().unwrap();
+    txn.put(db, b"written-by", b"parent", WriteFlags::empty()).unwrap();
+    txn.commit().unwrap();
+
+    let txn = env.begin_ro_txn().unwrap();
+    assert!(spawn("reader", dir.path()).wait().unwrap().success());
+    drop(txn);
+}
+
+#[test]
+fn test_check_readers_on_open() {
+    let dir = TempDir::new("test").unwrap();
+    // The environment is kept open, since the reader table is reset when it is opened by the
+    // only process using it.
+    let env = open(dir.path());
+    assert_eq!(None, env.stale_readers_cleared());
+    assert!(spawn("stale-reader", dir.path()).wait().unwrap().success());
+    assert!(spawn("check-readers", dir.path()).wait().unwrap().success());
+    assert_eq!(0, env.reader_check().unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reopen_after_fork() {
+    let dir = TempDir::new("test").unwrap();
+    let env = open(dir.path());
+    let db = env.open_db(None).unwrap();
+    let txn = env.begin_ro_txn().unwrap();
+
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        // Exit without unwinding into the test harness of the forked process.
+        let result = ::std::panic::catch_unwind(|| {
+            let reopened = env.reopen_after_fork().unwrap();
+            write(&reopened, "child");
+        });
+        unsafe { libc::_exit(if result.is_ok() { 0 } else { 1 }) };
+    }
+    let mut status = 0;
+    assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+
+    // The child left the reader slot of the parent intact.
+    let own = process::id() as i32;
+    assert!(env.reader_list().unwrap().iter().any(|reader| reader.pid() == own && reader.txn_id().is_some()));
+    drop(txn);
+
+    let txn = env.begin_ro_txn().unwrap();
+    assert_eq!(b"value", txn.get(db, b"child:099").unwrap());
+}
+
+#[test]
+fn test_map_resized() {
+    let dir = TempDir::new("test").unwrap();
+    let env = Environment::new().set_map_size(64 * 1024).open(dir.path()).unwrap();
+    assert!(spawn("grow", dir.path()).wait().unwrap().success());
+    assert_eq!(Error::MapResized, env.begin_ro_txn().unwrap_err());
+}
+
+#[test]
+fn test_adopt_map_resize() {
+    let dir = TempDir::new("test").unwrap();
+    let env = Environment::new().set_map_size(64 * 1024)
+                                .set_adopt_map_resize(true)
+                                .open(dir.path())
+                                .unwrap();
+    let db = env.open_db(None).unwrap();
+    assert!(spawn("grow", dir.path()).wait().unwrap().success());
+
+    let txn = env.begin_ro_txn().unwrap();
+    assert_eq!(16, txn.stat(db).unwrap().entries());
+    assert_eq!(1024 * 1024, env.info().unwrap().map_size());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reopen_readonly() {
+    let dir = TempDir::new("test").unwrap();
+    let env = Environment::new().set_max_dbs(2)
+                                .set_max_readers(10)
+                                .set_map_size(1024 * 1024)
+                                .set_flags(EnvironmentFlags::NO_TLS)
+                                .open(dir.path())
+                                .unwrap();
+    let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+    let mut txn = env.begin_rw_txn().unwrap();
+    txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+    txn.commit().unwrap();
+
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        // Exit without unwinding into the test harness of the forked process.
+        let result = ::std::panic::catch_unwind(|| {
+            let reader = env.reopen_readonly().unwrap();
+            let flags = reader.get_flags().unwrap();
+            assert!(flags.contains(EnvironmentFlags::READ_ONLY | EnvironmentFlags::NO_TLS));
+            assert_eq!(1024 * 1024, reader.info().unwrap().map_size());
+            assert_eq!(10, reader.info().unwrap().max_readers());
+
+            let db = reader.open_db(Some("db")).unwrap();
+            assert_eq!(b"value", reader.begin_ro_txn().unwrap().get(db, b"key").unwrap());
+            assert!(reader.begin_rw_txn().is_err());
+        });
+        unsafe { libc::_exit(if result.is_ok() { 0 } else { 1 }) };
+    }
+    let mut status = 0;
+    assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+use std::result;
+
+use cursor::Cursor;
+use database::Database;
+use error::{Error, Result};
+use flags::WriteFlags;
+use transaction::{RwTransaction, Transaction};
+
+/// A database with an index of normalized keys, allowing lookups such as case-insensitive
+/// matching without a custom comparator.
+///
+/// Entries are stored unchanged in the primary database. The index database maps the normalized
+/// form of each key to the original keys, and must be opened with `DatabaseFlags::DUP_SORT`,
+/// since several keys may share a normalized form. Writes through `NormalizedDatabase` update
+/// both databases in the same transaction; writes made directly to the primary database leave
+/// the index stale until `NormalizedDatabase::rebuild` is called.
+///
+/// Original keys are stored as index values, so they are limited to the maximum key size of the
+/// environment, as usual.
+pub struct NormalizedDatabase<F> {
+    primary: Database,
+    index: Database,
+    normalize: F,
+}
+
+impl <F> fmt::Debug for NormalizedDatabase<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("NormalizedDatabase")
+         .field("primary", &self.primary)
+         .field("index", &self.index)
+         .finish()
+    }
+}
+
+impl NormalizedDatabase<fn(&[u8]) -> Vec<u8>> {
+
+    /// Creates a database whose keys can be looked up ignoring ASCII case.
+    pub fn ascii_case_insensitive(primary: Database, index: Database) -> Self {
+        NormalizedDatabase::new(primary, index, <[u8]>::to_ascii_lowercase)
+    }
+}
+
+impl <F> NormalizedDatabase<F> where F: Fn(&[u8]) -> Vec<u8> {
+
+    /// Creates a database with the given primary and index databases, and key normalization.
+    ///
+    /// The index database must be opened with `DatabaseFlags::DUP_SORT`. Normalized keys must not
+    /// be empty.
+    pub fn new(primary: Database, index: Database, normalize: F) -> NormalizedDatabase<F> {
+        NormalizedDatabase { primary, index, normalize }
+    }
+
+    /// Returns the primary database.
+    pub fn primary(&self) -> Database {
+        self.primary
+    }
+
+    /// Returns the index database.
+    pub fn index(&self) -> Database {
+        self.index
+    }
+
+    /// Stores an item into the primary database and indexes its key.
+    pub fn put<K, D>(&self, txn: &mut RwTransaction, key: &K, data: &D, flags: WriteFlags) -> Result<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let key = key.as_ref();
+        txn.put(self.primary, &key, data, flags)?;
+        self.index_key(txn, key)
+    }
+
+    /// Deletes an item from the primary database and the index.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the primary database.
+    pub fn del<K>(&self, txn: &mut RwTransaction, key: &K) -> Result<()> where K: AsRef<[u8]> {
+        let key = key.as_ref();
+        txn.del(self.primary, &key, None)?;
+        match txn.del(self.index, &(self.normalize)(key), Some(key)) {
+            Ok(()) | Err(Error::NotFound) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Gets the first item whose key has the same normalized form as the given key.
+    ///
+    /// Returns the original key along with the data, or `Error::NotFound` if there is no such
+    /// item.
+    pub fn get<'txn, T, K>(&self, txn: &'txn T, key: &K) -> Result<(&'txn [u8], &'txn [u8])>
+    where T: Transaction, K: AsRef<[u8]> {
+        self.get_all(txn, key)?.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    /// Gets all items whose keys have the same normalized form as the given key, in the order of
+    /// their original keys.
+    pub fn get_all<'txn, T, K>(&self, txn: &'txn T, key: &K) -> Result<Vec<(&'txn [u8], &'txn [u8])>>
+    where T: Transaction, K: AsRef<[u8]> {
+        let normalized = (self.normalize)(key.as_ref());
+        if normalized.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut cursor = txn.open_ro_cursor(self.index)?;
+        let mut items = Vec::new();
+        for (_, original) in cursor.iter_dup_of(&normalized) {
+            match txn.get(self.primary, &original) {
+                Ok(data) => items.push((original, data)),
+                // The index is stale.
+                Err(Error::NotFound) => (),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(items)
+    }
+
+    /// Clears the index and rebuilds it from the keys of the primary database.
+    pub fn rebuild(&self, txn: &mut RwTransaction) -> Result<()> {
+        txn.clear_db(self.index)?;
+        let keys = {
+            let mut cursor = txn.open_ro_cursor(self.primary)?;
+            cursor.iter_start().map(|(key, _)| key.to_vec()).collect::<Vec<_>>()
+        };
+        for key in keys {
+            self.index_key(txn, &key)?;
+        }
+        Ok(())
+    }
+
+    fn index_key(&self, txn: &mut RwTransaction, key: &[u8]) -> Result<()> {
+        match txn.put(self.index, &(self.normalize)(key), &key, WriteFlags::NO_DUP_DATA) {
+            Ok(()) | Err(Error::KeyExist) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let primary = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+        let index = env.create_db(Some("users-lower"), DatabaseFlags::DUP_SORT).unwrap();
+        let db = NormalizedDatabase::ascii_case_insensitive(primary, index);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        db.put(&mut txn, b"Alice", b"1", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"ALICE", b"2", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"Bob", b"3", WriteFlags::empty()).unwrap();
+        db.put(&mut txn, b"Bob", b"4", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!((&b"Bob"[..], &b"4"[..]), db.get(&txn, b"bOB").unwrap());
+        assert_eq!(vec![(&b"ALICE"[..], &b"2"[..]), (&b"Alice"[..], &b"1"[..])],
+                   db.get_all(&txn, b"alice").unwrap());
+        assert_eq!(Err(Error::NotFound), db.get(&txn, b"carol"));
+        assert_eq!(Vec::<(&[u8], &[u8])>::new(), db.get_all(&txn, b"").unwrap());
+        drop(txn);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        db.del(&mut txn, b"ALICE").unwrap();
+        assert_eq!(Err(Error::NotFound), db.del(&mut txn, b"ALICE"));
+        assert_eq!(vec![(&b"Alice"[..], &b"1"[..])], db.get_all(&txn, b"alice").unwrap());
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let primary = env.create_db(Some("primary"), DatabaseFlags::empty()).unwrap();
+        let index = env.create_db(Some("index"), DatabaseFlags::DUP_SORT).unwrap();
+        let db = NormalizedDatabase::new(primary, index, |key: &[u8]| {
+            key.iter().cloned().filter(|byte| *byte != b'-').collect()
+        });
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(primary, b"a-b-c", b"1", WriteFlags::empty()).unwrap();
+        assert_eq!(Err(Error::NotFound), db.get(&txn, b"abc"));
+
+        db.rebuild(&mut txn).unwrap();
+        assert_eq!((&b"a-b-c"[..], &b"1"[..]), db.get(&txn, b"ab-c").unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+
+use database::Database;
+use transaction::Change;
+
+/// A committed write transaction, passed to commit hooks and subscribers.
+///
+/// See `RwTransaction::on_commit` and `Environment::subscribe_commits`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommitEvent {
+    txn_id: usize,
+    databases: Vec<Database>,
+    changes: Option<Vec<Change>>,
+}
+
+impl CommitEvent {
+
+    pub(crate) fn new(txn_id: usize, databases: Vec<Database>, changes: Option<Vec<Change>>) -> CommitEvent {
+        CommitEvent { txn_id, databases, changes }
+    }
+
+    /// The ID of the committed transaction.
+    pub fn txn_id(&self) -> usize {
+        self.txn_id
+    }
+
+    /// The databases written by the transaction, in the order they were first written.
+    pub fn databases(&self) -> &[Database] {
+        &self.databases
+    }
+
+    /// The writes made by the transaction, or `None` if it did not track them with
+    /// `RwTransaction::track_changes`.
+    pub fn changes(&self) -> Option<&[Change]> {
+        self.changes.as_deref()
+    }
+}
+
+/// Identifies a subscriber registered with `Environment::subscribe_commits`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CommitSubscription(usize);
+
+enum Subscriber {
+    Callback(Box<dyn Fn(&CommitEvent) + Send + Sync>),
+    Channel(Mutex<Sender<CommitEvent>>),
+}
+
+/// The commit subscribers of an environment.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    /// Whether there are subscribers, so that commits skip building events otherwise.
+    active: AtomicBool,
+    next_id: AtomicUsize,
+    subscribers: RwLock<Vec<(usize, Arc<Subscriber>)>>,
+}
+
+impl Subscribers {
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn subscribe<F>(&self, callback: F) -> CommitSubscription
+    where F: Fn(&CommitEvent) + Send + Sync + 'static {
+        self.add(Subscriber::Callback(Box::new(callback)))
+    }
+
+    pub(crate) fn channel(&self) -> Receiver<CommitEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.add(Subscriber::Channel(Mutex::new(sender)));
+        receiver
+    }
+
+    fn add(&self, subscriber: Subscriber) -> CommitSubscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut subscribers = self.subscribers.write().unwrap();
+        subscribers.push((id, Arc::new(subscriber)));
+        self.active.store(true, Ordering::Relaxed);
+        CommitSubscription(id)
+    }
+
+    pub(crate) fn unsubscribe(&self, subscription: CommitSubscription) -> bool {
+        self.retain(|id| id != subscription.0)
+    }
+
+    /// Removes the subscribers for which `keep` returns false, returning whether any was.
+    fn retain<F>(&self, keep: F) -> bool where F: Fn(usize) -> bool {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let len = subscribers.len();
+        subscribers.retain(|&(id, _)| keep(id));
+        self.active.store(!subscribers.is_empty(), Ordering::Relaxed);
+        subscribers.len() != len
+    }
+
+    /// Passes the event to every subscriber, removing channels whose receiver was dropped.
+    pub(crate) fn notify(&self, event: &CommitEvent) {
+        // The list is copied so that subscribers may subscribe and unsubscribe.
+        let subscribers = self.subscribers.read().unwrap().clone();
+        let mut disconnected = Vec::new();
+        for (id, subscriber) in subscribers {
+            match *subscriber {
+                Subscriber::Callback(ref callback) => callback(event),
+                Subscriber::Channel(ref sender) => {
+                    if sender.lock().unwrap().send(event.clone()).is_err() {
+                        disconnected.push(id);
+                    }
+                },
+            }
+        }
+        if !disconnected.is_empty() {
+            self.retain(|id| !disconnected.contains(&id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::atomic::AtomicUsize;
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_commit_notifications() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let users = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+        let events = env.create_db(Some("events"), DatabaseFlags::empty()).unwrap();
+
+        let commits = Arc::new(AtomicUsize::new(0));
+        let subscription = {
+            let commits = commits.clone();
+            env.subscribe_commits(move |_| {
+                commits.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        let receiver = env.commit_channel();
+
+        let hooked = Arc::new(Mutex::new(None));
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(events, b"event", b"1", WriteFlags::empty()).unwrap();
+        txn.track_changes();
+        txn.put(users, b"user", b"name", WriteFlags::empty()).unwrap();
+        txn.put(events, b"event", b"2", WriteFlags::empty()).unwrap();
+        let id = txn.id();
+        {
+            let hooked = hooked.clone();
+            txn.on_commit(move |event| *hooked.lock().unwrap() = Some(event.clone()));
+        }
+        txn.commit().unwrap();
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!((id, &[events, users][..]), (event.txn_id(), event.databases()));
+        assert_eq!(Some(&[Change::Put { db: users, key: b"user".to_vec() },
+                          Change::Put { db: events, key: b"event".to_vec() }][..]),
+                   event.changes());
+        assert_eq!(Some(event), hooked.lock().unwrap().take());
+        assert_eq!(1, commits.load(Ordering::SeqCst));
+
+        // Aborted transactions are not notified, and the hooks of one transaction don't run for
+        // another.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(users, b"user", b"other", WriteFlags::empty()).unwrap();
+        txn.abort();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.del(users, b"user", None).unwrap();
+        txn.commit().unwrap();
+        let event = receiver.try_recv().unwrap();
+        assert_eq!((&[users][..], None), (event.databases(), event.changes()));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(None, hooked.lock().unwrap().take());
+        assert_eq!(2, commits.load(Ordering::SeqCst));
+
+        assert!(env.unsubscribe_commits(subscription));
+        assert!(!env.unsubscribe_commits(subscription));
+        drop(receiver);
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(users, b"user", b"name", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(2, commits.load(Ordering::SeqCst));
+        // The dropped channel was removed by the commit.
+        assert!(!env.commit_subscribers().is_active());
+    }
+
+    #[test]
+    fn test_nested_commit_notifications() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).open(dir.path()).unwrap();
+        let users = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+        let events = env.create_db(Some("events"), DatabaseFlags::empty()).unwrap();
+        let tags = env.create_db(Some("tags"), DatabaseFlags::empty()).unwrap();
+        let receiver = env.commit_channel();
+
+        let hooked = Arc::new(Mutex::new(Vec::new()));
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.track_changes();
+        txn.put(users, b"user", b"name", WriteFlags::empty()).unwrap();
+        {
+            let mut nested = txn.begin_nested_txn().unwrap();
+            nested.put(events, b"event", b"1", WriteFlags::empty()).unwrap();
+            let hooked = hooked.clone();
+            nested.on_commit(move |event| hooked.lock().unwrap().push(event.txn_id()));
+            nested.commit().unwrap();
+        }
+        {
+            let mut nested = txn.begin_nested_txn().unwrap();
+            nested.put(tags, b"tag", b"a", WriteFlags::empty()).unwrap();
+            let hooked = hooked.clone();
+            nested.on_commit(move |_| hooked.lock().unwrap().push(0));
+            nested.abort();
+        }
+        {
+            // Opening a write cursor without writing through it does not write the database.
+            drop(txn.open_rw_cursor(tags).unwrap());
+            let mut cursor = txn.open_rw_cursor(users).unwrap();
+            cursor.put(b"other", b"name", WriteFlags::empty()).unwrap();
+        }
+        let id = txn.id();
+        // The hooks of a committed nested transaction wait for the top-level commit.
+        assert!(hooked.lock().unwrap().is_empty());
+        txn.commit().unwrap();
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!((id, &[users, events][..]), (event.txn_id(), event.databases()));
+        assert_eq!(Some(&[Change::Put { db: users, key: b"user".to_vec() },
+                          Change::Put { db: events, key: b"event".to_vec() }][..]),
+                   event.changes());
+        assert_eq!(vec![id], *hooked.lock().unwrap());
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::ops::Bound;
+use std::thread;
+
+use cursor::{Cursor, IterRange};
+use database::Database;
+use environment::Environment;
+use error::Result;
+use flags::DatabaseFlags;
+use transaction::{RoTransaction, Transaction};
+
+/// The number of key bytes after the common prefix of the first and last keys used to estimate
+/// split points.
+const SPLIT_WINDOW: usize = 8;
+
+/// Scans a database in parallel, splitting its key range into partitions which are each scanned
+/// by `f` on a separate thread with its own read-only transaction.
+///
+/// Returns the results of `f` in key order of the partitions. Split points are estimated by
+/// interpolating between the first and last keys of the database and seeking to the nearest
+/// keys, so partitions of databases with unevenly distributed keys may differ in size. Fewer than
+/// `partitions` partitions are scanned if the database is small, and databases with
+/// `DatabaseFlags::REVERSE_KEY` or `DatabaseFlags::INTEGER_KEY`, whose keys are not ordered as
+/// byte strings, are scanned as a single partition.
+///
+/// Each partition reads its own snapshot, so if the database is written concurrently the
+/// partitions may observe different versions of it. Each thread uses a reader slot, so the
+/// environment must be configured with enough readers through
+/// `EnvironmentBuilder::set_max_readers`.
+pub fn parallel_scan<F, R>(env: &Environment, db: Database, partitions: usize, f: F) -> Result<Vec<R>>
+where F: Fn(IterRange) -> R + Sync, R: Send {
+    let splits = {
+        let txn = env.begin_ro_txn()?;
+        let flags = txn.db_flags(db)?;
+        if partitions > 1 && !flags.intersects(DatabaseFlags::REVERSE_KEY | DatabaseFlags::INTEGER_KEY) {
+            split_points(&txn, db, partitions)?
+        } else {
+            Vec::new()
+        }
+    };
+
+    let mut bounds = Vec::with_capacity(splits.len() + 1);
+    let mut start = Bound::Unbounded;
+    for split in &splits {
+        bounds.push((start, Bound::Excluded(&split[..])));
+        start = Bound::Included(&split[..]);
+    }
+    bounds.push((start, Bound::Unbounded));
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles = bounds.into_iter().map(|range| {
+            scope.spawn(move || -> Result<R> {
+                let txn = env.begin_ro_txn()?;
+                let mut cursor = txn.open_ro_cursor(db)?;
+                Ok(f(cursor.iter_range::<&[u8], _>(range)))
+            })
+        }).collect::<Vec<_>>();
+        handles.into_iter()
+               .map(|handle| handle.join().unwrap_or_else(|panic| ::std::panic::resume_unwind(panic)))
+               .collect()
+    })
+}
+
+/// Returns up to `partitions - 1` increasing keys of the database which split it into partitions.
+fn split_points(txn: &RoTransaction, db: Database, partitions: usize) -> Result<Vec<Vec<u8>>> {
+    let cursor = txn.open_ro_cursor(db)?;
+    let (first, last) = match (cursor.first()?, cursor.last()?) {
+        (Some((first, _)), Some((last, _))) => (first, last),
+        _ => return Ok(Vec::new()),
+    };
+
+    let prefix_len = first.iter().zip(last).take_while(|&(a, b)| a == b).count();
+    let window = |key: &[u8]| -> u64 {
+        let mut bytes = [0u8; SPLIT_WINDOW];
+        let tail = &key[prefix_len..];
+        let len = tail.len().min(SPLIT_WINDOW);
+        bytes[..len].copy_from_slice(&tail[..len]);
+        u64::from_be_bytes(bytes)
+    };
+    let (low, high) = (window(first), window(last));
+
+    let mut splits: Vec<Vec<u8>> = Vec::new();
+    for i in 1..partitions {
+        let offset = (u128::from(high - low) * i as u128 / partitions as u128) as u64;
+        let mut probe = first[..prefix_len].to_vec();
+        probe.extend_from_slice(&(low + offset).to_be_bytes());
+        let key = match cursor.seek_range(&probe)? {
+            Some((key, _)) => key,
+            None => break,
+        };
+        let is_new = match splits.last() {
+            Some(prev) => key > &prev[..],
+            None => key > first,
+        };
+        if is_new {
+            splits.push(key.to_vec());
+        }
+    }
+    Ok(splits)
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_parallel_scan() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let empty = env.create_db(Some("empty"), DatabaseFlags::empty()).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for i in 0..10_000u32 {
+            txn.put(db, &format!("key{:05}", i), &i.to_be_bytes(), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let counts = parallel_scan(&env, db, 4, |iter| iter.count()).unwrap();
+        assert_eq!(4, counts.len());
+        assert_eq!(10_000, counts.iter().sum::<usize>());
+        assert!(counts.iter().all(|&count| count > 1000), "{:?}", counts);
+
+        // Partitions are returned in key order, and don't overlap.
+        let ranges = parallel_scan(&env, db, 4, |mut iter| {
+            let first = iter.next().map(|(key, _)| key.to_vec());
+            (first, iter.last().map(|(key, _)| key.to_vec()))
+        }).unwrap();
+        for pair in ranges.windows(2) {
+            assert!(pair[0].1 < pair[1].0);
+        }
+
+        assert_eq!(vec![10_000], parallel_scan(&env, db, 1, |iter| iter.count()).unwrap());
+        assert_eq!(vec![0], parallel_scan(&env, empty, 4, |iter| iter.count()).unwrap());
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fmt, mem, result};
+
+/// The maximum number of buffers kept for reuse by each thread.
+const MAX_POOLED: usize = 64;
+
+/// The capacity above which a buffer is freed rather than kept for reuse, so that a single large
+/// value does not pin its allocation.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    /// The buffers of this thread which are free for reuse.
+    static POOL: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+static RECYCLED: AtomicUsize = AtomicUsize::new(0);
+static DISCARDED: AtomicUsize = AtomicUsize::new(0);
+
+/// An owned copy of a value, in a buffer which is returned to a thread-local pool when dropped.
+///
+/// Returned by `Transaction::get_owned`. Reusing buffers spares the allocator when many values
+/// are copied out of short-lived transactions. A buffer is returned to the pool of the thread
+/// which drops it, unless the pool is full or the buffer is larger than 64 KiB.
+pub struct PooledBuf {
+    buf: Vec<u8>,
+}
+
+impl PooledBuf {
+
+    /// Copies the bytes into a buffer from the pool of the current thread.
+    pub(crate) fn copy_from(bytes: &[u8]) -> PooledBuf {
+        let buf = POOL.try_with(|pool| pool.borrow_mut().pop()).ok().and_then(|buf| buf);
+        let mut buf = match buf {
+            Some(buf) => {
+                HITS.fetch_add(1, Ordering::Relaxed);
+                buf
+            },
+            None => {
+                MISSES.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(bytes.len())
+            },
+        };
+        buf.extend_from_slice(bytes);
+        PooledBuf { buf }
+    }
+
+    /// Takes the buffer out of the pool, so that it is not returned when dropped.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        mem::take(&mut self.buf)
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut buf = mem::take(&mut self.buf);
+        if buf.capacity() == 0 {
+            return;
+        }
+        buf.clear();
+        let recycled = buf.capacity() <= MAX_POOLED_CAPACITY && POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED {
+                pool.push(buf);
+                true
+            } else {
+                false
+            }
+        }).unwrap_or(false);
+        if recycled {
+            RECYCLED.fetch_add(1, Ordering::Relaxed);
+        } else {
+            DISCARDED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for PooledBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl fmt::Debug for PooledBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_tuple("PooledBuf").field(&&self.buf[..]).finish()
+    }
+}
+
+/// Counters of the buffer pool used by `Transaction::get_owned`, summed over all threads since
+/// the process started.
+///
+/// A high ratio of misses to hits means that threads copy out more values at once than their
+/// pools hold, and a high number of discarded buffers that values are larger than the pool keeps.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PoolStats {
+    hits: usize,
+    misses: usize,
+    recycled: usize,
+    discarded: usize,
+}
+
+impl PoolStats {
+
+    /// The number of buffers taken from a pool.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The number of buffers allocated because the pool of the thread was empty.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// The number of buffers returned to a pool when dropped.
+    pub fn recycled(&self) -> usize {
+        self.recycled
+    }
+
+    /// The number of buffers freed when dropped, because the pool was full or the buffer too
+    /// large.
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+}
+
+/// Returns the counters of the buffer pool.
+pub fn pool_stats() -> PoolStats {
+    PoolStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        recycled: RECYCLED.load(Ordering::Relaxed),
+        discarded: DISCARDED.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_get_owned() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"value2", WriteFlags::empty()).unwrap();
+        txn.put(db, b"large", &vec![0; MAX_POOLED_CAPACITY + 1], WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let before = pool_stats();
+        let txn = env.begin_ro_txn().unwrap();
+        let value = txn.get_owned(db, b"key2").unwrap();
+        assert_eq!(b"value2", &*value);
+        drop(value);
+
+        // The buffer is reused by the next value copied on this thread, keeping its capacity.
+        let value = txn.get_owned(db, b"key1").unwrap();
+        assert_eq!(b"val1", &*value);
+        let value = value.into_vec();
+        assert_eq!(b"val1", &value[..]);
+        assert_eq!(b"value2".len(), value.capacity());
+        assert_eq!(Error::NotFound, txn.get_owned(db, b"missing").unwrap_err());
+        drop(txn.get_owned(db, b"large").unwrap());
+
+        let after = pool_stats();
+        assert!(after.hits() > before.hits());
+        assert!(after.recycled() > before.recycled());
+        assert!(after.discarded() > before.discarded());
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::{fmt, result, str};
+
+/// Formats raw keys or values for display.
+pub trait Printer: Send + Sync {
+
+    /// Writes a human readable representation of the bytes.
+    fn print(&self, bytes: &[u8], out: &mut dyn fmt::Write) -> fmt::Result;
+}
+
+impl <F> Printer for F where F: Fn(&[u8], &mut dyn fmt::Write) -> fmt::Result + Send + Sync {
+    fn print(&self, bytes: &[u8], out: &mut dyn fmt::Write) -> fmt::Result {
+        self(bytes, out)
+    }
+}
+
+/// Built-in encodings of keys and values.
+///
+/// Integer formats fall back to `Format::Hex` if the bytes are not the size of the integer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Hex digits prefixed with `0x`.
+    Hex,
+    /// A quoted UTF-8 string, or hex if the bytes are not valid UTF-8.
+    Utf8,
+    /// A quoted UTF-8 string if the bytes are valid UTF-8 without control characters,
+    /// otherwise hex.
+    Auto,
+    /// A big-endian `u32`.
+    U32Be,
+    /// A little-endian `u32`.
+    U32Le,
+    /// A big-endian `u64`.
+    U64Be,
+    /// A little-endian `u64`.
+    U64Le,
+    /// A native-endian integer of 4 or 8 bytes, as used by `DatabaseFlags::INTEGER_KEY`.
+    Native,
+}
+
+impl Format {
+
+    /// Parses the name of a format, as used by `lmdb-tool`.
+    ///
+    /// The names are `hex`, `utf8`, `auto`, `u32be`, `u32le`, `u64be`, `u64le` and `native`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "hex" => Some(Format::Hex),
+            "utf8" => Some(Format::Utf8),
+            "auto" => Some(Format::Auto),
+            "u32be" => Some(Format::U32Be),
+            "u32le" => Some(Format::U32Le),
+            "u64be" => Some(Format::U64Be),
+            "u64le" => Some(Format::U64Le),
+            "native" => Some(Format::Native),
+            _ => None,
+        }
+    }
+}
+
+fn print_hex(bytes: &[u8], out: &mut dyn fmt::Write) -> fmt::Result {
+    out.write_str("0x")?;
+    for byte in bytes {
+        write!(out, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+impl Printer for Format {
+    fn print(&self, bytes: &[u8], out: &mut dyn fmt::Write) -> fmt::Result {
+        let mut b4 = [0u8; 4];
+        let mut b8 = [0u8; 8];
+        match (*self, bytes.len()) {
+            (Format::Hex, _) => print_hex(bytes, out),
+            (Format::Utf8, _) => match str::from_utf8(bytes) {
+                Ok(s) => write!(out, "{:?}", s),
+                Err(..) => print_hex(bytes, out),
+            },
+            (Format::Auto, _) => match str::from_utf8(bytes) {
+                Ok(s) if !s.chars().any(char::is_control) => write!(out, "{:?}", s),
+                _ => print_hex(bytes, out),
+            },
+            (Format::U32Be, 4) => { b4.copy_from_slice(bytes); write!(out, "{}", u32::from_be_bytes(b4)) },
+            (Format::U32Le, 4) => { b4.copy_from_slice(bytes); write!(out, "{}", u32::from_le_bytes(b4)) },
+            (Format::Native, 4) => { b4.copy_from_slice(bytes); write!(out, "{}", u32::from_ne_bytes(b4)) },
+            (Format::U64Be, 8) => { b8.copy_from_slice(bytes); write!(out, "{}", u64::from_be_bytes(b8)) },
+            (Format::U64Le, 8) => { b8.copy_from_slice(bytes); write!(out, "{}", u64::from_le_bytes(b8)) },
+            (Format::Native, 8) => { b8.copy_from_slice(bytes); write!(out, "{}", u64::from_ne_bytes(b8)) },
+            _ => print_hex(bytes, out),
+        }
+    }
+}
+
+/// The key and value printers of a database.
+#[derive(Clone)]
+struct DbPrinters {
+    key: Arc<dyn Printer>,
+    value: Arc<dyn Printer>,
+}
+
+/// A registry of per-database key and value printers.
+///
+/// Databases are identified by name, with `None` denoting the main database. Databases without
+/// registered printers use `Format::Auto`.
+#[derive(Clone)]
+pub struct PrinterRegistry {
+    dbs: HashMap<Option<String>, DbPrinters>,
+    default: DbPrinters,
+}
+
+impl Default for PrinterRegistry {
+    fn default() -> PrinterRegistry {
+        PrinterRegistry::new()
+    }
+}
+
+impl fmt::Debug for PrinterRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("PrinterRegistry").field("dbs", &self.dbs.keys()).finish()
+    }
+}
+
+impl PrinterRegistry {
+
+    /// Creates an empty registry.
+    pub fn new() -> PrinterRegistry {
+        PrinterRegistry {
+            dbs: HashMap::new(),
+            default: DbPrinters { key: Arc::new(Format::Auto), value: Arc::new(Format::Auto) },
+        }
+    }
+
+    /// Registers the key printer of the named database.
+    pub fn set_key_printer<P>(&mut self, name: Option<&str>, printer: P) -> &mut PrinterRegistry
+    where P: Printer + 'static {
+        self.entry(name).key = Arc::new(printer);
+        self
+    }
+
+    /// Registers the value printer of the named database.
+    pub fn set_value_printer<P>(&mut self, name: Option<&str>, printer: P) -> &mut PrinterRegistry
+    where P: Printer + 'static {
+        self.entry(name).value = Arc::new(printer);
+        self
+    }
+
+    /// Removes the printers of the named database.
+    pub fn remove(&mut self, name: Option<&str>) {
+        self.dbs.remove(&name.map(str::to_owned));
+    }
+
+    /// Returns a displayable representation of a key of the named database.
+    pub fn key<'a>(&'a self, name: Option<&str>, key: &'a [u8]) -> Pretty<'a> {
+        Pretty { printer: &*self.printers(name).key, bytes: key }
+    }
+
+    /// Returns a displayable representation of a value of the named database.
+    pub fn value<'a>(&'a self, name: Option<&str>, value: &'a [u8]) -> Pretty<'a> {
+        Pretty { printer: &*self.printers(name).value, bytes: value }
+    }
+
+    fn printers(&self, name: Option<&str>) -> &DbPrinters {
+        self.dbs.get(&name.map(str::to_owned)).unwrap_or(&self.default)
+    }
+
+    fn entry(&mut self, name: Option<&str>) -> &mut DbPrinters {
+        let default = self.default.clone();
+        self.dbs.entry(name.map(str::to_owned)).or_insert(default)
+    }
+}
+
+/// A key or value formatted by a `Printer`.
+pub struct Pretty<'a> {
+    printer: &'a dyn Printer,
+    bytes: &'a [u8],
+}
+
+impl <'a> fmt::Display for Pretty<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        self.printer.print(self.bytes, f)
+    }
+}
+
+impl <'a> fmt::Debug for Pretty<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        self.printer.print(self.bytes, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        let pretty = |format: Format, bytes: &[u8]| {
+            let mut s = String::new();
+            format.print(bytes, &mut s).unwrap();
+            s
+        };
+        assert_eq!("0x00ff", pretty(Format::Hex, &[0, 255]));
+        assert_eq!("\"key\"", pretty(Format::Utf8, b"key"));
+        assert_eq!("\"a\\nb\"", pretty(Format::Utf8, b"a\nb"));
+        assert_eq!("0x610a62", pretty(Format::Auto, b"a\nb"));
+        assert_eq!("0xff", pretty(Format::Utf8, &[255]));
+        assert_eq!("258", pretty(Format::U32Be, &[0, 0, 1, 2]));
+        assert_eq!("513", pretty(Format::U64Le, &[1, 2, 0, 0, 0, 0, 0, 0]));
+        assert_eq!("0x0102", pretty(Format::U64Be, &[1, 2]));
+        assert_eq!("7", pretty(Format::Native, &7u64.to_ne_bytes()));
+        assert_eq!(Some(Format::U64Be), Format::from_name("u64be"));
+        assert_eq!(None, Format::from_name("u128"));
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = PrinterRegistry::new();
+        registry.set_key_printer(Some("counts"), Format::U64Be)
+                .set_value_printer(Some("counts"), |bytes: &[u8], out: &mut dyn fmt::Write| {
+                    write!(out, "{} bytes", bytes.len())
+                });
+
+        let key = 42u64.to_be_bytes();
+        assert_eq!("42", registry.key(Some("counts"), &key).to_string());
+        assert_eq!("3 bytes", registry.value(Some("counts"), b"abc").to_string());
+        assert_eq!("0x000000000000002a", registry.key(None, &key).to_string());
+        assert_eq!("\"abc\"", registry.value(None, b"abc").to_string());
+
+        registry.remove(Some("counts"));
+        assert_eq!("\"abc\"", registry.value(Some("counts"), b"abc").to_string());
+    }
+}
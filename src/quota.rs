@@ -0,0 +1,174 @@
+use libc::{c_uint, c_void, size_t};
+use std::collections::HashMap;
+use std::{mem, ptr};
+
+use ffi;
+
+use error::{Error, Result, lmdb_result};
+
+/// Limits on the size of a database, enforced on writes.
+///
+/// Quotas are set with `Environment::set_quota`, and checked against the counters which LMDB
+/// maintains for every database, so checking them does not scan the database. The checks are
+/// conservative: the size of a database is measured in whole pages, and a write is assumed to
+/// grow the database by the size of its key and data.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct Quota {
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl Quota {
+
+    /// Creates a quota without limits.
+    pub fn new() -> Quota {
+        Quota::default()
+    }
+
+    /// Returns the quota with a limit on the number of items in the database.
+    pub fn with_max_entries(self, max_entries: usize) -> Quota {
+        Quota { max_entries: Some(max_entries), ..self }
+    }
+
+    /// Returns the quota with a limit on the size in bytes of the pages used by the database.
+    pub fn with_max_bytes(self, max_bytes: usize) -> Quota {
+        Quota { max_bytes: Some(max_bytes), ..self }
+    }
+
+    /// Returns the limit on the number of items in the database.
+    pub fn max_entries(&self) -> Option<usize> {
+        self.max_entries
+    }
+
+    /// Returns the limit on the size in bytes of the pages used by the database.
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+}
+
+/// The quotas of an environment, by database handle.
+pub(crate) type Quotas = HashMap<ffi::MDB_dbi, Quota>;
+
+/// Returns `Error::QUOTA_EXCEEDED` if writing `entries` items with the key and `data_len` bytes of
+/// data in total would exceed the quota of the database.
+pub(crate) unsafe fn check_quota(txn: *mut ffi::MDB_txn,
+                                 dbi: ffi::MDB_dbi,
+                                 quota: &Quota,
+                                 key: &[u8],
+                                 entries: usize,
+                                 data_len: usize)
+                                 -> Result<()> {
+    let mut stat: ffi::MDB_stat = mem::zeroed();
+    lmdb_result(ffi::mdb_stat(txn, dbi, &mut stat))?;
+    if let Some(max_entries) = quota.max_entries {
+        if stat.ms_entries.saturating_add(entries) > max_entries && !replaces(txn, dbi, key)? {
+            return Err(Error::QUOTA_EXCEEDED);
+        }
+    }
+    if let Some(max_bytes) = quota.max_bytes {
+        let pages = stat.ms_branch_pages + stat.ms_leaf_pages + stat.ms_overflow_pages;
+        if pages * stat.ms_psize as usize + key.len() + data_len > max_bytes {
+            return Err(Error::QUOTA_EXCEEDED);
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether a write of the key replaces an existing item rather than adding one.
+unsafe fn replaces(txn: *mut ffi::MDB_txn, dbi: ffi::MDB_dbi, key: &[u8]) -> Result<bool> {
+    let mut flags: c_uint = 0;
+    lmdb_result(ffi::mdb_dbi_flags(txn, dbi, &mut flags))?;
+    if flags & ffi::MDB_DUPSORT != 0 {
+        return Ok(false);
+    }
+    let mut key_val = ffi::MDB_val { mv_size: key.len() as size_t, mv_data: key.as_ptr() as *mut c_void };
+    let mut data_val = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+    match lmdb_result(ffi::mdb_get(txn, dbi, &mut key_val, &mut data_val)) {
+        Ok(()) => Ok(true),
+        Err(Error::NotFound) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::*;
+    use super::*;
+
+    #[test]
+    fn test_max_entries() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("tenant"), DatabaseFlags::empty()).unwrap();
+        let other = env.create_db(Some("other"), DatabaseFlags::empty()).unwrap();
+        env.set_quota(db, Quota::new().with_max_entries(2));
+        assert_eq!(Some(Quota::new().with_max_entries(2)), env.quota(db));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        assert_eq!(Err(Error::QUOTA_EXCEEDED), txn.put(db, b"key3", b"val3", WriteFlags::empty()));
+        assert_eq!(Err(Error::QUOTA_EXCEEDED),
+                   txn.put_many(db, vec![(b"key3", b"val3")], WriteFlags::empty()));
+        // Replacing an item doesn't add an entry.
+        txn.put(db, b"key2", b"new2", WriteFlags::empty()).unwrap();
+        txn.put(other, b"key3", b"val3", WriteFlags::empty()).unwrap();
+        txn.del(db, b"key1", None).unwrap();
+        txn.put(db, b"key3", b"val3", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        env.remove_quota(db);
+        assert_eq!(None, env.quota(db));
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key4", b"val4", WriteFlags::empty()).unwrap();
+    }
+
+    #[test]
+    fn test_max_entries_many() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("tenant"), DatabaseFlags::empty()).unwrap();
+        let fixed = env.create_db(Some("fixed"), DatabaseFlags::DUP_SORT | DatabaseFlags::DUP_FIXED).unwrap();
+        env.set_quota(db, Quota::new().with_max_entries(3));
+        env.set_quota(fixed, Quota::new().with_max_entries(3));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let items = vec![(b"key1", b"val1"), (b"key2", b"val2"), (b"key3", b"val3"), (b"key4", b"val4")];
+        assert_eq!(Err(Error::QUOTA_EXCEEDED), txn.put_many(db, items, WriteFlags::empty()));
+        assert_eq!(3, txn.stat(db).unwrap().entries());
+
+        let mut cursor = txn.open_rw_cursor(fixed).unwrap();
+        assert_eq!(Err(Error::QUOTA_EXCEEDED), cursor.put_multiple(b"key", &[1, 2, 3, 4], 1, WriteFlags::empty()));
+        assert_eq!(Ok(3), cursor.put_multiple(b"key", &[1, 2, 3], 1, WriteFlags::empty()));
+        assert_eq!(Err(Error::QUOTA_EXCEEDED), cursor.put(b"key", &[4], WriteFlags::empty()));
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let page_size = env.stat().unwrap().page_size() as usize;
+        env.set_quota(db, Quota::new().with_max_bytes(2 * page_size));
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let value = vec![0u8; 100];
+        let mut written = 0;
+        let error = loop {
+            match txn.put(db, &format!("key{:05}", written), &value, WriteFlags::empty()) {
+                Ok(()) => written += 1,
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(Error::QUOTA_EXCEEDED, error);
+        assert!(written > 10, "{}", written);
+        assert_eq!(Err(Error::QUOTA_EXCEEDED),
+                   txn.reserve(db, b"big", 2 * page_size, WriteFlags::empty()).map(|_| ()));
+    }
+}
@@ -0,0 +1,142 @@
+#[cfg(unix)]
+use libc::size_t;
+use std::{fmt, result};
+
+#[cfg(unix)]
+use ffi;
+
+use environment::Environment;
+use error::Result;
+#[cfg(unix)]
+use error::{Error, lmdb_result};
+
+/// A guard which hints to the operating system that the environment is about to be read
+/// sequentially, such as by a full scan of a large database.
+///
+/// Created by `Environment::sequential_scan`. While a guard is alive the kernel reads ahead more
+/// aggressively when pages of the environment are faulted in, which speeds up cold scans on
+/// spinning disks and network filesystems. When the last guard of the environment is dropped the
+/// default readahead behavior is restored.
+///
+/// LMDB only exposes the address of its memory map when the environment is opened with
+/// `EnvironmentFlags::FIXED_MAP`. In that case the map is advised with `MADV_SEQUENTIAL`;
+/// otherwise the data file is advised with `POSIX_FADV_SEQUENTIAL`, which enlarges the readahead
+/// window used when the map faults in pages from the file. On platforms without either
+/// mechanism the guard has no effect.
+pub struct SequentialScan<'env> {
+    env: &'env Environment,
+}
+
+impl <'env> fmt::Debug for SequentialScan<'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("SequentialScan").finish()
+    }
+}
+
+impl <'env> SequentialScan<'env> {
+
+    /// Applies the sequential hint to the environment, unless another guard already has.
+    /// Prefer using `Environment::sequential_scan`.
+    pub(crate) fn new(env: &'env Environment) -> Result<SequentialScan<'env>> {
+        let mut scans = env.sequential_scans().lock().unwrap();
+        if *scans == 0 {
+            advise(env, true)?;
+        }
+        *scans += 1;
+        Ok(SequentialScan { env })
+    }
+}
+
+impl <'env> Drop for SequentialScan<'env> {
+    fn drop(&mut self) {
+        let mut scans = self.env.sequential_scans().lock().unwrap();
+        *scans -= 1;
+        if *scans == 0 {
+            let _ = advise(self.env, false);
+        }
+    }
+}
+
+/// Applies sequential or normal readahead advice to the environment.
+#[cfg(unix)]
+fn advise(env: &Environment, sequential: bool) -> Result<()> {
+    let info = env.info()?;
+    let map = info.0.me_mapaddr;
+    if !map.is_null() {
+        return advise_map(map, info.map_size(), sequential);
+    }
+    let mut fd: ffi::mdb_filehandle_t = 0;
+    unsafe { lmdb_result(ffi::mdb_env_get_fd(env.env(), &mut fd))?; }
+    advise_file(fd, sequential)
+}
+
+#[cfg(unix)]
+fn advise_map(map: *mut ::libc::c_void, len: size_t, sequential: bool) -> Result<()> {
+    let advice = if sequential { ::libc::MADV_SEQUENTIAL } else { ::libc::MADV_NORMAL };
+    if unsafe { ::libc::madvise(map, len, advice) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::Other(::std::io::Error::last_os_error().raw_os_error().unwrap_or(0)))
+    }
+}
+
+/// LMDB's file handles are not file descriptors on other platforms, and readahead advice is left
+/// to the operating system.
+#[cfg(not(unix))]
+fn advise(_env: &Environment, _sequential: bool) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise_file(fd: ffi::mdb_filehandle_t, sequential: bool) -> Result<()> {
+    let advice = if sequential { ::libc::POSIX_FADV_SEQUENTIAL } else { ::libc::POSIX_FADV_NORMAL };
+    match unsafe { ::libc::posix_fadvise(fd, 0, 0, advice) } {
+        0 => Ok(()),
+        err_code => Err(Error::Other(err_code)),
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android", target_os = "freebsd"))))]
+fn advise_file(_fd: ffi::mdb_filehandle_t, _sequential: bool) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+
+    #[test]
+    fn test_sequential_scan() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for i in 0..1000u32 {
+            txn.put(db, &i.to_be_bytes(), &[0u8; 100], WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let scan = env.sequential_scan().unwrap();
+        {
+            // Guards may overlap.
+            let _nested = env.sequential_scan().unwrap();
+        }
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(1000, txn.open_ro_cursor(db).unwrap().iter_start().count());
+        drop(scan);
+        assert_eq!(0, *env.sequential_scans().lock().unwrap());
+    }
+
+    #[test]
+    fn test_sequential_scan_fixed_map() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_flags(EnvironmentFlags::FIXED_MAP).open(dir.path()).unwrap();
+        let _scan = env.sequential_scan().unwrap();
+    }
+}
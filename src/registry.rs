@@ -0,0 +1,212 @@
+#[cfg(feature = "compression")]
+use compressed::CompressedDatabase;
+use database::Database;
+use environment::Environment;
+use error::{Error, Result};
+use flags::DatabaseFlags;
+use integer::{IntegerDatabase, IntegerKey};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use typed::TypedDatabase;
+
+/// A database handle which can be declared in a `define_databases!` registry.
+pub trait FromDatabase: Sized {
+
+    /// The flags which the database must be created with, in addition to those declared in the
+    /// registry.
+    const FLAGS: DatabaseFlags;
+
+    /// Wraps the handle of the opened database.
+    fn from_database(env: &Environment, db: Database) -> Result<Self>;
+}
+
+impl FromDatabase for Database {
+
+    const FLAGS: DatabaseFlags = DatabaseFlags::empty();
+
+    fn from_database(_env: &Environment, db: Database) -> Result<Database> {
+        Ok(db)
+    }
+}
+
+impl <K> FromDatabase for IntegerDatabase<K> where K: IntegerKey {
+
+    const FLAGS: DatabaseFlags = DatabaseFlags::INTEGER_KEY;
+
+    fn from_database(env: &Environment, db: Database) -> Result<IntegerDatabase<K>> {
+        IntegerDatabase::new(env, db)
+    }
+}
+
+#[cfg(feature = "compression")]
+impl FromDatabase for CompressedDatabase {
+
+    const FLAGS: DatabaseFlags = DatabaseFlags::empty();
+
+    fn from_database(_env: &Environment, db: Database) -> Result<CompressedDatabase> {
+        Ok(CompressedDatabase::new(db))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <K, V> FromDatabase for TypedDatabase<K, V>
+where K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned {
+
+    const FLAGS: DatabaseFlags = DatabaseFlags::empty();
+
+    fn from_database(_env: &Environment, db: Database) -> Result<TypedDatabase<K, V>> {
+        Ok(TypedDatabase::new(db))
+    }
+}
+
+/// Opens a database declared in a `define_databases!` registry, creating it if `create` is
+/// true, and checks that it has exactly the declared flags.
+#[doc(hidden)]
+pub fn open_registered<D>(env: &Environment, name: &str, flags: DatabaseFlags, create: bool) -> Result<D>
+where D: FromDatabase {
+    let flags = flags | D::FLAGS;
+    let db = match env.open_db(Some(name)) {
+        Err(Error::NotFound) if create => env.create_db(Some(name), flags)?,
+        result => result?,
+    };
+    if env.get_db_flags(db)? != flags {
+        return Err(Error::Incompatible);
+    }
+    D::from_database(env, db)
+}
+
+/// Defines a struct holding the handles of the named databases of an application, which are
+/// opened together at startup.
+///
+/// Each field declares a named database, called after the field, with the type of its handle,
+/// and optionally its flags. The type may be any `FromDatabase` implementation, such as
+/// `Database`, `IntegerDatabase<K>`, or `TypedDatabase<K, V>` with the `serde` feature, whose
+/// required flags are added to the declared ones. Declaring every database in one place turns
+/// a misspelled name into a compile error, and catches databases whose flags drifted from their
+/// declaration when the registry is opened.
+///
+/// The struct has two constructors, which fail with `Error::Incompatible` if a database exists
+/// with other flags:
+///
+/// * `create(env: &Environment) -> Result<Self>` creates the databases which don't exist, and
+/// * `open(env: &Environment) -> Result<Self>` fails with `Error::NotFound` if one doesn't,
+///   which is suitable for read-only environments.
+///
+/// Like `Environment::open_db`, they fail with `Error::BadRslot` if called by a thread with an
+/// open transaction. `NAMES` lists the names of the databases, so that the environment can be
+/// configured with enough of them through `EnvironmentBuilder::set_max_dbs`.
+///
+/// ## Example
+///
+/// ```ignore
+/// define_databases! {
+///     /// The databases of the application.
+///     pub struct Databases {
+///         users: TypedDatabase<u64, User>,
+///         events: IntegerDatabase<u64>,
+///         tags: Database = DatabaseFlags::DUP_SORT,
+///     }
+/// }
+///
+/// let dbs = Databases::create(&env)?;
+/// let txn = env.begin_ro_txn()?;
+/// let user = dbs.users.get(&txn, &user_id)?;
+/// ```
+#[macro_export]
+macro_rules! define_databases {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field:ident : $ty:ty $(= $flags:expr)*),* $(,)*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* pub $field: $ty,)*
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+
+            /// The names of the databases.
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            /// Opens the databases, creating those which don't exist.
+            pub fn create(env: &$crate::Environment) -> $crate::Result<$name> {
+                Ok($name {
+                    $($field: $crate::open_registered(env,
+                                                      stringify!($field),
+                                                      $crate::DatabaseFlags::empty() $(| $flags)*,
+                                                      true)?,)*
+                })
+            }
+
+            /// Opens the databases, which must exist.
+            pub fn open(env: &$crate::Environment) -> $crate::Result<$name> {
+                Ok($name {
+                    $($field: $crate::open_registered(env,
+                                                      stringify!($field),
+                                                      $crate::DatabaseFlags::empty() $(| $flags)*,
+                                                      false)?,)*
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    define_databases! {
+        /// The databases of a test application.
+        struct Databases {
+            users: Database,
+            /// Events by sequence number.
+            events: IntegerDatabase<u32>,
+            tags: Database = DatabaseFlags::DUP_SORT | DatabaseFlags::DUP_FIXED,
+        }
+    }
+
+    define_databases! {
+        #[allow(dead_code)]
+        struct Drifted {
+            tags: Database = DatabaseFlags::DUP_SORT,
+        }
+    }
+
+    #[test]
+    fn test_define_databases() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(Databases::NAMES.len() as u32).open(dir.path()).unwrap();
+        assert_eq!(&["users", "events", "tags"], Databases::NAMES);
+        assert_eq!(Err(Error::NotFound), Databases::open(&env).map(|_| ()));
+
+        let dbs = Databases::create(&env).unwrap();
+        assert_eq!(DatabaseFlags::INTEGER_KEY, env.get_db_flags(dbs.events.database()).unwrap());
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(dbs.users, b"user", b"name", WriteFlags::empty()).unwrap();
+        dbs.events.put(&mut txn, 1, b"event", WriteFlags::empty()).unwrap();
+        txn.put(dbs.tags, b"tag", b"a", WriteFlags::empty()).unwrap();
+        txn.put(dbs.tags, b"tag", b"b", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let dbs = Databases::open(&env).unwrap();
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(b"name", txn.get(dbs.users, b"user").unwrap());
+        assert_eq!(b"event", dbs.events.get(&txn, 1).unwrap());
+        drop(txn);
+
+        assert_eq!(Err(Error::Incompatible), Drifted::create(&env).map(|_| ()));
+        assert_eq!(Err(Error::Incompatible), Drifted::open(&env).map(|_| ()));
+    }
+}
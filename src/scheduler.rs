@@ -0,0 +1,324 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::{fmt, result};
+
+use environment::Environment;
+use error::{Error, Result};
+use transaction::{RwTransaction, Transaction};
+
+/// Serializes writes to an environment on a dedicated writer thread, committing writes which are
+/// submitted together in a single transaction.
+///
+/// LMDB allows a single write transaction at a time, so rather than contending for it, threads
+/// submit closures to the scheduler, which are run in the order they are submitted. While the
+/// writer thread is busy, further closures queue up, and are then run in one transaction, up to
+/// the maximum batch size, so that a burst of small writes shares a single commit and sync. With
+/// a commit window, the writer thread also waits for further closures after the first of a batch,
+/// which coalesces writes submitted at a steady rate into a single commit, at the cost of
+/// delaying each commit by up to the window.
+///
+/// Each closure runs in its own nested transaction, so a closure which fails has its writes
+/// discarded without affecting the others in the batch; the same holds for a closure which
+/// panics, whose panic is reported to its submitter only. Nested transactions are not supported in
+/// environments opened with `EnvironmentFlags::WRITE_MAP`, so each closure is committed alone
+/// there.
+///
+/// Dropping the scheduler waits for the submitted closures to be committed.
+pub struct WriteScheduler {
+    sender: Option<Sender<Box<dyn Write>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl fmt::Debug for WriteScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("WriteScheduler").finish()
+    }
+}
+
+impl WriteScheduler {
+
+    /// Starts a scheduler for the environment, which commits up to 128 closures per transaction.
+    pub fn new(env: Arc<Environment>) -> WriteScheduler {
+        WriteScheduler::with_max_batch(env, 128)
+    }
+
+    /// Starts a scheduler for the environment, which commits up to `max_batch` closures per
+    /// transaction.
+    pub fn with_max_batch(env: Arc<Environment>, max_batch: usize) -> WriteScheduler {
+        WriteScheduler::with_commit_window(env, max_batch, Duration::from_secs(0))
+    }
+
+    /// Starts a scheduler for the environment, which commits up to `max_batch` closures per
+    /// transaction, waiting up to `window` after the first closure of a batch for further
+    /// closures to join it.
+    ///
+    /// Small durable writes are dominated by the cost of syncing each commit, so a window of a
+    /// millisecond or so can multiply their throughput. The window is not used in environments
+    /// opened with `EnvironmentFlags::WRITE_MAP`, where each closure is committed alone.
+    pub fn with_commit_window(env: Arc<Environment>, max_batch: usize, window: Duration) -> WriteScheduler {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("lmdb-writer".to_owned())
+            .spawn(move || run_writer(&env, &receiver, max_batch.max(1), window))
+            .expect("failed to spawn the writer thread");
+        WriteScheduler { sender: Some(sender), thread: Some(thread) }
+    }
+
+    /// Submits a closure to be run in a write transaction, and returns a receiver for its result.
+    ///
+    /// The result is sent once the transaction is committed, or as soon as the closure fails. If
+    /// the commit fails, its error is sent instead of the result of the closure. If the closure
+    /// panics, its writes are discarded and the receiver is disconnected without a result; the
+    /// writer thread keeps running the other closures.
+    pub fn submit<F, T>(&self, f: F) -> Receiver<Result<T>>
+    where F: FnOnce(&mut RwTransaction) -> Result<T> + Send + 'static, T: Send + 'static {
+        let (sender, receiver) = mpsc::channel();
+        let job = Box::new(Job { f, sender });
+        // If the writer thread has stopped, the job is dropped and the receiver disconnected.
+        let _ = self.sender.as_ref().unwrap().send(job);
+        receiver
+    }
+
+    /// Runs a closure in a write transaction, and waits for it to be committed.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the closure panicked.
+    pub fn write<F, T>(&self, f: F) -> Result<T>
+    where F: FnOnce(&mut RwTransaction) -> Result<T> + Send + 'static, T: Send + 'static {
+        self.submit(f).recv().expect("the closure panicked")
+    }
+}
+
+impl Drop for WriteScheduler {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sends the result of a closure, given the result of committing its transaction.
+type Complete = Box<dyn FnOnce(Result<()>)>;
+
+/// A closure submitted to a `WriteScheduler`, with the sender for its result.
+trait Write: Send {
+
+    /// Runs the closure in the transaction, in a nested transaction if `nested` is true. Returns
+    /// whether it succeeded, and a function which sends its result given the result of the commit.
+    /// A panic of the closure is caught, and counts as a failure.
+    fn run(self: Box<Self>, txn: &mut RwTransaction, nested: bool) -> (bool, Complete);
+
+    /// Sends the error without running the closure.
+    fn fail(self: Box<Self>, error: Error);
+}
+
+struct Job<F, T> {
+    f: F,
+    sender: Sender<Result<T>>,
+}
+
+impl <F, T> Write for Job<F, T>
+where F: FnOnce(&mut RwTransaction) -> Result<T> + Send, T: Send + 'static {
+
+    fn run(self: Box<Self>, txn: &mut RwTransaction, nested: bool) -> (bool, Complete) {
+        let Job { f, sender } = *self;
+        // `None` if the closure panicked.
+        let result = if nested {
+            match txn.begin_nested_txn() {
+                Ok(mut nested) => match panic::catch_unwind(AssertUnwindSafe(|| f(&mut nested))) {
+                    Ok(Ok(value)) => Some(nested.commit().map(|()| value)),
+                    Ok(Err(error)) => {
+                        nested.abort();
+                        Some(Err(error))
+                    },
+                    Err(_) => {
+                        nested.abort();
+                        None
+                    },
+                },
+                Err(error) => Some(Err(error)),
+            }
+        } else {
+            panic::catch_unwind(AssertUnwindSafe(|| f(txn))).ok()
+        };
+        let ok = result.as_ref().is_some_and(Result::is_ok);
+        (ok, Box::new(move |commit: Result<()>| {
+            // The receiver of a closure which panicked is disconnected without a result.
+            if let Some(result) = result {
+                let _ = sender.send(result.and_then(|value| commit.map(|()| value)));
+            }
+        }))
+    }
+
+    fn fail(self: Box<Self>, error: Error) {
+        let _ = self.sender.send(Err(error));
+    }
+}
+
+/// Runs batches of submitted closures until the scheduler is dropped.
+fn run_writer(env: &Environment, receiver: &Receiver<Box<dyn Write>>, max_batch: usize, window: Duration) {
+    let nested = !env.write_map();
+    while let Ok(first) = receiver.recv() {
+        let deadline = Instant::now() + window;
+        let mut batch = vec![first];
+        while nested && batch.len() < max_batch {
+            let job = match deadline.checked_duration_since(Instant::now()) {
+                Some(timeout) if !timeout.is_zero() => receiver.recv_timeout(timeout).ok(),
+                _ => receiver.try_recv().ok(),
+            };
+            match job {
+                Some(job) => batch.push(job),
+                None => break,
+            }
+        }
+        let mut txn = match env.begin_rw_txn() {
+            Ok(txn) => txn,
+            Err(error) => {
+                for job in batch {
+                    job.fail(error);
+                }
+                continue;
+            },
+        };
+        let mut failed = false;
+        let completions = batch.into_iter().map(|job| {
+            let (ok, complete) = job.run(&mut txn, nested);
+            failed |= !ok;
+            complete
+        }).collect::<Vec<_>>();
+        // Without nested transactions, a failed closure may have left partial writes.
+        let commit = if failed && !nested {
+            txn.abort();
+            Ok(())
+        } else {
+            txn.commit()
+        };
+        for complete in completions {
+            complete(commit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_write_scheduler() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        let scheduler = WriteScheduler::new(env.clone());
+
+        let receivers = (0..100u32).map(|i| {
+            scheduler.submit(move |txn| {
+                txn.put(db, &i.to_be_bytes(), &i.to_string(), WriteFlags::empty())?;
+                Ok(i)
+            })
+        }).collect::<Vec<_>>();
+        let failed = scheduler.submit(move |txn| {
+            txn.put(db, b"failed", b"", WriteFlags::empty())?;
+            txn.get(db, b"missing").map(|_| ())
+        });
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(Ok(i as u32), receiver.recv().unwrap());
+        }
+        assert_eq!(Err(Error::NotFound), failed.recv().unwrap());
+        assert_eq!(Ok(b"99".to_vec()), scheduler.write(move |txn| Ok(txn.get(db, &99u32.to_be_bytes())?.to_vec())));
+        drop(scheduler);
+
+        // The writes of the failed closure were discarded.
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(100, txn.len(db).unwrap());
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"failed"));
+    }
+
+    #[test]
+    fn test_write_scheduler_panic() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        let scheduler = WriteScheduler::with_commit_window(env.clone(), 3, Duration::from_secs(10));
+
+        let before = scheduler.submit(move |txn| txn.put(db, b"key1", b"", WriteFlags::empty()));
+        let panicked = scheduler.submit(move |txn| -> Result<()> {
+            txn.put(db, b"key2", b"", WriteFlags::empty())?;
+            panic!("closure panicked");
+        });
+        let after = scheduler.submit(move |txn| txn.put(db, b"key3", b"", WriteFlags::empty()));
+        assert_eq!(Ok(()), before.recv().unwrap());
+        assert!(panicked.recv().is_err());
+        assert_eq!(Ok(()), after.recv().unwrap());
+
+        // The writer thread keeps running.
+        let later = scheduler.submit(move |txn| txn.put(db, b"key4", b"", WriteFlags::empty()));
+        drop(scheduler);
+        assert_eq!(Ok(()), later.recv().unwrap());
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(3, txn.len(db).unwrap());
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"key2"));
+    }
+
+    #[test]
+    fn test_write_scheduler_commit_window() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        let scheduler = WriteScheduler::with_commit_window(env.clone(), 4, Duration::from_secs(10));
+
+        // Writes submitted within the window share a commit, up to the maximum batch size.
+        let receivers = (0..6u32).map(|i| {
+            scheduler.submit(move |txn| {
+                txn.put(db, &i.to_be_bytes(), b"", WriteFlags::empty())?;
+                Ok(txn.id())
+            })
+        }).collect::<Vec<_>>();
+        let ids = receivers[..4].iter().map(|receiver| receiver.recv().unwrap().unwrap()).collect::<Vec<_>>();
+        assert!(ids.iter().all(|&id| id == ids[0]));
+        drop(scheduler);
+        for receiver in &receivers[4..] {
+            assert_eq!(Ok(ids[0] + 1), receiver.recv().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_write_scheduler_write_map() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().set_flags(EnvironmentFlags::WRITE_MAP).open(dir.path()).unwrap());
+        let db = env.open_db(None).unwrap();
+        let scheduler = WriteScheduler::new(env.clone());
+
+        let ok = scheduler.submit(move |txn| txn.put(db, b"key1", b"", WriteFlags::empty()));
+        let failed = scheduler.submit(move |txn| {
+            txn.put(db, b"key2", b"", WriteFlags::empty())?;
+            Err::<(), _>(Error::Incompatible)
+        });
+        assert_eq!(Ok(()), ok.recv().unwrap());
+        assert_eq!(Err(Error::Incompatible), failed.recv().unwrap());
+        let panicked = scheduler.submit(move |txn| -> Result<()> {
+            txn.put(db, b"key3", b"", WriteFlags::empty())?;
+            panic!("closure panicked");
+        });
+        assert!(panicked.recv().is_err());
+        drop(scheduler);
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(Ok(&b""[..]), txn.get(db, b"key1"));
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"key2"));
+        assert_eq!(Err(Error::NotFound), txn.get(db, b"key3"));
+    }
+}
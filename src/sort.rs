@@ -0,0 +1,303 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, process, result, vec};
+
+use bulk::BulkLoader;
+use database::Database;
+use environment::Environment;
+use error::LoadError;
+
+/// The memory counted for each buffered entry in addition to its key and value.
+const ENTRY_OVERHEAD: usize = 48;
+
+/// Distinguishes the run files of sorters in the same process.
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts key/value pairs which may not fit in memory, and bulk loads them into a database.
+///
+/// Pairs are buffered in memory up to a limit, then sorted and spilled to a temporary file as a
+/// sorted run. Loading merges the runs and writes the pairs in key order with a `BulkLoader`,
+/// which is much faster than writing unsorted pairs, and leaves the database densely packed.
+///
+/// Keys are sorted as byte strings, so the database must not use `DatabaseFlags::REVERSE_KEY`,
+/// `DatabaseFlags::INTEGER_KEY`, `DatabaseFlags::REVERSE_DUP` or `DatabaseFlags::INTEGER_DUP`.
+/// Since pairs are appended, the database should be empty, or all keys must sort after its
+/// existing keys.
+///
+/// Identical key/value pairs are loaded once. Otherwise, duplicate keys fail with
+/// `Error::KeyExist` unless the database uses `DatabaseFlags::DUP_SORT`.
+pub struct ExternalSorter {
+    memory_limit: usize,
+    commit_interval: usize,
+    temp_dir: PathBuf,
+    buffer: Vec<(Vec<u8>, Vec<u8>)>,
+    buffered_bytes: usize,
+    runs: Vec<Run>,
+}
+
+/// A sorted run spilled to a temporary file, which is removed when the run is dropped.
+struct Run {
+    path: PathBuf,
+    file: File,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A sorted source of pairs being merged.
+enum Source {
+    Memory(vec::IntoIter<(Vec<u8>, Vec<u8>)>),
+    Run(BufReader<File>),
+}
+
+impl Source {
+    fn next(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match *self {
+            Source::Memory(ref mut iter) => Ok(iter.next()),
+            Source::Run(ref mut reader) => read_pair(reader),
+        }
+    }
+}
+
+fn write_pair(writer: &mut dyn Write, key: &[u8], value: &[u8]) -> io::Result<()> {
+    writer.write_all(&(key.len() as u32).to_le_bytes())?;
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(value)
+}
+
+fn read_pair(reader: &mut dyn Read) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let mut key = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut len)?;
+    let mut value = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut key)?;
+    reader.read_exact(&mut value)?;
+    Ok(Some((key, value)))
+}
+
+impl Default for ExternalSorter {
+    fn default() -> ExternalSorter {
+        ExternalSorter::new()
+    }
+}
+
+impl ExternalSorter {
+
+    /// Creates a sorter with a memory limit of 64MiB, which commits every 100,000 pairs and
+    /// spills runs to the system temporary directory.
+    pub fn new() -> ExternalSorter {
+        ExternalSorter {
+            memory_limit: 64 << 20,
+            commit_interval: 100_000,
+            temp_dir: env::temp_dir(),
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Sets the approximate number of bytes of pairs buffered in memory before a sorted run is
+    /// spilled to disk.
+    pub fn set_memory_limit(&mut self, memory_limit: usize) -> &mut ExternalSorter {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Sets the number of pairs written per write transaction while loading.
+    ///
+    /// LMDB limits the number of pages a single transaction may dirty, so very large loads must
+    /// be split into several transactions. If loading fails, the transactions committed so far
+    /// remain in the database.
+    pub fn set_commit_interval(&mut self, commit_interval: usize) -> &mut ExternalSorter {
+        self.commit_interval = commit_interval.max(1);
+        self
+    }
+
+    /// Sets the directory in which sorted runs are spilled.
+    pub fn set_temp_dir<P>(&mut self, temp_dir: P) -> &mut ExternalSorter where P: Into<PathBuf> {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+
+    /// Adds a key/value pair, spilling a sorted run to disk if the memory limit is reached.
+    pub fn push(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.buffered_bytes += key.len() + value.len() + ENTRY_OVERHEAD;
+        self.buffer.push((key.to_vec(), value.to_vec()));
+        if self.buffered_bytes >= self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of sorted runs spilled to disk so far.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        let path = self.temp_dir.join(format!("lmdb-sort-{}-{}.run",
+                                              process::id(),
+                                              RUN_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        let mut run = Run { path, file };
+        {
+            let mut writer = BufWriter::new(&mut run.file);
+            for (key, value) in self.buffer.drain(..) {
+                write_pair(&mut writer, &key, &value)?;
+            }
+            writer.flush()?;
+        }
+        run.file.seek(SeekFrom::Start(0))?;
+        self.runs.push(run);
+        self.buffered_bytes = 0;
+        Ok(())
+    }
+
+    /// Sorts the pairs added so far and loads them into the database, returning the number of
+    /// pairs written.
+    ///
+    /// The sorter is left empty, and may be reused.
+    pub fn load(&mut self, env: &Environment, db: Database) -> result::Result<usize, LoadError> {
+        let mut loader = BulkLoader::new(env, db)?;
+        loader.set_commit_interval(self.commit_interval);
+
+        // Runs are removed once merged, or if the merge fails.
+        let runs = self.runs.drain(..).collect::<Vec<_>>();
+        let mut buffer = self.buffer.split_off(0);
+        self.buffered_bytes = 0;
+        buffer.sort_unstable();
+
+        let mut sources = vec![Source::Memory(buffer.into_iter())];
+        for run in &runs {
+            sources.push(Source::Run(BufReader::new(run.file.try_clone()?)));
+        }
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (index, source) in sources.iter_mut().enumerate() {
+            if let Some((key, value)) = source.next()? {
+                heap.push(Reverse((key, value, index)));
+            }
+        }
+
+        let mut prev: Option<(Vec<u8>, Vec<u8>)> = None;
+        while let Some(Reverse((key, value, index))) = heap.pop() {
+            if let Some((next_key, next_value)) = sources[index].next()? {
+                heap.push(Reverse((next_key, next_value, index)));
+            }
+            if let Some((ref prev_key, ref prev_value)) = prev {
+                if *prev_key == key && *prev_value == value {
+                    continue;
+                }
+            }
+            loader.put(&key, &value)?;
+            prev = Some((key, value));
+        }
+        Ok(loader.finish()?)
+    }
+
+    /// Adds the pairs, then sorts and loads them into the database, returning the number of
+    /// pairs written.
+    pub fn load_iter<I, K, V>(&mut self, env: &Environment, db: Database, pairs: I)
+                              -> result::Result<usize, LoadError>
+    where I: IntoIterator<Item = (K, V)>, K: AsRef<[u8]>, V: AsRef<[u8]> {
+        for (key, value) in pairs {
+            self.push(key.as_ref(), value.as_ref())?;
+        }
+        self.load(env, db)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{Rng, XorShiftRng};
+    use tempdir::TempDir;
+
+    use cursor::Cursor;
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_sort_and_load() {
+        let dir = TempDir::new("test").unwrap();
+        let runs_dir = TempDir::new("runs").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut keys = (0..1000u32).collect::<Vec<_>>();
+        XorShiftRng::new_unseeded().shuffle(&mut keys);
+
+        let mut sorter = ExternalSorter::new();
+        sorter.set_memory_limit(4096).set_commit_interval(100).set_temp_dir(runs_dir.path());
+        for key in &keys {
+            sorter.push(&key.to_be_bytes(), format!("value{}", key).as_bytes()).unwrap();
+        }
+        // Identical pairs are loaded once.
+        sorter.push(&7u32.to_be_bytes(), b"value7").unwrap();
+        assert!(sorter.runs() > 1);
+        assert_eq!(1000, sorter.load(&env, db).unwrap());
+        assert_eq!(0, sorter.runs());
+        assert_eq!(0, runs_dir.path().read_dir().unwrap().count());
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        let items = cursor.iter_start().collect::<Vec<_>>();
+        assert_eq!(1000, items.len());
+        for (i, &(key, value)) in items.iter().enumerate() {
+            assert_eq!(&(i as u32).to_be_bytes()[..], key);
+            assert_eq!(format!("value{}", i).as_bytes(), value);
+        }
+    }
+
+    #[test]
+    fn test_load_dup_sort() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+        let plain = env.create_db(Some("plain"), DatabaseFlags::empty()).unwrap();
+        let pairs = vec![(&b"b"[..], &b"2"[..]), (b"a", b"1"), (b"b", b"1"), (b"a", b"2")];
+
+        let mut sorter = ExternalSorter::new();
+        sorter.set_memory_limit(0);
+        assert_eq!(4, sorter.load_iter(&env, db, pairs.clone()).unwrap());
+        {
+            let txn = env.begin_ro_txn().unwrap();
+            let mut cursor = txn.open_ro_cursor(db).unwrap();
+            assert_eq!(vec![(&b"a"[..], &b"1"[..]), (b"a", b"2"), (b"b", b"1"), (b"b", b"2")],
+                       cursor.iter_start().collect::<Vec<_>>());
+        }
+
+        match sorter.load_iter(&env, plain, pairs) {
+            Err(LoadError::Lmdb(Error::KeyExist)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_incompatible() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::INTEGER_KEY).unwrap();
+
+        match ExternalSorter::new().load_iter(&env, db, vec![(&1u32.to_ne_bytes(), b"1")]) {
+            Err(LoadError::Lmdb(Error::Incompatible)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
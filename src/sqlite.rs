@@ -0,0 +1,268 @@
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::{fmt, result};
+
+use rusqlite::types::Value;
+use rusqlite::{self, params_from_iter, Connection};
+
+use cursor::Cursor;
+use database::Database;
+use error::Error;
+use flags::WriteFlags;
+use transaction::{RwTransaction, Transaction};
+
+/// An error from copying a table between SQLite and LMDB.
+#[derive(Debug)]
+pub enum SqliteError {
+    /// An error returned by LMDB.
+    Lmdb(Error),
+    /// An error returned by SQLite.
+    Sqlite(rusqlite::Error),
+    /// The key column of a row is `NULL` or a floating point number, which can't be a key.
+    InvalidKey,
+    /// The value of the item with the key is not a row encoded by `import_sqlite`.
+    InvalidRow {
+        /// The key of the item.
+        key: Vec<u8>,
+    },
+}
+
+impl fmt::Display for SqliteError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SqliteError::Lmdb(ref error) => error.fmt(fmt),
+            SqliteError::Sqlite(ref error) => error.fmt(fmt),
+            SqliteError::InvalidKey => fmt.write_str("key column is NULL or REAL"),
+            SqliteError::InvalidRow { ref key } => {
+                fmt.write_str("invalid row at key ")?;
+                for byte in key {
+                    write!(fmt, "{:02x}", byte)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl StdError for SqliteError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            SqliteError::Lmdb(ref error) => Some(error),
+            SqliteError::Sqlite(ref error) => Some(error),
+            SqliteError::InvalidKey | SqliteError::InvalidRow { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for SqliteError {
+    fn from(error: Error) -> SqliteError {
+        SqliteError::Lmdb(error)
+    }
+}
+
+impl From<rusqlite::Error> for SqliteError {
+    fn from(error: rusqlite::Error) -> SqliteError {
+        SqliteError::Sqlite(error)
+    }
+}
+
+/// A SQLite bridge result.
+pub type SqliteResult<T> = result::Result<T, SqliteError>;
+
+/// Copies the rows of a SQLite table into the database, and returns the number of rows copied.
+///
+/// Each row is stored under the value of its `key_column`, replacing any existing item with the
+/// same key. Text and blob keys are stored as their bytes, and integer keys as 8 big-endian bytes
+/// with the sign bit flipped, so that they sort numerically. The value of each item is the whole
+/// row, including the key column, encoded so that `decode_row` and `export_sqlite` can recover
+/// the values of its columns in table order.
+///
+/// Requires the `sqlite` feature.
+pub fn import_sqlite(conn: &Connection,
+                     table: &str,
+                     key_column: &str,
+                     txn: &mut RwTransaction,
+                     db: Database)
+                     -> SqliteResult<usize> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote(table)))?;
+    let columns = stmt.column_count();
+    let key_index = stmt.column_index(key_column)?;
+    let mut rows = stmt.query([])?;
+    let mut row_buf = Vec::new();
+    let mut copied = 0;
+    while let Some(row) = rows.next()? {
+        let key = match row.get(key_index)? {
+            Value::Integer(key) => ((key as u64) ^ (1 << 63)).to_be_bytes().to_vec(),
+            Value::Text(key) => key.into_bytes(),
+            Value::Blob(key) => key,
+            Value::Null | Value::Real(..) => return Err(SqliteError::InvalidKey),
+        };
+        row_buf.clear();
+        for index in 0..columns {
+            encode_value(&row.get(index)?, &mut row_buf);
+        }
+        txn.put(db, &key, &row_buf, WriteFlags::empty())?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Inserts the rows stored in the database by `import_sqlite` into a SQLite table, in key order,
+/// and returns the number of rows inserted.
+///
+/// The table must have the columns of the imported table, in the same order. Each row is inserted
+/// with its own statement, so large exports should be wrapped in a SQLite transaction.
+///
+/// Requires the `sqlite` feature.
+pub fn export_sqlite<T>(txn: &T, db: Database, conn: &Connection, table: &str) -> SqliteResult<usize>
+where T: Transaction {
+    let mut cursor = txn.open_ro_cursor(db)?;
+    let mut stmt = None;
+    let mut exported = 0;
+    for (key, row) in cursor.iter_start() {
+        let values = decode_row(row).ok_or_else(|| SqliteError::InvalidRow { key: key.to_vec() })?;
+        if stmt.is_none() {
+            let params = vec!["?"; values.len()].join(", ");
+            stmt = Some(conn.prepare(&format!("INSERT INTO {} VALUES ({})", quote(table), params))?);
+        }
+        stmt.as_mut().unwrap().execute(params_from_iter(values))?;
+        exported += 1;
+    }
+    Ok(exported)
+}
+
+/// Decodes the values of a row stored by `import_sqlite`, in table order.
+///
+/// Returns `None` if the bytes are not an encoded row.
+pub fn decode_row(mut bytes: &[u8]) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    while let Some((&tag, rest)) = bytes.split_first() {
+        let (value, rest) = match tag {
+            TAG_NULL => (Value::Null, rest),
+            TAG_INTEGER | TAG_REAL => {
+                let (number, rest) = split(rest, 8)?;
+                let number = <[u8; 8]>::try_from(number).ok()?;
+                if tag == TAG_INTEGER {
+                    (Value::Integer(i64::from_be_bytes(number)), rest)
+                } else {
+                    (Value::Real(f64::from_bits(u64::from_be_bytes(number))), rest)
+                }
+            },
+            TAG_TEXT | TAG_BLOB => {
+                let (len, rest) = split(rest, 4)?;
+                let len = u32::from_be_bytes(<[u8; 4]>::try_from(len).ok()?) as usize;
+                let (data, rest) = split(rest, len)?;
+                if tag == TAG_TEXT {
+                    (Value::Text(String::from_utf8(data.to_vec()).ok()?), rest)
+                } else {
+                    (Value::Blob(data.to_vec()), rest)
+                }
+            },
+            _ => return None,
+        };
+        values.push(value);
+        bytes = rest;
+    }
+    Some(values)
+}
+
+const TAG_NULL: u8 = 0;
+const TAG_INTEGER: u8 = 1;
+const TAG_REAL: u8 = 2;
+const TAG_TEXT: u8 = 3;
+const TAG_BLOB: u8 = 4;
+
+/// Appends the encoding of a value to the buffer: a tag byte, followed by 8 big-endian bytes for
+/// numbers, or a 4 byte big-endian length and the bytes for text and blobs.
+fn encode_value(value: &Value, buf: &mut Vec<u8>) {
+    match *value {
+        Value::Null => buf.push(TAG_NULL),
+        Value::Integer(number) => {
+            buf.push(TAG_INTEGER);
+            buf.extend_from_slice(&number.to_be_bytes());
+        },
+        Value::Real(number) => {
+            buf.push(TAG_REAL);
+            buf.extend_from_slice(&number.to_bits().to_be_bytes());
+        },
+        Value::Text(ref text) => {
+            buf.push(TAG_TEXT);
+            buf.extend_from_slice(&(text.len() as u32).to_be_bytes());
+            buf.extend_from_slice(text.as_bytes());
+        },
+        Value::Blob(ref blob) => {
+            buf.push(TAG_BLOB);
+            buf.extend_from_slice(&(blob.len() as u32).to_be_bytes());
+            buf.extend_from_slice(blob);
+        },
+    }
+}
+
+/// Splits the first `len` bytes off the slice, if it is long enough.
+fn split(bytes: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+    if bytes.len() < len {
+        return None;
+    }
+    Some(bytes.split_at(len))
+}
+
+/// Quotes a SQLite identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use super::*;
+
+    #[test]
+    fn test_sqlite_round_trip() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL, avatar BLOB);
+            INSERT INTO users VALUES (2, 'bob', 1.5, x'0102');
+            INSERT INTO users VALUES (-1, 'eve', NULL, NULL);
+            INSERT INTO users VALUES (10, 'alice', 3.0, x'');
+        ").unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(3, import_sqlite(&conn, "users", "id", &mut txn, db).unwrap());
+        txn.commit().unwrap();
+
+        // Integer keys sort numerically.
+        let txn = env.begin_ro_txn().unwrap();
+        let rows = {
+            let mut cursor = txn.open_ro_cursor(db).unwrap();
+            cursor.iter_start().map(|(_, row)| decode_row(row).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![Value::Integer(-1), Value::Text("eve".to_owned()), Value::Null, Value::Null], rows[0]);
+        assert_eq!(vec![Value::Integer(2), Value::Text("bob".to_owned()), Value::Real(1.5), Value::Blob(vec![1, 2])],
+                   rows[1]);
+        assert_eq!(Value::Integer(10), rows[2][0]);
+
+        conn.execute_batch("CREATE TABLE copy (id INTEGER PRIMARY KEY, name TEXT, score REAL, avatar BLOB)").unwrap();
+        assert_eq!(3, export_sqlite(&txn, db, &conn, "copy").unwrap());
+        let mismatched: i64 = conn.query_row("SELECT count(*) FROM (SELECT * FROM users EXCEPT SELECT * FROM copy)",
+                                             [], |row| row.get(0)).unwrap();
+        assert_eq!(0, mismatched);
+        drop(txn);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        match import_sqlite(&conn, "users", "score", &mut txn, db) {
+            Err(SqliteError::InvalidKey) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        txn.put(db, b"x", &[9], WriteFlags::empty()).unwrap();
+        match export_sqlite(&txn, db, &conn, "copy") {
+            Err(SqliteError::InvalidRow { key }) => assert_eq!(b"x", &key[..]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use ffi;
+
+/// Environment or database statistics.
+///
+/// Contains information about the size and layout of an LMDB environment or database.
+pub struct Stat(pub(crate) ffi::MDB_stat);
+
+impl Stat {
+    /// Size of a database page. This is the same for all databases in the environment.
+    ///
+    /// The page size is fixed when the environment is created.
+    #[inline]
+    pub fn page_size(&self) -> u32 {
+        self.0.ms_psize
+    }
+
+    /// Depth (height) of the B-tree.
+    #[inline]
+    pub fn depth(&self) -> u32 {
+        self.0.ms_depth
+    }
+
+    /// Number of internal (non-leaf) pages.
+    #[inline]
+    pub fn branch_pages(&self) -> usize {
+        self.0.ms_branch_pages
+    }
+
+    /// Number of leaf pages.
+    #[inline]
+    pub fn leaf_pages(&self) -> usize {
+        self.0.ms_leaf_pages
+    }
+
+    /// Number of overflow pages.
+    #[inline]
+    pub fn overflow_pages(&self) -> usize {
+        self.0.ms_overflow_pages
+    }
+
+    /// Number of data items.
+    #[inline]
+    pub fn entries(&self) -> usize {
+        self.0.ms_entries
+    }
+}
+
+/// Environment information.
+///
+/// Contains the size of the memory map and the usage of the environment, which can be used to
+/// decide when to grow the map.
+pub struct EnvironmentInfo(pub(crate) ffi::MDB_envinfo);
+
+impl EnvironmentInfo {
+    /// Size of the memory map, in bytes.
+    #[inline]
+    pub fn map_size(&self) -> usize {
+        self.0.me_mapsize
+    }
+
+    /// ID of the last used page.
+    ///
+    /// The number of bytes in use is `(last_pgno() + 1) * page_size`.
+    #[inline]
+    pub fn last_pgno(&self) -> usize {
+        self.0.me_last_pgno
+    }
+
+    /// ID of the last committed transaction.
+    #[inline]
+    pub fn last_txnid(&self) -> usize {
+        self.0.me_last_txnid
+    }
+
+    /// Maximum number of reader slots in the environment.
+    #[inline]
+    pub fn max_readers(&self) -> u32 {
+        self.0.me_maxreaders
+    }
+
+    /// Number of reader slots used in the environment.
+    #[inline]
+    pub fn num_readers(&self) -> u32 {
+        self.0.me_numreaders
+    }
+}
+
+/// Usage of the named database handles of an environment.
+///
+/// Returned by `Environment::dbi_stats`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DbiStats {
+    pub(crate) open: usize,
+    pub(crate) max: usize,
+}
+
+impl DbiStats {
+    /// Number of named database handles open in the environment.
+    #[inline]
+    pub fn open(&self) -> usize {
+        self.open
+    }
+
+    /// Maximum number of named database handles, as configured by
+    /// `EnvironmentBuilder::set_max_dbs`.
+    #[inline]
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Number of named database handles which can still be opened.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.max.saturating_sub(self.open)
+    }
+}
+
+/// An entry of the reader lock table of an environment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReaderInfo {
+    pub(crate) pid: i32,
+    pub(crate) thread: usize,
+    pub(crate) txn_id: Option<usize>,
+}
+
+impl ReaderInfo {
+    /// ID of the process owning the reader slot.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// ID of the thread owning the reader slot.
+    #[inline]
+    pub fn thread(&self) -> usize {
+        self.thread
+    }
+
+    /// ID of the snapshot read by the active read-only transaction using the slot, or `None` if
+    /// the slot is not in use by a transaction.
+    #[inline]
+    pub fn txn_id(&self) -> Option<usize> {
+        self.txn_id
+    }
+}
+
+/// The lag of the oldest reader of an environment behind its last committed transaction.
+///
+/// Returned by `Environment::reader_lag`. Pages freed by a write transaction can only be reused
+/// once every reader has moved past it, so a reader which lags far behind, such as a
+/// long-running or leaked read-only transaction, makes the database grow until the map is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReaderLag {
+    pub(crate) oldest_txn_id: Option<usize>,
+    pub(crate) last_txn_id: usize,
+}
+
+impl ReaderLag {
+    /// ID of the snapshot read by the oldest active read-only transaction, or `None` if there
+    /// is none.
+    #[inline]
+    pub fn oldest_txn_id(&self) -> Option<usize> {
+        self.oldest_txn_id
+    }
+
+    /// ID of the last committed write transaction.
+    #[inline]
+    pub fn last_txn_id(&self) -> usize {
+        self.last_txn_id
+    }
+
+    /// Number of write transactions committed since the snapshot of the oldest reader, or zero
+    /// if there is no active reader.
+    #[inline]
+    pub fn lag(&self) -> usize {
+        self.oldest_txn_id.map_or(0, |oldest| self.last_txn_id.saturating_sub(oldest))
+    }
+}
@@ -0,0 +1,243 @@
+//! Fixtures for tests of applications using LMDB.
+//!
+//! Enabled by the `testing` feature, which is meant to be enabled for the dev-dependency on this
+//! crate only.
+
+use std::fmt::Debug;
+use std::path::Path;
+use std::{result, thread};
+
+use tempdir::TempDir;
+
+use cursor::Cursor;
+use database::Database;
+use environment::{Environment, EnvironmentBuilder};
+use error::{LoadError, Result};
+use flags::{CopyFlags, EnvironmentFlags};
+use transaction::{RoTransaction, Transaction};
+
+/// An environment opened in a temporary directory, which is deleted once the environment is
+/// closed.
+///
+/// Returned by `env_from_template`.
+#[derive(Debug)]
+pub struct TemplateEnv {
+    // Declared before `dir` so that the environment is closed before the directory is deleted.
+    env: Environment,
+    dir: TempDir,
+}
+
+impl TemplateEnv {
+
+    /// Returns the environment.
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Returns the path of the temporary directory of the environment.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// Opens a copy of the environment in the template directory, in a new temporary directory.
+///
+/// The template is a directory holding a prepared, populated environment, such as one checked
+/// into the repository of the application. Each call copies it with `Environment::copy`, so tests
+/// start from a realistic dataset in isolation from each other and from the template.
+///
+/// The template is opened read-only and without a lock file, so that none is created in its
+/// directory, and so it must not be written while tests use it. Fails with `LoadError::Io` if the
+/// temporary directory can't be created.
+pub fn env_from_template(template_dir: &Path) -> result::Result<TemplateEnv, LoadError> {
+    env_from_template_with(Environment::new().set_max_dbs(64), template_dir)
+}
+
+/// Opens a copy of the environment in the template directory with the builder, in a new
+/// temporary directory.
+///
+/// See `env_from_template`.
+pub fn env_from_template_with(builder: &EnvironmentBuilder, template_dir: &Path)
+                              -> result::Result<TemplateEnv, LoadError> {
+    let dir = TempDir::new("lmdb-template")?;
+    {
+        let template = Environment::new().set_flags(EnvironmentFlags::READ_ONLY | EnvironmentFlags::NO_LOCK)
+                                         .open(template_dir)?;
+        template.copy(dir.path(), CopyFlags::empty())?;
+    }
+    let env = builder.open(dir.path())?;
+    Ok(TemplateEnv { env, dir })
+}
+
+/// The items of a database, in order.
+type Items = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The contents of databases as seen by a transaction, to check that another transaction sees
+/// the same.
+///
+/// Returned by `freeze`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrozenView {
+    dbs: Vec<(Database, Items)>,
+}
+
+/// Copies the items of the databases as seen by the transaction.
+///
+/// The copy is meant for the small databases of tests, since every item is copied.
+pub fn freeze<T>(txn: &T, dbs: &[Database]) -> Result<FrozenView> where T: Transaction {
+    let dbs = dbs.iter().map(|&db| Ok((db, items(txn, db)?))).collect::<Result<_>>()?;
+    Ok(FrozenView { dbs })
+}
+
+impl FrozenView {
+
+    /// Returns the keys, by database, which the transaction sees differently from the frozen
+    /// view: added, deleted, or with other data items.
+    pub fn changed_keys<T>(&self, txn: &T) -> Result<Vec<(Database, Vec<u8>)>> where T: Transaction {
+        let mut changed = Vec::new();
+        for &(db, ref frozen) in &self.dbs {
+            let current = items(txn, db)?;
+            let mut keys = frozen.iter().chain(&current).map(|(key, _)| key).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let data = |items: &[(Vec<u8>, Vec<u8>)]| {
+                    items.iter().filter(|&(k, _)| k == key).map(|(_, data)| data.clone()).collect::<Vec<_>>()
+                };
+                if data(frozen) != data(&current) {
+                    changed.push((db, key.clone()));
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Panics if the transaction sees the databases differently from the frozen view, listing
+    /// the changed keys.
+    pub fn assert_unchanged<T>(&self, txn: &T) where T: Transaction {
+        let changed = self.changed_keys(txn).expect("failed to read the databases");
+        assert!(changed.is_empty(), "the view of the transaction changed for keys {:?}", changed);
+    }
+}
+
+/// Checks that reads through a read-only transaction are isolated from the writes made by the
+/// `write` closure.
+///
+/// A read-only transaction is begun and read with the `read` closure, then the `write` closure is
+/// run on another thread, as LMDB requires, and must commit its writes. The transaction is read
+/// again, and the function panics if the two reads differ, or if either closure fails or panics.
+/// Use it to check that an abstraction over LMDB, such as a cache or a typed wrapper, keeps the
+/// snapshot semantics of its reads, by reading through the abstraction in `read`. To compare
+/// whole databases instead, read them with `freeze`.
+pub fn assert_snapshot_isolation<R, T, W>(env: &Environment, read: R, write: W)
+where R: Fn(&RoTransaction) -> Result<T>,
+      T: Debug + PartialEq,
+      W: FnOnce(&Environment) -> Result<()> + Send {
+    let txn = env.begin_ro_txn().expect("failed to begin a read-only transaction");
+    let before = read(&txn).expect("the reads failed");
+    thread::scope(|scope| {
+        scope.spawn(|| write(env)).join()
+    }).expect("the writes panicked").expect("the writes failed");
+    let after = read(&txn).expect("the reads failed");
+    assert_eq!(before, after, "the reads of the transaction changed");
+}
+
+/// Copies the items of the database, in order.
+fn items<T>(txn: &T, db: Database) -> Result<Items> where T: Transaction {
+    let mut cursor = txn.open_ro_cursor(db)?;
+    let items = cursor.iter_start().map(|(key, data)| (key.to_vec(), data.to_vec())).collect();
+    Ok(items)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::panic::{self, AssertUnwindSafe};
+
+    use error::Error;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    #[test]
+    fn test_env_from_template() {
+        let template = TempDir::new("template").unwrap();
+        {
+            let env = Environment::new().set_max_dbs(1).open(template.path()).unwrap();
+            let db = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"user", b"name", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let copy1 = env_from_template(template.path()).unwrap();
+        let copy2 = env_from_template(template.path()).unwrap();
+        let (env1, env2) = (copy1.env(), copy2.env());
+        let db = env1.open_db(Some("users")).unwrap();
+        let mut txn = env1.begin_rw_txn().unwrap();
+        assert_eq!(b"name", txn.get(db, b"user").unwrap());
+        txn.put(db, b"user", b"changed", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        // The copies and the template are isolated from each other.
+        let db = env2.open_db(Some("users")).unwrap();
+        assert_eq!(b"name", env2.begin_ro_txn().unwrap().get(db, b"user").unwrap());
+        let env = Environment::new().set_max_dbs(1).open(template.path()).unwrap();
+        let db = env.open_db(Some("users")).unwrap();
+        assert_eq!(b"name", env.begin_ro_txn().unwrap().get(db, b"user").unwrap());
+
+        let path = copy1.path().to_path_buf();
+        drop(copy1);
+        assert!(!path.exists());
+
+        match env_from_template(&template.path().join("missing")) {
+            Err(LoadError::Lmdb(Error::Other(2))) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let db = env.create_db(Some("users"), DatabaseFlags::DUP_SORT).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"user", b"a", WriteFlags::empty()).unwrap();
+        txn.put(db, b"other", b"a", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        assert_snapshot_isolation(&env, |txn| freeze(txn, &[db]), |env| {
+            let mut txn = env.begin_rw_txn()?;
+            txn.put(db, b"user", b"b", WriteFlags::empty())?;
+            txn.del(db, b"other", None)?;
+            txn.put(db, b"new", b"a", WriteFlags::empty())?;
+            txn.commit()
+        });
+        assert_snapshot_isolation(&env, |txn| Ok(txn.get(db, b"new")?.to_vec()), |env| {
+            let mut txn = env.begin_rw_txn()?;
+            txn.del(db, b"new", None)?;
+            txn.commit()
+        });
+
+        // Reads which do not go through the transaction see the writes.
+        let read = |_: &RoTransaction| Ok(env.begin_ro_txn()?.open_ro_cursor(db)?.iter_start().count());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            assert_snapshot_isolation(&env, read, |env| {
+                let mut txn = env.begin_rw_txn()?;
+                txn.put(db, b"late", b"a", WriteFlags::empty())?;
+                txn.commit()
+            })
+        }));
+        assert!(result.is_err());
+
+        let txn = env.begin_ro_txn().unwrap();
+        let view = freeze(&txn, &[db]).unwrap();
+        view.assert_unchanged(&txn);
+        drop(txn);
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(Vec::<(Database, Vec<u8>)>::new(), view.changed_keys(&txn).unwrap());
+        txn.del(db, b"user", Some(b"a")).unwrap();
+        txn.put(db, b"added", b"a", WriteFlags::empty()).unwrap();
+        assert_eq!(vec![(db, b"added".to_vec()), (db, b"user".to_vec())], view.changed_keys(&txn).unwrap());
+    }
+}
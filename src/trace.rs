@@ -0,0 +1,267 @@
+#[cfg(feature = "tracing")]
+use std::cmp;
+
+#[cfg(feature = "tracing")]
+use tracing::Span;
+
+use ffi;
+
+use error::{Error, Result};
+use stat::DbiStats;
+
+/// The span of a transaction, covering its lifetime, while `Instrumentation::LOGGING` is
+/// enabled. Without the `tracing` feature, spans are empty and emit nothing.
+///
+/// Write transactions are traced at the `INFO` level and read-only transactions at the `DEBUG`
+/// level, each with the ID of the transaction. The span is a child of the span current when the
+/// transaction was begun, such as the span of the request which began it.
+pub(crate) struct TxnSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+}
+
+impl TxnSpan {
+
+    /// Returns a span which emits nothing, such as for a transaction which is committed.
+    pub(crate) fn none() -> TxnSpan {
+        TxnSpan {
+            #[cfg(feature = "tracing")]
+            span: Span::none(),
+        }
+    }
+
+    /// Begins the span of the top-level transaction, if `enabled`.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn begin(enabled: bool, txn: *mut ffi::MDB_txn, read_only: bool) -> TxnSpan {
+        if !enabled {
+            return TxnSpan::none();
+        }
+        let txn_id = unsafe { ffi::mdb_txn_id(txn) };
+        let span = if read_only {
+            tracing::debug_span!("read_txn", txn_id)
+        } else {
+            tracing::info_span!("write_txn", txn_id)
+        };
+        TxnSpan { span }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[inline]
+    pub(crate) fn begin(_enabled: bool, _txn: *mut ffi::MDB_txn, _read_only: bool) -> TxnSpan {
+        TxnSpan::none()
+    }
+
+    /// Begins the span of a transaction nested in this one, as a child of this span.
+    pub(crate) fn nested(&self, _txn: *mut ffi::MDB_txn) -> TxnSpan {
+        #[cfg(feature = "tracing")]
+        {
+            if !self.span.is_disabled() {
+                let txn_id = unsafe { ffi::mdb_txn_id(_txn) };
+                let span = tracing::info_span!(parent: &self.span, "nested_write_txn", txn_id);
+                return TxnSpan { span };
+            }
+        }
+        TxnSpan::none()
+    }
+
+    /// Whether the span is recorded, so that a renewed transaction is traced like the one reset.
+    pub(crate) fn is_enabled(&self) -> bool {
+        #[cfg(feature = "tracing")]
+        {
+            !self.span.is_disabled()
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            false
+        }
+    }
+
+    /// Records the outcome of committing the transaction.
+    pub(crate) fn committed(&self, result: &Result<()>) {
+        match *result {
+            Ok(()) => self.event("commit"),
+            Err(error) => self.failed("commit", error),
+        }
+    }
+
+    /// Records that the transaction was aborted, explicitly or by dropping it.
+    pub(crate) fn aborted(&self) {
+        self.event("abort");
+    }
+
+    /// Records that the read-only transaction was reset by `RoTransaction::reset`.
+    pub(crate) fn reset(&self) {
+        self.event("reset");
+    }
+
+    /// Records a failed operation of the transaction. Only `Error::MapFull` is recorded, since
+    /// other errors, such as `Error::NotFound`, are routinely handled by the caller.
+    pub(crate) fn failed(&self, _operation: &'static str, error: Error) {
+        #[cfg(feature = "tracing")]
+        {
+            if error == Error::MapFull && !self.span.is_disabled() {
+                tracing::warn!(parent: &self.span, operation = _operation, "{}", error);
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = error;
+    }
+
+    fn event(&self, _message: &'static str) {
+        #[cfg(feature = "tracing")]
+        {
+            if !self.span.is_disabled() {
+                tracing::debug!(parent: &self.span, "{}", _message);
+            }
+        }
+    }
+}
+
+/// Warns, if `enabled`, that a database handle was opened which leaves a tenth or fewer of the
+/// handles allowed by `EnvironmentBuilder::set_max_dbs`, so that a handle leak is noticed before
+/// opening a database fails with `Error::DbsFull`.
+pub(crate) fn dbi_opened(_enabled: bool, stats: DbiStats) {
+    #[cfg(feature = "tracing")]
+    {
+        if _enabled && stats.remaining() <= cmp::max(1, stats.max() / 10) {
+            tracing::warn!(open = stats.open(), max = stats.max(), "few database handles remain");
+        }
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = stats;
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tempdir::TempDir;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use environment::*;
+    use error::Error;
+    use flags::*;
+    use transaction::Transaction;
+
+    /// Records the names and transaction IDs of new spans, and the messages of events with the
+    /// name of their span.
+    #[derive(Clone, Default)]
+    struct Capture {
+        spans: Arc<Mutex<Vec<(String, u64)>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields(Vec<(&'static str, String)>);
+
+    impl Fields {
+        fn get(&self, name: &str) -> &str {
+            &self.0.iter().find(|&&(field, _)| field == name).unwrap().1
+        }
+    }
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push((field.name(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut fields = Fields(Vec::new());
+            span.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name().to_string(), fields.get("txn_id").parse().unwrap()));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut fields = Fields(Vec::new());
+            event.record(&mut fields);
+            let message = match event.parent() {
+                Some(parent) => {
+                    let span = self.spans.lock().unwrap()[parent.into_u64() as usize - 1].0.clone();
+                    format!("{}: {}", span, fields.get("message"))
+                },
+                None => format!("{} of {}: {}", fields.get("open"), fields.get("max"), fields.get("message")),
+            };
+            self.events.lock().unwrap().push(message);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_txn_spans() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_map_size(64 * 1024).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let capture = Capture::default();
+
+        let id = tracing::subscriber::with_default(capture.clone(), || {
+            // Nothing is traced until logging is enabled.
+            env.begin_rw_txn().unwrap().commit().unwrap();
+            env.begin_ro_txn().unwrap();
+
+            env.set_instrumentation(Instrumentation::LOGGING);
+            let mut txn = env.begin_rw_txn().unwrap();
+            let id = txn.id() as u64;
+            txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+            txn.begin_nested_txn().unwrap().abort();
+            txn.commit().unwrap();
+            let inactive = env.begin_ro_txn().unwrap().reset();
+            inactive.renew().unwrap().commit().unwrap();
+
+            let mut txn = env.begin_rw_txn().unwrap();
+            let value = vec![0; 1024 * 1024];
+            assert_eq!(Err(Error::MapFull), txn.put(db, b"large", &value, WriteFlags::empty()));
+            id
+        });
+
+        assert_eq!(vec![("write_txn".to_string(), id),
+                        ("nested_write_txn".to_string(), id),
+                        ("read_txn".to_string(), id),
+                        ("read_txn".to_string(), id),
+                        ("write_txn".to_string(), id + 1)],
+                   *capture.spans.lock().unwrap());
+        assert_eq!(vec!["nested_write_txn: abort",
+                        "write_txn: commit",
+                        "read_txn: reset",
+                        "read_txn: commit",
+                        "write_txn: MDB_MAP_FULL: Environment mapsize limit reached",
+                        "write_txn: abort"],
+                   *capture.events.lock().unwrap());
+    }
+
+    #[test]
+    fn test_dbi_warning() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).open(dir.path()).unwrap();
+        let capture = Capture::default();
+
+        tracing::subscriber::with_default(capture.clone(), || {
+            env.create_db(Some("untraced"), DatabaseFlags::empty()).unwrap();
+            env.create_db(Some("untraced2"), DatabaseFlags::empty()).unwrap();
+            env.set_instrumentation(Instrumentation::LOGGING);
+            // Reopening a handle does not open another one.
+            env.open_db(Some("untraced2")).unwrap();
+            env.create_db(Some("last"), DatabaseFlags::empty()).unwrap();
+        });
+        let events = capture.events.lock().unwrap();
+        let warnings = events.iter().filter(|event| event.contains("handles")).collect::<Vec<_>>();
+        assert_eq!(vec!["3 of 3: few database handles remain"], warnings);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use environment::Environment;
+use flags::Instrumentation;
+
+/// The kind of a transaction or cursor tracked while `Instrumentation::TRACKING` is enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HandleKind {
+    /// A read-only transaction, including a sendable one.
+    RoTransaction,
+    /// A read-write transaction, including a nested one.
+    RwTransaction,
+    /// A read-only or read-write cursor.
+    Cursor,
+}
+
+/// A transaction or cursor open in an environment, returned by `Environment::open_handles`.
+#[derive(Clone, Debug)]
+pub struct OpenHandle {
+    kind: HandleKind,
+    thread: ThreadId,
+    thread_name: Option<String>,
+    opened: Instant,
+}
+
+impl OpenHandle {
+    /// The kind of the handle.
+    #[inline]
+    pub fn kind(&self) -> HandleKind {
+        self.kind
+    }
+
+    /// The ID of the thread which opened the handle. A `SendableRoTransaction` may have been sent
+    /// to another thread since.
+    #[inline]
+    pub fn thread(&self) -> ThreadId {
+        self.thread
+    }
+
+    /// The name of the thread which opened the handle, if it is named.
+    #[inline]
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread_name.as_deref()
+    }
+
+    /// When the handle was opened.
+    #[inline]
+    pub fn opened(&self) -> Instant {
+        self.opened
+    }
+}
+
+/// The registry of the transactions and cursors open in an environment, found by them through
+/// the environment they were begun or opened in.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    next_id: AtomicU64,
+    handles: Mutex<BTreeMap<u64, OpenHandle>>,
+}
+
+impl Tracker {
+
+    /// Returns the registered handles, in the order they were opened.
+    pub(crate) fn handles(&self) -> Vec<OpenHandle> {
+        self.handles.lock().unwrap_or_else(PoisonError::into_inner).values().cloned().collect()
+    }
+
+    fn register(&self, kind: HandleKind) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let thread = thread::current();
+        let handle = OpenHandle {
+            kind,
+            thread: thread.id(),
+            thread_name: thread.name().map(str::to_owned),
+            opened: Instant::now(),
+        };
+        self.handles.lock().unwrap_or_else(PoisonError::into_inner).insert(id, handle);
+        id
+    }
+
+    fn unregister(&self, id: u64) {
+        self.handles.lock().unwrap_or_else(PoisonError::into_inner).remove(&id);
+    }
+}
+
+/// The registration of a transaction or cursor in the tracker of its environment, removed when
+/// dropped.
+///
+/// Handles opened while tracking is disabled are not registered, and handles registered are
+/// removed even if tracking was disabled since.
+pub(crate) struct Tracked<'env> {
+    registration: Option<(&'env Tracker, u64)>,
+}
+
+impl <'env> Tracked<'env> {
+
+    /// Returns a registration of nothing, such as for a handle which is closed.
+    pub(crate) fn none() -> Tracked<'env> {
+        Tracked { registration: None }
+    }
+
+    /// Registers a handle opened in the environment, if it tracks handles. Handles not opened in
+    /// an `Environment` are not registered.
+    #[inline]
+    pub(crate) fn open(env: Option<&'env Environment>, kind: HandleKind) -> Tracked<'env> {
+        match env {
+            Some(env) if env.instrumentation().contains(Instrumentation::TRACKING) => {
+                let tracker = env.tracker();
+                Tracked { registration: Some((tracker, tracker.register(kind))) }
+            },
+            _ => Tracked::none(),
+        }
+    }
+}
+
+impl <'env> Drop for Tracked<'env> {
+    fn drop(&mut self) {
+        if let Some((tracker, id)) = self.registration.take() {
+            tracker.unregister(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::thread;
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+    use super::*;
+
+    fn kinds(env: &Environment) -> Vec<HandleKind> {
+        env.open_handles().iter().map(OpenHandle::kind).collect()
+    }
+
+    #[test]
+    fn test_open_handles() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        // Nothing is tracked until tracking is enabled.
+        let untracked = env.begin_ro_txn().unwrap();
+        env.set_instrumentation(Instrumentation::TRACKING);
+        assert!(env.open_handles().is_empty());
+        drop(untracked);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        {
+            let nested = txn.begin_nested_txn().unwrap();
+            let _cursor = nested.open_ro_cursor(db).unwrap();
+            assert_eq!(vec![HandleKind::RwTransaction, HandleKind::RwTransaction, HandleKind::Cursor], kinds(&env));
+        }
+        assert_eq!(vec![HandleKind::RwTransaction], kinds(&env));
+        let handle = env.open_handles().pop().unwrap();
+        assert_eq!(thread::current().id(), handle.thread());
+        assert_eq!(thread::current().name(), handle.thread_name());
+        txn.commit().unwrap();
+        assert!(env.open_handles().is_empty());
+
+        // A reset transaction is not open until it is renewed.
+        let inactive = env.begin_ro_txn().unwrap().reset();
+        assert!(env.open_handles().is_empty());
+        let txn = inactive.renew().unwrap();
+        assert_eq!(vec![HandleKind::RoTransaction], kinds(&env));
+
+        // Handles registered stay so until closed, even if tracking is disabled.
+        env.set_instrumentation(Instrumentation::empty());
+        assert_eq!(vec![HandleKind::RoTransaction], kinds(&env));
+        txn.commit().unwrap();
+        assert!(env.open_handles().is_empty());
+    }
+}
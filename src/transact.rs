@@ -0,0 +1,269 @@
+use std::error::Error as StdError;
+use std::{fmt, result};
+
+use ffi;
+
+use database::Database;
+use environment::Environment;
+use error::Error;
+use flags::WriteFlags;
+use transaction::{RwTransaction, Transaction};
+
+/// An error which aborted `Environment::transact_all`, naming the database and operation which
+/// failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransactError {
+    dbi: Option<ffi::MDB_dbi>,
+    db_name: Option<String>,
+    operation: &'static str,
+    error: Error,
+}
+
+impl TransactError {
+
+    /// The handle of the database which the failed operation accessed, or `None` if the
+    /// operation did not access a single database, such as the commit.
+    pub fn dbi(&self) -> Option<ffi::MDB_dbi> {
+        self.dbi
+    }
+
+    /// The name of the database, if it was opened by name through the environment.
+    pub fn db_name(&self) -> Option<&str> {
+        self.db_name.as_deref()
+    }
+
+    /// The failed operation, such as `"put"` or `"commit"`.
+    pub fn operation(&self) -> &'static str {
+        self.operation
+    }
+
+    /// The error returned by the operation.
+    pub fn error(&self) -> Error {
+        self.error
+    }
+}
+
+impl fmt::Display for TransactError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.db_name, self.dbi) {
+            (Some(name), _) => write!(fmt, "{} in database {} failed: {}", self.operation, name, self.error),
+            (None, Some(dbi)) => write!(fmt, "{} in database {} failed: {}", self.operation, dbi, self.error),
+            (None, None) => write!(fmt, "{} failed: {}", self.operation, self.error),
+        }
+    }
+}
+
+impl StdError for TransactError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<Error> for TransactError {
+    fn from(error: Error) -> TransactError {
+        TransactError { dbi: None, db_name: None, operation: "transaction", error }
+    }
+}
+
+/// A result of `Environment::transact_all`.
+pub type TransactResult<T> = result::Result<T, TransactError>;
+
+/// The database handles given to `Environment::transact_all`, which hands them back to its
+/// closure.
+///
+/// Implemented for `Database`, for the typed handles of this crate, such as `IntegerDatabase`
+/// and `TypedDatabase`, for arrays and slices of `Database`, for references to sets, and for
+/// tuples of up to four sets, so that a transaction may span handles of different types.
+pub trait DatabaseSet {
+    /// Appends the databases of the set to `dbs`.
+    fn databases(&self, dbs: &mut Vec<Database>);
+}
+
+impl DatabaseSet for Database {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.push(*self);
+    }
+}
+
+impl DatabaseSet for [Database] {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.extend_from_slice(self);
+    }
+}
+
+impl <S> DatabaseSet for &S where S: DatabaseSet + ?Sized {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        (**self).databases(dbs);
+    }
+}
+
+impl <const N: usize> DatabaseSet for [Database; N] {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.extend_from_slice(self);
+    }
+}
+
+macro_rules! tuple_database_set {
+    ($($name:ident),+) => {
+        impl <$($name),+> DatabaseSet for ($($name,)+) where $($name: DatabaseSet),+ {
+            #[allow(non_snake_case)]
+            fn databases(&self, dbs: &mut Vec<Database>) {
+                let ($($name,)+) = self;
+                $($name.databases(dbs);)+
+            }
+        }
+    }
+}
+
+tuple_database_set!(A);
+tuple_database_set!(A, B);
+tuple_database_set!(A, B, C);
+tuple_database_set!(A, B, C, D);
+
+/// The write transaction of `Environment::transact_all`, restricted to the databases it was
+/// given.
+///
+/// Errors name the database and the operation which failed. Accessing a database which was not
+/// given fails with `Error::UNREGISTERED_DB`.
+pub struct TransactContext<'env> {
+    txn: RwTransaction<'env>,
+    env: &'env Environment,
+    dbs: Vec<Database>,
+}
+
+impl <'env> fmt::Debug for TransactContext<'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("TransactContext").field("dbs", &self.dbs).finish()
+    }
+}
+
+impl <'env> TransactContext<'env> {
+
+    pub(crate) fn new(txn: RwTransaction<'env>, env: &'env Environment, dbs: Vec<Database>) -> TransactContext<'env> {
+        TransactContext { txn, env, dbs }
+    }
+
+    /// Returns the databases the context was given, in order.
+    pub fn databases(&self) -> &[Database] {
+        &self.dbs
+    }
+
+    /// Gets the data associated with the key in the database.
+    pub fn get<K>(&self, db: Database, key: &K) -> TransactResult<&[u8]> where K: AsRef<[u8]> {
+        self.check(db, "get")?;
+        self.txn.get(db, key).map_err(|error| self.error(db, "get", error))
+    }
+
+    /// Stores an item into the database.
+    pub fn put<K, D>(&mut self, db: Database, key: &K, data: &D, flags: WriteFlags) -> TransactResult<()>
+    where K: AsRef<[u8]>, D: AsRef<[u8]> {
+        self.check(db, "put")?;
+        let result = self.txn.put(db, key, data, flags);
+        result.map_err(|error| self.error(db, "put", error))
+    }
+
+    /// Deletes an item from the database.
+    ///
+    /// See `RwTransaction::del`.
+    pub fn del<K>(&mut self, db: Database, key: &K, data: Option<&[u8]>) -> TransactResult<()>
+    where K: AsRef<[u8]> {
+        self.check(db, "del")?;
+        let result = self.txn.del(db, key, data);
+        result.map_err(|error| self.error(db, "del", error))
+    }
+
+    /// Deletes every item of the database.
+    pub fn clear_db(&mut self, db: Database) -> TransactResult<()> {
+        self.check(db, "clear_db")?;
+        let result = self.txn.clear_db(db);
+        result.map_err(|error| self.error(db, "clear_db", error))
+    }
+
+    /// Returns the underlying transaction, for operations which the context does not provide,
+    /// such as those of typed handles.
+    ///
+    /// Errors of these operations are not attributed to a database.
+    pub fn txn(&mut self) -> &mut RwTransaction<'env> {
+        &mut self.txn
+    }
+
+    pub(crate) fn commit(self) -> TransactResult<()> {
+        self.txn.commit().map_err(|error| TransactError { dbi: None, db_name: None, operation: "commit", error })
+    }
+
+    pub(crate) fn abort(self) {
+        self.txn.abort();
+    }
+
+    fn check(&self, db: Database, operation: &'static str) -> TransactResult<()> {
+        if self.dbs.contains(&db) { Ok(()) } else { Err(self.error(db, operation, Error::UNREGISTERED_DB)) }
+    }
+
+    fn error(&self, db: Database, operation: &'static str, error: Error) -> TransactError {
+        TransactError { dbi: Some(db.dbi()), db_name: self.env.db_name(db.dbi()), operation, error }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use flags::*;
+    use integer::IntegerDatabase;
+    use super::*;
+
+    #[test]
+    fn test_transact_all() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3).open(dir.path()).unwrap();
+        let users = env.create_db(Some("users"), DatabaseFlags::empty()).unwrap();
+        let events = env.create_db(Some("events"), DatabaseFlags::empty()).unwrap();
+        let other = env.create_db(Some("other"), DatabaseFlags::empty()).unwrap();
+
+        let count = env.transact_all((users, events), |ctx, (users, events)| {
+            ctx.put(users, b"user", b"name", WriteFlags::empty())?;
+            ctx.put(events, b"event", b"created user", WriteFlags::empty())?;
+            Ok(ctx.databases().len())
+        }).unwrap();
+        assert_eq!(2, count);
+
+        // A failure aborts the writes to every database.
+        let error = env.transact_all([users, events], |ctx, _| {
+            ctx.del(users, b"user", None)?;
+            ctx.put(events, b"event", b"again", WriteFlags::NO_OVERWRITE)
+        }).unwrap_err();
+        assert_eq!((Some(events.dbi()), Some("events"), "put", Error::KeyExist),
+                   (error.dbi(), error.db_name(), error.operation(), error.error()));
+        assert_eq!("put in database events failed: MDB_KEYEXIST: Key/data pair already exists",
+                   error.to_string());
+
+        let error = env.transact_all(&[users], |ctx, _| ctx.get(other, b"key").map(|_| ())).unwrap_err();
+        assert_eq!((Some("other"), "get", Error::UNREGISTERED_DB),
+                   (error.db_name(), error.operation(), error.error()));
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(b"name", txn.get(users, b"user").unwrap());
+        assert_eq!(b"created user", txn.get(events, b"event").unwrap());
+    }
+
+    #[test]
+    fn test_transact_all_typed() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let counts = env.create_db(Some("counts"), DatabaseFlags::INTEGER_KEY).unwrap();
+        let names = env.create_db(Some("names"), DatabaseFlags::empty()).unwrap();
+        let counts = IntegerDatabase::<u32>::new(&env, counts).unwrap();
+
+        let dbs = env.transact_all((counts, names), |ctx, (counts, names)| {
+            counts.put(ctx.txn(), 7, b"seven", WriteFlags::empty())?;
+            ctx.put(names, b"seven", b"7", WriteFlags::empty())?;
+            Ok(ctx.databases().to_vec())
+        }).unwrap();
+        assert_eq!(vec![counts.database(), names], dbs);
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(b"seven", counts.get(&txn, 7).unwrap());
+        assert_eq!(b"7", txn.get(names, b"seven").unwrap());
+    }
+}
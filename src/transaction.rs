@@ -1,14 +1,25 @@
 use libc::{c_uint, c_void, size_t};
+use std::collections::{BTreeMap, HashMap};
 use std::{fmt, mem, ptr, result, slice};
-use std::marker::PhantomData ;
+use std::backtrace::Backtrace;
+use std::sync::Arc;
+use std::thread;
 
 use ffi;
 
-use cursor::{RoCursor, RwCursor};
-use environment::Environment;
-use database::Database;
-use error::{Error, Result, lmdb_result};
-use flags::{DatabaseFlags, EnvironmentFlags, WriteFlags};
+use cursor::{Cursor, CursorIter, Items, Iter, IterPrefix, RoCursor, RwCursor};
+use environment::{CheckLevel, DroppedWrites, Environment, decode_generation, generation_key};
+use database::{Database, DatabaseLimits};
+use error::{Error, Result, lmdb_result, with_context};
+use flags::{DatabaseFlags, EnvironmentFlags, Instrumentation, WriteFlags};
+use header::Header;
+use metrics::{self, Operation};
+use notify::CommitEvent;
+use pool::PooledBuf;
+use quota::{Quota, Quotas, check_quota};
+use stat::Stat;
+use trace::TxnSpan;
+use tracking::{HandleKind, Tracked};
 
 /// An LMDB transaction.
 ///
@@ -21,12 +32,30 @@ pub trait Transaction : Sized {
     /// lifetime of the transaction.
     fn txn(&self) -> *mut ffi::MDB_txn;
 
+    /// Returns the environment the transaction was begun in, which holds its read mirror, or
+    /// `None` for transactions not begun by an `Environment`.
+    #[doc(hidden)]
+    fn environment(&self) -> Option<&Environment> {
+        None
+    }
+
+    /// Returns the ID of the transaction.
+    ///
+    /// A read-only transaction has the ID of the transaction whose snapshot it reads. A
+    /// read-write transaction has the ID it will be committed with, one more than the last
+    /// committed transaction.
+    fn id(&self) -> usize {
+        unsafe { ffi::mdb_txn_id(self.txn()) as usize }
+    }
+
     /// Commits the transaction.
     ///
     /// Any pending operations will be saved.
     fn commit(self) -> Result<()> {
         unsafe {
-            let result = lmdb_result(ffi::mdb_txn_commit(self.txn()));
+            let txn = self.txn();
+            let commit = || lmdb_result(ffi::mdb_txn_commit(txn));
+            let result = metrics::timed(self.environment(), Operation::Commit, commit);
             mem::forget(self);
             result
         }
@@ -74,11 +103,14 @@ pub trait Transaction : Sized {
                     -> Result<&'txn [u8]>
     where K: AsRef<[u8]> {
         let key = key.as_ref();
+        if let Some(env) = self.environment() {
+            env.read_mirror().record(database.dbi(), key);
+        }
         let mut key_val: ffi::MDB_val = ffi::MDB_val { mv_size: key.len() as size_t,
                                                        mv_data: key.as_ptr() as *mut c_void };
         let mut data_val: ffi::MDB_val = ffi::MDB_val { mv_size: 0,
                                                         mv_data: ptr::null_mut() };
-        unsafe {
+        let result = metrics::timed(self.environment(), Operation::Get, || unsafe {
             match ffi::mdb_get(self.txn(), database.dbi(), &mut key_val, &mut data_val) {
                 ffi::MDB_SUCCESS => {
                     Ok(slice::from_raw_parts(data_val.mv_data as *const u8,
@@ -86,7 +118,40 @@ pub trait Transaction : Sized {
                 },
                 err_code => Err(Error::from_err_code(err_code)),
             }
+        });
+        with_context(result, "get", self.txn(), database.dbi(), key)
+    }
+
+    /// Gets an item from a database, split into a header of `header_len` bytes and a body.
+    ///
+    /// Returns `Error::MALFORMED_VALUE` if the data is shorter than the header.
+    fn get_split<'txn, K>(&'txn self, database: Database, key: &K, header_len: usize) -> Result<(&'txn [u8], &'txn [u8])>
+    where K: AsRef<[u8]> {
+        let data = self.get(database, key)?;
+        if data.len() < header_len {
+            return Err(Error::MALFORMED_VALUE);
         }
+        Ok(data.split_at(header_len))
+    }
+
+    /// Gets an item written by `RwTransaction::put_with_header`, with its decoded header and its
+    /// body.
+    ///
+    /// Returns `Error::MALFORMED_VALUE` if the data is shorter than the header, or the header fails
+    /// to decode.
+    fn get_with_header<'txn, H, K>(&'txn self, database: Database, key: &K) -> Result<(H, &'txn [u8])>
+    where H: Header, K: AsRef<[u8]> {
+        let (header, body) = self.get_split(database, key, H::LEN)?;
+        let header = H::decode(header).ok_or(Error::MALFORMED_VALUE)?;
+        Ok((header, body))
+    }
+
+    /// Gets an owned copy of an item from a database, which may outlive the transaction.
+    ///
+    /// The copy is made into a buffer from a thread-local pool, which the buffer is returned to
+    /// when dropped. See `PooledBuf`.
+    fn get_owned<K>(&self, database: Database, key: &K) -> Result<PooledBuf> where K: AsRef<[u8]> {
+        self.get(database, key).map(PooledBuf::copy_from)
     }
 
     /// Open a new read-only cursor on the given database.
@@ -94,6 +159,32 @@ pub trait Transaction : Sized {
         RoCursor::new(self, db)
     }
 
+    /// Iterates over all of the items of the database, with cursors owned by the iterator.
+    ///
+    /// Unlike `Cursor::iter_start`, the iterator can be reversed and knows its length. See
+    /// `Items`.
+    fn iter<'txn>(&'txn self, db: Database) -> Result<Items<'txn>> {
+        Items::new(self, db)
+    }
+
+    /// Iterates over the items of the database, starting from the given key, with a cursor owned
+    /// by the iterator.
+    ///
+    /// See `Cursor::iter_from`.
+    fn iter_from<'txn, K>(&'txn self, db: Database, key: K) -> Result<CursorIter<'txn, Iter<'txn>>>
+    where K: AsRef<[u8]> {
+        Ok(CursorIter::new(self.open_ro_cursor(db)?, |cursor| cursor.iter_from(key)))
+    }
+
+    /// Iterates over the items of the database with keys starting with the prefix, with a cursor
+    /// owned by the iterator.
+    ///
+    /// See `Cursor::iter_prefix`.
+    fn iter_prefix<'txn, K>(&'txn self, db: Database, prefix: K) -> Result<CursorIter<'txn, IterPrefix<'txn>>>
+    where K: AsRef<[u8]> {
+        Ok(CursorIter::new(self.open_ro_cursor(db)?, |cursor| cursor.iter_prefix(prefix)))
+    }
+
     /// Gets the option flags for the given database in the transaction.
     fn db_flags(&self, db: Database) -> Result<DatabaseFlags> {
         let mut flags: c_uint = 0;
@@ -102,12 +193,58 @@ pub trait Transaction : Sized {
         }
         Ok(DatabaseFlags::from_bits_truncate(flags))
     }
+
+    /// Retrieves statistics about the given database.
+    fn stat(&self, db: Database) -> Result<Stat> {
+        unsafe {
+            let mut stat = Stat(mem::zeroed());
+            lmdb_try!(ffi::mdb_stat(self.txn(), db.dbi(), &mut stat.0));
+            Ok(stat)
+        }
+    }
+
+    /// Returns the number of items in the given database.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), each duplicate is
+    /// counted.
+    fn len(&self, db: Database) -> Result<usize> {
+        Ok(self.stat(db)?.entries())
+    }
+
+    /// Returns whether the given database has no items.
+    fn is_empty(&self, db: Database) -> Result<bool> {
+        Ok(self.len(db)? == 0)
+    }
+
+    /// Copies the items of the given database into a `BTreeMap`.
+    ///
+    /// Intended for small databases which are more convenient to work with in memory. For
+    /// databases with duplicate data items (`DatabaseFlags::DUP_SORT`), only the last data item
+    /// of each key is kept.
+    fn to_btreemap(&self, db: Database) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut cursor = self.open_ro_cursor(db)?;
+        Ok(cursor.iter_start().map(|(key, data)| (key.to_vec(), data.to_vec())).collect())
+    }
+
+    /// Copies the items of the given database into a `HashMap`, allocated with capacity for
+    /// every item of the database.
+    ///
+    /// For databases with duplicate data items (`DatabaseFlags::DUP_SORT`), only the last data
+    /// item of each key is kept.
+    fn to_hashmap(&self, db: Database) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+        let mut map = HashMap::with_capacity(self.len(db)?);
+        let mut cursor = self.open_ro_cursor(db)?;
+        map.extend(cursor.iter_start().map(|(key, data)| (key.to_vec(), data.to_vec())));
+        Ok(map)
+    }
 }
 
 /// An LMDB read-only transaction.
 pub struct RoTransaction<'env> {
     txn: *mut ffi::MDB_txn,
-    _marker: PhantomData<&'env ()>,
+    span: TxnSpan,
+    tracked: Tracked<'env>,
+    env: &'env Environment,
 }
 
 impl <'env> fmt::Debug for RoTransaction<'env> {
@@ -118,7 +255,8 @@ impl <'env> fmt::Debug for RoTransaction<'env> {
 
 impl <'env> Drop for RoTransaction<'env> {
     fn drop(&mut self) {
-        unsafe { ffi::mdb_txn_abort(self.txn) }
+        metrics::timed(Some(self.env), Operation::Abort, || unsafe { ffi::mdb_txn_abort(self.txn) });
+        self.span.aborted();
     }
 }
 
@@ -127,11 +265,10 @@ impl <'env> RoTransaction<'env> {
     /// Creates a new read-only transaction in the given environment. Prefer
     /// using `Environment::begin_ro_txn`.
     pub(crate) fn new(env: &'env Environment) -> Result<RoTransaction<'env>> {
-        let mut txn: *mut ffi::MDB_txn = ptr::null_mut();
-        unsafe {
-            lmdb_result(ffi::mdb_txn_begin(env.env(), ptr::null_mut(), ffi::MDB_RDONLY, &mut txn))?;
-            Ok(RoTransaction { txn: txn, _marker: PhantomData })
-        }
+        let txn = env.begin_txn(ffi::MDB_RDONLY)?;
+        let span = TxnSpan::begin(env.instrumentation().contains(Instrumentation::LOGGING), txn, true);
+        let tracked = Tracked::open(Some(env), HandleKind::RoTransaction);
+        Ok(RoTransaction { txn: txn, span, tracked, env })
     }
 
     /// Resets the read-only transaction.
@@ -146,13 +283,16 @@ impl <'env> RoTransaction<'env> {
     /// database pages allocated. Thus they prevent the old pages from being
     /// reused when writers commit new data, and so under heavy load the
     /// database size may grow much more rapidly than otherwise.
-    pub fn reset(self) -> InactiveTransaction<'env> {
-        let txn = self.txn;
+    pub fn reset(mut self) -> InactiveTransaction<'env> {
+        let (txn, env) = (self.txn, self.env);
+        let span = mem::replace(&mut self.span, TxnSpan::none());
+        self.tracked = Tracked::none();
         unsafe {
             mem::forget(self);
             ffi::mdb_txn_reset(txn)
         };
-        InactiveTransaction { txn: txn, _marker: PhantomData }
+        span.reset();
+        InactiveTransaction { txn: txn, traced: span.is_enabled(), env }
     }
 }
 
@@ -160,12 +300,126 @@ impl <'env> Transaction for RoTransaction<'env> {
     fn txn(&self) -> *mut ffi::MDB_txn {
         self.txn
     }
+
+    fn environment(&self) -> Option<&Environment> {
+        Some(self.env)
+    }
+
+    fn commit(mut self) -> Result<()> {
+        let (txn, env) = (self.txn, self.env);
+        let span = mem::replace(&mut self.span, TxnSpan::none());
+        let tracked = mem::replace(&mut self.tracked, Tracked::none());
+        mem::forget(self);
+        let commit = || unsafe { lmdb_result(ffi::mdb_txn_commit(txn)) };
+        let result = metrics::timed(Some(env), Operation::Commit, commit);
+        span.committed(&result);
+        drop(tracked);
+        result
+    }
+}
+
+/// A read-only transaction which may be sent to another thread.
+///
+/// LMDB ties a read-only transaction to the thread which began it, through its slot in the reader
+/// table, unless the environment is opened with `EnvironmentFlags::NO_TLS`. Created by
+/// `Environment::begin_sendable_ro_txn`, which requires that flag, so that tasks of an async
+/// runtime holding a snapshot may be migrated between threads.
+pub struct SendableRoTransaction<'env> {
+    txn: RoTransaction<'env>,
+}
+
+unsafe impl <'env> Send for SendableRoTransaction<'env> {}
+
+impl <'env> fmt::Debug for SendableRoTransaction<'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("SendableRoTransaction").finish()
+    }
+}
+
+impl <'env> SendableRoTransaction<'env> {
+
+    /// Creates a new sendable read-only transaction in the given environment, which must have
+    /// been opened with `EnvironmentFlags::NO_TLS`. Prefer using
+    /// `Environment::begin_sendable_ro_txn`.
+    pub(crate) fn new(env: &'env Environment) -> Result<SendableRoTransaction<'env>> {
+        if !env.get_flags()?.contains(EnvironmentFlags::NO_TLS) {
+            return Err(Error::Incompatible);
+        }
+        Ok(SendableRoTransaction { txn: RoTransaction::new(env)? })
+    }
+}
+
+impl <'env> Transaction for SendableRoTransaction<'env> {
+    fn txn(&self) -> *mut ffi::MDB_txn {
+        self.txn.txn
+    }
+
+    fn environment(&self) -> Option<&Environment> {
+        Some(self.txn.env)
+    }
+
+    fn commit(self) -> Result<()> {
+        self.txn.commit()
+    }
+}
+
+/// A long-lived read-only view of an environment, which can be refreshed to the latest
+/// committed state.
+///
+/// Created by `Environment::snapshot`. Reads go through the `Transaction` trait, like those of
+/// an `RoTransaction`. `Snapshot::refresh` resets and renews the underlying transaction, keeping
+/// its handle and its slot in the reader table. Like any read-only transaction, a snapshot keeps
+/// the pages of its state from being reused, so it should be refreshed regularly.
+pub struct Snapshot<'env> {
+    txn: RoTransaction<'env>,
+}
+
+impl <'env> fmt::Debug for Snapshot<'env> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("Snapshot").field("id", &self.id()).finish()
+    }
+}
+
+impl <'env> Snapshot<'env> {
+
+    /// Creates a snapshot of the latest committed state of the environment. Prefer using
+    /// `Environment::snapshot`.
+    pub(crate) fn new(env: &'env Environment) -> Result<Snapshot<'env>> {
+        Ok(Snapshot { txn: RoTransaction::new(env)? })
+    }
+
+    /// Moves the snapshot to the latest committed state of the environment.
+    ///
+    /// If renewing the transaction fails, reads fail with `Error::BadTxn` until a later refresh
+    /// succeeds.
+    pub fn refresh(&mut self) -> Result<()> {
+        unsafe {
+            ffi::mdb_txn_reset(self.txn.txn);
+            lmdb_result(ffi::mdb_txn_renew(self.txn.txn))
+        }
+    }
+}
+
+impl <'env> Transaction for Snapshot<'env> {
+    fn txn(&self) -> *mut ffi::MDB_txn {
+        self.txn.txn
+    }
+
+    fn environment(&self) -> Option<&Environment> {
+        Some(self.txn.env)
+    }
+
+    fn commit(self) -> Result<()> {
+        self.txn.commit()
+    }
 }
 
 /// An inactive read-only transaction.
 pub struct InactiveTransaction<'env> {
     txn: *mut ffi::MDB_txn,
-    _marker: PhantomData<&'env ()>,
+    /// Whether the transaction was traced before it was reset.
+    traced: bool,
+    env: &'env Environment,
 }
 
 impl <'env> fmt::Debug for InactiveTransaction<'env> {
@@ -188,21 +442,48 @@ impl <'env> InactiveTransaction<'env> {
     /// This acquires a new reader lock for a transaction handle that had been
     /// released by `RoTransaction::reset`.
     pub fn renew(self) -> Result<RoTransaction<'env>> {
-        let txn = self.txn;
+        let (txn, traced, env) = (self.txn, self.traced, self.env);
         unsafe {
             mem::forget(self);
             lmdb_result(ffi::mdb_txn_renew(txn))?
         };
-        Ok(RoTransaction { txn: txn, _marker: PhantomData })
+        Ok(RoTransaction {
+            txn: txn,
+            span: TxnSpan::begin(traced, txn, true),
+            tracked: Tracked::open(Some(env), HandleKind::RoTransaction),
+            env,
+        })
     }
 }
 
 /// An LMDB read-write transaction.
 pub struct RwTransaction<'env> {
     txn: *mut ffi::MDB_txn,
-    _marker: PhantomData<&'env ()>,
+    check_level: CheckLevel,
+    write_map: bool,
+    quotas: Option<Arc<Quotas>>,
+    /// The writes made through the transaction, if tracking is enabled.
+    changes: Option<Vec<Change>>,
+    /// The databases written through the transaction.
+    dirty: Vec<Database>,
+    /// The hooks to run once the transaction commits.
+    hooks: Vec<CommitHook>,
+    /// The transaction this one is nested in, which its writes and hooks are merged into when it
+    /// commits, or null. Only the fields which don't depend on `'env` are accessed through it.
+    parent: *mut RwTransaction<'env>,
+    /// Where the transaction was begun, if dropping it with writes is reported.
+    created: Option<Box<Backtrace>>,
+    span: TxnSpan,
+    _tracked: Tracked<'env>,
+    /// The estimated bytes of pages dirtied by the writes, see `RwTransaction::approx_dirty_bytes`.
+    dirty_bytes: usize,
+    /// The page size of the environment, read on the first write, or 0.
+    page_size: usize,
+    env: &'env Environment,
 }
 
+type CommitHook = Box<dyn FnOnce(&CommitEvent)>;
+
 impl <'env> fmt::Debug for RwTransaction<'env> {
     fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
         f.debug_struct("RwTransaction").finish()
@@ -211,7 +492,26 @@ impl <'env> fmt::Debug for RwTransaction<'env> {
 
 impl <'env> Drop for RwTransaction<'env> {
     fn drop(&mut self) {
-        unsafe { ffi::mdb_txn_abort(self.txn) }
+        // The transaction was committed.
+        if self.txn.is_null() {
+            return;
+        }
+        metrics::timed(Some(self.env), Operation::Abort, || unsafe { ffi::mdb_txn_abort(self.txn) });
+        self.span.aborted();
+        // Reported after the transaction is aborted, so that a panic does not leak it.
+        if let Some(created) = self.created.take() {
+            if !self.dirty.is_empty() && !thread::panicking() {
+                let message = format!("write transaction dropped without commit or abort, discarding its writes to \
+                                       databases {:?}; begun at:\n{}",
+                                      self.dirty.iter().map(Database::dbi).collect::<Vec<_>>(),
+                                      created);
+                match self.env.dropped_writes() {
+                    DroppedWrites::Abort => (),
+                    DroppedWrites::Log => eprintln!("lmdb: {}", message),
+                    DroppedWrites::Panic => panic!("{}", message),
+                }
+            }
+        }
     }
 }
 
@@ -220,14 +520,23 @@ impl <'env> RwTransaction<'env> {
     /// Creates a new read-write transaction in the given environment. Prefer
     /// using `Environment::begin_ro_txn`.
     pub(crate) fn new(env: &'env Environment) -> Result<RwTransaction<'env>> {
-        let mut txn: *mut ffi::MDB_txn = ptr::null_mut();
-        unsafe {
-            lmdb_result(ffi::mdb_txn_begin(env.env(),
-                        ptr::null_mut(),
-                        EnvironmentFlags::empty().bits(),
-                        &mut txn))?;
-            Ok(RwTransaction { txn: txn, _marker: PhantomData })
-        }
+        let txn = env.begin_txn(EnvironmentFlags::empty().bits())?;
+        Ok(RwTransaction {
+            txn,
+            check_level: env.check_level(),
+            write_map: env.write_map(),
+            quotas: env.quotas(),
+            changes: None,
+            dirty: Vec::new(),
+            hooks: Vec::new(),
+            parent: ptr::null_mut(),
+            created: creation_backtrace(env),
+            span: TxnSpan::begin(env.instrumentation().contains(Instrumentation::LOGGING), txn, false),
+            _tracked: Tracked::open(Some(env), HandleKind::RwTransaction),
+            dirty_bytes: 0,
+            page_size: 0,
+            env,
+        })
     }
 
     /// Opens a database in the provided transaction, creating it if necessary.
@@ -251,9 +560,192 @@ impl <'env> RwTransaction<'env> {
         Database::new(self.txn(), name, flags.bits() | ffi::MDB_CREATE)
     }
 
+    /// Starts recording the writes made through the transaction, to be reviewed with
+    /// `RwTransaction::pending_changes` before it is committed.
+    ///
+    /// Writes made with `RwTransaction::put`, `RwTransaction::put_many`, `RwTransaction::reserve`,
+    /// `RwTransaction::del` and `RwTransaction::clear_db` are recorded once they succeed. Writes
+    /// made through a cursor from `RwTransaction::open_rw_cursor` are not recorded. Nested
+    /// transactions begun afterwards record their writes too, which are added to those of this
+    /// transaction when they commit. Each write copies its key, so tracking is disabled by
+    /// default.
+    pub fn track_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the writes recorded since `RwTransaction::track_changes` was called, in the order
+    /// they were made.
+    ///
+    /// The iterator is empty if tracking is not enabled. The current data of a written key can be
+    /// read with `Transaction::get`.
+    pub fn pending_changes<'txn>(&'txn self) -> slice::Iter<'txn, Change> {
+        match self.changes {
+            Some(ref changes) => changes.iter(),
+            None => [].iter(),
+        }
+    }
+
+    /// Records a write if tracking is enabled.
+    fn record<F>(&mut self, change: F) where F: FnOnce() -> Change {
+        if let Some(ref mut changes) = self.changes {
+            changes.push(change());
+        }
+    }
+
+    /// Returns the environment of the transaction.
+    pub(crate) fn env(&self) -> &'env Environment {
+        self.env
+    }
+
+    /// Runs the hook once the transaction commits, with the databases written through it.
+    ///
+    /// The hook is not run if the transaction is aborted. The hooks of a nested transaction are
+    /// passed to its parent when it commits, so they run once the top-level transaction commits,
+    /// with its event; which is why hooks must be `'static`. See `Environment::subscribe_commits`.
+    pub fn on_commit<F>(&mut self, hook: F) where F: FnOnce(&CommitEvent) + 'static {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Commits the transaction without syncing the environment to disk, unlike
+    /// `Transaction::commit`.
+    ///
+    /// The transaction is durable after a crash of the application, but may be lost on a crash
+    /// of the operating system or a power failure, until the environment is synced, such as by
+    /// `Environment::barrier`, a later commit which syncs, or dropping the environment. Nested
+    /// transactions are never synced, since they commit into their parent.
+    pub fn commit_no_sync(self) -> Result<()> {
+        self.commit_with(true)
+    }
+
+    fn commit_with(mut self, no_sync: bool) -> Result<()> {
+        self.created = None;
+        let (env, parent) = (self.env, self.parent);
+        let nested = !parent.is_null();
+        // The databases written by a nested transaction are counted when its parent commits.
+        if !nested {
+            self.bump_generations()?;
+        }
+        let (id, dirty_bytes) = (self.id(), self.dirty_bytes);
+        let changes = self.changes.take();
+        let dirty = mem::take(&mut self.dirty);
+        let hooks = mem::take(&mut self.hooks);
+        let span = mem::replace(&mut self.span, TxnSpan::none());
+        // Committing frees the transaction, so it must not be aborted when dropped. The other
+        // fields are dropped as usual.
+        let txn = mem::replace(&mut self.txn, ptr::null_mut());
+        drop(self);
+        let result = metrics::timed(Some(env), Operation::Commit, || if nested {
+            unsafe { lmdb_result(ffi::mdb_txn_commit(txn)) }
+        } else {
+            env.commit_txn(txn, no_sync)
+        });
+        span.committed(&result);
+        result?;
+        if nested {
+            // Safe, since the parent is mutably borrowed by this transaction, and only fields
+            // which don't depend on its lifetime are accessed.
+            let parent = unsafe { &mut *parent };
+            for db in dirty {
+                parent.mark_dirty(db);
+            }
+            if let (Some(changes), Some(parent_changes)) = (changes, parent.changes.as_mut()) {
+                parent_changes.extend(changes);
+            }
+            parent.hooks.extend(hooks);
+            parent.dirty_bytes = parent.dirty_bytes.saturating_add(dirty_bytes);
+            return Ok(());
+        }
+        // The transaction is committed even if the flush of the sync period fails.
+        env.committed(no_sync);
+
+        let subscribed = env.commit_subscribers().is_active();
+        if hooks.is_empty() && !subscribed {
+            return Ok(());
+        }
+        let event = CommitEvent::new(id, dirty, changes);
+        for hook in hooks {
+            hook(&event);
+        }
+        if subscribed {
+            env.commit_subscribers().notify(&event);
+        }
+        Ok(())
+    }
+
+    /// Returns an estimate of the bytes of pages dirtied by the writes of the transaction, to
+    /// decide when to commit a batch and begin a new transaction before the transaction fails
+    /// with `Error::TxnFull`.
+    ///
+    /// LMDB keeps the pages written by a transaction in memory until it commits, and by default
+    /// fails once about 131,000 pages are dirty, such as 512 MiB of 4 KiB pages. The estimate
+    /// totals the size of each item written by `put`, `put_many`, `reserve` and `del`, as stored
+    /// in a leaf page, plus the whole overflow pages of data items too large for a leaf page;
+    /// failed writes are not counted. It understates writes scattered across a large database,
+    /// since each write dirties a whole page, and overstates repeated writes to the same keys.
+    /// Writes through cursors opened with `RwTransaction::open_rw_cursor` are not counted. A
+    /// nested transaction counts only its own writes, which are added to those of its parent when
+    /// it commits.
+    pub fn approx_dirty_bytes(&self) -> usize {
+        self.dirty_bytes
+    }
+
+    /// Adds the estimated size of writing an item to the bytes dirtied by the transaction.
+    fn count_dirty(&mut self, key_len: usize, data_len: usize) {
+        let size = item_size(self.page_size(), key_len, data_len);
+        self.dirty_bytes = self.dirty_bytes.saturating_add(size);
+    }
+
+    /// Returns the page size of the environment, reading it on first use.
+    fn page_size(&mut self) -> usize {
+        if self.page_size == 0 {
+            self.page_size = self.env.stat().map_or(4096, |stat| stat.page_size() as usize);
+        }
+        self.page_size
+    }
+
+    /// Marks the database as written.
+    fn mark_dirty(&mut self, db: Database) {
+        if !self.dirty.contains(&db) {
+            self.dirty.push(db);
+        }
+    }
+
+    /// Increments the generations of the databases written through the transaction.
+    fn bump_generations(&mut self) -> Result<()> {
+        let generations = match self.env.generations() {
+            Some(generations) => generations,
+            None => return Ok(()),
+        };
+        for i in 0..self.dirty.len() {
+            let dbi = self.dirty[i].dbi();
+            if dbi == generations.dbi() {
+                continue;
+            }
+            let key = match generation_key(dbi, self.env.db_name(dbi).as_deref()) {
+                Some(key) => key,
+                None => continue,
+            };
+            let generation = match self.get(generations, &key) {
+                Ok(generation) => decode_generation(generation)?,
+                Err(Error::NotFound) => 0,
+                Err(error) => return Err(error),
+            };
+            let generation = (generation + 1).to_ne_bytes();
+            let mut key_val = ffi::MDB_val { mv_size: key.len() as size_t,
+                                             mv_data: key.as_ptr() as *mut c_void };
+            let mut data_val = ffi::MDB_val { mv_size: generation.len() as size_t,
+                                              mv_data: generation.as_ptr() as *mut c_void };
+            unsafe {
+                lmdb_result(ffi::mdb_put(self.txn, generations.dbi(), &mut key_val, &mut data_val, 0))?;
+            }
+        }
+        Ok(())
+    }
+
     /// Opens a new read-write cursor on the given database and transaction.
     pub fn open_rw_cursor<'txn>(&'txn mut self, db: Database) -> Result<RwCursor<'txn>> {
-        RwCursor::new(self, db)
+        let dirty: *mut Vec<Database> = &mut self.dirty;
+        RwCursor::new(self, db, self.check_level, self.quota(db), dirty)
     }
 
     /// Stores an item into a database.
@@ -275,18 +767,80 @@ impl <'env> RwTransaction<'env> {
                                                        mv_data: key.as_ptr() as *mut c_void };
         let mut data_val: ffi::MDB_val = ffi::MDB_val { mv_size: data.len() as size_t,
                                                         mv_data: data.as_ptr() as *mut c_void };
-        unsafe {
+        let put = || unsafe {
+            if self.check_level == CheckLevel::Strict {
+                check_write(self.txn(), database.dbi(), key, data, flags.bits())?;
+            }
+            if let Some(quota) = self.quota(database) {
+                check_quota(self.txn(), database.dbi(), &quota, key, 1, data.len())?;
+            }
             lmdb_result(ffi::mdb_put(self.txn(),
                                      database.dbi(),
                                      &mut key_val,
                                      &mut data_val,
                                      flags.bits()))
+        };
+        let result = metrics::timed(Some(self.env), Operation::Put, put);
+        let result = with_context(result, "put", self.txn(), database.dbi(), key);
+        if let Err(error) = result {
+            self.span.failed("put", error);
         }
+        if result.is_ok() {
+            self.count_dirty(key.len(), data.len());
+            self.mark_dirty(database);
+            self.record(|| Change::Put { db: database, key: key.to_vec() });
+        }
+        result
+    }
+
+    /// Stores many items into a database, reusing a single cursor.
+    ///
+    /// This is equivalent to calling `put` for each item with the same flags,
+    /// but avoids the per-call overhead of locating the database's B-tree from
+    /// its root. With `WriteFlags::APPEND` (or `WriteFlags::APPEND_DUP`) and
+    /// items in database order, each item is written at the end of the tree
+    /// without searching, which is the fastest way to bulk load a database.
+    ///
+    /// Items written before an error remain in the transaction.
+    pub fn put_many<I, K, D>(&mut self, database: Database, items: I, flags: WriteFlags) -> Result<()>
+    where I: IntoIterator<Item=(K, D)>, K: AsRef<[u8]>, D: AsRef<[u8]> {
+        let tracking = self.changes.is_some();
+        let mut written = Vec::new();
+        let page_size = self.page_size();
+        let mut dirty_bytes = 0usize;
+        let cursor = RwCursor::new(self, database, self.check_level, self.quota(database), ptr::null_mut());
+        let result = cursor.and_then(|mut cursor| {
+            for (key, data) in items {
+                cursor.put(&key, &data, flags)?;
+                let size = item_size(page_size, key.as_ref().len(), data.as_ref().len());
+                dirty_bytes = dirty_bytes.saturating_add(size);
+                if tracking {
+                    written.push(key.as_ref().to_vec());
+                }
+            }
+            Ok(())
+        });
+        if dirty_bytes > 0 {
+            self.dirty_bytes = self.dirty_bytes.saturating_add(dirty_bytes);
+            self.mark_dirty(database);
+        }
+        for key in written {
+            self.record(|| Change::Put { db: database, key });
+        }
+        if let Err(error) = result {
+            self.span.failed("put_many", error);
+        }
+        result
     }
 
     /// Returns a buffer which can be used to write a value into the item at the
     /// given key and with the given length. The buffer must be completely
     /// filled by the caller.
+    ///
+    /// The buffer is the value's location in the memory map, so the value can be
+    /// constructed in place, for example with `copy_from_slice`, without an
+    /// intermediate copy. It borrows the transaction mutably, so it cannot be held
+    /// across another write, which may move the value.
     pub fn reserve<'txn, K>(&'txn mut self,
                             database: Database,
                             key: &K,
@@ -299,7 +853,13 @@ impl <'env> RwTransaction<'env> {
                                                        mv_data: key.as_ptr() as *mut c_void };
         let mut data_val: ffi::MDB_val = ffi::MDB_val { mv_size: len,
                                                         mv_data: ptr::null_mut::<c_void>() };
-        unsafe {
+        let mut reserve = || unsafe {
+            if self.check_level == CheckLevel::Strict {
+                check_write(self.txn(), database.dbi(), key, &[], flags.bits() | ffi::MDB_RESERVE)?;
+            }
+            if let Some(quota) = self.quota(database) {
+                check_quota(self.txn(), database.dbi(), &quota, key, 1, len)?;
+            }
             lmdb_result(ffi::mdb_put(self.txn(),
                         database.dbi(),
                         &mut key_val,
@@ -307,7 +867,50 @@ impl <'env> RwTransaction<'env> {
                         flags.bits() | ffi::MDB_RESERVE))?;
             Ok(slice::from_raw_parts_mut(data_val.mv_data as *mut u8,
                                          data_val.mv_size as usize))
+        };
+        let result = with_context(reserve(), "reserve", self.txn(), database.dbi(), key);
+        if let Err(error) = result {
+            self.span.failed("reserve", error);
+        }
+        if result.is_ok() {
+            self.count_dirty(key.len(), len);
+            self.mark_dirty(database);
+            self.record(|| Change::Put { db: database, key: key.to_vec() });
+        }
+        result
+    }
+
+    /// Stores an item whose data is the concatenation of the fragments into a database.
+    ///
+    /// The total length is reserved with `RwTransaction::reserve`, and the fragments are copied
+    /// straight into the database, so a composite value such as a header and a body need not be
+    /// concatenated first. Like `RwTransaction::reserve`, it is not supported by databases with
+    /// `DatabaseFlags::DUP_SORT`.
+    pub fn put_vectored<K>(&mut self, database: Database, key: &K, parts: &[&[u8]], flags: WriteFlags) -> Result<()>
+    where K: AsRef<[u8]> {
+        let len = parts.iter().map(|part| part.len()).sum();
+        let mut buf = self.reserve(database, key, len, flags)?;
+        for part in parts {
+            let (head, tail) = buf.split_at_mut(part.len());
+            head.copy_from_slice(part);
+            buf = tail;
         }
+        Ok(())
+    }
+
+    /// Stores an item whose data is the encoded header followed by the body into a database.
+    ///
+    /// The header is encoded directly into space reserved with `RwTransaction::reserve`, so it
+    /// is not supported by databases with `DatabaseFlags::DUP_SORT`. Read the item with
+    /// `Transaction::get_with_header`.
+    pub fn put_with_header<K, H>(&mut self, database: Database, key: &K, header: &H, body: &[u8], flags: WriteFlags)
+                                 -> Result<()>
+    where K: AsRef<[u8]>, H: Header {
+        let buf = self.reserve(database, key, H::LEN + body.len(), flags)?;
+        let (head, tail) = buf.split_at_mut(H::LEN);
+        header.encode(head);
+        tail.copy_from_slice(body);
+        Ok(())
     }
 
     /// Deletes an item from a database.
@@ -329,21 +932,36 @@ impl <'env> RwTransaction<'env> {
         let key = key.as_ref();
         let mut key_val: ffi::MDB_val = ffi::MDB_val { mv_size: key.len() as size_t,
                                                        mv_data: key.as_ptr() as *mut c_void };
-        let data_val: Option<ffi::MDB_val> =
+        let mut data_val: Option<ffi::MDB_val> =
             data.map(|data| ffi::MDB_val { mv_size: data.len() as size_t,
                                            mv_data: data.as_ptr() as *mut c_void });
-        unsafe {
-            lmdb_result(ffi::mdb_del(self.txn(),
-                                     database.dbi(),
-                                     &mut key_val,
-                                     data_val.map(|mut data_val| &mut data_val as *mut _)
-                                             .unwrap_or(ptr::null_mut())))
+        // The pointer borrows `data_val`, which outlives the call; mapping the option by value
+        // would point LMDB at a temporary which is dropped before the call.
+        let data_ptr = match data_val {
+            Some(ref mut data_val) => data_val as *mut _,
+            None => ptr::null_mut(),
+        };
+        let result = unsafe {
+            lmdb_result(ffi::mdb_del(self.txn(), database.dbi(), &mut key_val, data_ptr))
+        };
+        let result = with_context(result, "del", self.txn(), database.dbi(), key);
+        if let Err(error) = result {
+            self.span.failed("del", error);
         }
+        if result.is_ok() {
+            self.count_dirty(key.len(), 0);
+            self.mark_dirty(database);
+            self.record(|| Change::Del { db: database, key: key.to_vec(), data: data.map(<[u8]>::to_vec) });
+        }
+        result
     }
 
     /// Empties the given database. All items will be removed.
     pub fn clear_db(&mut self, db: Database) -> Result<()> {
-        unsafe { lmdb_result(ffi::mdb_drop(self.txn(), db.dbi(), 0)) }
+        unsafe { lmdb_result(ffi::mdb_drop(self.txn(), db.dbi(), 0))?; }
+        self.mark_dirty(db);
+        self.record(|| Change::Clear { db });
+        Ok(())
     }
 
     /// Drops the database from the environment.
@@ -356,21 +974,187 @@ impl <'env> RwTransaction<'env> {
         lmdb_result(ffi::mdb_drop(self.txn, db.dbi(), 1))
     }
 
+    /// Applies `f` to the transaction unless the idempotency key is already recorded in the
+    /// `applied` database, then records the key.
+    ///
+    /// The key is recorded in the same transaction as the writes of `f`, so once the transaction
+    /// commits a retried job with the same key is skipped, and if it aborts the job can be
+    /// retried. Returns `None` if the key was already recorded. If `f` fails, the transaction may
+    /// contain some of its writes, and should be aborted.
+    pub fn apply_once<K, F, R>(&mut self, applied: Database, idempotency_key: &K, f: F) -> Result<Option<R>>
+    where K: AsRef<[u8]>, F: FnOnce(&mut RwTransaction<'env>) -> Result<R> {
+        match self.get(applied, idempotency_key) {
+            Ok(..) => return Ok(None),
+            Err(Error::NotFound) => (),
+            Err(error) => return Err(error),
+        }
+        let result = f(self)?;
+        self.put(applied, idempotency_key, &[], WriteFlags::NO_OVERWRITE)?;
+        Ok(Some(result))
+    }
+
+    /// Returns the quota of the database, if it has one.
+    fn quota(&self, db: Database) -> Option<Quota> {
+        self.quotas.as_ref().and_then(|quotas| quotas.get(&db.dbi()).cloned())
+    }
+
     /// Begins a new nested transaction inside of this transaction.
+    ///
+    /// LMDB forbids using a transaction while it has an active child. The nested transaction
+    /// mutably borrows this transaction, so the borrow checker rejects any use of it, including
+    /// committing it or opening cursors, until the nested transaction is committed or dropped.
+    ///
+    /// Fails with `Error::NESTED_TXN_UNSUPPORTED` if the environment was opened with
+    /// `EnvironmentFlags::WRITE_MAP`.
     pub fn begin_nested_txn<'txn>(&'txn mut self) -> Result<RwTransaction<'txn>> {
+        if self.write_map {
+            return Err(Error::NESTED_TXN_UNSUPPORTED);
+        }
         let mut nested: *mut ffi::MDB_txn = ptr::null_mut();
         unsafe {
             let env: *mut ffi::MDB_env = ffi::mdb_txn_env(self.txn());
-            ffi::mdb_txn_begin(env, self.txn(), 0, &mut nested);
+            lmdb_result(ffi::mdb_txn_begin(env, self.txn(), 0, &mut nested))?;
+        }
+        Ok(RwTransaction {
+            txn: nested,
+            check_level: self.check_level,
+            write_map: self.write_map,
+            quotas: self.quotas.clone(),
+            changes: self.changes.as_ref().map(|_| Vec::new()),
+            dirty: Vec::new(),
+            hooks: Vec::new(),
+            parent: self as *mut RwTransaction<'env> as *mut _,
+            created: creation_backtrace(self.env),
+            span: self.span.nested(nested),
+            _tracked: Tracked::open(Some(self.env), HandleKind::RwTransaction),
+            dirty_bytes: 0,
+            page_size: self.page_size,
+            env: self.env,
+        })
+    }
+}
+
+/// Returns the size of an item in a leaf page, plus that of the overflow pages holding its data
+/// item if it is too large for the leaf page, as laid out by LMDB.
+fn item_size(page_size: usize, key_len: usize, data_len: usize) -> usize {
+    const WORD: usize = mem::size_of::<usize>();
+    const PAGE_HEADER: usize = WORD + 8;
+    const NODE_HEADER: usize = 8;
+    /// The size of the offset of a node in the index of its page.
+    const INDEX: usize = 2;
+    // A node must leave room for another in its page.
+    let max_node = (((page_size - PAGE_HEADER) / 2) & !1) - INDEX;
+    let node = NODE_HEADER + key_len + data_len;
+    if node <= max_node {
+        return (node + INDEX + 1) & !1;
+    }
+    // The node holds the number of the first overflow page instead of the data item.
+    let node = (NODE_HEADER + key_len + WORD + INDEX + 1) & !1;
+    node + (PAGE_HEADER + data_len).div_ceil(page_size) * page_size
+}
+
+/// Captures where a write transaction is begun, if dropping it with writes is reported.
+fn creation_backtrace(env: &Environment) -> Option<Box<Backtrace>> {
+    if cfg!(debug_assertions) && env.dropped_writes() != DroppedWrites::Abort {
+        Some(Box::new(Backtrace::force_capture()))
+    } else {
+        None
+    }
+}
+
+/// A write recorded by a `RwTransaction` with change tracking enabled.
+///
+/// See `RwTransaction::track_changes`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// A key/data pair was stored.
+    Put {
+        /// The database written to.
+        db: Database,
+        /// The key of the item.
+        key: Vec<u8>,
+    },
+    /// The items of a key were deleted.
+    Del {
+        /// The database deleted from.
+        db: Database,
+        /// The key of the items.
+        key: Vec<u8>,
+        /// The data item deleted, if only one of the duplicate data items of the key was.
+        data: Option<Vec<u8>>,
+    },
+    /// Every item of a database was deleted.
+    Clear {
+        /// The database cleared.
+        db: Database,
+    },
+}
+
+/// Performs the checks of `CheckLevel::Strict` for a write of the key/data pair with the given
+/// raw write flags.
+pub(crate) unsafe fn check_write(txn: *mut ffi::MDB_txn,
+                                 dbi: ffi::MDB_dbi,
+                                 key: &[u8],
+                                 data: &[u8],
+                                 flags: c_uint)
+                                 -> Result<()> {
+    let limits = DatabaseLimits::from_raw(txn, dbi)?;
+    let dup_sort = limits.flags().contains(DatabaseFlags::DUP_SORT);
+    if !dup_sort && flags & (ffi::MDB_NODUPDATA | ffi::MDB_APPENDDUP) != 0 {
+        return Err(Error::Incompatible);
+    }
+    if dup_sort && flags & ffi::MDB_RESERVE != 0 {
+        return Err(Error::Incompatible);
+    }
+    if limits.validate_entry(key, data).is_err() {
+        return Err(Error::BadValSize);
+    }
+    if flags & ffi::MDB_APPEND != 0 {
+        let mut cursor: *mut ffi::MDB_cursor = ptr::null_mut();
+        lmdb_result(ffi::mdb_cursor_open(txn, dbi, &mut cursor))?;
+        let mut last = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+        let mut last_data = ffi::MDB_val { mv_size: 0, mv_data: ptr::null_mut() };
+        let result = lmdb_result(ffi::mdb_cursor_get(cursor, &mut last, &mut last_data, ffi::MDB_LAST));
+        ffi::mdb_cursor_close(cursor);
+        match result {
+            Ok(()) => {
+                let key_val = ffi::MDB_val { mv_size: key.len() as size_t,
+                                             mv_data: key.as_ptr() as *mut c_void };
+                if ffi::mdb_cmp(txn, dbi, &key_val, &last) <= 0 {
+                    return Err(Error::KeyExist);
+                }
+            },
+            Err(Error::NotFound) => (),
+            Err(error) => return Err(error),
         }
-        Ok(RwTransaction { txn: nested, _marker: PhantomData })
     }
+    Ok(())
 }
 
 impl <'env> Transaction for RwTransaction<'env> {
     fn txn(&self) -> *mut ffi::MDB_txn {
         self.txn
     }
+
+    fn environment(&self) -> Option<&Environment> {
+        Some(self.env)
+    }
+
+    /// Commits the transaction, incrementing the generations of the databases written through it
+    /// if generations are tracked.
+    ///
+    /// Any pending operations will be saved. Once committed, the hooks added with
+    /// `RwTransaction::on_commit` are run, and the subscribers of the environment are notified.
+    /// If the environment has a sync period, the commit may flush it to disk. A failed flush is
+    /// reported by `Environment::sync_error` and retried by a later commit, and the commit still
+    /// succeeds.
+    fn commit(self) -> Result<()> {
+        self.commit_with(false)
+    }
+
+    fn abort(mut self) {
+        self.created = None;
+    }
 }
 
 #[cfg(test)]
@@ -386,6 +1170,7 @@ mod test {
 
     use tempdir::TempDir;
 
+    use cursor::Cursor;
     use environment::*;
     use error::*;
     use ffi::*;
@@ -393,6 +1178,75 @@ mod test {
     use super::*;
     use test_utils::*;
 
+    #[test]
+    fn test_strict_checks() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(3)
+                                    .set_check_level(CheckLevel::Strict)
+                                    .open(dir.path())
+                                    .unwrap();
+        assert_eq!(CheckLevel::Strict, env.check_level());
+        let db = env.create_db(Some("plain"), DatabaseFlags::empty()).unwrap();
+        let int_db = env.create_db(Some("int"), DatabaseFlags::INTEGER_KEY).unwrap();
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"b", b"1", WriteFlags::APPEND).unwrap();
+        assert_eq!(Err(Error::KeyExist), txn.put(db, b"a", b"1", WriteFlags::APPEND));
+        assert_eq!(Err(Error::KeyExist), txn.put(db, b"b", b"2", WriteFlags::APPEND));
+        assert_eq!(Err(Error::Incompatible), txn.put(db, b"c", b"1", WriteFlags::APPEND_DUP));
+        assert_eq!(Err(Error::Incompatible), txn.put(db, b"c", b"1", WriteFlags::NO_DUP_DATA));
+        assert_eq!(Err(Error::BadValSize), txn.put(int_db, b"abc", b"1", WriteFlags::empty()));
+        txn.put(int_db, &7u32.to_ne_bytes(), b"1", WriteFlags::empty()).unwrap();
+
+        txn.put(dup_db, b"b", b"1", WriteFlags::empty()).unwrap();
+        txn.put(dup_db, b"b", b"2", WriteFlags::APPEND_DUP).unwrap();
+        assert_eq!(Err(Error::KeyExist), txn.put(dup_db, b"b", b"3", WriteFlags::APPEND));
+        assert_eq!(Err(Error::Incompatible), txn.reserve(dup_db, b"c", 4, WriteFlags::empty()).map(|_| ()));
+
+        {
+            let mut cursor = txn.open_rw_cursor(db).unwrap();
+            cursor.put(b"c", b"1", WriteFlags::APPEND).unwrap();
+            assert_eq!(Err(Error::KeyExist), cursor.put(b"a", b"1", WriteFlags::APPEND));
+        }
+
+        let mut nested = txn.begin_nested_txn().unwrap();
+        assert_eq!(Err(Error::BadValSize), nested.put(int_db, b"abc", b"1", WriteFlags::empty()));
+    }
+
+    #[test]
+    fn test_permissive_checks() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        assert_eq!(CheckLevel::Permissive, env.check_level());
+        let db = env.create_db(Some("plain"), DatabaseFlags::empty()).unwrap();
+        let int_db = env.create_db(Some("int"), DatabaseFlags::INTEGER_KEY).unwrap();
+
+        // LMDB does not check this, and neither does permissive mode.
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"c", b"1", WriteFlags::NO_DUP_DATA).unwrap();
+        // Nor are integer keys checked, so they must be `u32` or `u64`: LMDB would read past the
+        // end of a shorter key.
+        txn.put(int_db, &7u32.to_ne_bytes(), b"1", WriteFlags::empty()).unwrap();
+        txn.put(int_db, &3u32.to_ne_bytes(), b"2", WriteFlags::empty()).unwrap();
+        let cursor = txn.open_ro_cursor(int_db).unwrap();
+        assert_eq!(Some((&3u32.to_ne_bytes()[..], &b"2"[..])), cursor.first().unwrap());
+    }
+
+    #[test]
+    fn test_del_dup_data() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key", b"val2", WriteFlags::empty()).unwrap();
+        txn.del(db, b"key", Some(b"val1")).unwrap();
+        assert_eq!(b"val2", txn.get(db, b"key").unwrap());
+        assert_eq!(Err(Error::NotFound), txn.del(db, b"key", Some(b"val1")));
+    }
+
     #[test]
     fn test_put_get_del() {
         let dir = TempDir::new("test").unwrap();
@@ -415,6 +1269,178 @@ mod test {
         assert_eq!(txn.get(db, b"key1"), Err(Error::NotFound));
     }
 
+    #[test]
+    fn test_iter() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(1).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        txn.put(db, b"other", b"val3", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let items: Vec<(&[u8], &[u8])> = vec![(b"key1", b"val1"), (b"key2", b"val2"), (b"other", b"val3")];
+        assert_eq!(items, txn.iter(db).unwrap().collect::<Vec<_>>());
+        let reversed = items.iter().rev().cloned().collect::<Vec<_>>();
+        assert_eq!(reversed, txn.iter(db).unwrap().rev().collect::<Vec<_>>());
+        assert_eq!(Some(items[2]), txn.iter(db).unwrap().last());
+        assert_eq!(items[1..].to_vec(), txn.iter_from(db, b"key15").unwrap().collect::<Vec<_>>());
+        assert_eq!(items[..2].to_vec(), txn.iter_prefix(db, b"key").unwrap().collect::<Vec<_>>());
+        assert_eq!(0, txn.iter_prefix(db, b"missing").unwrap().count());
+
+        // The ends meet in the middle.
+        let mut iter = txn.iter(db).unwrap();
+        assert_eq!((3, Some(3)), iter.size_hint());
+        assert_eq!(Some(items[0]), iter.next());
+        assert_eq!(Some(items[2]), iter.next_back());
+        assert_eq!(1, iter.len());
+        assert_eq!(Some(items[1]), iter.next_back());
+        assert_eq!(None, iter.next());
+        assert_eq!(None, iter.next_back());
+        assert_eq!(0, iter.len());
+        drop(iter);
+        drop(txn);
+
+        // Duplicate data items are counted individually.
+        let dup_db = env.create_db(Some("dup"), DatabaseFlags::DUP_SORT).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for data in &[b"a", b"b", b"c"] {
+            txn.put(dup_db, b"key", data, WriteFlags::empty()).unwrap();
+        }
+        assert_eq!(3, txn.iter(dup_db).unwrap().len());
+        assert_eq!(vec![&b"c"[..], b"b", b"a"],
+                   txn.iter(dup_db).unwrap().rev().map(|(_, data)| data).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_reserve_in_place() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        {
+            let value = txn.reserve(db, b"key", 8, WriteFlags::empty()).unwrap();
+            value[..4].copy_from_slice(b"head");
+            for (i, byte) in value[4..].iter_mut().enumerate() {
+                *byte = b'0' + i as u8;
+            }
+        }
+        assert_eq!(b"head0123", txn.get(db, b"key").unwrap());
+    }
+
+    #[test]
+    fn test_put_many() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        let items: Vec<(&[u8], &[u8])> = vec![(b"key1", b"val1"), (b"key2", b"val2"), (b"key3", b"val3")];
+        txn.put_many(db, items, WriteFlags::APPEND).unwrap();
+        txn.put_many(db, vec![(b"key0", b"val0")], WriteFlags::empty()).unwrap();
+        assert_eq!(Err(Error::KeyExist),
+                   txn.put_many(db, vec![(b"key4", b"val4"), (b"key2", b"val2")], WriteFlags::APPEND));
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let mut cursor = txn.open_ro_cursor(db).unwrap();
+        assert_eq!(vec![(&b"key0"[..], &b"val0"[..]),
+                        (&b"key1"[..], &b"val1"[..]),
+                        (&b"key2"[..], &b"val2"[..]),
+                        (&b"key3"[..], &b"val3"[..]),
+                        (&b"key4"[..], &b"val4"[..])],
+                   cursor.iter_start().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_approx_dirty_bytes() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let page_size = env.stat().unwrap().page_size() as usize;
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(0, txn.approx_dirty_bytes());
+        // A leaf node has an 8 byte header and a 2 byte offset in its page.
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        assert_eq!(18, txn.approx_dirty_bytes());
+        txn.del(db, b"key1", None).unwrap();
+        assert_eq!(32, txn.approx_dirty_bytes());
+        txn.put_many(db, vec![(b"key1", b"val1"), (b"key2", b"val2")], WriteFlags::empty()).unwrap();
+        assert_eq!(68, txn.approx_dirty_bytes());
+
+        // A large data item is written to overflow pages, with the page number in the node.
+        txn.reserve(db, b"key3", 10000, WriteFlags::empty()).unwrap().copy_from_slice(&[0; 10000]);
+        assert_eq!(68 + 22 + (16 + 10000usize).div_ceil(page_size) * page_size, txn.approx_dirty_bytes());
+
+        // Failed writes are not counted.
+        let dirty = txn.approx_dirty_bytes();
+        assert_eq!(Err(Error::KeyExist), txn.put(db, b"key1", b"val1", WriteFlags::NO_OVERWRITE));
+        assert_eq!(Err(Error::NotFound), txn.del(db, b"missing", None));
+        let items = vec![(&b"key5"[..], &b"val5"[..]), (b"key1", b"val1")];
+        assert_eq!(Err(Error::KeyExist), txn.put_many(db, items, WriteFlags::NO_OVERWRITE));
+        assert_eq!(dirty + 18, txn.approx_dirty_bytes());
+
+        // The writes of a nested transaction are added to its parent when it commits.
+        let dirty = txn.approx_dirty_bytes();
+        {
+            let mut nested = txn.begin_nested_txn().unwrap();
+            nested.put(db, b"key4", b"val4", WriteFlags::empty()).unwrap();
+            assert_eq!(18, nested.approx_dirty_bytes());
+        }
+        assert_eq!(dirty, txn.approx_dirty_bytes());
+        let mut nested = txn.begin_nested_txn().unwrap();
+        nested.put(db, b"key4", b"val4", WriteFlags::empty()).unwrap();
+        nested.commit().unwrap();
+        assert_eq!(dirty + 18, txn.approx_dirty_bytes());
+    }
+
+    #[test]
+    fn test_pending_changes() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"untracked", b"", WriteFlags::empty()).unwrap();
+        assert_eq!(0, txn.pending_changes().count());
+
+        txn.track_changes();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        assert!(txn.put(db, b"key1", b"val1", WriteFlags::NO_OVERWRITE).is_err());
+        txn.put_many(db, vec![(b"key3", b"val3"), (b"key1", b"val1")], WriteFlags::NO_OVERWRITE).unwrap_err();
+        txn.reserve(db, b"key4", 4, WriteFlags::empty()).unwrap().copy_from_slice(b"val4");
+        txn.del(db, b"untracked", None).unwrap();
+        assert!(txn.del(db, b"missing", None).is_err());
+        txn.clear_db(db).unwrap();
+        assert_eq!(vec![Change::Put { db, key: b"key1".to_vec() },
+                        Change::Put { db, key: b"key3".to_vec() },
+                        Change::Put { db, key: b"key4".to_vec() },
+                        Change::Del { db, key: b"untracked".to_vec(), data: None },
+                        Change::Clear { db }],
+                   txn.pending_changes().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_commit_drops_fields() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        env.set_quota(db, Quota::new().with_max_entries(10));
+        let quotas = env.quotas().unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.track_changes();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        assert_eq!(3, Arc::strong_count(&quotas));
+        txn.commit().unwrap();
+        assert_eq!(2, Arc::strong_count(&quotas));
+    }
+
     #[test]
     fn test_reserve() {
         let dir = TempDir::new("test").unwrap();
@@ -436,6 +1462,41 @@ mod test {
         assert_eq!(txn.get(db, b"key1"), Err(Error::NotFound));
     }
 
+    #[test]
+    fn test_apply_once() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+        let applied = env.create_db(Some("applied"), DatabaseFlags::empty()).unwrap();
+
+        let job = |txn: &mut RwTransaction| -> Result<usize> {
+            let count = txn.len(db)?;
+            txn.put(db, &format!("key{}", count), b"val", WriteFlags::empty())?;
+            Ok(count + 1)
+        };
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(Some(1), txn.apply_once(applied, b"job1", job).unwrap());
+        assert_eq!(None, txn.apply_once(applied, b"job1", job).unwrap());
+        txn.commit().unwrap();
+
+        // An aborted job may be retried.
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(Some(2), txn.apply_once(applied, b"job2", job).unwrap());
+        drop(txn);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(None, txn.apply_once(applied, b"job1", job).unwrap());
+        assert_eq!(Some(2), txn.apply_once(applied, b"job2", job).unwrap());
+        assert_eq!(Err(Error::NotFound),
+                   txn.apply_once(applied, b"job3", |txn| txn.del(db, b"missing", None)));
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(2, txn.len(db).unwrap());
+        assert_eq!(2, txn.len(applied).unwrap());
+    }
+
     #[test]
     fn test_inactive_txn() {
         let dir = TempDir::new("test").unwrap();
@@ -454,6 +1515,108 @@ mod test {
         assert!(active.get(db, b"key").is_ok());
     }
 
+    #[test]
+    fn test_snapshot() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+        let write = |data: &[u8]| {
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", &data, WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        };
+        write(b"val1");
+
+        let mut snapshot = env.snapshot().unwrap();
+        assert_eq!(1, snapshot.id());
+        // The writes are made by another thread, as this thread holds the snapshot.
+        thread::scope(|scope| {
+            scope.spawn(|| write(b"val2"));
+        });
+        assert_eq!(b"val1", snapshot.get(db, b"key").unwrap());
+
+        snapshot.refresh().unwrap();
+        assert_eq!(2, snapshot.id());
+        assert_eq!(b"val2", snapshot.get(db, b"key").unwrap());
+        assert_eq!(1, snapshot.iter(db).unwrap().count());
+        // The snapshot kept its reader slot.
+        let readers = env.reader_list().unwrap();
+        assert_eq!(1, readers.len());
+        assert_eq!(Some(2), readers[0].txn_id());
+    }
+
+    #[test]
+    fn test_dropped_writes() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_dropped_writes(DroppedWrites::Panic).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        // Transactions without writes, and those committed or aborted, are not reported.
+        drop(env.begin_rw_txn().unwrap());
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.abort();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.del(db, b"key", None).unwrap();
+        let panic = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| drop(txn))).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("write transaction dropped without commit or abort"), "{}", message);
+        assert!(message.contains("test_dropped_writes"), "{}", message);
+
+        // The transaction was aborted before the panic, releasing the write lock.
+        let txn = env.begin_rw_txn().unwrap();
+        assert_eq!(b"val", txn.get(db, b"key").unwrap());
+    }
+
+    #[test]
+    fn test_dropped_writes_on_error() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_dropped_writes(DroppedWrites::Panic).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        // The helpers abort the transaction when `f` fails after writing.
+        let result: Result<()> = env.with_write_txn(|txn| {
+            txn.put(db, b"key", b"val", WriteFlags::empty())?;
+            txn.get(db, b"missing").map(|_| ())
+        });
+        assert_eq!(Err(Error::NotFound), result);
+        let result: Result<((), usize)> = env.write_and_read(|txn| {
+            txn.put(db, b"key", b"val", WriteFlags::empty())?;
+            Err(Error::Incompatible)
+        });
+        assert_eq!(Err(Error::Incompatible), result);
+        let result = env.transact_all(db, |ctx, db| {
+            ctx.put(db, b"key", b"val", WriteFlags::empty())?;
+            ctx.get(db, b"missing").map(|_| ())
+        });
+        assert_eq!(Error::NotFound, result.unwrap_err().error());
+
+        // Dropping a bulk loader discards its current batch.
+        let mut loader = ::bulk::BulkLoader::new(&env, db).unwrap();
+        loader.put(b"key", b"val").unwrap();
+        drop(loader);
+
+        assert_eq!(Err(Error::NotFound), env.begin_ro_txn().unwrap().get(db, b"key"));
+    }
+
+    #[test]
+    fn test_put_vectored() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put_vectored(db, b"key1", &[b"head", b"", b"body"], WriteFlags::empty()).unwrap();
+        txn.put_vectored(db, b"key2", &[], WriteFlags::empty()).unwrap();
+        assert_eq!(Err(Error::KeyExist), txn.put_vectored(db, b"key1", &[b"x"], WriteFlags::NO_OVERWRITE));
+        assert_eq!(b"headbody", txn.get(db, b"key1").unwrap());
+        assert_eq!(b"", txn.get(db, b"key2").unwrap());
+    }
+
     #[test]
     fn test_nested_txn() {
         let dir = TempDir::new("test").unwrap();
@@ -474,6 +1637,80 @@ mod test {
         assert_eq!(txn.get(db, b"key2"), Err(Error::NotFound));
     }
 
+    #[test]
+    fn test_nested_txn_error() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_flags(EnvironmentFlags::WRITE_MAP).open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        // LMDB does not support nested transactions in write-mapped environments.
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(Err(Error::NESTED_TXN_UNSUPPORTED), txn.begin_nested_txn().map(|_| ()));
+        txn.put(db, b"key", b"val", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_stat() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().set_max_dbs(2).open(dir.path()).unwrap();
+        let db = env.create_db(Some("db"), DatabaseFlags::empty()).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"val2", WriteFlags::empty()).unwrap();
+        assert_eq!(2, txn.stat(db).unwrap().entries());
+        txn.commit().unwrap();
+
+        // The main database only contains the named database.
+        let main = env.open_db(None).unwrap();
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(2, txn.stat(db).unwrap().entries());
+        assert_eq!(1, txn.stat(main).unwrap().entries());
+    }
+
+    #[test]
+    fn test_len() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!(0, txn.len(db).unwrap());
+        assert!(txn.is_empty(db).unwrap());
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key1", b"val2", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key2", b"val1", WriteFlags::empty()).unwrap();
+        assert_eq!(3, txn.len(db).unwrap());
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        assert_eq!(3, txn.len(db).unwrap());
+        assert!(!txn.is_empty(db).unwrap());
+    }
+
+    #[test]
+    fn test_to_map() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.create_db(None, DatabaseFlags::DUP_SORT).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert!(txn.to_btreemap(db).unwrap().is_empty());
+        txn.put(db, b"key2", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key1", b"val1", WriteFlags::empty()).unwrap();
+        txn.put(db, b"key1", b"val2", WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let map = txn.to_btreemap(db).unwrap();
+        assert_eq!(vec![(b"key1".to_vec(), b"val2".to_vec()), (b"key2".to_vec(), b"val1".to_vec())],
+                   map.into_iter().collect::<Vec<_>>());
+        let map = txn.to_hashmap(db).unwrap();
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&b"val2".to_vec()), map.get(&b"key1"[..]));
+    }
+
     #[test]
     fn test_clear_db() {
         let dir = TempDir::new("test").unwrap();
@@ -663,6 +1900,56 @@ mod test {
         });
     }
 
+    #[bench]
+    fn bench_put_many_rand(b: &mut Bencher) {
+        let n = 100u32;
+        let (_dir, env) = setup_bench_db(0);
+        let db = env.open_db(None).unwrap();
+
+        let mut items: Vec<(String, String)> = (0..n).map(|n| (get_key(n), get_data(n))).collect();
+        XorShiftRng::new_unseeded().shuffle(&mut items[..]);
+
+        b.iter(|| {
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put_many(db, items.iter().map(|(key, data)| (key, data)), WriteFlags::empty()).unwrap();
+            txn.abort();
+        });
+    }
+
+    #[bench]
+    fn bench_put_seq(b: &mut Bencher) {
+        let n = 100u32;
+        let (_dir, env) = setup_bench_db(0);
+        let db = env.open_db(None).unwrap();
+
+        let mut items: Vec<(String, String)> = (0..n).map(|n| (get_key(n), get_data(n))).collect();
+        items.sort();
+
+        b.iter(|| {
+            let mut txn = env.begin_rw_txn().unwrap();
+            for (key, data) in &items {
+                txn.put(db, key, data, WriteFlags::empty()).unwrap();
+            }
+            txn.abort();
+        });
+    }
+
+    #[bench]
+    fn bench_put_many_append(b: &mut Bencher) {
+        let n = 100u32;
+        let (_dir, env) = setup_bench_db(0);
+        let db = env.open_db(None).unwrap();
+
+        let mut items: Vec<(String, String)> = (0..n).map(|n| (get_key(n), get_data(n))).collect();
+        items.sort();
+
+        b.iter(|| {
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put_many(db, items.iter().map(|(key, data)| (key, data)), WriteFlags::APPEND).unwrap();
+            txn.abort();
+        });
+    }
+
     #[bench]
     fn bench_put_rand_raw(b: &mut Bencher) {
         let n = 100u32;
@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error as StdError;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::{fmt, result};
+
+use bincode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use cursor::{Cursor, CursorIter, Iter};
+use database::Database;
+use error::Error;
+use flags::WriteFlags;
+use transact::DatabaseSet;
+use transaction::{RwTransaction, Transaction};
+
+/// An error from a typed database operation.
+#[derive(Debug)]
+pub enum TypedError {
+    /// An error returned by LMDB.
+    Lmdb(Error),
+    /// A key or value could not be encoded or decoded.
+    Codec(bincode::Error),
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypedError::Lmdb(ref error) => error.fmt(fmt),
+            TypedError::Codec(ref error) => error.fmt(fmt),
+        }
+    }
+}
+
+impl StdError for TypedError {
+    fn description(&self) -> &str {
+        match *self {
+            TypedError::Lmdb(..) => "LMDB error",
+            TypedError::Codec(..) => "codec error",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            TypedError::Lmdb(ref error) => Some(error),
+            TypedError::Codec(ref error) => Some(&**error),
+        }
+    }
+}
+
+impl From<Error> for TypedError {
+    fn from(error: Error) -> TypedError {
+        TypedError::Lmdb(error)
+    }
+}
+
+impl From<bincode::Error> for TypedError {
+    fn from(error: bincode::Error) -> TypedError {
+        TypedError::Codec(error)
+    }
+}
+
+/// A typed database result.
+pub type TypedResult<T> = result::Result<T, TypedError>;
+
+/// A database handle which encodes keys and values with `bincode`.
+///
+/// Keys are compared by LMDB in their encoded form, so iteration order follows the encoded
+/// bytes rather than the `Ord` implementation of `K`. In particular, `bincode` encodes integers
+/// in little-endian order.
+///
+/// Requires the `serde` feature.
+pub struct TypedDatabase<K, V> {
+    db: Database,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl <K, V> Clone for TypedDatabase<K, V> {
+    fn clone(&self) -> TypedDatabase<K, V> {
+        *self
+    }
+}
+
+impl <K, V> Copy for TypedDatabase<K, V> {}
+
+impl <K, V> fmt::Debug for TypedDatabase<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("TypedDatabase").field("db", &self.db).finish()
+    }
+}
+
+impl <K, V> DatabaseSet for TypedDatabase<K, V> {
+    fn databases(&self, dbs: &mut Vec<Database>) {
+        dbs.push(self.db);
+    }
+}
+
+impl <K, V> TypedDatabase<K, V> where K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned {
+
+    /// Wraps a database handle.
+    pub fn new(db: Database) -> TypedDatabase<K, V> {
+        TypedDatabase { db, _marker: PhantomData }
+    }
+
+    /// Returns the underlying database handle.
+    pub fn database(&self) -> Database {
+        self.db
+    }
+
+    /// Gets the value associated with the key.
+    ///
+    /// Returns `Error::NotFound` if the key is not in the database.
+    pub fn get<T>(&self, txn: &T, key: &K) -> TypedResult<V> where T: Transaction {
+        let key = bincode::serialize(key)?;
+        let data = txn.get(self.db, &key)?;
+        Ok(bincode::deserialize(data)?)
+    }
+
+    /// Stores the key/value pair into the database.
+    pub fn put(&self,
+               txn: &mut RwTransaction,
+               key: &K,
+               value: &V,
+               flags: WriteFlags)
+               -> TypedResult<()> {
+        let key = bincode::serialize(key)?;
+        let value = bincode::serialize(value)?;
+        Ok(txn.put(self.db, &key, &value, flags)?)
+    }
+
+    /// Deletes the key from the database.
+    ///
+    /// If `value` is `Some` and the database supports duplicates, only the matching
+    /// key/value pair is deleted.
+    pub fn del(&self, txn: &mut RwTransaction, key: &K, value: Option<&V>) -> TypedResult<()> {
+        let key = bincode::serialize(key)?;
+        let value = match value {
+            Some(value) => Some(bincode::serialize(value)?),
+            None => None,
+        };
+        Ok(txn.del(self.db, &key, value.as_ref().map(|value| &value[..]))?)
+    }
+
+    /// Iterates over the decoded items of the database, starting from the beginning.
+    pub fn iter<'txn, T>(&self, txn: &'txn T) -> TypedResult<TypedIter<'txn, K, V>>
+    where T: Transaction {
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_start());
+        Ok(TypedIter { iter, _marker: PhantomData })
+    }
+
+    /// Iterates over the decoded items of the database, starting from the given key.
+    pub fn iter_from<'txn, T>(&self, txn: &'txn T, key: &K) -> TypedResult<TypedIter<'txn, K, V>>
+    where T: Transaction {
+        let key = bincode::serialize(key)?;
+        let iter = CursorIter::new(txn.open_ro_cursor(self.db)?, |cursor| cursor.iter_from(key));
+        Ok(TypedIter { iter, _marker: PhantomData })
+    }
+}
+
+impl <K, V> TypedDatabase<K, V> where K: Serialize + DeserializeOwned + Ord, V: Serialize + DeserializeOwned {
+
+    /// Decodes the items of the database into a `BTreeMap`.
+    ///
+    /// The map is ordered by the `Ord` implementation of `K`, which may differ from the order of
+    /// the encoded keys in the database.
+    pub fn to_btreemap<T>(&self, txn: &T) -> TypedResult<BTreeMap<K, V>> where T: Transaction {
+        self.iter(txn)?.collect()
+    }
+}
+
+impl <K, V> TypedDatabase<K, V> where K: Serialize + DeserializeOwned + Hash + Eq, V: Serialize + DeserializeOwned {
+
+    /// Decodes the items of the database into a `HashMap`, allocated with capacity for every
+    /// item of the database.
+    pub fn to_hashmap<T>(&self, txn: &T) -> TypedResult<HashMap<K, V>> where T: Transaction {
+        let mut map = HashMap::with_capacity(txn.len(self.db)?);
+        for item in self.iter(txn)? {
+            let (key, value) = item?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+/// An iterator over the decoded items of a `TypedDatabase`.
+pub struct TypedIter<'txn, K, V> {
+    iter: CursorIter<'txn, Iter<'txn>>,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl <'txn, K, V> fmt::Debug for TypedIter<'txn, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("TypedIter").finish()
+    }
+}
+
+impl <'txn, K, V> Iterator for TypedIter<'txn, K, V> where K: DeserializeOwned, V: DeserializeOwned {
+
+    type Item = TypedResult<(K, V)>;
+
+    fn next(&mut self) -> Option<TypedResult<(K, V)>> {
+        self.iter.next().map(|(key, value)| {
+            Ok((bincode::deserialize(key)?, bincode::deserialize(value)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use error::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_put_get_del() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db: TypedDatabase<String, (u32, Vec<u8>)> =
+            TypedDatabase::new(env.open_db(None).unwrap());
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        db.put(&mut txn, &"a".to_owned(), &(1, vec![1]), WriteFlags::empty()).unwrap();
+        db.put(&mut txn, &"b".to_owned(), &(2, vec![2, 2]), WriteFlags::empty()).unwrap();
+        txn.commit().unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        assert_eq!((1, vec![1]), db.get(&txn, &"a".to_owned()).unwrap());
+        assert_eq!((2, vec![2, 2]), db.get(&txn, &"b".to_owned()).unwrap());
+
+        db.del(&mut txn, &"a".to_owned(), None).unwrap();
+        match db.get(&txn, &"a".to_owned()) {
+            Err(TypedError::Lmdb(Error::NotFound)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db: TypedDatabase<String, u64> = TypedDatabase::new(env.open_db(None).unwrap());
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            db.put(&mut txn, &key.to_string(), &(i as u64), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let items = db.iter(&txn).unwrap().collect::<TypedResult<Vec<_>>>().unwrap();
+        assert_eq!(vec![("a".to_owned(), 0), ("b".to_owned(), 1), ("c".to_owned(), 2)], items);
+
+        let items = db.iter_from(&txn, &"b".to_owned())
+                      .unwrap()
+                      .collect::<TypedResult<Vec<_>>>()
+                      .unwrap();
+        assert_eq!(vec![("b".to_owned(), 1), ("c".to_owned(), 2)], items);
+    }
+
+    #[test]
+    fn test_to_map() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db: TypedDatabase<u32, String> = TypedDatabase::new(env.open_db(None).unwrap());
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &key in &[1u32, 256, 2] {
+            db.put(&mut txn, &key, &key.to_string(), WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let txn = env.begin_ro_txn().unwrap();
+        let map = db.to_btreemap(&txn).unwrap();
+        assert_eq!(vec![1, 2, 256], map.keys().cloned().collect::<Vec<_>>());
+        let map = db.to_hashmap(&txn).unwrap();
+        assert_eq!(3, map.len());
+        assert_eq!(Some(&"256".to_owned()), map.get(&256));
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let raw = env.open_db(None).unwrap();
+        let db: TypedDatabase<u8, u64> = TypedDatabase::new(raw);
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(raw, &[1], b"abc", WriteFlags::empty()).unwrap();
+        match db.get(&txn, &1) {
+            Err(TypedError::Codec(..)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
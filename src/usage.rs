@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use cursor::Cursor;
+use database::Database;
+use error::Result;
+use transaction::Transaction;
+
+/// The entries of a database sharing a key prefix, and the bytes they occupy.
+///
+/// Returned by `usage_by_prefix`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PrefixUsage {
+    entries: usize,
+    key_bytes: usize,
+    value_bytes: usize,
+}
+
+impl PrefixUsage {
+    /// Number of entries with the prefix. For databases with duplicate data items
+    /// (`DatabaseFlags::DUP_SORT`), each data item counts as an entry.
+    #[inline]
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Total length of the keys of the entries.
+    #[inline]
+    pub fn key_bytes(&self) -> usize {
+        self.key_bytes
+    }
+
+    /// Total length of the data items of the entries.
+    #[inline]
+    pub fn value_bytes(&self) -> usize {
+        self.value_bytes
+    }
+
+    /// Total length of the keys and data items of the entries.
+    ///
+    /// This excludes the overhead of the pages and nodes storing them, so it understates the
+    /// space used, especially for small entries.
+    #[inline]
+    pub fn total_bytes(&self) -> usize {
+        self.key_bytes + self.value_bytes
+    }
+
+    fn add(&mut self, other: PrefixUsage) {
+        self.entries += other.entries;
+        self.key_bytes += other.key_bytes;
+        self.value_bytes += other.value_bytes;
+    }
+}
+
+/// Scans the database and aggregates its entries by the first `prefix_len` bytes of their keys,
+/// such as a tenant or table identifier, to attribute the space used by each namespace.
+///
+/// Keys shorter than `prefix_len` are grouped under the whole key. The prefixes are returned in
+/// byte order.
+pub fn usage_by_prefix<T>(txn: &T, db: Database, prefix_len: usize) -> Result<BTreeMap<Vec<u8>, PrefixUsage>>
+where T: Transaction {
+    let mut cursor = txn.open_ro_cursor(db)?;
+    let mut usage: BTreeMap<Vec<u8>, PrefixUsage> = BTreeMap::new();
+    for (key, data) in cursor.iter_start() {
+        let prefix = &key[..key.len().min(prefix_len)];
+        let item = PrefixUsage { entries: 1, key_bytes: key.len(), value_bytes: data.len() };
+        // The prefix is only copied the first time it is seen.
+        match usage.get_mut(prefix) {
+            Some(total) => total.add(item),
+            None => {
+                usage.insert(prefix.to_vec(), item);
+            },
+        }
+    }
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use super::*;
+
+    #[test]
+    fn test_usage_by_prefix() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Environment::new().open(dir.path()).unwrap();
+        let db = env.open_db(None).unwrap();
+
+        let mut txn = env.begin_rw_txn().unwrap();
+        for &(key, data) in &[(&b"t1:a"[..], &b"12345"[..]), (b"t1:b", b"1"), (b"t2:a", b"123"), (b"t", b"")] {
+            txn.put(db, &key, &data, WriteFlags::empty()).unwrap();
+        }
+
+        let usage = usage_by_prefix(&txn, db, 2).unwrap();
+        assert_eq!(vec![&b"t"[..], b"t1", b"t2"], usage.keys().map(Vec::as_slice).collect::<Vec<_>>());
+        let t1 = usage[&b"t1"[..]];
+        assert_eq!((2, 8, 6, 14), (t1.entries(), t1.key_bytes(), t1.value_bytes(), t1.total_bytes()));
+        assert_eq!(PrefixUsage { entries: 1, key_bytes: 4, value_bytes: 3 }, usage[&b"t2"[..]]);
+        assert_eq!(PrefixUsage { entries: 1, key_bytes: 1, value_bytes: 0 }, usage[&b"t"[..]]);
+
+        // A zero-length prefix totals the whole database.
+        let usage = usage_by_prefix(&txn, db, 0).unwrap();
+        assert_eq!(4, usage[&b""[..]].entries());
+    }
+}
@@ -0,0 +1,38 @@
+use libc::c_int;
+use std::ffi::CStr;
+use std::ptr;
+
+use ffi;
+
+/// Returns the `(major, minor, patch)` version of the LMDB library the crate is linked against.
+pub fn version() -> (i32, i32, i32) {
+    let (mut major, mut minor, mut patch): (c_int, c_int, c_int) = (0, 0, 0);
+    unsafe {
+        ffi::mdb_version(&mut major, &mut minor, &mut patch);
+    }
+    (major, minor, patch)
+}
+
+/// Returns the version string of the LMDB library the crate is linked against, e.g.
+/// `"LMDB 0.9.22: (March 21, 2018)"`.
+pub fn version_string() -> &'static str {
+    unsafe {
+        let version = ffi::mdb_version(ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+        // The version string is a static ASCII string.
+        CStr::from_ptr(version).to_str().unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let (major, minor, patch) = version();
+        assert_eq!(ffi::MDB_VERSION_MAJOR, major);
+        assert!(minor >= ffi::MDB_VERSION_MINOR);
+        assert!(version_string().starts_with(&format!("LMDB {}.{}.{}", major, minor, patch)));
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{Arc, Weak};
+use std::{fmt, result};
+
+use environment::Environment;
+
+/// A weak reference to a shared environment, which does not keep the environment open.
+///
+/// Created by `Environment::downgrade`. This is a building block for state kept by the
+/// application which outlives individual requests, such as its caches of database handles or a
+/// metrics exporter: holding a weak reference, rather than a clone of the `Arc`, lets the owner
+/// of the environment reclaim and close it with `Environment::drain`. Such state should upgrade
+/// the reference only for the duration of each use, and discard itself once the environment is
+/// gone.
+///
+/// The long-lived helpers of this crate do not use weak references: a `WriteScheduler` and a
+/// `CompactionScheduler` each hold the environment open until they are dropped, so they must be
+/// dropped before the environment can be drained.
+#[derive(Clone, Default)]
+pub struct WeakEnvironment {
+    env: Weak<Environment>,
+}
+
+impl fmt::Debug for WeakEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        f.debug_struct("WeakEnvironment").field("closed", &self.is_closed()).finish()
+    }
+}
+
+impl WeakEnvironment {
+
+    /// Creates a weak reference to a shared environment.
+    /// Prefer using `Environment::downgrade`.
+    pub(crate) fn new(env: &Arc<Environment>) -> WeakEnvironment {
+        WeakEnvironment { env: Arc::downgrade(env) }
+    }
+
+    /// Returns a strong reference to the environment, or `None` if every strong reference has
+    /// been dropped.
+    ///
+    /// The returned reference keeps the environment open, so it should not be retained longer
+    /// than necessary.
+    pub fn upgrade(&self) -> Option<Arc<Environment>> {
+        self.env.upgrade()
+    }
+
+    /// Returns whether every strong reference to the environment has been dropped, in which
+    /// case the environment is closed, or is being closed.
+    pub fn is_closed(&self) -> bool {
+        self.env.strong_count() == 0
+    }
+
+    /// Returns whether the two weak references refer to the same environment.
+    pub fn ptr_eq(&self, other: &WeakEnvironment) -> bool {
+        self.env.ptr_eq(&other.env)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    use environment::*;
+    use flags::*;
+    use transaction::Transaction;
+
+    #[test]
+    fn test_upgrade() {
+        let dir = TempDir::new("test").unwrap();
+        let env = Arc::new(Environment::new().open(dir.path()).unwrap());
+        let weak = Environment::downgrade(&env);
+        assert!(!weak.is_closed());
+        assert!(weak.ptr_eq(&weak.clone()));
+
+        {
+            let env = weak.upgrade().unwrap();
+            let db = env.open_db(None).unwrap();
+            let mut txn = env.begin_rw_txn().unwrap();
+            txn.put(db, b"key", b"value", WriteFlags::empty()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        // Weak references don't prevent the environment from being drained and closed.
+        let env = Environment::drain(env, Duration::from_millis(10)).unwrap();
+        env.close().unwrap();
+        assert!(weak.is_closed());
+        assert!(weak.upgrade().is_none());
+    }
+}